@@ -5,10 +5,13 @@
 
 use agent_client_protocol::{self as acp, Client};
 use anyhow::Result;
+use futures::future::{AbortHandle, Abortable};
 
+use std::collections::{HashMap, VecDeque};
 use std::path::PathBuf;
 use std::sync::Arc;
 use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
 use tokio::sync::{Mutex, mpsc, oneshot};
 use tokio_util::compat::{TokioAsyncReadCompatExt, TokioAsyncWriteCompatExt};
 use tracing::{debug, error, info, warn};
@@ -16,7 +19,16 @@ use tracing::{debug, error, info, warn};
 use crate::agents::AgentConfig;
 use crate::agents::chat::ChatAgent;
 use crate::context::ContextStore;
+use crate::embeddings::EmbeddingService;
 use crate::logging::Logger;
+use crate::streaming::TextDeltaSender;
+use crate::tools::{Approval, ApprovalRequest, CommandEvent, ShellSessions, Workspace};
+
+/// Lines of a running command's output shown in its tool call
+const TOOL_CALL_OUTPUT_LINES: usize = 100;
+
+/// Minimum time between two updates of a running command's tool call
+const TOOL_CALL_UPDATE_INTERVAL: Duration = Duration::from_millis(200);
 
 /// ACP Agent implementation for Vega
 pub struct AcpAgent {
@@ -24,14 +36,20 @@ pub struct AcpAgent {
     config: AgentConfig,
     /// Context store for conversation history
     context_store: Arc<ContextStore>,
+    /// Embedding service shared by the prompts of all sessions
+    embedding_service: Arc<EmbeddingService>,
     /// Logger for ACP operations
     logger: Arc<Logger>,
     /// Channel for sending session updates to the client
-    session_update_tx: mpsc::UnboundedSender<(acp::SessionNotification, oneshot::Sender<()>)>,
+    session_update_tx: mpsc::UnboundedSender<ClientMessage>,
     /// Counter for generating session IDs
     next_session_id: AtomicU64,
-    /// Current working directory
-    cwd: Arc<Mutex<PathBuf>>,
+    /// Working directories of the sessions, the roots of their workspaces
+    session_cwds: std::sync::Mutex<HashMap<String, PathBuf>>,
+    /// Prompts being answered, by session, so the client can cancel them
+    running_prompts: std::sync::Mutex<HashMap<String, AbortHandle>>,
+    /// Persistent shells of the sessions, kept across prompts
+    shells: Arc<ShellSessions>,
 }
 
 impl AcpAgent {
@@ -39,16 +57,20 @@ impl AcpAgent {
     pub fn new(
         config: AgentConfig,
         context_store: Arc<ContextStore>,
+        embedding_service: Arc<EmbeddingService>,
         logger: Arc<Logger>,
-        session_update_tx: mpsc::UnboundedSender<(acp::SessionNotification, oneshot::Sender<()>)>,
+        session_update_tx: mpsc::UnboundedSender<ClientMessage>,
     ) -> Self {
         Self {
             config,
             context_store,
+            embedding_service,
             logger,
             session_update_tx,
             next_session_id: AtomicU64::new(0),
-            cwd: Arc::new(Mutex::new(std::env::current_dir().unwrap_or_default())),
+            session_cwds: std::sync::Mutex::new(HashMap::new()),
+            running_prompts: std::sync::Mutex::new(HashMap::new()),
+            shells: Arc::new(ShellSessions::new()),
         }
     }
 
@@ -60,7 +82,7 @@ impl AcpAgent {
     ) -> Result<()> {
         let (tx, rx) = oneshot::channel();
         self.session_update_tx
-            .send((
+            .send(ClientMessage::Notification(
                 acp::SessionNotification {
                     session_id: session_id.clone(),
                     update,
@@ -89,10 +111,248 @@ impl AcpAgent {
         .await
     }
 
+    /// Show a command the agent runs as a tool call, updated as its output arrives
+    async fn send_command_event(
+        &self,
+        session_id: &acp::SessionId,
+        commands: &mut HashMap<u64, CommandOutput>,
+        event: CommandEvent,
+    ) -> Result<()> {
+        match event {
+            CommandEvent::Started { id, command } => {
+                commands.insert(id, CommandOutput::default());
+                self.send_session_update(
+                    session_id,
+                    acp::SessionUpdate::ToolCall(acp::ToolCall {
+                        id: command_tool_call_id(id),
+                        title: command.clone(),
+                        kind: acp::ToolKind::Execute,
+                        status: acp::ToolCallStatus::InProgress,
+                        content: Vec::new(),
+                        locations: Vec::new(),
+                        raw_input: Some(serde_json::json!({ "command": command })),
+                        raw_output: None,
+                    }),
+                )
+                .await
+            }
+            CommandEvent::Output { id, line, .. } => {
+                let Some(output) = commands.get_mut(&id) else {
+                    return Ok(());
+                };
+                output.push(line);
+                if !output.update_due() {
+                    return Ok(());
+                }
+                let content = output.content();
+                self.send_command_update(session_id, id, content, None)
+                    .await
+            }
+            CommandEvent::Finished {
+                id,
+                exit_code,
+                timed_out,
+            } => {
+                let content = commands.remove(&id).unwrap_or_default().content();
+                let status = if exit_code == Some(0) && !timed_out {
+                    acp::ToolCallStatus::Completed
+                } else {
+                    acp::ToolCallStatus::Failed
+                };
+                self.send_command_update(session_id, id, content, Some(status))
+                    .await
+            }
+        }
+    }
+
+    /// Replace the output shown in a command's tool call
+    async fn send_command_update(
+        &self,
+        session_id: &acp::SessionId,
+        id: u64,
+        content: String,
+        status: Option<acp::ToolCallStatus>,
+    ) -> Result<()> {
+        let content = acp::ContentBlock::Text(acp::TextContent {
+            text: content,
+            annotations: None,
+        });
+        self.send_session_update(
+            session_id,
+            acp::SessionUpdate::ToolCallUpdate(acp::ToolCallUpdate {
+                id: command_tool_call_id(id),
+                fields: acp::ToolCallUpdateFields {
+                    status,
+                    content: Some(vec![content.into()]),
+                    ..Default::default()
+                },
+            }),
+        )
+        .await
+    }
+
+    /// Ask the client to confirm a tool call, showing the change it makes to a file
+    async fn request_approval(
+        &self,
+        session_id: &acp::SessionId,
+        id: u64,
+        request: &ApprovalRequest,
+    ) -> Result<Approval> {
+        let tool_call_id = acp::ToolCallId(format!("approval-{}", id).into());
+        let (kind, content, locations) = match request.change {
+            Some(ref change) => (
+                acp::ToolKind::Edit,
+                vec![acp::ToolCallContent::Diff {
+                    diff: acp::Diff {
+                        path: change.path.clone(),
+                        old_text: change.old.clone(),
+                        new_text: change.new.clone(),
+                    },
+                }],
+                vec![acp::ToolCallLocation {
+                    path: change.path.clone(),
+                    line: None,
+                }],
+            ),
+            None => (acp::ToolKind::Other, Vec::new(), Vec::new()),
+        };
+        self.send_session_update(
+            session_id,
+            acp::SessionUpdate::ToolCall(acp::ToolCall {
+                id: tool_call_id.clone(),
+                title: request.description.clone(),
+                kind,
+                status: acp::ToolCallStatus::Pending,
+                content,
+                locations,
+                raw_input: None,
+                raw_output: None,
+            }),
+        )
+        .await?;
+
+        let rules = request
+            .rules
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>()
+            .join(", ");
+        let option = |id: &str, name: String, kind| acp::PermissionOption {
+            id: acp::PermissionOptionId(id.into()),
+            name,
+            kind,
+        };
+        let permission = acp::RequestPermissionRequest {
+            session_id: session_id.clone(),
+            tool_call: acp::ToolCallUpdate {
+                id: tool_call_id.clone(),
+                fields: Default::default(),
+            },
+            options: vec![
+                option(
+                    "once",
+                    "Allow".to_string(),
+                    acp::PermissionOptionKind::AllowOnce,
+                ),
+                option(
+                    "session",
+                    format!("Allow {} for this session", rules),
+                    acp::PermissionOptionKind::AllowAlways,
+                ),
+                option(
+                    "always",
+                    format!("Always allow {}", rules),
+                    acp::PermissionOptionKind::AllowAlways,
+                ),
+                option(
+                    "reject",
+                    "Reject".to_string(),
+                    acp::PermissionOptionKind::RejectOnce,
+                ),
+            ],
+        };
+        let (tx, rx) = oneshot::channel();
+        self.session_update_tx
+            .send(ClientMessage::Permission(permission, tx))
+            .map_err(|_| anyhow::anyhow!("Failed to send permission request"))?;
+        let response = rx
+            .await
+            .map_err(|_| anyhow::anyhow!("Failed to receive permission response"))?
+            .map_err(|e| anyhow::anyhow!("Permission request failed: {:?}", e))?;
+
+        let approval = match response.outcome {
+            acp::RequestPermissionOutcome::Selected { option_id } => match option_id.0.as_ref() {
+                "once" => Approval::Once,
+                "session" => Approval::Session,
+                "always" => Approval::Always,
+                _ => Approval::Denied,
+            },
+            acp::RequestPermissionOutcome::Cancelled => Approval::Denied,
+        };
+        let status = if approval == Approval::Denied {
+            acp::ToolCallStatus::Failed
+        } else {
+            acp::ToolCallStatus::Completed
+        };
+        self.send_session_update(
+            session_id,
+            acp::SessionUpdate::ToolCallUpdate(acp::ToolCallUpdate {
+                id: tool_call_id,
+                fields: acp::ToolCallUpdateFields {
+                    status: Some(status),
+                    ..Default::default()
+                },
+            }),
+        )
+        .await?;
+        Ok(approval)
+    }
+
+    /// The agent configuration of a session, with the file tools confined to
+    /// the session's working directory
+    fn session_config(&self, session_id: &acp::SessionId) -> AgentConfig {
+        let config = self.config.clone();
+        let cwd = self
+            .session_cwds
+            .lock()
+            .unwrap()
+            .get(session_id.0.as_ref())
+            .cloned();
+        let Some(cwd) = cwd else {
+            return config;
+        };
+
+        let workspace = match config.workspace {
+            Some(ref workspace) => workspace.rooted_at(&cwd),
+            None => Workspace::new(&cwd),
+        };
+        match workspace {
+            Ok(workspace) => config.with_workspace(workspace),
+            Err(e) => {
+                warn!(
+                    "Keeping the default workspace, {} cannot be used: {}",
+                    cwd.display(),
+                    e
+                );
+                config
+            }
+        }
+    }
+
     /// Process a prompt using the underlying Vega chat agent
     async fn process_prompt(&self, session_id: &acp::SessionId, prompt: &str) -> Result<()> {
-        // Create a chat agent for this session (we don't store them as they're stateless)
-        let chat_agent = ChatAgent::new(self.config.clone())?.with_logger(self.logger.clone());
+        // Create a chat agent for this session (we don't store them as they're
+        // stateless, apart from the shell the session's commands run in)
+        let (command_tx, mut command_rx) = mpsc::unbounded_channel();
+        let (approval_tx, mut approval_rx) = mpsc::unbounded_channel();
+        let chat_agent = ChatAgent::with_embeddings(
+            self.session_config(session_id),
+            self.embedding_service.clone(),
+        )
+        .with_logger(self.logger.clone())
+        .with_shells(self.shells.clone())
+        .with_command_events(command_tx)
+        .with_approvals(approval_tx);
 
         // Log the prompt processing
         self.logger
@@ -102,16 +362,64 @@ impl AcpAgent {
             ))
             .await?;
 
-        // Send the prompt to the chat agent and stream the response
-        // For now, we'll use a simplified approach - in a full implementation,
-        // we'd want to stream the response as it's generated
-        match self
-            .get_agent_response(&chat_agent, prompt, &session_id.0.to_string())
-            .await
-        {
+        // Forward each text delta to the client as its own message chunk
+        let (delta_tx, mut delta_rx) = mpsc::unbounded_channel::<String>();
+        let forward_deltas = async {
+            let mut streamed = false;
+            while let Some(delta) = delta_rx.recv().await {
+                streamed = true;
+                if let Err(e) = self.send_message_chunk(session_id, &delta).await {
+                    warn!("Failed to send message chunk: {}", e);
+                }
+            }
+            streamed
+        };
+
+        // Show the commands the agent runs as tool calls
+        let forward_commands = async {
+            let mut commands = HashMap::new();
+            while let Some(event) = command_rx.recv().await {
+                if let Err(e) = self
+                    .send_command_event(session_id, &mut commands, event)
+                    .await
+                {
+                    warn!("Failed to send command output: {}", e);
+                }
+            }
+        };
+
+        // Tool calls that need confirmation are asked about in the client,
+        // showing the changes edits make
+        let forward_approvals = async {
+            let mut next_id = 0;
+            while let Some(request) = approval_rx.recv().await {
+                next_id += 1;
+                let approval = self
+                    .request_approval(session_id, next_id, &request)
+                    .await
+                    .unwrap_or_else(|e| {
+                        warn!("Failed to ask for permission: {}", e);
+                        Approval::Denied
+                    });
+                request.reply.send(approval).ok();
+            }
+        };
+
+        // The agent is dropped with the response, closing the command and approval channels
+        let session_id_str = session_id.0.to_string();
+        let respond = async move {
+            self.get_agent_response(&chat_agent, prompt, &session_id_str, delta_tx)
+                .await
+        };
+        let (result, streamed, (), ()) =
+            tokio::join!(respond, forward_deltas, forward_commands, forward_approvals);
+
+        match result {
             Ok(response) => {
-                // Send the response as message chunks
-                self.send_message_chunk(session_id, &response).await?;
+                // Models that produced no incremental text still get their full response
+                if !streamed {
+                    self.send_message_chunk(session_id, &response).await?;
+                }
                 Ok(())
             }
             Err(e) => {
@@ -129,6 +437,7 @@ impl AcpAgent {
         chat_agent: &ChatAgent,
         prompt: &str,
         session_id: &str,
+        deltas: TextDeltaSender,
     ) -> Result<String> {
         // Store user input in context first
         use crate::context::ContextEntry;
@@ -159,7 +468,7 @@ impl AcpAgent {
 
         // Use the ChatAgent to generate a proper response with tools and context
         let response = chat_agent
-            .get_response_with_tools(prompt, &self.context_store, session_id)
+            .stream_response_with_tools(prompt, &self.context_store, session_id, Some(deltas))
             .await?;
 
         // Store agent response in context
@@ -188,6 +497,13 @@ impl AcpAgent {
             warn!("Failed to store agent context: {}", e);
         }
 
+        if let Err(e) = chat_agent
+            .compact_if_needed(&self.context_store, session_id)
+            .await
+        {
+            warn!("Failed to compact session history: {}", e);
+        }
+
         Ok(response)
     }
 }
@@ -241,9 +557,11 @@ impl acp::Agent for AcpAgent {
         let session_id = self.next_session_id.fetch_add(1, Ordering::SeqCst);
         let session_id_str = format!("acp-{}", session_id);
 
-        // Update working directory if provided
-        let mut current_cwd = self.cwd.lock().await;
-        *current_cwd = arguments.cwd;
+        // The session's file tools are confined to its working directory
+        self.session_cwds
+            .lock()
+            .unwrap()
+            .insert(session_id_str.clone(), arguments.cwd);
 
         self.logger
             .info(format!("Created new ACP session: {}", session_id_str))
@@ -295,23 +613,47 @@ impl acp::Agent for AcpAgent {
             }
         }
 
-        // Process the prompt
-        if let Err(e) = self
-            .process_prompt(&arguments.session_id, &prompt_text.trim())
-            .await
-        {
-            error!("Failed to process prompt: {}", e);
-            return Err(acp::Error::internal_error());
+        // Process the prompt; cancelling it drops the response along with
+        // any command a tool is running
+        let session_key = arguments.session_id.0.to_string();
+        let (abort, registration) = AbortHandle::new_pair();
+        self.running_prompts
+            .lock()
+            .unwrap()
+            .insert(session_key.clone(), abort);
+        let result = Abortable::new(
+            self.process_prompt(&arguments.session_id, prompt_text.trim()),
+            registration,
+        )
+        .await;
+        self.running_prompts.lock().unwrap().remove(&session_key);
+
+        match result {
+            Ok(Ok(())) => Ok(acp::PromptResponse {
+                stop_reason: acp::StopReason::EndTurn,
+            }),
+            Ok(Err(e)) => {
+                error!("Failed to process prompt: {}", e);
+                Err(acp::Error::internal_error())
+            }
+            Err(_) => Ok(acp::PromptResponse {
+                stop_reason: acp::StopReason::Cancelled,
+            }),
         }
-
-        Ok(acp::PromptResponse {
-            stop_reason: acp::StopReason::EndTurn,
-        })
     }
 
     async fn cancel(&self, args: acp::CancelNotification) -> Result<(), acp::Error> {
         info!("ACP Cancel request received: {:?}", args);
 
+        if let Some(prompt) = self
+            .running_prompts
+            .lock()
+            .unwrap()
+            .remove(args.session_id.0.as_ref())
+        {
+            prompt.abort();
+        }
+
         self.logger
             .info(format!(
                 "ACP operation cancelled for session: {:?}",
@@ -324,6 +666,55 @@ impl acp::Agent for AcpAgent {
     }
 }
 
+/// The latest output of a running command, as shown in its tool call
+#[derive(Debug, Default)]
+struct CommandOutput {
+    lines: VecDeque<String>,
+    last_update: Option<Instant>,
+}
+
+impl CommandOutput {
+    fn push(&mut self, line: String) {
+        self.lines.push_back(line);
+        if self.lines.len() > TOOL_CALL_OUTPUT_LINES {
+            self.lines.pop_front();
+        }
+    }
+
+    /// Whether enough time passed since the tool call was last updated
+    fn update_due(&mut self) -> bool {
+        let now = Instant::now();
+        let due = self
+            .last_update
+            .is_none_or(|last| now.duration_since(last) >= TOOL_CALL_UPDATE_INTERVAL);
+        if due {
+            self.last_update = Some(now);
+        }
+        due
+    }
+
+    fn content(&self) -> String {
+        let lines: Vec<&str> = self.lines.iter().map(String::as_str).collect();
+        format!("```\n{}\n```", lines.join("\n"))
+    }
+}
+
+/// A message the agent sends to the client through the connection
+pub enum ClientMessage {
+    /// A session update, acknowledged once sent
+    Notification(acp::SessionNotification, oneshot::Sender<()>),
+    /// A permission request, answered with the client's response
+    Permission(
+        acp::RequestPermissionRequest,
+        oneshot::Sender<Result<acp::RequestPermissionResponse, acp::Error>>,
+    ),
+}
+
+/// Tool call id of a command run by the bash tool
+fn command_tool_call_id(id: u64) -> acp::ToolCallId {
+    acp::ToolCallId(format!("command-{}", id).into())
+}
+
 /// ACP Client implementation for handling client-side operations
 pub struct AcpClient {
     /// Logger for client operations
@@ -437,6 +828,7 @@ impl acp::Client for AcpClient {
 pub async fn start_acp_server(
     config: AgentConfig,
     context_store: Arc<ContextStore>,
+    embedding_service: Arc<EmbeddingService>,
     logger: Arc<Logger>,
 ) -> Result<()> {
     info!("Starting ACP server on stdio");
@@ -448,7 +840,13 @@ pub async fn start_acp_server(
     let (session_update_tx, mut session_update_rx) = mpsc::unbounded_channel();
 
     // Create the ACP agent
-    let agent = AcpAgent::new(config, context_store, logger.clone(), session_update_tx);
+    let agent = AcpAgent::new(
+        config,
+        context_store,
+        embedding_service,
+        logger.clone(),
+        session_update_tx,
+    );
 
     // Use LocalSet for non-Send futures
     let local_set = tokio::task::LocalSet::new();
@@ -461,15 +859,27 @@ pub async fn start_acp_server(
                     tokio::task::spawn_local(fut);
                 });
 
-            // Handle session notifications
+            // Handle session notifications and permission requests; the user
+            // may take a while to answer, so notifications are not held up
+            let conn = std::rc::Rc::new(conn);
             tokio::task::spawn_local(async move {
-                while let Some((session_notification, tx)) = session_update_rx.recv().await {
-                    let result = conn.session_notification(session_notification).await;
-                    if let Err(e) = result {
-                        error!("Failed to send session notification: {}", e);
-                        break;
+                while let Some(message) = session_update_rx.recv().await {
+                    match message {
+                        ClientMessage::Notification(session_notification, tx) => {
+                            let result = conn.session_notification(session_notification).await;
+                            if let Err(e) = result {
+                                error!("Failed to send session notification: {}", e);
+                                break;
+                            }
+                            tx.send(()).ok();
+                        }
+                        ClientMessage::Permission(request, tx) => {
+                            let conn = conn.clone();
+                            tokio::task::spawn_local(async move {
+                                tx.send(conn.request_permission(request).await).ok();
+                            });
+                        }
                     }
-                    tx.send(()).ok();
                 }
             });
 
@@ -483,6 +893,7 @@ pub async fn start_acp_server(
 mod tests {
     use super::*;
     use crate::agents::AgentConfig;
+    use crate::embeddings::EmbeddingProvider;
     use crate::logging::{LogLevel, LoggerConfig};
     use agent_client_protocol::Agent;
     use tempfile::TempDir;
@@ -522,7 +933,8 @@ mod tests {
         let logger = create_test_logger().await?;
         let (tx, _rx) = mpsc::unbounded_channel();
 
-        let agent = AcpAgent::new(config, context_store, logger, tx);
+        let embedding_service = Arc::new(EmbeddingProvider::default().create_service());
+        let agent = AcpAgent::new(config, context_store, embedding_service, logger, tx);
 
         // Test initialization
         let init_request = acp::InitializeRequest {
@@ -537,6 +949,20 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_command_output_shows_latest_lines() {
+        let mut output = CommandOutput::default();
+        for n in 0..TOOL_CALL_OUTPUT_LINES + 2 {
+            output.push(n.to_string());
+        }
+        assert!(output.update_due());
+        assert!(!output.update_due());
+
+        let content = output.content();
+        assert!(content.starts_with("```\n2\n3\n"));
+        assert!(content.ends_with(&format!("{}\n```", TOOL_CALL_OUTPUT_LINES + 1)));
+    }
+
     #[tokio::test]
     async fn test_acp_client_creation() -> Result<()> {
         let logger = create_test_logger().await?;
@@ -1,30 +1,106 @@
 use anyhow::Result;
 use async_trait::async_trait;
-use rig::completion::Prompt;
-use rig::prelude::*;
-use rig::providers;
+use futures::StreamExt;
+use rig::OneOrMany;
+use rig::agent::{Agent as RigAgent, AgentBuilder};
+use rig::completion::{CompletionModel, Message};
+use rig::message::{AssistantContent, ToolResultContent, UserContent};
+use rig::streaming::{StreamedAssistantContent, StreamingCompletion};
+use std::io::Write;
+use std::path::Path;
 
 use tracing::{debug, error, info, trace, warn};
 use uuid::Uuid;
 
-use super::{Agent, AgentConfig};
+use super::summary::{self, KEEP_RECENT_TURNS, SUMMARY_PREAMBLE, SessionTranscript};
+use super::{Agent, AgentConfig, history};
 use crate::agent_instructions::format_instructions_for_prompt;
-use crate::context::{ContextEntry, ContextStore};
+use crate::context::{ContextEntry, ContextFilter, ContextStore, ExportFormat, SessionExport};
 use crate::embeddings::{EmbeddingProvider, EmbeddingService};
 use crate::input::InputHandler;
-use crate::streaming::{ProgressPhase, StreamingProgress};
+use crate::mcp::{ConnectionState, McpManager, McpTool};
+use crate::providers::{LLMProvider, ProviderModel};
+use crate::streaming::{ProgressHandoff, ProgressPhase, StreamingProgress, TextDeltaSender};
 use crate::tools::*;
 
+/// Maximum number of tool round-trips the model may make for a single prompt
+const MAX_TOOL_TURNS: usize = 8;
+
+/// Number of retrieved entries added to the prompt
+const RELEVANT_CONTEXT_LIMIT: usize = 5;
+
+/// Entries requested from retrieval, leaving room for those already in the history
+const RELEVANT_CONTEXT_CANDIDATES: usize = 20;
+
+/// Characters of a checkpoint's prompt shown by `/checkpoints`
+const CHECKPOINT_PROMPT_CHARS: usize = 60;
+
+/// Maximum length of a generated conversation summary
+const SUMMARY_MAX_TOKENS: u64 = 1024;
+
+/// System prompt template of the chat agent, rendered with [`super::render_prompt_template`]
+pub const SYSTEM_PROMPT: &str = r#"The assistant is Vega.
+
+The current date is {{currentDateTime}}.
+
+Vega enjoys helping humans and sees its role as an intelligent and kind assistant to the people, with depth and wisdom that makes it more than a mere tool.
+
+Vega always responds to the person in the language they use or request. If the person messages Vega in French then Vega responds in French, if the person messages Vega in Icelandic then Vega responds in Icelandic, and so on for any language. Vega is fluent in a wide variety of world languages.
+
+Vega is now being connected with a person."#;
+
+/// Errors providers return for a model that cannot call tools
+const NO_TOOL_SUPPORT_ERRORS: [&str; 3] = [
+    // OpenRouter
+    "No endpoints found that support tool use",
+    // Ollama
+    "does not support tools",
+    // OpenAI and compatible servers
+    "'tools' is not supported",
+];
+
+/// Whether a failed request was refused because the model cannot call tools
+fn is_missing_tool_support(error: &anyhow::Error) -> bool {
+    let message = error.to_string();
+    NO_TOOL_SUPPORT_ERRORS
+        .iter()
+        .any(|pattern| message.contains(pattern))
+}
+
 /// Chat agent that provides interactive conversation with an LLM and tool support
 pub struct ChatAgent {
     config: AgentConfig,
-    embedding_service: EmbeddingService,
+    embedding_service: std::sync::Arc<EmbeddingService>,
     logger: Option<std::sync::Arc<crate::logging::Logger>>,
+    mcp: Option<std::sync::Arc<McpManager>>,
+    /// Persistent shells of the sessions, when enabled in the configuration
+    shells: Option<std::sync::Arc<ShellSessions>>,
+    /// Receives the output of the commands the agent runs, to show it live
+    command_events: Option<CommandEventSender>,
+    /// Asks for tool call confirmations instead of the terminal
+    approvals: Option<ApprovalSender>,
 }
 
 impl ChatAgent {
     /// Create a new chat agent with the given configuration
     pub fn new(config: AgentConfig) -> Result<Self> {
+        // Create embedding provider from configuration
+        let embedding_provider = EmbeddingProvider::new(
+            &config.embedding_provider,
+            config.embedding_model.as_deref(),
+            config.openai_api_key.as_deref(),
+        )?;
+
+        let embedding_service = std::sync::Arc::new(embedding_provider.create_service());
+        Ok(Self::with_embeddings(config, embedding_service))
+    }
+
+    /// Create a chat agent sharing an embedding service that is already set up,
+    /// e.g. a local model loaded once for all sessions
+    pub fn with_embeddings(
+        config: AgentConfig,
+        embedding_service: std::sync::Arc<EmbeddingService>,
+    ) -> Self {
         if config.verbose {
             info!(
                 "Initializing tool-enabled {} client with model: {}",
@@ -36,20 +112,19 @@ impl ChatAgent {
             );
         }
 
-        // Create embedding provider from configuration
-        let embedding_provider = EmbeddingProvider::new(
-            &config.embedding_provider,
-            config.embedding_model.as_deref(),
-            config.openai_api_key.as_deref(),
-        )?;
+        let shells = config
+            .persistent_shell
+            .then(|| std::sync::Arc::new(ShellSessions::new()));
 
-        let embedding_service = embedding_provider.create_service();
-
-        Ok(ChatAgent {
+        ChatAgent {
             config,
             embedding_service,
             logger: None,
-        })
+            mcp: None,
+            shells,
+            command_events: None,
+            approvals: None,
+        }
     }
 
     /// Get a reference to the agent's configuration
@@ -68,8 +143,54 @@ impl ChatAgent {
         self
     }
 
+    /// Offer the tools of connected MCP servers to the model alongside the built-in tools
+    pub fn with_mcp(mut self, manager: std::sync::Arc<McpManager>) -> Self {
+        self.mcp = Some(manager);
+        self
+    }
+
+    /// Share persistent shells with other agents, e.g. one per prompt of an ACP session
+    ///
+    /// Has no effect unless persistent shells are enabled in the configuration.
+    pub fn with_shells(mut self, shells: std::sync::Arc<ShellSessions>) -> Self {
+        if self.config.persistent_shell {
+            self.shells = Some(shells);
+        }
+        self
+    }
+
+    /// Send the output of the commands the agent runs to a channel as it arrives
+    pub fn with_command_events(mut self, events: CommandEventSender) -> Self {
+        self.command_events = Some(events);
+        self
+    }
+
+    /// Ask for tool call confirmations on a channel instead of the terminal
+    pub fn with_approvals(mut self, approvals: ApprovalSender) -> Self {
+        self.approvals = Some(approvals);
+        self
+    }
+
+    /// A tool whose calls are checked against the permission policy first
+    fn confirmed<T>(&self, tool: T) -> ConfirmedTool<T> {
+        let confirmed = ConfirmedTool::new(tool, self.config.permissions.clone(), self.config.yolo);
+        match self.approvals {
+            Some(ref approvals) => confirmed.with_approvals(approvals.clone()),
+            None => confirmed,
+        }
+    }
+
+    /// Get the rendered system prompt for the agent, followed by the session summary
+    fn get_system_prompt(&self, summary: Option<&ContextEntry>) -> Result<String> {
+        let mut prompt = self.base_system_prompt()?;
+        if let Some(summary) = summary {
+            prompt.push_str(&summary::format_summary_for_prompt(summary));
+        }
+        Ok(prompt)
+    }
+
     /// Get the rendered system prompt for the agent
-    fn get_system_prompt(&self) -> Result<String> {
+    fn base_system_prompt(&self) -> Result<String> {
         let mut rendered_prompt = self.render_system_prompt()?;
 
         // Add agent instructions if available
@@ -111,6 +232,22 @@ Respond in a conversational and helpful manner, using tools as needed to provide
         prompt: &str,
         context: &ContextStore,
         session_id: &str,
+    ) -> Result<String> {
+        self.stream_response_with_tools(prompt, context, session_id, None)
+            .await
+    }
+
+    /// Get a response from the AI, forwarding text deltas as they arrive.
+    ///
+    /// The progress phases are displayed until the first token is received, after
+    /// which each delta is sent to `deltas`. The complete response is returned once
+    /// the model finishes.
+    pub async fn stream_response_with_tools(
+        &self,
+        prompt: &str,
+        context: &ContextStore,
+        session_id: &str,
+        deltas: Option<TextDeltaSender>,
     ) -> Result<String> {
         trace!("Received user prompt: '{}'", prompt);
 
@@ -121,12 +258,13 @@ Respond in a conversational and helpful manner, using tools as needed to provide
         // Create streaming progress indicator
         let progress = StreamingProgress::new();
         let progress_handle = progress.start_indicator().await;
+        let mut handoff = ProgressHandoff::new(&progress, progress_handle, deltas);
 
         // Phase 1: Preparing
-        progress.update_phase(ProgressPhase::Preparing, None).await;
+        handoff.update_phase(ProgressPhase::Preparing).await;
 
         // Phase 2: Generate embedding for the current prompt
-        progress.update_phase(ProgressPhase::Embedding, None).await;
+        handoff.update_phase(ProgressPhase::Embedding).await;
         trace!("Generating embedding for prompt...");
         let query_embedding = self.embedding_service.embed(prompt).await?;
         trace!(
@@ -134,21 +272,46 @@ Respond in a conversational and helpful manner, using tools as needed to provide
             query_embedding.len()
         );
 
-        // Phase 3: Retrieve relevant context from previous conversations
-        progress
-            .update_phase(ProgressPhase::ContextRetrieval, None)
-            .await;
-        trace!("Retrieving relevant context...");
-        let relevant_context = context
-            .get_relevant_context(query_embedding, Some(session_id), 5)
+        // Phase 3: Retrieve the running dialogue and relevant earlier context
+        handoff.update_phase(ProgressPhase::ContextRetrieval).await;
+        trace!("Retrieving conversation history...");
+        let SessionTranscript {
+            summary,
+            turns: history,
+        } = self
+            .conversation_history(context, session_id, prompt)
             .await?;
+        trace!(
+            "Replaying {} conversation turns (summary: {})",
+            history.len(),
+            summary.is_some()
+        );
+
+        trace!("Retrieving relevant context...");
+        let relevant_context: Vec<ContextEntry> = context
+            .search_hybrid(
+                prompt,
+                Some(&query_embedding),
+                &ContextFilter::session(session_id),
+                RELEVANT_CONTEXT_CANDIDATES,
+            )
+            .await?
+            .into_iter()
+            .map(|(entry, _)| entry)
+            .filter(|entry| {
+                (entry.role == "user" || entry.role == "assistant")
+                    && !history.iter().any(|turn| turn.id == entry.id)
+                    && !(entry.role == "user" && entry.content == prompt)
+            })
+            .take(RELEVANT_CONTEXT_LIMIT)
+            .collect();
         trace!("Retrieved {} context entries", relevant_context.len());
 
-        // Build context-aware prompt
+        // Build the current user turn, with retrieval hits outside the replayed history
         let mut full_prompt = String::new();
 
         if !relevant_context.is_empty() {
-            full_prompt.push_str("Context from previous conversations:\n");
+            full_prompt.push_str("Relevant context from earlier in this conversation:\n");
             for entry in &relevant_context {
                 full_prompt.push_str(&format!(
                     "[{}] {}: {}\n",
@@ -157,11 +320,11 @@ Respond in a conversational and helpful manner, using tools as needed to provide
                     entry.content
                 ));
             }
-            full_prompt.push_str("\n");
+            full_prompt.push_str("\nCurrent request: ");
         }
 
-        full_prompt.push_str("Current request: ");
         full_prompt.push_str(prompt);
+        let history = history::to_messages(&history);
 
         trace!(
             "Built full prompt for LLM (length: {} chars)",
@@ -172,28 +335,37 @@ Respond in a conversational and helpful manner, using tools as needed to provide
         }
 
         // Phase 4: Thinking/Processing
-        progress.update_phase(ProgressPhase::Thinking, None).await;
+        handoff.update_phase(ProgressPhase::Thinking).await;
         trace!("Sending request to LLM with tools...");
 
         // Try with tools first, fallback to no tools if not supported
-        let response = match self.try_with_tools(&full_prompt, session_id).await {
+        let checkpoint = TurnCheckpoint::new(context.clone(), session_id, prompt);
+        let response = match self
+            .try_with_tools(
+                &full_prompt,
+                &history,
+                summary.as_ref(),
+                session_id,
+                checkpoint,
+                &mut handoff,
+            )
+            .await
+        {
             Ok(response) => {
                 trace!("LLM responded successfully");
                 response
             }
             Err(e) => {
                 trace!("LLM request failed with error: {}", e);
-                let error_msg = e.to_string();
-                if error_msg.contains("No endpoints found that support tool use")
-                    || error_msg.contains("tool")
-                    || error_msg.contains("function")
-                {
+                // Retrying without tools would repeat any text already streamed
+                if handoff.is_active() && is_missing_tool_support(&e) {
                     if self.config.verbose {
                         warn!(
                             "Tools not supported by model {}, falling back to non-tool response",
                             self.config.model
                         );
                     }
+                    handoff.stop_indicator();
                     println!(
                         "⚠️  Note: The current model doesn't support tools. Consider using a tool-compatible model like:"
                     );
@@ -204,20 +376,27 @@ Respond in a conversational and helpful manner, using tools as needed to provide
                     println!("   - Or use Ollama with a compatible model");
                     println!();
 
-                    self.get_response_without_tools(&full_prompt).await?
+                    self.get_response_without_tools(
+                        &full_prompt,
+                        &history,
+                        summary.as_ref(),
+                        &mut handoff,
+                    )
+                    .await?
                 } else {
                     return Err(e);
                 }
             }
         };
 
-        // Phase 5: Finalizing
-        progress.update_phase(ProgressPhase::Finalizing, None).await;
-        tokio::time::sleep(tokio::time::Duration::from_millis(200)).await; // Brief pause for visual feedback
+        // Phase 5: Finalizing (only visible when nothing has been streamed)
+        if handoff.is_active() {
+            handoff.update_phase(ProgressPhase::Finalizing).await;
+            tokio::time::sleep(tokio::time::Duration::from_millis(200)).await; // Brief pause for visual feedback
+        }
 
         // Stop the progress indicator
-        progress_handle.abort();
-        progress.stop();
+        handoff.stop_indicator();
 
         if self.config.verbose {
             debug!("Received response from AI model");
@@ -226,246 +405,350 @@ Respond in a conversational and helpful manner, using tools as needed to provide
         Ok(response)
     }
 
-    /// Show a thinking indicator while the LLM is processing
-    async fn show_thinking_indicator() {
-        Self::show_progress_indicator("Thinking", "🧠").await;
+    /// Load the session summary and the dialogue after it that fits within the
+    /// configured history budget.
+    ///
+    /// The current prompt has usually been stored already, so a trailing user
+    /// turn matching it is left out of the replayed history.
+    async fn conversation_history(
+        &self,
+        context: &ContextStore,
+        session_id: &str,
+        prompt: &str,
+    ) -> Result<SessionTranscript> {
+        let mut transcript = self.session_transcript(context, session_id).await?;
+        if transcript
+            .turns
+            .last()
+            .is_some_and(|entry| entry.role == "user" && entry.content == prompt)
+        {
+            transcript.turns.pop();
+        }
+
+        transcript.turns = history::select_history(
+            &transcript.turns,
+            self.config.history_token_budget,
+            self.config.history_truncation,
+        );
+        Ok(transcript)
     }
 
-    /// Show a customizable progress indicator
-    async fn show_progress_indicator(message: &str, emoji: &str) {
-        use std::io::{self, Write};
+    /// Load a session split at its latest summary
+    async fn session_transcript(
+        &self,
+        context: &ContextStore,
+        session_id: &str,
+    ) -> Result<SessionTranscript> {
+        let entries = context.get_session_history(session_id, None).await?;
+        Ok(SessionTranscript::from_entries(&entries))
+    }
 
-        let frames = ["⠋", "⠙", "⠹", "⠸", "⠼", "⠴", "⠦", "⠧", "⠇", "⠏"];
-        let mut frame_index = 0;
-        let start_time = std::time::Instant::now();
+    /// Compact the session if its unsummarized history exceeds the configured threshold.
+    ///
+    /// Returns the number of entries folded into the new summary, if one was written.
+    pub async fn compact_if_needed(
+        &self,
+        context: &ContextStore,
+        session_id: &str,
+    ) -> Result<Option<usize>> {
+        if self.config.compact_threshold == 0 {
+            return Ok(None);
+        }
 
-        loop {
-            let elapsed = start_time.elapsed();
-            let elapsed_str = if elapsed.as_secs() > 0 {
-                format!(" ({}s)", elapsed.as_secs())
-            } else {
-                String::new()
-            };
+        let transcript = self.session_transcript(context, session_id).await?;
+        let tokens = transcript.unsummarized_tokens();
+        if tokens <= self.config.compact_threshold {
+            return Ok(None);
+        }
 
-            print!(
-                "\r\x1b[93m{}\x1b[0m {} {}{}...",
-                frames[frame_index], emoji, message, elapsed_str
-            );
-            io::stdout().flush().unwrap();
+        debug!(
+            "Session {} has {} unsummarized history tokens (threshold {}), compacting",
+            session_id, tokens, self.config.compact_threshold
+        );
+        self.compact_transcript(context, session_id, &transcript)
+            .await
+    }
 
-            frame_index = (frame_index + 1) % frames.len();
-            tokio::time::sleep(tokio::time::Duration::from_millis(100)).await;
-        }
+    /// Summarize all but the most recent turns of a session into a summary entry.
+    ///
+    /// The summarized entries are kept in the context store. Returns the number
+    /// of entries folded into the summary, or `None` if there was nothing to compact.
+    pub async fn compact_session(
+        &self,
+        context: &ContextStore,
+        session_id: &str,
+    ) -> Result<Option<usize>> {
+        let transcript = self.session_transcript(context, session_id).await?;
+        self.compact_transcript(context, session_id, &transcript)
+            .await
     }
 
-    /// Try to get response with tools enabled
-    async fn try_with_tools(&self, full_prompt: &str, session_id: &str) -> Result<String> {
-        trace!(
-            "Attempting LLM request with provider: {}",
-            self.config.provider
+    /// Ask the model to fold the older turns of a transcript into its summary
+    async fn compact_transcript(
+        &self,
+        context: &ContextStore,
+        session_id: &str,
+        transcript: &SessionTranscript,
+    ) -> Result<Option<usize>> {
+        let turns = transcript.turns_to_summarize(KEEP_RECENT_TURNS);
+        if turns.is_empty() {
+            return Ok(None);
+        }
+
+        let prompt = summary::summary_prompt(transcript.summary.as_ref(), turns);
+        let content = self
+            .provider()?
+            .prompt(&prompt, SUMMARY_PREAMBLE, SUMMARY_MAX_TOKENS)
+            .await?;
+        let content = content.trim();
+        if content.is_empty() {
+            return Err(anyhow::anyhow!("Model returned an empty summary"));
+        }
+
+        let entry = summary::summary_entry(
+            self.name(),
+            session_id,
+            content.to_string(),
+            transcript,
+            turns,
         );
-        match self.config.provider.as_str() {
-            "openai" => {
-                trace!("Creating OpenAI client and agent...");
-                let client = providers::openai::Client::from_env();
-                let system_prompt = self.get_system_prompt()?;
-                trace!("Building agent with model: {}", self.config.model);
-                let agent = client
-                    .agent(&self.config.model)
-                    .preamble(&system_prompt)
-                    .max_tokens(2048)
-                    .tool(WebSearchTool::new())
-                    .tool(ConfirmedBashTool::new(self.config.yolo))
-                    .tool(CodeSearchTool::new())
-                    .tool(ReadFileTool::new())
-                    .tool(ConfirmedEditFileTool::new(self.config.yolo))
-                    .tool(ListFilesTool::new())
-                    .tool(if let Some(ref logger) = self.logger {
-                        ReadLogsTool::new()
-                            .with_logger(logger.clone())
-                            .with_session_id(session_id.to_string())
-                    } else {
-                        ReadLogsTool::new().with_session_id(session_id.to_string())
-                    })
-                    .build();
-
-                trace!("Sending prompt to OpenAI agent...");
-                let result = agent
-                    .prompt(full_prompt)
-                    .await
-                    .map_err(|e| anyhow::anyhow!(e));
-
-                match &result {
-                    Ok(response) => trace!(
-                        "OpenAI agent returned response (length: {} chars)",
-                        response.len()
-                    ),
-                    Err(e) => trace!("OpenAI agent failed: {}", e),
-                }
+        let embedding = self.embedding_service.embed(&entry.content).await?;
+        context.store_context(entry, embedding).await?;
 
-                result
+        info!(
+            "Summarized {} entries of session {}",
+            turns.len(),
+            session_id
+        );
+        Ok(Some(turns.len()))
+    }
+
+    /// Stream a completion from a Rig agent, executing tool calls between turns.
+    ///
+    /// Rig's multi-turn streaming request is not `Send`, so the tool loop is driven
+    /// here on top of the lower level `stream_completion` API.
+    async fn stream_agent_response<M>(
+        agent: &RigAgent<M>,
+        prompt: &str,
+        history: &[Message],
+        handoff: &mut ProgressHandoff<'_>,
+    ) -> Result<String>
+    where
+        M: CompletionModel,
+    {
+        let mut history = history.to_vec();
+        let mut current_prompt = Message::user(prompt);
+        let mut response = String::new();
+
+        for turn in 0..=MAX_TOOL_TURNS {
+            let mut stream = agent
+                .stream_completion(current_prompt.clone(), history.clone())
+                .await?
+                .stream()
+                .await?;
+            history.push(current_prompt);
+
+            let mut turn_text = String::new();
+            let mut tool_calls = Vec::new();
+            let mut tool_results = Vec::new();
+
+            while let Some(chunk) = stream.next().await {
+                match chunk? {
+                    StreamedAssistantContent::Text(text) => {
+                        // Separate text from consecutive turns with a blank line
+                        if turn_text.is_empty() && !response.is_empty() {
+                            response.push_str("\n\n");
+                            handoff.push_text("\n\n".to_string());
+                        }
+                        turn_text.push_str(&text.text);
+                        response.push_str(&text.text);
+                        handoff.push_text(text.text);
+                    }
+                    StreamedAssistantContent::ToolCall(tool_call) => {
+                        let name = tool_call.function.name.clone();
+                        handoff
+                            .update_phase(ProgressPhase::ToolExecution(name.clone()))
+                            .await;
+                        trace!("Executing tool call: {}", name);
+
+                        // A refused or failed call is reported to the model, which
+                        // can then try something else
+                        let output = match agent
+                            .tools
+                            .call(&name, tool_call.function.arguments.to_string())
+                            .await
+                        {
+                            Ok(output) => output,
+                            Err(e) => {
+                                debug!("Tool call {} failed: {}", name, e);
+                                format!("Error: {}", e)
+                            }
+                        };
+                        let content = OneOrMany::one(ToolResultContent::text(output));
+                        tool_results.push(match tool_call.call_id.clone() {
+                            Some(call_id) => UserContent::tool_result_with_call_id(
+                                tool_call.id.clone(),
+                                call_id,
+                                content,
+                            ),
+                            None => UserContent::tool_result(tool_call.id.clone(), content),
+                        });
+                        tool_calls.push(AssistantContent::ToolCall(tool_call));
+                    }
+                    StreamedAssistantContent::Reasoning(_) | StreamedAssistantContent::Final(_) => {
+                    }
+                }
             }
-            "openrouter" => {
-                let client = providers::openrouter::Client::from_env();
-                let system_prompt = self.get_system_prompt()?;
-                let agent = client
-                    .agent(&self.config.model)
-                    .preamble(&system_prompt)
-                    .max_tokens(2048)
-                    .tool(WebSearchTool::new())
-                    .tool(ConfirmedBashTool::new(self.config.yolo))
-                    .tool(CodeSearchTool::new())
-                    .tool(ReadFileTool::new())
-                    .tool(ConfirmedEditFileTool::new(self.config.yolo))
-                    .tool(ListFilesTool::new())
-                    .tool(if let Some(ref logger) = self.logger {
-                        ReadLogsTool::new()
-                            .with_logger(logger.clone())
-                            .with_session_id(session_id.to_string())
-                    } else {
-                        ReadLogsTool::new().with_session_id(session_id.to_string())
-                    })
-                    .build();
-
-                agent
-                    .prompt(full_prompt)
-                    .await
-                    .map_err(|e| anyhow::anyhow!(e))
+
+            if tool_calls.is_empty() {
+                return Ok(response);
             }
-            "anthropic" => {
-                let client = providers::anthropic::Client::from_env();
-                let system_prompt = self.get_system_prompt()?;
-                let agent = client
-                    .agent(&self.config.model)
-                    .preamble(&system_prompt)
-                    .max_tokens(2048)
-                    .tool(WebSearchTool::new())
-                    .tool(ConfirmedBashTool::new(self.config.yolo))
-                    .tool(CodeSearchTool::new())
-                    .tool(ReadFileTool::new())
-                    .tool(ConfirmedEditFileTool::new(self.config.yolo))
-                    .tool(ListFilesTool::new())
-                    .tool(if let Some(ref logger) = self.logger {
-                        ReadLogsTool::new()
-                            .with_logger(logger.clone())
-                            .with_session_id(session_id.to_string())
-                    } else {
-                        ReadLogsTool::new().with_session_id(session_id.to_string())
-                    })
-                    .build();
-
-                agent
-                    .prompt(full_prompt)
-                    .await
-                    .map_err(|e| anyhow::anyhow!(e))
+
+            if turn == MAX_TOOL_TURNS {
+                break;
             }
-            "ollama" => {
-                let client = providers::ollama::Client::new();
-                let system_prompt = self.get_system_prompt()?;
-                let agent = client
-                    .agent(&self.config.model)
-                    .preamble(&system_prompt)
-                    .max_tokens(2048)
-                    .tool(WebSearchTool::new())
-                    .tool(ConfirmedBashTool::new(self.config.yolo))
-                    .tool(CodeSearchTool::new())
-                    .tool(ReadFileTool::new())
-                    .tool(ConfirmedEditFileTool::new(self.config.yolo))
-                    .tool(ListFilesTool::new())
-                    .tool(if let Some(ref logger) = self.logger {
-                        ReadLogsTool::new()
-                            .with_logger(logger.clone())
-                            .with_session_id(session_id.to_string())
-                    } else {
-                        ReadLogsTool::new().with_session_id(session_id.to_string())
-                    })
-                    .build();
-
-                agent
-                    .prompt(full_prompt)
-                    .await
-                    .map_err(|e| anyhow::anyhow!(e))
+
+            if !turn_text.is_empty() {
+                tool_calls.insert(0, AssistantContent::text(turn_text));
             }
-            _ => Err(anyhow::anyhow!(
-                "Unsupported provider for tool-enabled agent: {}",
-                self.config.provider
-            )),
+            history.push(Message::Assistant {
+                id: None,
+                content: OneOrMany::many(tool_calls)?,
+            });
+            current_prompt = Message::User {
+                content: OneOrMany::many(tool_results)?,
+            };
+            handoff.update_phase(ProgressPhase::Thinking).await;
         }
+
+        Err(anyhow::anyhow!(
+            "Model exceeded the maximum of {} tool turns",
+            MAX_TOOL_TURNS
+        ))
     }
 
-    /// Get response without tools (fallback for models that don't support tools)
-    async fn get_response_without_tools(&self, full_prompt: &str) -> Result<String> {
-        let simple_preamble = "You are a helpful AI assistant. Respond in a conversational and helpful manner. While you don't have access to tools in this mode, you can still provide helpful information, explanations, and guidance.";
+    /// Create the LLM provider selected in the agent configuration
+    fn provider(&self) -> Result<LLMProvider> {
+        LLMProvider::new(
+            &self.config.provider,
+            &self.config.model,
+            self.config.api_key.as_deref(),
+        )
+    }
 
-        // Show simple thinking indicator
-        let thinking_handle = tokio::spawn(Self::show_thinking_indicator());
+    /// Attach the agent's tool set to an agent builder.
+    ///
+    /// MCP tools are looked up on every call, so tools a server adds or removes
+    /// are picked up by the next prompt. File changes are recorded in `checkpoint`.
+    fn with_tools(
+        &self,
+        builder: AgentBuilder<ProviderModel>,
+        session_id: &str,
+        checkpoint: TurnCheckpoint,
+    ) -> AgentBuilder<ProviderModel> {
+        let read_logs = match self.logger {
+            Some(ref logger) => ReadLogsTool::new().with_logger(logger.clone()),
+            None => ReadLogsTool::new(),
+        };
 
-        let result = match self.config.provider.as_str() {
-            "openai" => {
-                let client = providers::openai::Client::from_env();
-                let agent = client
-                    .agent(&self.config.model)
-                    .preamble(simple_preamble)
-                    .max_tokens(2048)
-                    .build();
-
-                agent
-                    .prompt(full_prompt)
-                    .await
-                    .map_err(|e| anyhow::anyhow!(e))
-            }
-            "openrouter" => {
-                let client = providers::openrouter::Client::from_env();
-                let agent = client
-                    .agent(&self.config.model)
-                    .preamble(simple_preamble)
-                    .max_tokens(2048)
-                    .build();
-
-                agent
-                    .prompt(full_prompt)
-                    .await
-                    .map_err(|e| anyhow::anyhow!(e))
-            }
-            "anthropic" => {
-                let client = providers::anthropic::Client::from_env();
-                let agent = client
-                    .agent(&self.config.model)
-                    .preamble(simple_preamble)
-                    .max_tokens(2048)
-                    .build();
-
-                agent
-                    .prompt(full_prompt)
-                    .await
-                    .map_err(|e| anyhow::anyhow!(e))
-            }
-            "ollama" => {
-                let client = providers::ollama::Client::new();
-                let agent = client
-                    .agent(&self.config.model)
-                    .preamble(simple_preamble)
-                    .max_tokens(2048)
-                    .build();
-
-                agent
-                    .prompt(full_prompt)
-                    .await
-                    .map_err(|e| anyhow::anyhow!(e))
+        let mut bash = BashTool::new().with_output_limit(self.config.command_output);
+        if let Some(ref shells) = self.shells {
+            bash = bash.with_shell(shells.shell(session_id));
+        }
+        if let Some(ref events) = self.command_events {
+            bash = bash.with_events(events.clone());
+        }
+        if let Some(ref sandbox) = self.config.sandbox {
+            bash = bash.with_sandbox(sandbox.clone());
+        }
+
+        let mut code_search = CodeSearchTool::new();
+        let mut read_file = ReadFileTool::new();
+        let mut edit_file = EditFileTool::new().with_checkpoint(checkpoint);
+        let mut list_files = ListFilesTool::new();
+        if let Some(ref workspace) = self.config.workspace {
+            code_search = code_search.with_workspace(workspace.clone());
+            read_file = read_file.with_workspace(workspace.clone());
+            edit_file = edit_file.with_workspace(workspace.clone());
+            list_files = list_files.with_workspace(workspace.clone());
+            bash = bash.with_workspace(workspace.clone());
+        }
+
+        let mut builder = builder
+            .tool(self.confirmed(WebSearchTool::new()))
+            .tool(self.confirmed(bash))
+            .tool(self.confirmed(code_search))
+            .tool(self.confirmed(read_file))
+            .tool(self.confirmed(edit_file))
+            .tool(self.confirmed(list_files))
+            .tool(self.confirmed(read_logs.with_session_id(session_id.to_string())));
+
+        if let Some(ref mcp) = self.mcp {
+            for tool in mcp.tools() {
+                builder = builder.tool(self.confirmed(tool));
             }
-            _ => Err(anyhow::anyhow!(
-                "Unsupported provider: {}",
-                self.config.provider
-            )),
-        };
+        }
+        builder
+    }
 
-        // Stop the thinking indicator
-        thinking_handle.abort();
-        crate::streaming::stop_progress();
+    /// Try to get response with tools enabled
+    async fn try_with_tools(
+        &self,
+        full_prompt: &str,
+        history: &[Message],
+        summary: Option<&ContextEntry>,
+        session_id: &str,
+        checkpoint: TurnCheckpoint,
+        handoff: &mut ProgressHandoff<'_>,
+    ) -> Result<String> {
+        trace!(
+            "Attempting LLM request with provider: {}",
+            self.config.provider
+        );
+        let provider = self.provider()?;
+        let system_prompt = self.get_system_prompt(summary)?;
+        trace!("Building agent with model: {}", self.config.model);
+        let builder = provider.agent().preamble(&system_prompt).max_tokens(2048);
+        let agent = self.with_tools(builder, session_id, checkpoint).build();
+
+        trace!("Sending prompt to {} agent...", self.config.provider);
+        let result = Self::stream_agent_response(&agent, full_prompt, history, handoff).await;
+
+        match &result {
+            Ok(response) => trace!(
+                "{} agent returned response (length: {} chars)",
+                self.config.provider,
+                response.len()
+            ),
+            Err(e) => trace!("{} agent failed: {}", self.config.provider, e),
+        }
 
         result
     }
 
+    /// Get response without tools (fallback for models that don't support tools)
+    async fn get_response_without_tools(
+        &self,
+        full_prompt: &str,
+        history: &[Message],
+        summary: Option<&ContextEntry>,
+        handoff: &mut ProgressHandoff<'_>,
+    ) -> Result<String> {
+        let mut simple_preamble = "You are a helpful AI assistant. Respond in a conversational and helpful manner. While you don't have access to tools in this mode, you can still provide helpful information, explanations, and guidance.".to_string();
+        if let Some(summary) = summary {
+            simple_preamble.push_str(&summary::format_summary_for_prompt(summary));
+        }
+
+        let agent = self
+            .provider()?
+            .agent()
+            .preamble(&simple_preamble)
+            .max_tokens(2048)
+            .build();
+
+        Self::stream_agent_response(&agent, full_prompt, history, handoff).await
+    }
+
     /// Handle slash commands
     async fn handle_command(
         &self,
@@ -536,36 +819,78 @@ Respond in a conversational and helpful manner, using tools as needed to provide
                 context.clear_session(current_session_id).await?;
                 println!("Session history cleared.");
             }
+            "compact" => {
+                println!("Summarizing earlier messages...");
+                match self.compact_session(context, current_session_id).await? {
+                    Some(count) => println!(
+                        "Summarized {} earlier messages. The originals are kept and still appear in /export.",
+                        count
+                    ),
+                    None => println!("Nothing to compact: the conversation is already short."),
+                }
+            }
             "export" => {
-                if parts.len() != 2 {
-                    println!("Usage: /export <filename>");
+                if parts.len() < 2 || parts.len() > 3 {
+                    println!("Usage: /export <filename> [json|markdown|jsonl]");
                     return Ok(None);
                 }
                 let filename = parts[1];
-                let entries = context
-                    .get_session_history(current_session_id, None)
-                    .await?;
-
-                let mut export_content = String::new();
-                export_content.push_str(&format!(
-                    "# Chat Session Export: {}\n\n",
-                    current_session_id
-                ));
+                let format = match parts.get(2) {
+                    Some(format) => match format.parse::<ExportFormat>() {
+                        Ok(format) => format,
+                        Err(e) => {
+                            println!("{}", e);
+                            return Ok(None);
+                        }
+                    },
+                    None => ExportFormat::from_path(Path::new(filename)).unwrap_or_default(),
+                };
 
-                for entry in entries {
-                    export_content.push_str(&format!(
-                        "## {} - {}\n{}\n\n",
-                        entry.role,
-                        entry.timestamp.format("%Y-%m-%d %H:%M:%S"),
-                        entry.content
-                    ));
-                }
+                let export = match context.export_session(current_session_id, false).await {
+                    Ok(export) => export,
+                    Err(e) => {
+                        println!("Failed to export session: {}", e);
+                        return Ok(None);
+                    }
+                };
 
-                match tokio::fs::write(filename, export_content).await {
-                    Ok(_) => println!("Session exported to {}", filename),
+                match tokio::fs::write(filename, export.render(format)?).await {
+                    Ok(_) => println!("Session exported to {} ({})", filename, format),
                     Err(e) => println!("Failed to export session: {}", e),
                 }
             }
+            "import" => {
+                if parts.len() != 2 {
+                    println!("Usage: /import <filename>");
+                    return Ok(None);
+                }
+                let filename = parts[1];
+                let export = match tokio::fs::read_to_string(filename).await {
+                    Ok(text) => {
+                        SessionExport::parse(&text, ExportFormat::from_path(Path::new(filename)))
+                    }
+                    Err(e) => Err(e.into()),
+                };
+
+                let result = match export {
+                    Ok(export) => {
+                        context
+                            .import_session(&export, Some(&self.embedding_service))
+                            .await
+                    }
+                    Err(e) => Err(e),
+                };
+                match result {
+                    Ok(summary) => {
+                        println!(
+                            "Imported session {}: {} entries ({} already present), {} commands",
+                            summary.session_id, summary.entries, summary.skipped, summary.commands
+                        );
+                        println!("(Use /session {} to switch to it)", summary.session_id);
+                    }
+                    Err(e) => println!("Failed to import session: {}", e),
+                }
+            }
             "help" => {
                 self.print_help();
             }
@@ -578,6 +903,54 @@ Respond in a conversational and helpful manner, using tools as needed to provide
             "env" => {
                 self.print_environment_variables();
             }
+            "mcp" => {
+                self.print_mcp_status();
+            }
+            "shell" => match (&self.shells, parts.get(1).copied()) {
+                (None, _) => {
+                    println!(
+                        "Each command runs in a new shell. Start Vega with --persistent-shell to keep one per session."
+                    );
+                }
+                (Some(shells), Some("reset")) => {
+                    if shells.reset(current_session_id).await {
+                        println!("Shell stopped. The next command starts in a fresh shell.");
+                    } else {
+                        println!("No shell is running for this session.");
+                    }
+                }
+                (Some(_), _) => {
+                    println!(
+                        "Commands of this session share one shell, keeping its working directory and environment."
+                    );
+                    println!("Usage: /shell reset - stop the shell and everything it started");
+                }
+            },
+            "checkpoints" => {
+                self.print_checkpoints(context, current_session_id).await?;
+            }
+            "undo" => match context.list_checkpoints(current_session_id).await?.last() {
+                Some(checkpoint) => {
+                    self.restore(context, current_session_id, checkpoint.id)
+                        .await?;
+                }
+                None => println!("Nothing to undo: the agent has not changed any files."),
+            },
+            "restore" => {
+                let Some(id) = parts.get(1).and_then(|id| id.parse::<i64>().ok()) else {
+                    println!("Usage: /restore <checkpoint id>");
+                    return Ok(None);
+                };
+                let checkpoints = context.list_checkpoints(current_session_id).await?;
+                if checkpoints.iter().any(|checkpoint| checkpoint.id == id) {
+                    self.restore(context, current_session_id, id).await?;
+                } else {
+                    println!(
+                        "Checkpoint {} not found. Use /checkpoints to list the checkpoints of this session.",
+                        id
+                    );
+                }
+            }
             "logs" => {
                 let count = if parts.len() > 1 {
                     parts[1].parse::<usize>().unwrap_or(10).min(10)
@@ -606,8 +979,17 @@ Respond in a conversational and helpful manner, using tools as needed to provide
         println!("  /session    - Show current session ID or switch to another session");
         println!("  /sessions   - List all available sessions");
         println!("  /clear      - Clear current session history");
-        println!("  /export <filename> - Export current session to a file");
+        println!("  /compact    - Summarize older messages to shorten the conversation history");
+        println!(
+            "  /export <filename> [format] - Export current session (json, markdown or jsonl)"
+        );
+        println!("  /import <filename> - Import a session from an export file");
         println!("  /env        - Show all environment variables and their values");
+        println!("  /mcp        - Show the connection health of MCP servers");
+        println!("  /shell [reset] - Show or restart the persistent shell of this session");
+        println!("  /checkpoints - List the file changes the agent made, by turn");
+        println!("  /undo       - Revert the files changed in the agent's last turn");
+        println!("  /restore <id> - Revert the files to how they were before checkpoint <id>");
         println!("  /logs [count] - Show last 0-10 log lines for current session (default: 10)");
         println!();
         println!(
@@ -631,6 +1013,20 @@ Respond in a conversational and helpful manner, using tools as needed to provide
         println!("  ✏️  edit_file     - Create or modify files");
         println!("  📁 list_files    - List files and directories");
         println!("  📜 read_logs     - Read log messages for a specific session");
+        if let Some(ref mcp) = self.mcp {
+            let tools = mcp.tools();
+            if !tools.is_empty() {
+                println!();
+                println!("MCP tools (confirmation required):");
+                for tool in tools {
+                    println!(
+                        "  🔌 {} - {}",
+                        RigTool::name(&tool),
+                        McpTool::description(&tool)
+                    );
+                }
+            }
+        }
         println!();
         println!("Examples:");
         println!("  \"Search for the latest news about Rust programming\"");
@@ -748,6 +1144,90 @@ Respond in a conversational and helpful manner, using tools as needed to provide
         println!("═══════════════════════════════════════════════════════════════");
     }
 
+    /// Print the connection health of the configured MCP servers
+    fn print_mcp_status(&self) {
+        let health = self
+            .mcp
+            .as_ref()
+            .map(|mcp| mcp.health())
+            .unwrap_or_default();
+        if health.is_empty() {
+            println!("No MCP servers configured. Start with --mcp-client --mcp-config <file>.");
+            return;
+        }
+
+        println!("🔌 MCP servers:");
+        for (name, health) in health {
+            let icon = match health.state {
+                ConnectionState::Connected => "✅",
+                ConnectionState::Connecting | ConnectionState::Reconnecting { .. } => "🔄",
+                ConnectionState::Failed | ConnectionState::Disconnected => "❌",
+            };
+            println!("  {} {} - {}", icon, name, health.state);
+
+            if health.state == ConnectionState::Connected
+                && let Some(connected_at) = health.connected_at
+            {
+                println!(
+                    "     {} tools, {}/{} calls running, connected since {}",
+                    health.tools,
+                    health.active_calls,
+                    health.max_concurrent_calls,
+                    connected_at.format("%Y-%m-%d %H:%M:%S UTC")
+                );
+            }
+            if health.reconnects > 0 {
+                println!("     reconnected {} times", health.reconnects);
+            }
+            if let Some(error) = health.last_error {
+                println!("     last error: {}", error);
+            }
+        }
+    }
+
+    /// Print the checkpoints of a session
+    async fn print_checkpoints(&self, context: &ContextStore, session_id: &str) -> Result<()> {
+        let checkpoints = context.list_checkpoints(session_id).await?;
+        if checkpoints.is_empty() {
+            println!("No checkpoints: the agent has not changed any files in this session.");
+            return Ok(());
+        }
+
+        println!("Checkpoints (use /restore <id> to go back to before one):");
+        for checkpoint in checkpoints {
+            let prompt = checkpoint.prompt.lines().next().unwrap_or_default();
+            let prompt = match prompt.char_indices().nth(CHECKPOINT_PROMPT_CHARS) {
+                Some((end, _)) => format!("{}...", &prompt[..end]),
+                None => prompt.to_string(),
+            };
+            println!(
+                "  {} - {}, {} file{} - {}",
+                checkpoint.id,
+                checkpoint.timestamp.format("%Y-%m-%d %H:%M:%S UTC"),
+                checkpoint.files,
+                if checkpoint.files == 1 { "" } else { "s" },
+                prompt
+            );
+        }
+        Ok(())
+    }
+
+    /// Revert the files changed since a checkpoint and report what changed
+    async fn restore(&self, context: &ContextStore, session_id: &str, id: i64) -> Result<()> {
+        let restored = restore_checkpoint(context, session_id, id).await?;
+        println!(
+            "Restored the files to how they were before checkpoint {}:",
+            id
+        );
+        for file in restored {
+            match file {
+                RestoredFile::Written(path) => println!("  restored {}", path.display()),
+                RestoredFile::Removed(path) => println!("  removed  {}", path.display()),
+            }
+        }
+        Ok(())
+    }
+
     /// Print session logs for the current session
     async fn print_session_logs(&self, session_id: &str, count: usize) -> Result<()> {
         if let Some(ref logger) = self.logger {
@@ -889,13 +1369,46 @@ impl Agent for ChatAgent {
                         warn!("Failed to store user context: {}", e);
                     }
 
-                    // Send message to AI and get response with tools
-                    match self
-                        .get_response_with_tools(user_input, context, session_id)
-                        .await
-                    {
-                        Ok(response) => {
-                            println!("\x1b[93mAgent\x1b[0m: {}", response);
+                    // Print response text as it streams in from the model
+                    let (delta_tx, mut delta_rx) = tokio::sync::mpsc::unbounded_channel();
+                    let printer = tokio::spawn(async move {
+                        let mut streamed = false;
+                        while let Some(delta) = delta_rx.recv().await {
+                            if !streamed {
+                                print!("\x1b[93mAgent\x1b[0m: ");
+                                streamed = true;
+                            }
+                            print!("{}", delta);
+                            let _ = std::io::stdout().flush();
+                        }
+                        streamed
+                    });
+
+                    // Send message to AI and get response with tools; Ctrl+C
+                    // abandons the response and kills any command it is running
+                    let result = tokio::select! {
+                        result = self.stream_response_with_tools(
+                            user_input,
+                            context,
+                            session_id,
+                            Some(delta_tx),
+                        ) => Some(result),
+                        _ = tokio::signal::ctrl_c() => None,
+                    };
+                    let streamed = printer.await.unwrap_or(false);
+                    if streamed {
+                        println!();
+                    }
+
+                    match result {
+                        None => {
+                            println!("\x1b[91mCancelled\x1b[0m");
+                            println!();
+                        }
+                        Some(Ok(response)) => {
+                            if !streamed {
+                                println!("\x1b[93mAgent\x1b[0m: {}", response);
+                            }
                             println!();
 
                             // Store agent response in context
@@ -912,8 +1425,13 @@ impl Agent for ChatAgent {
                             {
                                 warn!("Failed to store agent context: {}", e);
                             }
+
+                            // Fold older turns into a summary once the session grows too long
+                            if let Err(e) = self.compact_if_needed(context, session_id).await {
+                                warn!("Failed to compact session history: {}", e);
+                            }
                         }
-                        Err(e) => {
+                        Some(Err(e)) => {
                             error!("Error getting response: {}", e);
                             println!("\x1b[91mError\x1b[0m: Failed to get response from AI agent");
                             println!();
@@ -945,15 +1463,7 @@ impl Agent for ChatAgent {
     }
 
     fn system_prompt(&self) -> &str {
-        r#"The assistant is Vega.
-
-The current date is {{currentDateTime}}.
-
-Vega enjoys helping humans and sees its role as an intelligent and kind assistant to the people, with depth and wisdom that makes it more than a mere tool.
-
-Vega always responds to the person in the language they use or request. If the person messages Vega in French then Vega responds in French, if the person messages Vega in Icelandic then Vega responds in Icelandic, and so on for any language. Vega is fluent in a wide variety of world languages.
-
-Vega is now being connected with a person."#
+        SYSTEM_PROMPT
     }
 }
 
@@ -1036,6 +1546,25 @@ mod tests {
         assert_eq!(agent.config().model, original_model);
     }
 
+    #[test]
+    fn test_only_tool_support_errors_fall_back() {
+        let unsupported = anyhow::anyhow!(
+            "ProviderError: registry.ollama.ai/library/gemma:2b does not support tools"
+        );
+        assert!(is_missing_tool_support(&unsupported));
+
+        for message in [
+            "ToolCallError: IO error: No such file or directory",
+            "Model exceeded the maximum of 8 tool turns",
+            "HttpError: error decoding response body: expected function",
+        ] {
+            assert!(
+                !is_missing_tool_support(&anyhow::anyhow!(message)),
+                "{message}"
+            );
+        }
+    }
+
     #[test]
     fn test_chat_agent_system_prompt() {
         let config = create_test_config("ollama", "llama3.2", None);
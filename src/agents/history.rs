@@ -0,0 +1,222 @@
+//! Conversation history selection for multi-turn prompts
+//!
+//! Session history from the [`ContextStore`](crate::context::ContextStore) is
+//! replayed to the model as real user/assistant turns. Because context windows
+//! are finite, the turns are trimmed to a token budget using a configurable
+//! [`TruncationPolicy`].
+
+use rig::completion::Message;
+use std::fmt;
+use std::str::FromStr;
+
+use crate::context::ContextEntry;
+
+/// Default number of tokens of session history sent with each prompt
+pub const DEFAULT_HISTORY_TOKEN_BUDGET: usize = 4000;
+
+/// How session history is trimmed when it exceeds the token budget
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TruncationPolicy {
+    /// Drop the oldest turns first, keeping the most recent dialogue
+    #[default]
+    DropOldest,
+    /// Always keep the first user turn of the session, then the most recent turns
+    KeepFirst,
+}
+
+impl FromStr for TruncationPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "drop-oldest" => Ok(TruncationPolicy::DropOldest),
+            "keep-first" => Ok(TruncationPolicy::KeepFirst),
+            _ => Err(format!(
+                "Unknown truncation policy: {}. Supported policies: drop-oldest, keep-first",
+                s
+            )),
+        }
+    }
+}
+
+impl fmt::Display for TruncationPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TruncationPolicy::DropOldest => write!(f, "drop-oldest"),
+            TruncationPolicy::KeepFirst => write!(f, "keep-first"),
+        }
+    }
+}
+
+/// Estimate the number of tokens in a piece of text (roughly four characters per token)
+pub fn estimate_tokens(text: &str) -> usize {
+    text.chars().count().div_ceil(4)
+}
+
+/// Select the conversation turns that fit within `budget` tokens.
+///
+/// Only `user` and `assistant` entries are considered; other roles such as
+/// session logs are skipped. The returned turns are in chronological order and
+/// never start with an assistant turn.
+pub fn select_history(
+    entries: &[ContextEntry],
+    budget: usize,
+    policy: TruncationPolicy,
+) -> Vec<ContextEntry> {
+    let turns: Vec<&ContextEntry> = entries
+        .iter()
+        .filter(|entry| entry.role == "user" || entry.role == "assistant")
+        .collect();
+
+    let mut remaining = budget;
+    let mut pinned = None;
+
+    if policy == TruncationPolicy::KeepFirst
+        && let Some(index) = turns.iter().position(|entry| entry.role == "user")
+    {
+        let cost = estimate_tokens(&turns[index].content);
+        if cost <= remaining {
+            remaining -= cost;
+            pinned = Some(index);
+        }
+    }
+
+    // Walk backwards from the newest turn until the budget is exhausted
+    let mut start = turns.len();
+    for (index, entry) in turns.iter().enumerate().rev() {
+        if Some(index) == pinned {
+            break;
+        }
+        let cost = estimate_tokens(&entry.content);
+        if cost > remaining {
+            break;
+        }
+        remaining -= cost;
+        start = index;
+    }
+
+    // Models expect the dialogue to open with a user turn
+    while start < turns.len() && turns[start].role != "user" && pinned.is_none() {
+        start += 1;
+    }
+
+    pinned
+        .filter(|&index| index < start)
+        .into_iter()
+        .chain(start..turns.len())
+        .map(|index| turns[index].clone())
+        .collect()
+}
+
+/// Convert context entries into chat messages for the model
+pub fn to_messages(entries: &[ContextEntry]) -> Vec<Message> {
+    entries
+        .iter()
+        .map(|entry| match entry.role.as_str() {
+            "assistant" => Message::assistant(entry.content.clone()),
+            _ => Message::user(entry.content.clone()),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(role: &str, content: &str) -> ContextEntry {
+        ContextEntry::new(
+            "chat".to_string(),
+            "session".to_string(),
+            content.to_string(),
+            role.to_string(),
+        )
+    }
+
+    fn contents(entries: &[ContextEntry]) -> Vec<&str> {
+        entries.iter().map(|e| e.content.as_str()).collect()
+    }
+
+    #[test]
+    fn test_truncation_policy_parsing() {
+        assert_eq!(
+            "drop-oldest".parse::<TruncationPolicy>().unwrap(),
+            TruncationPolicy::DropOldest
+        );
+        assert_eq!(
+            "keep-first".parse::<TruncationPolicy>().unwrap(),
+            TruncationPolicy::KeepFirst
+        );
+        assert!("newest".parse::<TruncationPolicy>().is_err());
+        assert_eq!(TruncationPolicy::KeepFirst.to_string(), "keep-first");
+    }
+
+    #[test]
+    fn test_select_history_within_budget() {
+        let entries = vec![
+            entry("user", "hello"),
+            entry("assistant", "hi there"),
+            entry("log", "tool executed"),
+            entry("user", "how are you"),
+        ];
+
+        let selected = select_history(&entries, 1000, TruncationPolicy::DropOldest);
+        assert_eq!(
+            contents(&selected),
+            vec!["hello", "hi there", "how are you"]
+        );
+    }
+
+    #[test]
+    fn test_select_history_drop_oldest() {
+        let entries = vec![
+            entry("user", &"a".repeat(40)),
+            entry("assistant", &"b".repeat(40)),
+            entry("user", &"c".repeat(40)),
+            entry("assistant", &"d".repeat(40)),
+        ];
+
+        // 10 tokens per turn: only the last three turns fit, and the leading
+        // assistant turn is dropped so the dialogue starts with the user
+        let selected = select_history(&entries, 30, TruncationPolicy::DropOldest);
+        assert_eq!(
+            contents(&selected),
+            vec!["c".repeat(40).as_str(), "d".repeat(40).as_str()]
+        );
+    }
+
+    #[test]
+    fn test_select_history_keep_first() {
+        let entries = vec![
+            entry("user", &"a".repeat(40)),
+            entry("assistant", &"b".repeat(40)),
+            entry("user", &"c".repeat(40)),
+            entry("assistant", &"d".repeat(40)),
+        ];
+
+        let selected = select_history(&entries, 30, TruncationPolicy::KeepFirst);
+        assert_eq!(
+            contents(&selected),
+            vec![
+                "a".repeat(40).as_str(),
+                "c".repeat(40).as_str(),
+                "d".repeat(40).as_str()
+            ]
+        );
+    }
+
+    #[test]
+    fn test_select_history_zero_budget() {
+        let entries = vec![entry("user", "hello"), entry("assistant", "hi")];
+        assert!(select_history(&entries, 0, TruncationPolicy::DropOldest).is_empty());
+        assert!(select_history(&entries, 0, TruncationPolicy::KeepFirst).is_empty());
+    }
+
+    #[test]
+    fn test_to_messages_roles() {
+        let entries = vec![entry("user", "hello"), entry("assistant", "hi")];
+        let messages = to_messages(&entries);
+        assert_eq!(messages.len(), 2);
+        assert!(matches!(messages[0], Message::User { .. }));
+        assert!(matches!(messages[1], Message::Assistant { .. }));
+    }
+}
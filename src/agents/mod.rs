@@ -6,9 +6,15 @@ use std::collections::HashMap;
 use std::env;
 
 pub mod chat;
+pub mod history;
+pub mod summary;
 
 use crate::agent_instructions::AgentInstructions;
 use crate::context::ContextStore;
+use crate::tools::{OutputLimit, PermissionPolicy, Sandbox, Workspace};
+use history::{DEFAULT_HISTORY_TOKEN_BUDGET, TruncationPolicy};
+use std::sync::Arc;
+use summary::DEFAULT_COMPACT_THRESHOLD;
 
 /// Base trait for all agent types
 #[async_trait]
@@ -52,6 +58,22 @@ pub struct AgentConfig {
     pub openai_api_key: Option<String>,
     pub yolo: bool,
     pub agent_instructions: Option<AgentInstructions>,
+    /// Maximum number of tokens of session history replayed to the model
+    pub history_token_budget: usize,
+    /// How session history is trimmed to fit the token budget
+    pub history_truncation: TruncationPolicy,
+    /// Unsummarized history tokens that trigger automatic compaction (0 disables it)
+    pub compact_threshold: usize,
+    /// Run the bash tool in one shell per session that keeps its directory and environment
+    pub persistent_shell: bool,
+    /// Lines of command output returned to the model, the rest being saved to a file
+    pub command_output: OutputLimit,
+    /// Sandbox the bash tool runs commands in
+    pub sandbox: Option<Sandbox>,
+    /// Directories the file tools are confined to
+    pub workspace: Option<Workspace>,
+    /// Rules deciding which tool calls run, ask first or are refused
+    pub permissions: Arc<PermissionPolicy>,
 }
 
 impl AgentConfig {
@@ -75,6 +97,14 @@ impl AgentConfig {
             openai_api_key,
             yolo,
             agent_instructions: None,
+            history_token_budget: DEFAULT_HISTORY_TOKEN_BUDGET,
+            history_truncation: TruncationPolicy::default(),
+            compact_threshold: DEFAULT_COMPACT_THRESHOLD,
+            persistent_shell: false,
+            command_output: OutputLimit::default(),
+            sandbox: None,
+            workspace: None,
+            permissions: Arc::new(PermissionPolicy::default()),
         }
     }
 
@@ -83,6 +113,49 @@ impl AgentConfig {
         self.agent_instructions = Some(instructions);
         self
     }
+
+    /// Set the token budget and truncation policy for conversation history
+    pub fn with_history(mut self, token_budget: usize, truncation: TruncationPolicy) -> Self {
+        self.history_token_budget = token_budget;
+        self.history_truncation = truncation;
+        self
+    }
+
+    /// Set the unsummarized history size that triggers automatic compaction
+    pub fn with_compaction(mut self, threshold: usize) -> Self {
+        self.compact_threshold = threshold;
+        self
+    }
+
+    /// Keep one shell per session for the bash tool instead of one per command
+    pub fn with_persistent_shell(mut self, persistent_shell: bool) -> Self {
+        self.persistent_shell = persistent_shell;
+        self
+    }
+
+    /// Set how many lines of command output are returned to the model
+    pub fn with_command_output(mut self, limit: OutputLimit) -> Self {
+        self.command_output = limit;
+        self
+    }
+
+    /// Check tool calls against a permission policy
+    pub fn with_permissions(mut self, permissions: Arc<PermissionPolicy>) -> Self {
+        self.permissions = permissions;
+        self
+    }
+
+    /// Run the commands of the bash tool in a sandbox
+    pub fn with_sandbox(mut self, sandbox: Sandbox) -> Self {
+        self.sandbox = Some(sandbox);
+        self
+    }
+
+    /// Confine the file tools to a workspace
+    pub fn with_workspace(mut self, workspace: Workspace) -> Self {
+        self.workspace = Some(workspace);
+        self
+    }
 }
 
 /// Render a prompt template with supported variables
@@ -146,6 +219,26 @@ mod tests {
         assert_eq!(config.embedding_model, None);
         assert_eq!(config.openai_api_key, None);
         assert!(config.agent_instructions.is_none());
+        assert_eq!(config.history_token_budget, DEFAULT_HISTORY_TOKEN_BUDGET);
+        assert_eq!(config.history_truncation, TruncationPolicy::DropOldest);
+    }
+
+    #[test]
+    fn test_agent_config_with_history() {
+        let config = AgentConfig::new(
+            false,
+            "ollama".to_string(),
+            "llama3.2".to_string(),
+            None,
+            "simple".to_string(),
+            None,
+            None,
+            false,
+        )
+        .with_history(1000, TruncationPolicy::KeepFirst);
+
+        assert_eq!(config.history_token_budget, 1000);
+        assert_eq!(config.history_truncation, TruncationPolicy::KeepFirst);
     }
 
     #[test]
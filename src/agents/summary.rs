@@ -0,0 +1,244 @@
+//! Rolling conversation summaries for long sessions
+//!
+//! Once the unsummarized dialogue in a session grows past a threshold, the
+//! older turns are condensed by the model into a `summary` entry in the
+//! [`ContextStore`](crate::context::ContextStore). Each summary records the last
+//! entry it covers, so later prompts replay only the turns after it and carry
+//! the summary in the system prompt instead. Summarized entries are never
+//! deleted; they stay in the store for export and audit.
+
+use std::collections::HashMap;
+
+use super::history::estimate_tokens;
+use crate::context::ContextEntry;
+
+/// Role of context entries holding a conversation summary
+pub const SUMMARY_ROLE: &str = "summary";
+
+/// Default number of unsummarized history tokens that triggers compaction
+pub const DEFAULT_COMPACT_THRESHOLD: usize = 8000;
+
+/// Number of most recent turns left out of a summary so they are replayed verbatim
+pub const KEEP_RECENT_TURNS: usize = 4;
+
+/// Metadata key holding the id of the last entry covered by a summary
+const SUMMARIZED_THROUGH: &str = "summarized_through";
+
+/// Metadata key holding the total number of entries covered by a summary
+const SUMMARIZED_ENTRIES: &str = "summarized_entries";
+
+/// System prompt used when asking the model for a summary
+pub const SUMMARY_PREAMBLE: &str = "You summarize conversations between a user and an AI assistant so the assistant can continue them without the full transcript. Write a concise summary in the conversation's language that preserves the user's goals, decisions that were made, facts and preferences the user stated, file names, commands, identifiers and any open questions or unfinished tasks. Do not add commentary or address the user.";
+
+/// A session's history split at its most recent summary
+#[derive(Debug, Clone, Default)]
+pub struct SessionTranscript {
+    /// The latest summary of the session, if it has been compacted
+    pub summary: Option<ContextEntry>,
+    /// User and assistant turns not covered by the summary, oldest first
+    pub turns: Vec<ContextEntry>,
+}
+
+impl SessionTranscript {
+    /// Split chronologically ordered session entries at the latest summary
+    pub fn from_entries(entries: &[ContextEntry]) -> Self {
+        let summary = entries
+            .iter()
+            .rev()
+            .find(|entry| entry.role == SUMMARY_ROLE)
+            .cloned();
+
+        // Turns up to and including the summary's last covered entry are skipped.
+        // If that entry no longer exists the whole dialogue is kept.
+        let start = summary
+            .as_ref()
+            .and_then(|summary| summary.metadata.get(SUMMARIZED_THROUGH))
+            .and_then(|id| entries.iter().position(|entry| &entry.id == id))
+            .map_or(0, |index| index + 1);
+
+        let turns = entries[start..]
+            .iter()
+            .filter(|entry| entry.role == "user" || entry.role == "assistant")
+            .cloned()
+            .collect();
+
+        Self { summary, turns }
+    }
+
+    /// Estimated number of tokens in the unsummarized turns
+    pub fn unsummarized_tokens(&self) -> usize {
+        self.turns
+            .iter()
+            .map(|entry| estimate_tokens(&entry.content))
+            .sum()
+    }
+
+    /// Turns that a new summary should cover, leaving the most recent `keep_recent`.
+    ///
+    /// The kept turns always begin with a user turn so the replayed dialogue
+    /// stays well formed. Returns an empty slice when there is nothing to compact.
+    pub fn turns_to_summarize(&self, keep_recent: usize) -> &[ContextEntry] {
+        let mut end = self.turns.len().saturating_sub(keep_recent);
+        while end > 0 && end < self.turns.len() && self.turns[end].role != "user" {
+            end -= 1;
+        }
+        &self.turns[..end]
+    }
+
+    /// Number of entries covered by the current summary
+    pub fn summarized_entries(&self) -> usize {
+        self.summary
+            .as_ref()
+            .and_then(|summary| summary.metadata.get(SUMMARIZED_ENTRIES))
+            .and_then(|count| count.parse().ok())
+            .unwrap_or(0)
+    }
+}
+
+/// Build the prompt asking the model to fold `turns` into the previous summary
+pub fn summary_prompt(previous: Option<&ContextEntry>, turns: &[ContextEntry]) -> String {
+    let mut prompt = String::new();
+    if let Some(previous) = previous {
+        prompt.push_str("Summary of the conversation so far:\n");
+        prompt.push_str(&previous.content);
+        prompt.push_str("\n\nUpdate the summary with the following later messages:\n");
+    } else {
+        prompt.push_str("Summarize the following conversation:\n");
+    }
+
+    for entry in turns {
+        prompt.push_str(&format!("\n{}: {}\n", entry.role, entry.content));
+    }
+    prompt
+}
+
+/// Create the summary entry covering `turns` on top of the transcript's previous summary
+pub fn summary_entry(
+    agent_name: &str,
+    session_id: &str,
+    content: String,
+    transcript: &SessionTranscript,
+    turns: &[ContextEntry],
+) -> ContextEntry {
+    let mut metadata = HashMap::new();
+    if let Some(last) = turns.last() {
+        metadata.insert(SUMMARIZED_THROUGH.to_string(), last.id.clone());
+    }
+    metadata.insert(
+        SUMMARIZED_ENTRIES.to_string(),
+        (transcript.summarized_entries() + turns.len()).to_string(),
+    );
+
+    ContextEntry::new(
+        agent_name.to_string(),
+        session_id.to_string(),
+        content,
+        SUMMARY_ROLE.to_string(),
+    )
+    .with_metadata(metadata)
+}
+
+/// Format a summary for inclusion in the system prompt
+pub fn format_summary_for_prompt(summary: &ContextEntry) -> String {
+    format!(
+        "\n\nSummary of the earlier part of this conversation (older messages are not shown):\n{}",
+        summary.content
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(role: &str, content: &str) -> ContextEntry {
+        ContextEntry::new(
+            "chat".to_string(),
+            "session".to_string(),
+            content.to_string(),
+            role.to_string(),
+        )
+    }
+
+    fn contents(entries: &[ContextEntry]) -> Vec<&str> {
+        entries.iter().map(|e| e.content.as_str()).collect()
+    }
+
+    fn dialogue(count: usize) -> Vec<ContextEntry> {
+        (0..count)
+            .map(|i| {
+                let role = if i % 2 == 0 { "user" } else { "assistant" };
+                entry(role, &format!("turn {}", i))
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_transcript_without_summary() {
+        let mut entries = dialogue(3);
+        entries.insert(1, entry("session_log", "ignored"));
+
+        let transcript = SessionTranscript::from_entries(&entries);
+        assert!(transcript.summary.is_none());
+        assert_eq!(
+            contents(&transcript.turns),
+            vec!["turn 0", "turn 1", "turn 2"]
+        );
+        assert_eq!(transcript.summarized_entries(), 0);
+    }
+
+    #[test]
+    fn test_transcript_skips_summarized_turns() {
+        let mut entries = dialogue(6);
+        let empty = SessionTranscript::default();
+        let summary = summary_entry("chat", "session", "s".into(), &empty, &entries[..2]);
+        // The summary is written after the turns it leaves unsummarized
+        entries.push(summary);
+        entries.push(entry("user", "turn 6"));
+
+        let transcript = SessionTranscript::from_entries(&entries);
+        assert_eq!(transcript.summary.as_ref().unwrap().content, "s");
+        assert_eq!(transcript.summarized_entries(), 2);
+        assert_eq!(
+            contents(&transcript.turns),
+            vec!["turn 2", "turn 3", "turn 4", "turn 5", "turn 6"]
+        );
+    }
+
+    #[test]
+    fn test_turns_to_summarize_keeps_recent_user_turn_first() {
+        let transcript = SessionTranscript::from_entries(&dialogue(7));
+        assert_eq!(
+            contents(transcript.turns_to_summarize(3)),
+            vec!["turn 0", "turn 1", "turn 2", "turn 3"]
+        );
+        // Four kept turns would start with an assistant turn, so one more is kept
+        assert_eq!(
+            contents(transcript.turns_to_summarize(4)),
+            vec!["turn 0", "turn 1"]
+        );
+        assert!(transcript.turns_to_summarize(7).is_empty());
+        assert_eq!(transcript.turns_to_summarize(0).len(), 7);
+    }
+
+    #[test]
+    fn test_summary_entries_accumulate() {
+        let entries = dialogue(4);
+        let empty = SessionTranscript::default();
+        let first = summary_entry("chat", "session", "a".into(), &empty, &entries[..2]);
+
+        let transcript = SessionTranscript::from_entries(&[entries.clone(), vec![first]].concat());
+        let turns = transcript.turns_to_summarize(0);
+        let second = summary_entry("chat", "session", "b".into(), &transcript, turns);
+        assert_eq!(second.role, SUMMARY_ROLE);
+        assert_eq!(second.metadata.get(SUMMARIZED_ENTRIES).unwrap(), "4");
+        assert_eq!(
+            second.metadata.get(SUMMARIZED_THROUGH).unwrap(),
+            &entries[3].id
+        );
+
+        let prompt = summary_prompt(transcript.summary.as_ref(), turns);
+        assert!(prompt.starts_with("Summary of the conversation so far:\na"));
+        assert!(prompt.contains("user: turn 2"));
+        assert!(prompt.contains("assistant: turn 3"));
+    }
+}
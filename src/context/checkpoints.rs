@@ -0,0 +1,234 @@
+//! Checkpoints of the files changed by the agent
+//!
+//! Before the agent changes a file for the first time in a turn, the content
+//! the file had is recorded in the checkpoint of that turn; files that did not
+//! exist yet are recorded without content, along with the directories created
+//! for them. Restoring a checkpoint puts every file changed since then back the
+//! way it was before the checkpoint's turn, which takes the content recorded by
+//! the earliest checkpoint of each file.
+
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use rusqlite::params;
+use std::collections::HashSet;
+use std::path::PathBuf;
+use tracing::debug;
+
+use super::ContextStore;
+
+/// The files changed during one turn of a session
+#[derive(Debug, Clone, PartialEq)]
+pub struct Checkpoint {
+    /// Number identifying the checkpoint, increasing with every turn
+    pub id: i64,
+    pub session_id: String,
+    /// Prompt of the turn
+    pub prompt: String,
+    /// When the first file of the turn was changed
+    pub timestamp: DateTime<Utc>,
+    /// Number of files changed during the turn, not counting directories
+    pub files: usize,
+}
+
+/// A file as it was before a checkpoint
+#[derive(Debug, Clone, PartialEq)]
+pub struct FileSnapshot {
+    pub path: PathBuf,
+    /// Content of the file, `None` if it did not exist
+    pub content: Option<Vec<u8>>,
+    /// Whether the path is a directory that did not exist, created for a file
+    pub directory: bool,
+}
+
+impl ContextStore {
+    /// Record the content of a file before the turn `turn_id` changes it.
+    ///
+    /// The checkpoint of the turn is created with its first file; only the
+    /// first snapshot of each file in a turn is kept.
+    pub async fn record_file_snapshot(
+        &self,
+        session_id: &str,
+        turn_id: &str,
+        prompt: &str,
+        snapshot: &FileSnapshot,
+    ) -> Result<()> {
+        let mut conn = self.connection.lock().unwrap();
+        let tx = conn.transaction()?;
+
+        tx.execute(
+            "INSERT OR IGNORE INTO checkpoints (session_id, turn_id, prompt, timestamp)
+             VALUES (?1, ?2, ?3, ?4)",
+            params![session_id, turn_id, prompt, Utc::now().timestamp()],
+        )?;
+        let checkpoint_id: i64 = tx.query_row(
+            "SELECT id FROM checkpoints WHERE turn_id = ?1",
+            params![turn_id],
+            |row| row.get(0),
+        )?;
+        tx.execute(
+            "INSERT OR IGNORE INTO checkpoint_files (checkpoint_id, path, content, directory)
+             VALUES (?1, ?2, ?3, ?4)",
+            params![
+                checkpoint_id,
+                snapshot.path.to_string_lossy(),
+                snapshot.content,
+                snapshot.directory
+            ],
+        )?;
+        tx.commit()?;
+
+        debug!(
+            "Recorded {} in checkpoint {}",
+            snapshot.path.display(),
+            checkpoint_id
+        );
+        Ok(())
+    }
+
+    /// The checkpoints of a session, oldest first
+    pub async fn list_checkpoints(&self, session_id: &str) -> Result<Vec<Checkpoint>> {
+        let conn = self.connection.lock().unwrap();
+
+        let mut stmt = conn.prepare(
+            "SELECT c.id, c.session_id, c.prompt, c.timestamp,
+                    COUNT(f.path) FILTER (WHERE f.directory = 0)
+             FROM checkpoints c
+             LEFT JOIN checkpoint_files f ON f.checkpoint_id = c.id
+             WHERE c.session_id = ?1
+             GROUP BY c.id
+             ORDER BY c.id ASC",
+        )?;
+        let checkpoints = stmt
+            .query_map(params![session_id], |row| {
+                Ok(Checkpoint {
+                    id: row.get(0)?,
+                    session_id: row.get(1)?,
+                    prompt: row.get(2)?,
+                    timestamp: DateTime::from_timestamp(row.get::<_, i64>(3)?, 0)
+                        .unwrap_or_else(Utc::now),
+                    files: row.get::<_, i64>(4)? as usize,
+                })
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(checkpoints)
+    }
+
+    /// The files changed since checkpoint `id` of a session, as they were
+    /// before its turn
+    pub async fn checkpoint_snapshots(
+        &self,
+        session_id: &str,
+        id: i64,
+    ) -> Result<Vec<FileSnapshot>> {
+        let conn = self.connection.lock().unwrap();
+
+        let mut stmt = conn.prepare(
+            "SELECT f.path, f.content, f.directory FROM checkpoint_files f
+             JOIN checkpoints c ON c.id = f.checkpoint_id
+             WHERE c.session_id = ?1 AND c.id >= ?2
+             ORDER BY c.id ASC, f.path ASC",
+        )?;
+        let snapshots = stmt
+            .query_map(params![session_id, id], |row| {
+                Ok(FileSnapshot {
+                    path: PathBuf::from(row.get::<_, String>(0)?),
+                    content: row.get(1)?,
+                    directory: row.get(2)?,
+                })
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        // The earliest snapshot of a file is its content before checkpoint `id`
+        let mut seen = HashSet::new();
+        Ok(snapshots
+            .into_iter()
+            .filter(|snapshot| seen.insert(snapshot.path.clone()))
+            .collect())
+    }
+
+    /// Delete checkpoint `id` of a session and the checkpoints after it,
+    /// returning how many were deleted
+    pub async fn delete_checkpoints(&self, session_id: &str, id: i64) -> Result<usize> {
+        let mut conn = self.connection.lock().unwrap();
+        let tx = conn.transaction()?;
+
+        tx.execute(
+            "DELETE FROM checkpoint_files WHERE checkpoint_id IN (
+                SELECT id FROM checkpoints WHERE session_id = ?1 AND id >= ?2
+            )",
+            params![session_id, id],
+        )?;
+        let deleted = tx.execute(
+            "DELETE FROM checkpoints WHERE session_id = ?1 AND id >= ?2",
+            params![session_id, id],
+        )?;
+        tx.commit()?;
+
+        Ok(deleted)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    fn snapshot(path: &str, content: Option<&str>) -> FileSnapshot {
+        FileSnapshot {
+            path: PathBuf::from(path),
+            content: content.map(|content| content.as_bytes().to_vec()),
+            directory: false,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_checkpoints_keep_the_earliest_content() {
+        let temp_dir = tempdir().unwrap();
+        let store = ContextStore::new(temp_dir.path().join("test.db"), 4)
+            .await
+            .unwrap();
+
+        let first = [
+            snapshot("/p/a.rs", Some("a0")),
+            snapshot("/p/a.rs", Some("a1")),
+        ];
+        for snapshot in &first {
+            store
+                .record_file_snapshot("s1", "turn-1", "edit a", snapshot)
+                .await
+                .unwrap();
+        }
+        let second = [snapshot("/p/a.rs", Some("a2")), snapshot("/p/new.rs", None)];
+        for snapshot in &second {
+            store
+                .record_file_snapshot("s1", "turn-2", "add new", snapshot)
+                .await
+                .unwrap();
+        }
+        store
+            .record_file_snapshot("s2", "turn-3", "other", &snapshot("/q/b.rs", None))
+            .await
+            .unwrap();
+
+        let checkpoints = store.list_checkpoints("s1").await.unwrap();
+        assert_eq!(checkpoints.len(), 2);
+        assert_eq!(checkpoints[0].prompt, "edit a");
+        assert_eq!(checkpoints[0].files, 1);
+        assert_eq!(checkpoints[1].files, 2);
+
+        let (first_id, second_id) = (checkpoints[0].id, checkpoints[1].id);
+        assert_eq!(
+            store.checkpoint_snapshots("s1", second_id).await.unwrap(),
+            second
+        );
+        assert_eq!(
+            store.checkpoint_snapshots("s1", first_id).await.unwrap(),
+            [first[0].clone(), second[1].clone()]
+        );
+
+        assert_eq!(store.delete_checkpoints("s1", second_id).await.unwrap(), 1);
+        assert_eq!(store.list_checkpoints("s1").await.unwrap().len(), 1);
+        assert_eq!(store.list_checkpoints("s2").await.unwrap().len(), 1);
+    }
+}
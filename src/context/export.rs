@@ -0,0 +1,712 @@
+//! Session export and import
+//!
+//! A [`SessionExport`] holds everything the context store knows about one
+//! session: its entries with their metadata, optionally their embeddings, and
+//! the session's command history. Exports can be written as a single JSON
+//! document, as JSON Lines (one record per line, convenient for streaming and
+//! `grep`), or as Markdown that reads like a transcript while carrying the
+//! structured data in HTML comments, so every format imports back losslessly.
+
+use anyhow::{Context, Result, bail};
+use chrono::{DateTime, Utc};
+use rusqlite::params;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::path::Path;
+use std::str::FromStr;
+use tracing::{info, warn};
+
+use super::{ContextEntry, ContextStore};
+use crate::embeddings::{EmbeddingModelInfo, EmbeddingService};
+use crate::vector_index::{decode_vector, encode_vector};
+
+/// Version of the export format written by this release
+pub const EXPORT_FORMAT_VERSION: u32 = 1;
+
+/// Entries embedded per request when imported entries are embedded on import
+const IMPORT_EMBEDDING_BATCH: usize = 32;
+
+/// Markers used to carry structured data through Markdown exports
+const MARKDOWN_SESSION: &str = "<!-- vega:session ";
+const MARKDOWN_ENTRY: &str = "<!-- vega:entry ";
+const MARKDOWN_COMMAND: &str = "<!-- vega:command ";
+const MARKDOWN_END: &str = "<!-- vega:end -->";
+const MARKDOWN_COMMENT_END: &str = " -->";
+
+/// File formats a session can be exported to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ExportFormat {
+    /// A single pretty-printed JSON document
+    #[default]
+    Json,
+    /// A readable transcript with the structured data in HTML comments
+    Markdown,
+    /// One JSON record per line: the session header, then entries and commands
+    Jsonl,
+}
+
+impl ExportFormat {
+    /// Guess the format from a file extension
+    pub fn from_path(path: &Path) -> Option<Self> {
+        match path.extension()?.to_str()?.to_ascii_lowercase().as_str() {
+            "json" => Some(ExportFormat::Json),
+            "md" | "markdown" => Some(ExportFormat::Markdown),
+            "jsonl" | "ndjson" => Some(ExportFormat::Jsonl),
+            _ => None,
+        }
+    }
+
+    /// Guess the format of an export from its contents
+    fn detect(text: &str) -> Self {
+        let text = text.trim_start();
+        if !text.starts_with('{') {
+            ExportFormat::Markdown
+        } else if serde_json::from_str::<serde_json::Value>(text).is_ok() {
+            ExportFormat::Json
+        } else {
+            ExportFormat::Jsonl
+        }
+    }
+}
+
+impl FromStr for ExportFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "json" => Ok(ExportFormat::Json),
+            "markdown" | "md" => Ok(ExportFormat::Markdown),
+            "jsonl" => Ok(ExportFormat::Jsonl),
+            _ => Err(format!(
+                "Unknown export format: {}. Supported formats: json, markdown, jsonl",
+                s
+            )),
+        }
+    }
+}
+
+impl fmt::Display for ExportFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ExportFormat::Json => write!(f, "json"),
+            ExportFormat::Markdown => write!(f, "markdown"),
+            ExportFormat::Jsonl => write!(f, "jsonl"),
+        }
+    }
+}
+
+/// A context entry together with its embedding, if exported
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExportedEntry {
+    #[serde(flatten)]
+    pub entry: ContextEntry,
+    /// Embedding produced by the export's `embedding_model`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub embedding: Option<Vec<f32>>,
+}
+
+/// A command from the session's input history
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ExportedCommand {
+    pub id: String,
+    pub command: String,
+    pub timestamp: DateTime<Utc>,
+}
+
+/// Everything stored for a single session
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SessionExport {
+    /// Export format version
+    pub version: u32,
+    pub session_id: String,
+    pub exported_at: DateTime<Utc>,
+    /// Model that produced the exported embeddings, if embeddings were included
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub embedding_model: Option<EmbeddingModelInfo>,
+    /// Context entries, oldest first
+    pub entries: Vec<ExportedEntry>,
+    /// Command history, oldest first
+    #[serde(default)]
+    pub commands: Vec<ExportedCommand>,
+}
+
+/// Session-level fields written as the first JSON Lines record and Markdown marker
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct SessionHeader {
+    version: u32,
+    session_id: String,
+    exported_at: DateTime<Utc>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    embedding_model: Option<EmbeddingModelInfo>,
+}
+
+/// A single JSON Lines record
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum JsonlRecord {
+    Session(SessionHeader),
+    Entry(ExportedEntry),
+    Command(ExportedCommand),
+}
+
+impl SessionExport {
+    /// Render the export in the given format
+    pub fn render(&self, format: ExportFormat) -> Result<String> {
+        match format {
+            ExportFormat::Json => Ok(serde_json::to_string_pretty(self)? + "\n"),
+            ExportFormat::Jsonl => self.render_jsonl(),
+            ExportFormat::Markdown => self.render_markdown(),
+        }
+    }
+
+    /// Parse an export, detecting the format from its contents if not given
+    pub fn parse(text: &str, format: Option<ExportFormat>) -> Result<Self> {
+        let format = format.unwrap_or_else(|| ExportFormat::detect(text));
+        let export = match format {
+            ExportFormat::Json => {
+                serde_json::from_str(text).context("Failed to parse JSON session export")?
+            }
+            ExportFormat::Jsonl => Self::parse_jsonl(text)?,
+            ExportFormat::Markdown => Self::parse_markdown(text)?,
+        };
+
+        if export.version > EXPORT_FORMAT_VERSION {
+            bail!(
+                "Session export version {} is newer than the latest supported version {}",
+                export.version,
+                EXPORT_FORMAT_VERSION
+            );
+        }
+        Ok(export)
+    }
+
+    fn header(&self) -> SessionHeader {
+        SessionHeader {
+            version: self.version,
+            session_id: self.session_id.clone(),
+            exported_at: self.exported_at,
+            embedding_model: self.embedding_model.clone(),
+        }
+    }
+
+    fn from_header(header: SessionHeader) -> Self {
+        Self {
+            version: header.version,
+            session_id: header.session_id,
+            exported_at: header.exported_at,
+            embedding_model: header.embedding_model,
+            entries: Vec::new(),
+            commands: Vec::new(),
+        }
+    }
+
+    fn render_jsonl(&self) -> Result<String> {
+        let mut output = serde_json::to_string(&JsonlRecord::Session(self.header()))?;
+        output.push('\n');
+        for entry in &self.entries {
+            output.push_str(&serde_json::to_string(&JsonlRecord::Entry(entry.clone()))?);
+            output.push('\n');
+        }
+        for command in &self.commands {
+            output.push_str(&serde_json::to_string(&JsonlRecord::Command(
+                command.clone(),
+            ))?);
+            output.push('\n');
+        }
+        Ok(output)
+    }
+
+    fn parse_jsonl(text: &str) -> Result<Self> {
+        let mut export: Option<Self> = None;
+        for (number, line) in text.lines().enumerate() {
+            if line.trim().is_empty() {
+                continue;
+            }
+            let record: JsonlRecord = serde_json::from_str(line)
+                .with_context(|| format!("Invalid session export record on line {}", number + 1))?;
+            match (record, export.as_mut()) {
+                (JsonlRecord::Session(header), None) => export = Some(Self::from_header(header)),
+                (JsonlRecord::Session(_), Some(_)) => {
+                    bail!("Duplicate session record on line {}", number + 1)
+                }
+                (_, None) => bail!("Session export must start with a session record"),
+                (JsonlRecord::Entry(entry), Some(export)) => export.entries.push(entry),
+                (JsonlRecord::Command(command), Some(export)) => export.commands.push(command),
+            }
+        }
+        export.context("Session export is empty")
+    }
+
+    fn render_markdown(&self) -> Result<String> {
+        let mut output = format!("# Chat Session Export: {}\n\n", self.session_id);
+        output.push_str(&format!(
+            "{}{}{}\n\n",
+            MARKDOWN_SESSION,
+            serde_json::to_string(&self.header())?,
+            MARKDOWN_COMMENT_END
+        ));
+
+        for exported in &self.entries {
+            // The content is written as Markdown; everything else goes in the marker
+            let mut fields = serde_json::to_value(exported)?;
+            if let Some(fields) = fields.as_object_mut() {
+                fields.remove("content");
+            }
+            output.push_str(&format!(
+                "## {} - {}\n{}{}{}\n\n{}\n\n{}\n\n",
+                exported.entry.role,
+                exported.entry.timestamp.format("%Y-%m-%d %H:%M:%S"),
+                MARKDOWN_ENTRY,
+                serde_json::to_string(&fields)?,
+                MARKDOWN_COMMENT_END,
+                exported.entry.content,
+                MARKDOWN_END
+            ));
+        }
+
+        if !self.commands.is_empty() {
+            output.push_str("## Command History\n\n");
+            for command in &self.commands {
+                output.push_str(&format!(
+                    "{}{}{}\n- `{}`\n",
+                    MARKDOWN_COMMAND,
+                    serde_json::to_string(command)?,
+                    MARKDOWN_COMMENT_END,
+                    command.command
+                ));
+            }
+        }
+        Ok(output)
+    }
+
+    fn parse_markdown(text: &str) -> Result<Self> {
+        let mut export: Option<Self> = None;
+        let mut lines = text.split('\n');
+
+        while let Some(line) = lines.next() {
+            if let Some(json) = marker_json(line, MARKDOWN_SESSION) {
+                let header: SessionHeader =
+                    serde_json::from_str(json).context("Invalid session marker")?;
+                export = Some(Self::from_header(header));
+            } else if let Some(json) = marker_json(line, MARKDOWN_ENTRY) {
+                let export = export
+                    .as_mut()
+                    .context("Entry found before the session marker")?;
+
+                // Content sits between a blank line after the marker and one before the end marker
+                let mut content = Vec::new();
+                let mut closed = false;
+                for line in lines.by_ref() {
+                    if line == MARKDOWN_END {
+                        closed = true;
+                        break;
+                    }
+                    content.push(line);
+                }
+                if !closed {
+                    bail!("Unterminated entry in Markdown session export");
+                }
+                if content.first() == Some(&"") {
+                    content.remove(0);
+                }
+                if content.last() == Some(&"") {
+                    content.pop();
+                }
+
+                let mut fields: serde_json::Value =
+                    serde_json::from_str(json).context("Invalid entry marker")?;
+                fields
+                    .as_object_mut()
+                    .context("Invalid entry marker")?
+                    .insert("content".to_string(), content.join("\n").into());
+                export.entries.push(serde_json::from_value(fields)?);
+            } else if let Some(json) = marker_json(line, MARKDOWN_COMMAND) {
+                let export = export
+                    .as_mut()
+                    .context("Command found before the session marker")?;
+                export
+                    .commands
+                    .push(serde_json::from_str(json).context("Invalid command marker")?);
+            }
+        }
+
+        export.context("Not a Vega session export: no session marker found")
+    }
+}
+
+/// Extract the JSON payload of a Markdown marker line
+fn marker_json<'a>(line: &'a str, marker: &str) -> Option<&'a str> {
+    line.strip_prefix(marker)?
+        .strip_suffix(MARKDOWN_COMMENT_END)
+}
+
+/// Outcome of importing a session
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ImportSummary {
+    pub session_id: String,
+    /// Entries added to the store
+    pub entries: usize,
+    /// Entries skipped because an entry with the same id already exists
+    pub skipped: usize,
+    /// Embeddings taken from the export
+    pub embeddings: usize,
+    /// Entries embedded with the store's embedding service during the import
+    pub embedded: usize,
+    /// Commands added to the session's command history
+    pub commands: usize,
+}
+
+impl ContextStore {
+    /// Export a session's entries and command history.
+    ///
+    /// With `include_embeddings`, embeddings comparable with the store's model
+    /// are included as well; other entries are exported without one.
+    pub async fn export_session(
+        &self,
+        session_id: &str,
+        include_embeddings: bool,
+    ) -> Result<SessionExport> {
+        let entries = self.get_session_history(session_id, None).await?;
+        if entries.is_empty() {
+            bail!("Session '{}' not found", session_id);
+        }
+
+        let conn = self.connection.lock().unwrap();
+
+        let mut embeddings = std::collections::HashMap::new();
+        if include_embeddings {
+            let mut params: Vec<Box<dyn rusqlite::ToSql>> = vec![Box::new(session_id.to_string())];
+            let condition = self.compatible_embeddings(&mut params);
+            let param_refs: Vec<&dyn rusqlite::ToSql> = params.iter().map(|p| p.as_ref()).collect();
+            let mut stmt = conn.prepare(&format!(
+                "SELECT e.entry_id, e.embedding FROM embeddings e
+                 JOIN context_entries ce ON ce.id = e.entry_id
+                 WHERE ce.session_id = ?1 AND {}",
+                condition
+            ))?;
+            let rows = stmt.query_map(&param_refs[..], |row| {
+                Ok((
+                    row.get::<_, String>(0)?,
+                    decode_vector(&row.get::<_, Vec<u8>>(1)?),
+                ))
+            })?;
+            for row in rows {
+                let (entry_id, embedding) = row?;
+                embeddings.insert(entry_id, embedding);
+            }
+        }
+
+        let mut stmt = conn.prepare(
+            "SELECT id, command, timestamp FROM command_history
+             WHERE session_id = ?1
+             ORDER BY timestamp ASC, rowid ASC",
+        )?;
+        let commands = stmt
+            .query_map(params![session_id], |row| {
+                Ok(ExportedCommand {
+                    id: row.get(0)?,
+                    command: row.get(1)?,
+                    timestamp: DateTime::from_timestamp(row.get::<_, i64>(2)?, 0)
+                        .unwrap_or_else(Utc::now),
+                })
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let entries = entries
+            .into_iter()
+            .map(|entry| ExportedEntry {
+                embedding: embeddings.remove(&entry.id),
+                entry,
+            })
+            .collect();
+
+        Ok(SessionExport {
+            version: EXPORT_FORMAT_VERSION,
+            session_id: session_id.to_string(),
+            exported_at: Utc::now(),
+            embedding_model: include_embeddings.then(|| self.embedding_model.clone()),
+            entries,
+            commands,
+        })
+    }
+
+    /// Import a session export into the store.
+    ///
+    /// Entries and commands that already exist (by id) are skipped, so
+    /// importing the same export twice is harmless. Exported embeddings are
+    /// kept when they are comparable with the store's model; other new entries
+    /// are embedded with `service` if given, or left for `vega reindex`.
+    pub async fn import_session(
+        &self,
+        export: &SessionExport,
+        service: Option<&EmbeddingService>,
+    ) -> Result<ImportSummary> {
+        let mut summary = ImportSummary {
+            session_id: export.session_id.clone(),
+            ..ImportSummary::default()
+        };
+
+        let embedding_model = export
+            .embedding_model
+            .as_ref()
+            .filter(|model| model.is_compatible_with(&self.embedding_model));
+
+        let mut unembedded = Vec::new();
+        {
+            let mut conn = self.connection.lock().unwrap();
+            let tx = conn.transaction()?;
+            for exported in &export.entries {
+                let entry = &exported.entry;
+                if entry.session_id != export.session_id {
+                    bail!(
+                        "Entry {} belongs to session '{}', not '{}'",
+                        entry.id,
+                        entry.session_id,
+                        export.session_id
+                    );
+                }
+
+                let inserted = tx.execute(
+                    "INSERT OR IGNORE INTO context_entries (id, agent_name, session_id, timestamp, content, role, metadata)
+                     VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+                    params![
+                        entry.id,
+                        entry.agent_name,
+                        entry.session_id,
+                        entry.timestamp.timestamp(),
+                        entry.content,
+                        entry.role,
+                        serde_json::to_string(&entry.metadata)?
+                    ],
+                )?;
+                if inserted == 0 {
+                    summary.skipped += 1;
+                    continue;
+                }
+                summary.entries += 1;
+
+                match (embedding_model, &exported.embedding) {
+                    (Some(model), Some(embedding)) if embedding.len() == model.dimension => {
+                        tx.execute(
+                            "INSERT OR REPLACE INTO embeddings (entry_id, embedding, provider, model, dimension)
+                             VALUES (?1, ?2, ?3, ?4, ?5)",
+                            params![
+                                entry.id,
+                                encode_vector(embedding),
+                                model.provider,
+                                model.model,
+                                embedding.len() as i64
+                            ],
+                        )?;
+                        summary.embeddings += 1;
+                    }
+                    _ => unembedded.push(entry),
+                }
+            }
+
+            for command in &export.commands {
+                summary.commands += tx.execute(
+                    "INSERT OR IGNORE INTO command_history (id, session_id, command, timestamp)
+                     VALUES (?1, ?2, ?3, ?4)",
+                    params![
+                        command.id,
+                        export.session_id,
+                        command.command,
+                        command.timestamp.timestamp()
+                    ],
+                )?;
+            }
+            tx.commit()?;
+        }
+
+        if let Some(service) = service
+            && service.model_info() == self.embedding_model
+        {
+            for batch in unembedded.chunks(IMPORT_EMBEDDING_BATCH) {
+                let texts: Vec<String> = batch.iter().map(|entry| entry.content.clone()).collect();
+                let embeddings = match service.embed_batch(&texts).await {
+                    Ok(embeddings) => embeddings,
+                    Err(e) => {
+                        warn!(
+                            "Failed to embed imported entries, run `vega reindex` to embed them: {}",
+                            e
+                        );
+                        break;
+                    }
+                };
+                let replacements: Vec<(String, Vec<f32>)> = batch
+                    .iter()
+                    .map(|entry| entry.id.clone())
+                    .zip(embeddings)
+                    .collect();
+                self.replace_embeddings(&replacements).await?;
+                summary.embedded += replacements.len();
+            }
+        }
+
+        if summary.embeddings + summary.embedded > 0 {
+            self.rebuild_vector_index().await?;
+        }
+
+        info!(
+            "Imported session {}: {} entries ({} skipped), {} commands",
+            summary.session_id, summary.entries, summary.skipped, summary.commands
+        );
+        Ok(summary)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::embeddings::EmbeddingProvider;
+    use std::collections::HashMap;
+    use tempfile::tempdir;
+
+    async fn sample_store(path: &Path) -> ContextStore {
+        let store = ContextStore::new(path, 4).await.unwrap();
+        let mut metadata = HashMap::new();
+        metadata.insert("model".to_string(), "llama3.1".to_string());
+        let entries = [
+            ContextEntry::new(
+                "chat".into(),
+                "session-1".into(),
+                "How do I run the tests?\n\n## Not a heading\n".into(),
+                "user".into(),
+            ),
+            ContextEntry::new(
+                "chat".into(),
+                "session-1".into(),
+                "Run `cargo test`.".into(),
+                "assistant".into(),
+            )
+            .with_metadata(metadata),
+        ];
+        for (i, entry) in entries.into_iter().enumerate() {
+            let mut embedding = vec![0.0; 4];
+            embedding[i] = 1.0;
+            store.store_context(entry, embedding).await.unwrap();
+        }
+        store
+            .store_command_history("session-1", "/help")
+            .await
+            .unwrap();
+        store
+    }
+
+    #[test]
+    fn test_export_format_parsing() {
+        assert_eq!(
+            "jsonl".parse::<ExportFormat>().unwrap(),
+            ExportFormat::Jsonl
+        );
+        assert_eq!(
+            "md".parse::<ExportFormat>().unwrap(),
+            ExportFormat::Markdown
+        );
+        assert!("yaml".parse::<ExportFormat>().is_err());
+        assert_eq!(ExportFormat::Markdown.to_string(), "markdown");
+        assert_eq!(
+            ExportFormat::from_path(Path::new("session.NDJSON")),
+            Some(ExportFormat::Jsonl)
+        );
+        assert_eq!(ExportFormat::from_path(Path::new("session")), None);
+    }
+
+    #[tokio::test]
+    async fn test_export_round_trips_in_every_format() {
+        let temp_dir = tempdir().unwrap();
+        let store = sample_store(&temp_dir.path().join("source.db")).await;
+        let export = store.export_session("session-1", true).await.unwrap();
+        assert_eq!(export.entries.len(), 2);
+        assert_eq!(export.commands.len(), 1);
+        assert_eq!(export.entries[0].embedding, Some(vec![1.0, 0.0, 0.0, 0.0]));
+
+        for format in [
+            ExportFormat::Json,
+            ExportFormat::Markdown,
+            ExportFormat::Jsonl,
+        ] {
+            let text = export.render(format).unwrap();
+            let parsed = SessionExport::parse(&text, None).unwrap();
+            assert_eq!(parsed.session_id, "session-1", "{}", format);
+            assert_eq!(parsed.embedding_model, export.embedding_model);
+            assert_eq!(parsed.commands, export.commands);
+            for (parsed, original) in parsed.entries.iter().zip(&export.entries) {
+                assert_eq!(parsed.entry.id, original.entry.id);
+                assert_eq!(parsed.entry.content, original.entry.content, "{}", format);
+                assert_eq!(parsed.entry.metadata, original.entry.metadata);
+                assert_eq!(parsed.entry.timestamp, original.entry.timestamp);
+                assert_eq!(parsed.embedding, original.embedding);
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_import_session() {
+        let temp_dir = tempdir().unwrap();
+        let source = sample_store(&temp_dir.path().join("source.db")).await;
+        let export = source.export_session("session-1", true).await.unwrap();
+
+        let target = ContextStore::new(temp_dir.path().join("target.db"), 4)
+            .await
+            .unwrap();
+        let summary = target.import_session(&export, None).await.unwrap();
+        assert_eq!(summary.entries, 2);
+        assert_eq!(summary.embeddings, 2);
+        assert_eq!(summary.commands, 1);
+
+        let history = target.get_session_history("session-1", None).await.unwrap();
+        assert_eq!(history[1].metadata.get("model").unwrap(), "llama3.1");
+        let commands = target.get_command_history("session-1", None).await.unwrap();
+        assert_eq!(commands, vec!["/help".to_string()]);
+        let similar = target
+            .search_similar(
+                &[0.0, 1.0, 0.0, 0.0],
+                &super::super::ContextFilter::default(),
+                1,
+            )
+            .await
+            .unwrap();
+        assert_eq!(similar[0].0.content, "Run `cargo test`.");
+
+        // Importing again adds nothing
+        let again = target.import_session(&export, None).await.unwrap();
+        assert_eq!(again.entries, 0);
+        assert_eq!(again.skipped, 2);
+        assert_eq!(again.commands, 0);
+    }
+
+    #[tokio::test]
+    async fn test_import_without_embeddings_embeds_entries() {
+        let temp_dir = tempdir().unwrap();
+        let source = sample_store(&temp_dir.path().join("source.db")).await;
+        let export = source.export_session("session-1", false).await.unwrap();
+        assert!(export.entries.iter().all(|e| e.embedding.is_none()));
+
+        let service = EmbeddingProvider::Simple { dimension: 4 }.create_service();
+        let target = ContextStore::with_embedding_model(
+            temp_dir.path().join("target.db"),
+            service.model_info(),
+        )
+        .await
+        .unwrap();
+        let summary = target
+            .import_session(&export, Some(&service))
+            .await
+            .unwrap();
+        assert_eq!(summary.embeddings, 0);
+        assert_eq!(summary.embedded, 2);
+        assert_eq!(target.count_entries_needing_embedding().await.unwrap(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_export_unknown_session_fails() {
+        let temp_dir = tempdir().unwrap();
+        let store = ContextStore::new(temp_dir.path().join("test.db"), 4)
+            .await
+            .unwrap();
+        assert!(store.export_session("missing", false).await.is_err());
+        assert!(SessionExport::parse("# Notes\n\nNothing here", None).is_err());
+    }
+}
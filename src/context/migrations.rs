@@ -0,0 +1,257 @@
+//! Schema migrations for the context database
+//!
+//! Every change to the SQLite schema is an entry in [`MIGRATIONS`], applied in
+//! order inside its own transaction and recorded in the `schema_version` table.
+//! Opening a database runs whichever migrations it has not seen yet, so older
+//! `vega_context.db` files are upgraded in place, while databases written by a
+//! newer version of Vega are refused rather than misread.
+//!
+//! Databases created before versioning existed have no `schema_version` table
+//! and are treated as version 0. Migrations therefore use `IF NOT EXISTS` for
+//! objects that such databases may already contain.
+
+use anyhow::{Result, bail};
+use rusqlite::{Connection, OptionalExtension, params};
+use tracing::info;
+
+/// A single, ordered schema change
+pub struct Migration {
+    /// Version the database is at once this migration has been applied
+    pub version: u32,
+    /// Short description recorded alongside the version
+    pub description: &'static str,
+    /// SQL statements making up the migration
+    pub sql: &'static str,
+}
+
+/// All schema migrations, in the order they are applied
+pub const MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        description: "context entries, embeddings and command history",
+        sql: "CREATE TABLE IF NOT EXISTS context_entries (
+                id TEXT PRIMARY KEY,
+                agent_name TEXT NOT NULL,
+                session_id TEXT NOT NULL,
+                timestamp INTEGER NOT NULL,
+                content TEXT NOT NULL,
+                role TEXT NOT NULL,
+                metadata TEXT NOT NULL
+            );
+            CREATE TABLE IF NOT EXISTS embeddings (
+                entry_id TEXT PRIMARY KEY,
+                embedding BLOB NOT NULL,
+                FOREIGN KEY(entry_id) REFERENCES context_entries(id)
+            );
+            CREATE TABLE IF NOT EXISTS command_history (
+                id TEXT PRIMARY KEY,
+                session_id TEXT NOT NULL,
+                command TEXT NOT NULL,
+                timestamp INTEGER NOT NULL
+            );
+            CREATE INDEX IF NOT EXISTS idx_session_id ON context_entries(session_id);
+            CREATE INDEX IF NOT EXISTS idx_timestamp ON context_entries(timestamp);
+            CREATE INDEX IF NOT EXISTS idx_command_session_id ON command_history(session_id);
+            CREATE INDEX IF NOT EXISTS idx_command_timestamp ON command_history(timestamp);",
+    },
+    Migration {
+        version: 2,
+        description: "vector index centroids and list assignments",
+        sql: "CREATE TABLE IF NOT EXISTS vector_index_centroids (
+                list_id INTEGER PRIMARY KEY,
+                centroid BLOB NOT NULL
+            );
+            CREATE TABLE IF NOT EXISTS vector_index_lists (
+                entry_id TEXT PRIMARY KEY,
+                list_id INTEGER NOT NULL,
+                FOREIGN KEY(entry_id) REFERENCES context_entries(id)
+            );
+            CREATE INDEX IF NOT EXISTS idx_vector_index_list ON vector_index_lists(list_id);",
+    },
+    Migration {
+        version: 3,
+        description: "full-text index over context entry content",
+        // The index is keyed by an INTEGER PRIMARY KEY mapped to each entry id,
+        // as the implicit rowid of context_entries may change on VACUUM; its
+        // content is read from the entries through a view
+        sql: "CREATE TABLE IF NOT EXISTS context_fts_entries (
+                fts_id INTEGER PRIMARY KEY,
+                entry_id TEXT NOT NULL UNIQUE
+            );
+            CREATE VIEW IF NOT EXISTS context_fts_content AS
+                SELECT f.fts_id, ce.content FROM context_fts_entries f
+                JOIN context_entries ce ON ce.id = f.entry_id;
+            CREATE VIRTUAL TABLE IF NOT EXISTS context_fts USING fts5(
+                content,
+                content='context_fts_content',
+                content_rowid='fts_id'
+            );
+            CREATE TRIGGER IF NOT EXISTS context_fts_insert AFTER INSERT ON context_entries BEGIN
+                INSERT INTO context_fts_entries(entry_id) VALUES (new.id);
+                INSERT INTO context_fts(rowid, content)
+                    SELECT fts_id, new.content FROM context_fts_entries WHERE entry_id = new.id;
+            END;
+            CREATE TRIGGER IF NOT EXISTS context_fts_delete AFTER DELETE ON context_entries BEGIN
+                INSERT INTO context_fts(context_fts, rowid, content)
+                    SELECT 'delete', fts_id, old.content FROM context_fts_entries WHERE entry_id = old.id;
+                DELETE FROM context_fts_entries WHERE entry_id = old.id;
+            END;
+            CREATE TRIGGER IF NOT EXISTS context_fts_update AFTER UPDATE OF content ON context_entries BEGIN
+                INSERT INTO context_fts(context_fts, rowid, content)
+                    SELECT 'delete', fts_id, old.content FROM context_fts_entries WHERE entry_id = old.id;
+                INSERT INTO context_fts(rowid, content)
+                    SELECT fts_id, new.content FROM context_fts_entries WHERE entry_id = old.id;
+            END;
+            INSERT OR IGNORE INTO context_fts_entries(entry_id) SELECT id FROM context_entries;
+            INSERT INTO context_fts(context_fts) VALUES ('rebuild');",
+    },
+    Migration {
+        version: 4,
+        description: "embedding provider, model and dimension tags",
+        // Existing embeddings are left untagged (matched by dimension only) until
+        // they are re-embedded, and the untagged vector index is discarded
+        sql: "ALTER TABLE embeddings ADD COLUMN provider TEXT NOT NULL DEFAULT '';
+            ALTER TABLE embeddings ADD COLUMN model TEXT NOT NULL DEFAULT '';
+            ALTER TABLE embeddings ADD COLUMN dimension INTEGER NOT NULL DEFAULT 0;
+            UPDATE embeddings SET dimension = length(embedding) / 4;
+            CREATE INDEX IF NOT EXISTS idx_embeddings_model ON embeddings(provider, model, dimension);
+            CREATE TABLE IF NOT EXISTS vector_index_model (
+                id INTEGER PRIMARY KEY CHECK (id = 0),
+                provider TEXT NOT NULL,
+                model TEXT NOT NULL,
+                dimension INTEGER NOT NULL
+            );
+            DELETE FROM vector_index_lists;
+            DELETE FROM vector_index_centroids;",
+    },
+    Migration {
+        version: 5,
+        description: "checkpoints of the files changed by the agent",
+        sql: "CREATE TABLE IF NOT EXISTS checkpoints (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                session_id TEXT NOT NULL,
+                turn_id TEXT NOT NULL UNIQUE,
+                prompt TEXT NOT NULL,
+                timestamp INTEGER NOT NULL
+            );
+            CREATE TABLE IF NOT EXISTS checkpoint_files (
+                checkpoint_id INTEGER NOT NULL,
+                path TEXT NOT NULL,
+                content BLOB,
+                directory INTEGER NOT NULL DEFAULT 0,
+                PRIMARY KEY (checkpoint_id, path),
+                FOREIGN KEY(checkpoint_id) REFERENCES checkpoints(id)
+            );
+            CREATE INDEX IF NOT EXISTS idx_checkpoints_session ON checkpoints(session_id);",
+    },
+];
+
+/// Schema version produced by the latest migration
+pub const SCHEMA_VERSION: u32 = MIGRATIONS[MIGRATIONS.len() - 1].version;
+
+/// Read the schema version of a database (0 if it predates versioning)
+pub fn current_version(conn: &Connection) -> Result<u32> {
+    let versioned: bool = conn.query_row(
+        "SELECT COUNT(*) > 0 FROM sqlite_master WHERE type = 'table' AND name = 'schema_version'",
+        [],
+        |row| row.get(0),
+    )?;
+    if !versioned {
+        return Ok(0);
+    }
+
+    let version: Option<u32> = conn
+        .query_row("SELECT MAX(version) FROM schema_version", [], |row| {
+            row.get(0)
+        })
+        .optional()?
+        .flatten();
+    Ok(version.unwrap_or(0))
+}
+
+/// Bring a database up to [`SCHEMA_VERSION`], returning the version it started at.
+///
+/// Fails without modifying the database if it was written by a newer version.
+pub fn migrate(conn: &mut Connection) -> Result<u32> {
+    let initial = current_version(conn)?;
+    if initial > SCHEMA_VERSION {
+        bail!(
+            "Context database schema version {} is newer than the latest supported version {}. \
+             Upgrade Vega or use a different --context-db.",
+            initial,
+            SCHEMA_VERSION
+        );
+    }
+
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS schema_version (
+            version INTEGER PRIMARY KEY,
+            description TEXT NOT NULL,
+            applied_at INTEGER NOT NULL
+        )",
+        [],
+    )?;
+
+    for migration in MIGRATIONS.iter().filter(|m| m.version > initial) {
+        let tx = conn.transaction()?;
+        tx.execute_batch(migration.sql)?;
+        tx.execute(
+            "INSERT INTO schema_version (version, description, applied_at) VALUES (?1, ?2, ?3)",
+            params![
+                migration.version,
+                migration.description,
+                chrono::Utc::now().timestamp()
+            ],
+        )?;
+        tx.commit()?;
+
+        info!(
+            "Applied context schema migration {}: {}",
+            migration.version, migration.description
+        );
+    }
+
+    Ok(initial)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_migrations_are_contiguous() {
+        for (i, migration) in MIGRATIONS.iter().enumerate() {
+            assert_eq!(migration.version as usize, i + 1);
+        }
+    }
+
+    #[test]
+    fn test_migrate_fresh_database() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        assert_eq!(current_version(&conn).unwrap(), 0);
+
+        assert_eq!(migrate(&mut conn).unwrap(), 0);
+        assert_eq!(current_version(&conn).unwrap(), SCHEMA_VERSION);
+
+        // Running again is a no-op
+        assert_eq!(migrate(&mut conn).unwrap(), SCHEMA_VERSION);
+        let applied: u32 = conn
+            .query_row("SELECT COUNT(*) FROM schema_version", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(applied, SCHEMA_VERSION);
+    }
+
+    #[test]
+    fn test_migrate_refuses_newer_database() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        migrate(&mut conn).unwrap();
+        conn.execute(
+            "INSERT INTO schema_version (version, description, applied_at) VALUES (?1, 'future', 0)",
+            params![SCHEMA_VERSION + 1],
+        )
+        .unwrap();
+
+        let error = migrate(&mut conn).unwrap_err();
+        assert!(error.to_string().contains("newer"));
+    }
+}
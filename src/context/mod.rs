@@ -0,0 +1,1686 @@
+//! # Context Management System
+//!
+//! This module provides persistent conversation context storage and retrieval
+//! using SQLite with vector embeddings for semantic search. It enables agents
+//! to maintain conversation history and find relevant context across sessions.
+//!
+//! ## Features
+//!
+//! - **Persistent Storage**: SQLite-based storage for conversation history
+//! - **Vector Embeddings**: Semantic search using cosine similarity
+//! - **Vector Index**: Persistent IVF index so searches avoid full table scans
+//! - **Full-Text Search**: FTS5 index with BM25 ranking, fused with semantic
+//!   results for hybrid retrieval
+//! - **Schema Migrations**: Versioned schema upgraded in place on open
+//! - **Embedding Models**: Embeddings tagged with the model that produced them,
+//!   with batch re-embedding when the model changes
+//! - **Session Management**: Organize conversations by agent and session
+//! - **Session Export**: Move sessions between databases as JSON, JSON Lines
+//!   or Markdown
+//! - **Checkpoints**: The content files had before the agent changed them,
+//!   per turn, so the changes can be undone
+//! - **Metadata Support**: Attach custom metadata to context entries
+//! - **Cross-Agent Context**: Share context between different agent instances
+//!
+//! ## Example Usage
+//!
+//! ```rust,no_run
+//! use vega::context::{ContextStore, ContextEntry};
+//!
+//! #[tokio::main]
+//! async fn main() -> anyhow::Result<()> {
+//!     let store = ContextStore::new("context.db", 384).await?;
+//!     
+//!     // Add context entry
+//!     let entry = ContextEntry::new(
+//!         "vega".to_string(),
+//!         "session-123".to_string(),
+//!         "Hello, how can I help you?".to_string(),
+//!         "assistant".to_string()
+//!     );
+//!     
+//!     let embedding = vec![0.1; 384]; // Would be generated by embedding service
+//!     store.add_context(&entry, &embedding).await?;
+//!     
+//!     // Search for similar context
+//!     let query_embedding = vec![0.1; 384];
+//!     let results = store.search_similar_context(&query_embedding, 5, None).await?;
+//!     
+//!     Ok(())
+//! }
+//! ```
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use rusqlite::{Connection, OptionalExtension, params};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use tracing::{debug, info, warn};
+use uuid::Uuid;
+
+pub mod checkpoints;
+pub mod export;
+pub mod migrations;
+
+pub use checkpoints::{Checkpoint, FileSnapshot};
+pub use export::{ExportFormat, ImportSummary, SessionExport};
+pub use migrations::SCHEMA_VERSION;
+
+use crate::embeddings::{EmbeddingModelInfo, EmbeddingService};
+use crate::vector_index::{
+    DEFAULT_NPROBE, IvfIndex, MIN_TRAINING_VECTORS, decode_vector, encode_vector,
+};
+
+/// Filtered collections at or below this size are scored exhaustively
+const EXACT_SEARCH_THRESHOLD: usize = 1024;
+
+/// Maximum number of embeddings sampled to train the vector index
+const MAX_TRAINING_SAMPLE: usize = 16384;
+
+/// Rank offset for reciprocal rank fusion (the conventional value from the RRF paper)
+const RRF_K: f32 = 60.0;
+
+/// Candidates fetched from each ranking per requested hybrid result
+const HYBRID_CANDIDATE_FACTOR: usize = 4;
+
+/// SQL condition selecting entries (aliased `ce`, left-joined to embeddings `e`)
+/// without an embedding tagged with exactly the model bound to `?1`, `?2`, `?3`
+const NEEDS_EMBEDDING: &str =
+    "e.entry_id IS NULL OR e.provider != ?1 OR e.model != ?2 OR e.dimension != ?3";
+
+/// Represents a single context entry in the conversation history.
+///
+/// Context entries store individual messages or interactions along with
+/// their metadata, embeddings, and session information for later retrieval
+/// and semantic search.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ContextEntry {
+    /// Unique identifier for this context entry
+    pub id: String,
+    /// Name of the agent that created this entry
+    pub agent_name: String,
+    /// Session ID this entry belongs to
+    pub session_id: String,
+    /// When this entry was created
+    pub timestamp: DateTime<Utc>,
+    /// The actual content/message text
+    pub content: String,
+    /// Role of the message sender ("user" or "assistant"), or "summary" for
+    /// conversation summaries
+    pub role: String,
+    /// Additional metadata as key-value pairs
+    pub metadata: HashMap<String, String>,
+}
+
+impl ContextEntry {
+    /// Creates a new context entry with the specified parameters.
+    ///
+    /// # Arguments
+    ///
+    /// * `agent_name` - Name of the agent creating this entry
+    /// * `session_id` - Session identifier this entry belongs to
+    /// * `content` - The message or content text
+    /// * `role` - Role of the sender ("user" or "assistant")
+    ///
+    /// # Returns
+    ///
+    /// A new `ContextEntry` with a generated UUID and current timestamp.
+    pub fn new(agent_name: String, session_id: String, content: String, role: String) -> Self {
+        Self {
+            id: Uuid::new_v4().to_string(),
+            agent_name,
+            session_id,
+            timestamp: Utc::now(),
+            content,
+            role,
+            metadata: HashMap::new(),
+        }
+    }
+
+    /// Build a context entry from a row whose first seven columns are
+    /// `id, agent_name, session_id, timestamp, content, role, metadata`
+    fn from_row(row: &rusqlite::Row<'_>) -> rusqlite::Result<Self> {
+        let metadata_json: String = row.get(6)?;
+        let metadata: HashMap<String, String> =
+            serde_json::from_str(&metadata_json).unwrap_or_default();
+
+        let timestamp = DateTime::from_timestamp(row.get::<_, i64>(3)?, 0).unwrap_or_else(Utc::now);
+
+        Ok(ContextEntry {
+            id: row.get(0)?,
+            agent_name: row.get(1)?,
+            session_id: row.get(2)?,
+            timestamp,
+            content: row.get(4)?,
+            role: row.get(5)?,
+            metadata,
+        })
+    }
+
+    /// Adds metadata to this context entry.
+    ///
+    /// # Arguments
+    ///
+    /// * `metadata` - Key-value pairs to attach to this entry
+    ///
+    /// # Returns
+    ///
+    /// The modified context entry with the new metadata.
+    pub fn with_metadata(mut self, metadata: HashMap<String, String>) -> Self {
+        self.metadata = metadata;
+        self
+    }
+}
+
+/// Context store for managing conversation history and cross-agent context.
+///
+/// Uses SQLite for persistent single-file storage with vector embeddings for
+/// semantic similarity search via cosine distance. This enables agents to
+/// maintain conversation context and find relevant historical interactions.
+///
+/// # Features
+///
+/// - Persistent SQLite storage
+/// - Vector embedding storage and search
+/// - Session-based organization
+/// - Cross-agent context sharing
+/// - Metadata support
+#[derive(Clone)]
+pub struct ContextStore {
+    connection: Arc<Mutex<Connection>>,
+    embedding_model: EmbeddingModelInfo,
+    vector_index: Arc<Mutex<VectorIndexState>>,
+}
+
+/// In-memory view of the persisted vector index
+#[derive(Default)]
+struct VectorIndexState {
+    /// Trained index, if enough embeddings have been stored
+    index: Option<IvfIndex>,
+    /// Number of stored embeddings comparable with the store's model
+    entries: usize,
+    /// Whether a background rebuild has been started and not finished yet
+    rebuilding: bool,
+}
+
+/// Filters applied to context entries before similarity scoring
+#[derive(Debug, Clone, Default)]
+pub struct ContextFilter {
+    /// Only consider entries from this session
+    pub session_id: Option<String>,
+    /// Only consider entries created by this agent
+    pub agent_name: Option<String>,
+    /// Only consider entries with this role
+    pub role: Option<String>,
+}
+
+impl ContextFilter {
+    /// Create a filter matching a single session
+    pub fn session(session_id: &str) -> Self {
+        Self {
+            session_id: Some(session_id.to_string()),
+            ..Self::default()
+        }
+    }
+
+    /// Restrict the filter to entries created by an agent
+    pub fn with_agent(mut self, agent_name: &str) -> Self {
+        self.agent_name = Some(agent_name.to_string());
+        self
+    }
+
+    /// Restrict the filter to entries with a role
+    pub fn with_role(mut self, role: &str) -> Self {
+        self.role = Some(role.to_string());
+        self
+    }
+
+    /// Build the SQL conditions for this filter, appending their parameters
+    fn conditions(&self, params: &mut Vec<Box<dyn rusqlite::ToSql>>) -> Vec<String> {
+        let mut conditions = Vec::new();
+        for (column, value) in [
+            ("ce.session_id", &self.session_id),
+            ("ce.agent_name", &self.agent_name),
+            ("ce.role", &self.role),
+        ] {
+            if let Some(value) = value {
+                params.push(Box::new(value.clone()));
+                conditions.push(format!("{} = ?{}", column, params.len()));
+            }
+        }
+        conditions
+    }
+}
+
+impl ContextStore {
+    /// Create a new context store with the specified database path.
+    ///
+    /// Embeddings stored through this store are untagged and compared with any
+    /// stored embedding of the same dimension; use
+    /// [`ContextStore::with_embedding_model`] to record which model produced them.
+    pub async fn new<P: AsRef<Path>>(db_path: P, embedding_dim: usize) -> Result<Self> {
+        Self::with_embedding_model(db_path, EmbeddingModelInfo::untagged(embedding_dim)).await
+    }
+
+    /// Create a context store whose embeddings come from `embedding_model`.
+    ///
+    /// Similarity search only compares embeddings produced by the same model;
+    /// entries embedded by other models can be re-embedded with [`ContextStore::reindex`].
+    pub async fn with_embedding_model<P: AsRef<Path>>(
+        db_path: P,
+        embedding_model: EmbeddingModelInfo,
+    ) -> Result<Self> {
+        let connection =
+            Connection::open(db_path.as_ref()).context("Failed to open SQLite database")?;
+
+        let store = Self {
+            connection: Arc::new(Mutex::new(connection)),
+            embedding_model,
+            vector_index: Arc::new(Mutex::new(VectorIndexState::default())),
+        };
+
+        store.initialize_tables().await?;
+        store.load_vector_index()?;
+        Ok(store)
+    }
+
+    /// Bring the database schema up to date, upgrading older databases in place
+    async fn initialize_tables(&self) -> Result<()> {
+        let mut conn = self.connection.lock().unwrap();
+
+        let initial = migrations::migrate(&mut conn)?;
+        if initial < SCHEMA_VERSION {
+            info!(
+                "Context database upgraded from schema version {} to {}",
+                initial, SCHEMA_VERSION
+            );
+        }
+
+        info!("Context store tables initialized");
+        Ok(())
+    }
+
+    /// Schema version of the underlying database
+    pub fn schema_version(&self) -> Result<u32> {
+        let conn = self.connection.lock().unwrap();
+        migrations::current_version(&conn)
+    }
+
+    /// The model whose embeddings this store stores and compares
+    pub fn embedding_model(&self) -> &EmbeddingModelInfo {
+        &self.embedding_model
+    }
+
+    /// Build the SQL condition selecting embeddings (aliased `e`) comparable with
+    /// the store's model, appending its parameters.
+    ///
+    /// Dimensions must always match; untagged embeddings (or an untagged store)
+    /// are otherwise compared by dimension alone.
+    fn compatible_embeddings(&self, params: &mut Vec<Box<dyn rusqlite::ToSql>>) -> String {
+        params.push(Box::new(self.embedding_model.dimension as i64));
+        let dimension = params.len();
+        params.push(Box::new(self.embedding_model.provider.clone()));
+        let provider = params.len();
+        params.push(Box::new(self.embedding_model.model.clone()));
+        let model = params.len();
+        format!(
+            "e.dimension = ?{dimension} AND (?{provider} = '' OR e.provider = '' \
+             OR (e.provider = ?{provider} AND e.model = ?{model}))"
+        )
+    }
+
+    /// Count the stored embeddings comparable with the store's model
+    fn count_compatible_embeddings(&self, conn: &Connection) -> Result<usize> {
+        let mut params = Vec::new();
+        let condition = self.compatible_embeddings(&mut params);
+        let param_refs: Vec<&dyn rusqlite::ToSql> = params.iter().map(|p| p.as_ref()).collect();
+        let count: i64 = conn.query_row(
+            &format!("SELECT COUNT(*) FROM embeddings e WHERE {}", condition),
+            &param_refs[..],
+            |row| row.get(0),
+        )?;
+        Ok(count as usize)
+    }
+
+    /// Load the persisted vector index into memory
+    fn load_vector_index(&self) -> Result<()> {
+        let conn = self.connection.lock().unwrap();
+
+        let entries = self.count_compatible_embeddings(&conn)?;
+
+        // An index trained on another model's embeddings is ignored until rebuilt
+        let index_model = conn
+            .query_row(
+                "SELECT provider, model, dimension FROM vector_index_model WHERE id = 0",
+                [],
+                |row| {
+                    Ok(EmbeddingModelInfo::new(
+                        row.get::<_, String>(0)?,
+                        row.get::<_, String>(1)?,
+                        row.get::<_, i64>(2)? as usize,
+                    ))
+                },
+            )
+            .optional()?;
+
+        let centroids = if index_model.as_ref() == Some(&self.embedding_model) {
+            let mut stmt =
+                conn.prepare("SELECT centroid FROM vector_index_centroids ORDER BY list_id ASC")?;
+            stmt.query_map([], |row| Ok(decode_vector(&row.get::<_, Vec<u8>>(0)?)))?
+                .collect::<Result<Vec<_>, _>>()?
+        } else {
+            Vec::new()
+        };
+
+        let mut state = self.vector_index.lock().unwrap();
+        state.entries = entries;
+        state.index = if centroids.is_empty() {
+            None
+        } else {
+            Some(IvfIndex::new(centroids))
+        };
+
+        debug!(
+            "Loaded vector index with {} lists over {} embeddings",
+            state.index.as_ref().map_or(0, IvfIndex::len),
+            state.entries
+        );
+        Ok(())
+    }
+
+    /// Rebuild the vector index from the stored embeddings.
+    ///
+    /// Trains new centroids over the embeddings comparable with the store's model
+    /// and reassigns those entries. Returns the number of lists in the new index,
+    /// or zero when there are too few embeddings to index.
+    ///
+    /// Training runs on a blocking thread without holding the connection, so
+    /// the store stays usable meanwhile; entries stored during training are
+    /// assigned to the new index when it is written.
+    pub async fn rebuild_vector_index(&self) -> Result<usize> {
+        let rows: Vec<(String, Vec<f32>)> = {
+            let conn = self.connection.lock().unwrap();
+            let mut params = Vec::new();
+            let condition = self.compatible_embeddings(&mut params);
+            let param_refs: Vec<&dyn rusqlite::ToSql> = params.iter().map(|p| p.as_ref()).collect();
+            let mut stmt = conn.prepare(&format!(
+                "SELECT e.entry_id, e.embedding FROM embeddings e WHERE {}",
+                condition
+            ))?;
+            stmt.query_map(&param_refs[..], |row| {
+                Ok((
+                    row.get::<_, String>(0)?,
+                    decode_vector(&row.get::<_, Vec<u8>>(1)?),
+                ))
+            })?
+            .collect::<Result<Vec<_>, _>>()?
+        };
+
+        let (index, assignments) = tokio::task::spawn_blocking(move || {
+            if rows.len() < MIN_TRAINING_VECTORS {
+                return (None, Vec::new());
+            }
+            let stride = rows.len().div_ceil(MAX_TRAINING_SAMPLE);
+            let sample: Vec<Vec<f32>> = rows
+                .iter()
+                .step_by(stride)
+                .map(|(_, embedding)| embedding.clone())
+                .collect();
+            let Some(index) = IvfIndex::train(&sample, IvfIndex::num_lists_for(rows.len())) else {
+                return (None, Vec::new());
+            };
+            let assignments: Vec<(String, usize)> = rows
+                .into_iter()
+                .map(|(entry_id, embedding)| {
+                    let list_id = index.assign(&embedding);
+                    (entry_id, list_id)
+                })
+                .collect();
+            (Some(index), assignments)
+        })
+        .await?;
+
+        let mut conn = self.connection.lock().unwrap();
+        let tx = conn.transaction()?;
+        tx.execute("DELETE FROM vector_index_lists", [])?;
+        tx.execute("DELETE FROM vector_index_centroids", [])?;
+        tx.execute("DELETE FROM vector_index_model", [])?;
+        if let Some(ref index) = index {
+            tx.execute(
+                "INSERT INTO vector_index_model (id, provider, model, dimension) VALUES (0, ?1, ?2, ?3)",
+                params![
+                    self.embedding_model.provider,
+                    self.embedding_model.model,
+                    self.embedding_model.dimension as i64
+                ],
+            )?;
+            for (list_id, centroid) in index.centroids().iter().enumerate() {
+                tx.execute(
+                    "INSERT INTO vector_index_centroids (list_id, centroid) VALUES (?1, ?2)",
+                    params![list_id as i64, encode_vector(centroid)],
+                )?;
+            }
+            // Entries deleted during training are skipped
+            for (entry_id, list_id) in &assignments {
+                tx.execute(
+                    "INSERT INTO vector_index_lists (entry_id, list_id)
+                     SELECT ?1, ?2 WHERE EXISTS (SELECT 1 FROM embeddings WHERE entry_id = ?1)",
+                    params![entry_id, *list_id as i64],
+                )?;
+            }
+
+            // Entries stored during training
+            let mut params = Vec::new();
+            let condition = self.compatible_embeddings(&mut params);
+            let param_refs: Vec<&dyn rusqlite::ToSql> = params.iter().map(|p| p.as_ref()).collect();
+            let mut stmt = tx.prepare(&format!(
+                "SELECT e.entry_id, e.embedding FROM embeddings e
+                 LEFT JOIN vector_index_lists l ON l.entry_id = e.entry_id
+                 WHERE l.entry_id IS NULL AND {}",
+                condition
+            ))?;
+            let added: Vec<(String, Vec<f32>)> = stmt
+                .query_map(&param_refs[..], |row| {
+                    Ok((
+                        row.get::<_, String>(0)?,
+                        decode_vector(&row.get::<_, Vec<u8>>(1)?),
+                    ))
+                })?
+                .collect::<Result<Vec<_>, _>>()?;
+            drop(stmt);
+            for (entry_id, embedding) in &added {
+                tx.execute(
+                    "INSERT INTO vector_index_lists (entry_id, list_id) VALUES (?1, ?2)",
+                    params![entry_id, index.assign(embedding) as i64],
+                )?;
+            }
+        }
+        let entries = self.count_compatible_embeddings(&tx)?;
+        tx.commit()?;
+
+        let lists = index.as_ref().map_or(0, IvfIndex::len);
+        let mut state = self.vector_index.lock().unwrap();
+        state.entries = entries;
+        state.index = index;
+
+        info!(
+            "Rebuilt vector index with {} lists over {} embeddings",
+            lists, entries
+        );
+        Ok(lists)
+    }
+
+    /// Store a context entry with its embedding
+    pub async fn store_context(&self, entry: ContextEntry, embedding: Vec<f32>) -> Result<()> {
+        self.check_dimension(&embedding)?;
+
+        let needs_rebuild = {
+            let conn = self.connection.lock().unwrap();
+
+            // Store context entry
+            conn.execute(
+                "INSERT INTO context_entries (id, agent_name, session_id, timestamp, content, role, metadata)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+                params![
+                    entry.id,
+                    entry.agent_name,
+                    entry.session_id,
+                    entry.timestamp.timestamp(),
+                    entry.content,
+                    entry.role,
+                    serde_json::to_string(&entry.metadata)?
+                ],
+            )?;
+
+            // Store embedding as binary data, tagged with the model that produced it
+            conn.execute(
+                "INSERT INTO embeddings (entry_id, embedding, provider, model, dimension)
+                 VALUES (?1, ?2, ?3, ?4, ?5)",
+                params![
+                    entry.id,
+                    encode_vector(&embedding),
+                    self.embedding_model.provider,
+                    self.embedding_model.model,
+                    embedding.len() as i64
+                ],
+            )?;
+
+            // Add the entry to the vector index, retraining once the collection has
+            // outgrown it (the ideal list count has doubled since training)
+            let mut state = self.vector_index.lock().unwrap();
+            state.entries += 1;
+            let outgrown = match state.index {
+                Some(ref index) => {
+                    conn.execute(
+                        "INSERT INTO vector_index_lists (entry_id, list_id) VALUES (?1, ?2)",
+                        params![entry.id, index.assign(&embedding) as i64],
+                    )?;
+                    IvfIndex::num_lists_for(state.entries) >= 2 * index.len()
+                }
+                None => state.entries >= MIN_TRAINING_VECTORS,
+            };
+            let needs_rebuild = outgrown && !state.rebuilding;
+            state.rebuilding |= needs_rebuild;
+            needs_rebuild
+        };
+
+        debug!("Stored context entry: {}", entry.id);
+
+        // Retrain in the background rather than making this store wait for it
+        if needs_rebuild {
+            let store = self.clone();
+            tokio::spawn(async move {
+                if let Err(e) = store.rebuild_vector_index().await {
+                    warn!("Failed to rebuild the vector index: {}", e);
+                }
+                store.vector_index.lock().unwrap().rebuilding = false;
+            });
+        }
+        Ok(())
+    }
+
+    /// Retrieve relevant context entries using simple cosine similarity
+    pub async fn get_relevant_context(
+        &self,
+        query_embedding: Vec<f32>,
+        session_id: Option<&str>,
+        limit: usize,
+    ) -> Result<Vec<ContextEntry>> {
+        let filter = ContextFilter {
+            session_id: session_id.map(str::to_string),
+            ..ContextFilter::default()
+        };
+
+        let entries = self
+            .search_similar(&query_embedding, &filter, limit)
+            .await?
+            .into_iter()
+            .map(|(entry, _)| entry)
+            .collect();
+
+        Ok(entries)
+    }
+
+    /// Find the entries most similar to `query_embedding`, with their cosine scores.
+    ///
+    /// The filter is applied in SQL before any vectors are scored. Small filtered
+    /// sets are scored exhaustively; larger ones only score the entries in the
+    /// nearest vector index lists, probing more lists until `limit` is reached.
+    pub async fn search_similar(
+        &self,
+        query_embedding: &[f32],
+        filter: &ContextFilter,
+        limit: usize,
+    ) -> Result<Vec<(ContextEntry, f32)>> {
+        if query_embedding.len() != self.embedding_model.dimension {
+            return Err(anyhow::anyhow!(
+                "Query embedding dimension mismatch: expected {}, got {}",
+                self.embedding_model.dimension,
+                query_embedding.len()
+            ));
+        }
+
+        let conn = self.connection.lock().unwrap();
+        let state = self.vector_index.lock().unwrap();
+
+        let mut count_params = Vec::new();
+        let conditions = filter.conditions(&mut count_params);
+        let where_clause = if conditions.is_empty() {
+            String::new()
+        } else {
+            format!("WHERE {}", conditions.join(" AND "))
+        };
+        let count_refs: Vec<&dyn rusqlite::ToSql> =
+            count_params.iter().map(|p| p.as_ref()).collect();
+        let filtered: i64 = conn.query_row(
+            &format!("SELECT COUNT(*) FROM context_entries ce {}", where_clause),
+            &count_refs[..],
+            |row| row.get(0),
+        )?;
+
+        let mut entries_with_scores = match state.index {
+            Some(ref index) if filtered as usize > EXACT_SEARCH_THRESHOLD => {
+                let mut nprobe = DEFAULT_NPROBE;
+                loop {
+                    let lists = index.probe(query_embedding, nprobe);
+                    let candidates =
+                        self.score_candidates(&conn, query_embedding, filter, Some(&lists))?;
+                    if candidates.len() >= limit || nprobe >= index.len() {
+                        break candidates;
+                    }
+                    nprobe *= 2;
+                }
+            }
+            _ => self.score_candidates(&conn, query_embedding, filter, None)?,
+        };
+
+        // Sort by similarity (descending) and take top N
+        entries_with_scores
+            .sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(std::cmp::Ordering::Equal));
+        entries_with_scores.truncate(limit);
+
+        debug!(
+            "Retrieved {} relevant context entries",
+            entries_with_scores.len()
+        );
+        Ok(entries_with_scores)
+    }
+
+    /// Find entries matching the words in `query` using the full-text index.
+    ///
+    /// Each whitespace-separated word is matched as a phrase of its alphanumeric
+    /// parts, so identifiers like `get_relevant_context` and file names like
+    /// `src/context.rs` match exactly. Results are ranked by BM25 and returned with
+    /// scores where higher is better.
+    pub async fn search_lexical(
+        &self,
+        query: &str,
+        filter: &ContextFilter,
+        limit: usize,
+    ) -> Result<Vec<(ContextEntry, f32)>> {
+        let Some(match_expression) = fts_match_expression(query) else {
+            return Ok(Vec::new());
+        };
+
+        let conn = self.connection.lock().unwrap();
+
+        let mut params: Vec<Box<dyn rusqlite::ToSql>> = vec![Box::new(match_expression)];
+        let mut conditions = vec!["context_fts MATCH ?1".to_string()];
+        conditions.extend(filter.conditions(&mut params));
+        params.push(Box::new(limit as i64));
+
+        let mut stmt = conn.prepare(&format!(
+            "SELECT ce.id, ce.agent_name, ce.session_id, ce.timestamp, ce.content, ce.role, ce.metadata, bm25(context_fts)
+             FROM context_fts
+             JOIN context_fts_entries f ON f.fts_id = context_fts.rowid
+             JOIN context_entries ce ON ce.id = f.entry_id
+             WHERE {}
+             ORDER BY bm25(context_fts)
+             LIMIT ?{}",
+            conditions.join(" AND "),
+            params.len()
+        ))?;
+        let param_refs: Vec<&dyn rusqlite::ToSql> = params.iter().map(|p| p.as_ref()).collect();
+
+        // BM25 scores are negative, with more relevant matches further below zero
+        let entries = stmt
+            .query_map(&param_refs[..], |row| {
+                Ok((ContextEntry::from_row(row)?, -row.get::<_, f64>(7)? as f32))
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        debug!("Retrieved {} lexical matches", entries.len());
+        Ok(entries)
+    }
+
+    /// Retrieve entries using both full-text and embedding search.
+    ///
+    /// The BM25 and cosine rankings are combined with reciprocal rank fusion, so an
+    /// entry ranked highly by either method surfaces even when the other misses it.
+    /// Without a query embedding only the lexical ranking is used.
+    pub async fn search_hybrid(
+        &self,
+        query: &str,
+        query_embedding: Option<&[f32]>,
+        filter: &ContextFilter,
+        limit: usize,
+    ) -> Result<Vec<(ContextEntry, f32)>> {
+        let candidates = limit.saturating_mul(HYBRID_CANDIDATE_FACTOR);
+
+        let mut rankings = vec![self.search_lexical(query, filter, candidates).await?];
+        if let Some(query_embedding) = query_embedding {
+            rankings.push(
+                self.search_similar(query_embedding, filter, candidates)
+                    .await?,
+            );
+        }
+
+        let mut fused: HashMap<String, (ContextEntry, f32)> = HashMap::new();
+        for ranking in rankings {
+            for (rank, (entry, _)) in ranking.into_iter().enumerate() {
+                let score = 1.0 / (RRF_K + rank as f32 + 1.0);
+                fused
+                    .entry(entry.id.clone())
+                    .or_insert_with(|| (entry, 0.0))
+                    .1 += score;
+            }
+        }
+
+        let mut entries: Vec<(ContextEntry, f32)> = fused.into_values().collect();
+        entries.sort_by(|a, b| {
+            b.1.partial_cmp(&a.1)
+                .unwrap_or(std::cmp::Ordering::Equal)
+                .then_with(|| b.0.timestamp.cmp(&a.0.timestamp))
+        });
+        entries.truncate(limit);
+
+        debug!("Retrieved {} hybrid context entries", entries.len());
+        Ok(entries)
+    }
+
+    /// Ensure an embedding has the dimension of the store's model
+    fn check_dimension(&self, embedding: &[f32]) -> Result<()> {
+        if embedding.len() != self.embedding_model.dimension {
+            return Err(anyhow::anyhow!(
+                "Embedding dimension mismatch: expected {}, got {}",
+                self.embedding_model.dimension,
+                embedding.len()
+            ));
+        }
+        Ok(())
+    }
+
+    /// Count the entries needing embedding while holding the connection lock
+    fn count_entries_needing_embedding_locked(&self, conn: &Connection) -> Result<usize> {
+        let count: i64 = conn.query_row(
+            &format!(
+                "SELECT COUNT(*) FROM context_entries ce
+                 LEFT JOIN embeddings e ON ce.id = e.entry_id
+                 WHERE {}",
+                NEEDS_EMBEDDING
+            ),
+            params![
+                self.embedding_model.provider,
+                self.embedding_model.model,
+                self.embedding_model.dimension as i64
+            ],
+            |row| row.get(0),
+        )?;
+        Ok(count as usize)
+    }
+
+    /// Count the entries without an embedding from the store's model
+    pub async fn count_entries_needing_embedding(&self) -> Result<usize> {
+        let conn = self.connection.lock().unwrap();
+        self.count_entries_needing_embedding_locked(&conn)
+    }
+
+    /// Entries without an embedding from the store's model, oldest first
+    pub async fn entries_needing_embedding(&self, limit: usize) -> Result<Vec<ContextEntry>> {
+        let conn = self.connection.lock().unwrap();
+
+        let mut stmt = conn.prepare(&format!(
+            "SELECT ce.id, ce.agent_name, ce.session_id, ce.timestamp, ce.content, ce.role, ce.metadata
+             FROM context_entries ce
+             LEFT JOIN embeddings e ON ce.id = e.entry_id
+             WHERE {}
+             ORDER BY ce.timestamp ASC, ce.rowid ASC
+             LIMIT ?4",
+            NEEDS_EMBEDDING
+        ))?;
+
+        let entries = stmt
+            .query_map(
+                params![
+                    self.embedding_model.provider,
+                    self.embedding_model.model,
+                    self.embedding_model.dimension as i64,
+                    limit as i64
+                ],
+                ContextEntry::from_row,
+            )?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(entries)
+    }
+
+    /// Replace the embeddings of existing entries with vectors from the store's model.
+    ///
+    /// The entries are removed from the vector index until it is next rebuilt.
+    pub async fn replace_embeddings(&self, embeddings: &[(String, Vec<f32>)]) -> Result<()> {
+        for (_, embedding) in embeddings {
+            self.check_dimension(embedding)?;
+        }
+
+        let mut conn = self.connection.lock().unwrap();
+        let tx = conn.transaction()?;
+        for (entry_id, embedding) in embeddings {
+            tx.execute(
+                "INSERT OR REPLACE INTO embeddings (entry_id, embedding, provider, model, dimension)
+                 VALUES (?1, ?2, ?3, ?4, ?5)",
+                params![
+                    entry_id,
+                    encode_vector(embedding),
+                    self.embedding_model.provider,
+                    self.embedding_model.model,
+                    embedding.len() as i64
+                ],
+            )?;
+            tx.execute(
+                "DELETE FROM vector_index_lists WHERE entry_id = ?1",
+                params![entry_id],
+            )?;
+        }
+        tx.commit()?;
+
+        let entries = self.count_compatible_embeddings(&conn)?;
+        self.vector_index.lock().unwrap().entries = entries;
+        Ok(())
+    }
+
+    /// Re-embed every entry not yet embedded by the store's model, in batches.
+    ///
+    /// `service` must produce the store's model. The vector index is rebuilt once
+    /// all entries are re-embedded. Returns the number of entries re-embedded.
+    pub async fn reindex(&self, service: &EmbeddingService, batch_size: usize) -> Result<usize> {
+        let service_model = service.model_info();
+        if service_model != self.embedding_model {
+            return Err(anyhow::anyhow!(
+                "Embedding service model {} does not match the context store model {}",
+                service_model,
+                self.embedding_model
+            ));
+        }
+        if batch_size == 0 {
+            return Err(anyhow::anyhow!("Reindex batch size must be at least 1"));
+        }
+
+        let mut reindexed = 0;
+        loop {
+            let batch = self.entries_needing_embedding(batch_size).await?;
+            if batch.is_empty() {
+                break;
+            }
+
+            let texts: Vec<String> = batch.iter().map(|entry| entry.content.clone()).collect();
+            let embeddings = service.embed_batch(&texts).await?;
+            if embeddings.len() != batch.len() {
+                return Err(anyhow::anyhow!(
+                    "Embedding service returned {} embeddings for {} entries",
+                    embeddings.len(),
+                    batch.len()
+                ));
+            }
+
+            let replacements: Vec<(String, Vec<f32>)> = batch
+                .into_iter()
+                .map(|entry| entry.id)
+                .zip(embeddings)
+                .collect();
+            self.replace_embeddings(&replacements).await?;
+
+            reindexed += replacements.len();
+            info!("Re-embedded {} context entries", reindexed);
+        }
+
+        self.rebuild_vector_index().await?;
+        Ok(reindexed)
+    }
+
+    /// Score the entries matching `filter`, optionally restricted to index lists
+    fn score_candidates(
+        &self,
+        conn: &Connection,
+        query_embedding: &[f32],
+        filter: &ContextFilter,
+        lists: Option<&[usize]>,
+    ) -> Result<Vec<(ContextEntry, f32)>> {
+        let mut params: Vec<Box<dyn rusqlite::ToSql>> = Vec::new();
+        let mut conditions = filter.conditions(&mut params);
+        conditions.push(self.compatible_embeddings(&mut params));
+        let mut join = String::new();
+        if let Some(lists) = lists {
+            join.push_str("JOIN vector_index_lists l ON ce.id = l.entry_id");
+            let ids: Vec<String> = lists.iter().map(|list| list.to_string()).collect();
+            conditions.push(format!("l.list_id IN ({})", ids.join(", ")));
+        }
+        let where_clause = format!("WHERE {}", conditions.join(" AND "));
+
+        let mut stmt = conn.prepare(&format!(
+            "SELECT ce.id, ce.agent_name, ce.session_id, ce.timestamp, ce.content, ce.role, ce.metadata, e.embedding
+             FROM context_entries ce
+             JOIN embeddings e ON ce.id = e.entry_id
+             {} {}",
+            join, where_clause
+        ))?;
+        let param_refs: Vec<&dyn rusqlite::ToSql> = params.iter().map(|p| p.as_ref()).collect();
+
+        let rows = stmt.query_map(&param_refs[..], |row| {
+            let entry = ContextEntry::from_row(row)?;
+            let embedding = decode_vector(&row.get::<_, Vec<u8>>(7)?);
+            Ok((entry, embedding))
+        })?;
+
+        let mut entries_with_scores = Vec::new();
+        for row_result in rows {
+            let (entry, embedding) = row_result?;
+            let similarity = self.cosine_similarity(query_embedding, &embedding);
+            entries_with_scores.push((entry, similarity));
+        }
+
+        Ok(entries_with_scores)
+    }
+
+    /// Get conversation history for a specific session
+    pub async fn get_session_history(
+        &self,
+        session_id: &str,
+        limit: Option<usize>,
+    ) -> Result<Vec<ContextEntry>> {
+        let conn = self.connection.lock().unwrap();
+
+        let (query, params): (String, Vec<Box<dyn rusqlite::ToSql>>) = match limit {
+            Some(limit) => (
+                "SELECT id, agent_name, session_id, timestamp, content, role, metadata 
+                 FROM context_entries 
+                 WHERE session_id = ?1 
+                 ORDER BY timestamp ASC, rowid ASC 
+                 LIMIT ?2"
+                    .to_string(),
+                vec![Box::new(session_id.to_string()), Box::new(limit as i64)],
+            ),
+            None => (
+                "SELECT id, agent_name, session_id, timestamp, content, role, metadata 
+                 FROM context_entries 
+                 WHERE session_id = ?1 
+                 ORDER BY timestamp ASC, rowid ASC"
+                    .to_string(),
+                vec![Box::new(session_id.to_string())],
+            ),
+        };
+
+        let mut stmt = conn.prepare(&query)?;
+        let param_refs: Vec<&dyn rusqlite::ToSql> = params.iter().map(|p| p.as_ref()).collect();
+
+        let entries = stmt
+            .query_map(&param_refs[..], ContextEntry::from_row)?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        debug!("Retrieved {} session history entries", entries.len());
+        Ok(entries)
+    }
+
+    /// Clear all context entries for a specific session
+    pub async fn clear_session(&self, session_id: &str) -> Result<()> {
+        let conn = self.connection.lock().unwrap();
+
+        // Delete index assignments and embeddings first (foreign key constraints)
+        conn.execute(
+            "DELETE FROM vector_index_lists WHERE entry_id IN (
+                SELECT id FROM context_entries WHERE session_id = ?1
+            )",
+            params![session_id],
+        )?;
+
+        conn.execute(
+            "DELETE FROM embeddings WHERE entry_id IN (
+                SELECT id FROM context_entries WHERE session_id = ?1
+            )",
+            params![session_id],
+        )?;
+
+        // Delete context entries
+        conn.execute(
+            "DELETE FROM context_entries WHERE session_id = ?1",
+            params![session_id],
+        )?;
+
+        // Delete command history
+        conn.execute(
+            "DELETE FROM command_history WHERE session_id = ?1",
+            params![session_id],
+        )?;
+
+        // Delete checkpoints, their files first
+        conn.execute(
+            "DELETE FROM checkpoint_files WHERE checkpoint_id IN (
+                SELECT id FROM checkpoints WHERE session_id = ?1
+            )",
+            params![session_id],
+        )?;
+        conn.execute(
+            "DELETE FROM checkpoints WHERE session_id = ?1",
+            params![session_id],
+        )?;
+
+        let entries = self.count_compatible_embeddings(&conn)?;
+        self.vector_index.lock().unwrap().entries = entries;
+
+        info!(
+            "Cleared context, command history and checkpoints for session: {}",
+            session_id
+        );
+        Ok(())
+    }
+
+    /// Get statistics about the context store
+    pub async fn get_stats(&self) -> Result<ContextStats> {
+        let conn = self.connection.lock().unwrap();
+
+        let mut stmt = conn.prepare("SELECT COUNT(*) FROM context_entries")?;
+        let total_entries: i64 = stmt.query_row([], |row| row.get(0))?;
+
+        let vector_index_lists = self
+            .vector_index
+            .lock()
+            .unwrap()
+            .index
+            .as_ref()
+            .map_or(0, IvfIndex::len);
+
+        let stale_embeddings = self.count_entries_needing_embedding_locked(&conn)?;
+
+        Ok(ContextStats {
+            total_entries: total_entries as usize,
+            embedding_dimension: self.embedding_model.dimension,
+            vector_index_lists,
+            stale_embeddings,
+        })
+    }
+
+    /// List all session IDs that have context entries
+    pub async fn list_sessions(&self) -> Result<Vec<SessionInfo>> {
+        let conn = self.connection.lock().unwrap();
+
+        let mut stmt = conn.prepare(
+            "SELECT session_id, COUNT(*) as entry_count, MIN(timestamp) as first_entry, MAX(timestamp) as last_entry
+             FROM context_entries 
+             GROUP BY session_id 
+             ORDER BY last_entry DESC"
+        )?;
+
+        let sessions = stmt
+            .query_map([], |row| {
+                let first_timestamp =
+                    DateTime::from_timestamp(row.get::<_, i64>(2)?, 0).unwrap_or_else(Utc::now);
+                let last_timestamp =
+                    DateTime::from_timestamp(row.get::<_, i64>(3)?, 0).unwrap_or_else(Utc::now);
+
+                Ok(SessionInfo {
+                    session_id: row.get(0)?,
+                    entry_count: row.get::<_, i64>(1)? as usize,
+                    first_entry: first_timestamp,
+                    last_entry: last_timestamp,
+                })
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        debug!("Retrieved {} sessions", sessions.len());
+        Ok(sessions)
+    }
+
+    /// Check if a session exists in the database
+    pub async fn session_exists(&self, session_id: &str) -> Result<bool> {
+        let conn = self.connection.lock().unwrap();
+
+        let mut stmt =
+            conn.prepare("SELECT COUNT(*) FROM context_entries WHERE session_id = ?1")?;
+        let count: i64 = stmt.query_row(params![session_id], |row| row.get(0))?;
+
+        Ok(count > 0)
+    }
+
+    /// Store a command in the command history
+    pub async fn store_command_history(&self, session_id: &str, command: &str) -> Result<()> {
+        let conn = self.connection.lock().unwrap();
+
+        let command_id = Uuid::new_v4().to_string();
+        let timestamp = Utc::now().timestamp();
+
+        conn.execute(
+            "INSERT INTO command_history (id, session_id, command, timestamp) VALUES (?1, ?2, ?3, ?4)",
+            params![command_id, session_id, command, timestamp],
+        )?;
+
+        debug!("Stored command in history: {}", command);
+        Ok(())
+    }
+
+    /// Get command history for a session
+    pub async fn get_command_history(
+        &self,
+        session_id: &str,
+        limit: Option<usize>,
+    ) -> Result<Vec<String>> {
+        let conn = self.connection.lock().unwrap();
+
+        let (query, params): (String, Vec<Box<dyn rusqlite::ToSql>>) = match limit {
+            Some(limit) => (
+                "SELECT command FROM command_history 
+                 WHERE session_id = ?1 
+                 ORDER BY timestamp DESC 
+                 LIMIT ?2"
+                    .to_string(),
+                vec![Box::new(session_id.to_string()), Box::new(limit as i64)],
+            ),
+            None => (
+                "SELECT command FROM command_history 
+                 WHERE session_id = ?1 
+                 ORDER BY timestamp DESC"
+                    .to_string(),
+                vec![Box::new(session_id.to_string())],
+            ),
+        };
+
+        let mut stmt = conn.prepare(&query)?;
+        let param_refs: Vec<&dyn rusqlite::ToSql> = params.iter().map(|p| p.as_ref()).collect();
+
+        let commands = stmt
+            .query_map(&param_refs[..], |row| Ok(row.get::<_, String>(0)?))?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        debug!("Retrieved {} command history entries", commands.len());
+        Ok(commands)
+    }
+
+    /// Clear command history for a session
+    pub async fn clear_command_history(&self, session_id: &str) -> Result<()> {
+        let conn = self.connection.lock().unwrap();
+
+        conn.execute(
+            "DELETE FROM command_history WHERE session_id = ?1",
+            params![session_id],
+        )?;
+
+        info!("Cleared command history for session: {}", session_id);
+        Ok(())
+    }
+
+    /// Trim command history to keep only the most recent N commands
+    pub async fn trim_command_history(&self, session_id: &str, max_commands: usize) -> Result<()> {
+        let conn = self.connection.lock().unwrap();
+
+        // Delete all but the most recent max_commands entries
+        conn.execute(
+            "DELETE FROM command_history 
+             WHERE session_id = ?1 
+             AND timestamp NOT IN (
+                 SELECT timestamp FROM command_history 
+                 WHERE session_id = ?1 
+                 ORDER BY timestamp DESC 
+                 LIMIT ?2
+             )",
+            params![session_id, max_commands as i64],
+        )?;
+
+        debug!(
+            "Trimmed command history for session {} to {} entries",
+            session_id, max_commands
+        );
+        Ok(())
+    }
+
+    /// Calculate cosine similarity between two embeddings
+    fn cosine_similarity(&self, a: &[f32], b: &[f32]) -> f32 {
+        if a.len() != b.len() {
+            return 0.0;
+        }
+
+        let dot_product: f32 = a.iter().zip(b.iter()).map(|(x, y)| x * y).sum();
+        let norm_a: f32 = a.iter().map(|x| x * x).sum::<f32>().sqrt();
+        let norm_b: f32 = b.iter().map(|x| x * x).sum::<f32>().sqrt();
+
+        if norm_a == 0.0 || norm_b == 0.0 {
+            return 0.0;
+        }
+
+        dot_product / (norm_a * norm_b)
+    }
+}
+
+/// Build an FTS5 match expression that ORs one phrase per word of `query`
+fn fts_match_expression(query: &str) -> Option<String> {
+    let phrases: Vec<String> = query
+        .split_whitespace()
+        .filter_map(|word| {
+            let tokens: Vec<&str> = word
+                .split(|c: char| !c.is_alphanumeric())
+                .filter(|token| !token.is_empty())
+                .collect();
+            (!tokens.is_empty()).then(|| format!("\"{}\"", tokens.join(" ")))
+        })
+        .collect();
+
+    (!phrases.is_empty()).then(|| phrases.join(" OR "))
+}
+
+/// Statistics about the context store
+#[derive(Debug, Clone)]
+pub struct ContextStats {
+    pub total_entries: usize,
+    pub embedding_dimension: usize,
+    /// Number of lists in the vector index (zero when not yet trained)
+    pub vector_index_lists: usize,
+    /// Entries without an embedding from the store's model (see [`ContextStore::reindex`])
+    pub stale_embeddings: usize,
+}
+
+/// Information about a session
+#[derive(Debug, Clone)]
+pub struct SessionInfo {
+    pub session_id: String,
+    pub entry_count: usize,
+    pub first_entry: DateTime<Utc>,
+    pub last_entry: DateTime<Utc>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::embeddings::EmbeddingProvider;
+    use tempfile::tempdir;
+
+    #[tokio::test]
+    async fn test_context_store_creation() {
+        let temp_dir = tempdir().unwrap();
+        let db_path = temp_dir.path().join("test.db");
+
+        let store = ContextStore::new(&db_path, 384).await;
+        assert!(store.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_store_and_retrieve_context() {
+        let temp_dir = tempdir().unwrap();
+        let db_path = temp_dir.path().join("test.db");
+
+        let store = ContextStore::new(&db_path, 3).await.unwrap();
+
+        let entry = ContextEntry::new(
+            "test_agent".to_string(),
+            "session_123".to_string(),
+            "Hello, world!".to_string(),
+            "user".to_string(),
+        );
+
+        let embedding = vec![0.1, 0.2, 0.3];
+        let result = store.store_context(entry.clone(), embedding.clone()).await;
+        assert!(result.is_ok());
+
+        let query_embedding = vec![0.1, 0.2, 0.3];
+        let retrieved = store
+            .get_relevant_context(query_embedding, Some("session_123"), 10)
+            .await
+            .unwrap();
+
+        assert_eq!(retrieved.len(), 1);
+        assert_eq!(retrieved[0].content, "Hello, world!");
+        assert_eq!(retrieved[0].agent_name, "test_agent");
+    }
+
+    #[tokio::test]
+    async fn test_session_history() {
+        let temp_dir = tempdir().unwrap();
+        let db_path = temp_dir.path().join("test.db");
+
+        let store = ContextStore::new(&db_path, 3).await.unwrap();
+
+        // Store multiple entries
+        for i in 0..3 {
+            let entry = ContextEntry::new(
+                "test_agent".to_string(),
+                "session_123".to_string(),
+                format!("Message {}", i),
+                if i % 2 == 0 { "user" } else { "assistant" }.to_string(),
+            );
+            let embedding = vec![i as f32, (i + 1) as f32, (i + 2) as f32];
+            store.store_context(entry, embedding).await.unwrap();
+        }
+
+        let history = store
+            .get_session_history("session_123", None)
+            .await
+            .unwrap();
+        assert_eq!(history.len(), 3);
+        assert_eq!(history[0].content, "Message 0");
+        assert_eq!(history[2].content, "Message 2");
+    }
+
+    #[tokio::test]
+    async fn test_clear_session() {
+        let temp_dir = tempdir().unwrap();
+        let db_path = temp_dir.path().join("test.db");
+
+        let store = ContextStore::new(&db_path, 3).await.unwrap();
+
+        let entry = ContextEntry::new(
+            "test_agent".to_string(),
+            "session_123".to_string(),
+            "Hello, world!".to_string(),
+            "user".to_string(),
+        );
+
+        let embedding = vec![0.1, 0.2, 0.3];
+        store.store_context(entry, embedding).await.unwrap();
+        let snapshot = FileSnapshot {
+            path: "/p/new.rs".into(),
+            content: None,
+            directory: false,
+        };
+        store
+            .record_file_snapshot("session_123", "turn-1", "add new", &snapshot)
+            .await
+            .unwrap();
+
+        let history_before = store
+            .get_session_history("session_123", None)
+            .await
+            .unwrap();
+        assert_eq!(history_before.len(), 1);
+
+        store.clear_session("session_123").await.unwrap();
+
+        let history_after = store
+            .get_session_history("session_123", None)
+            .await
+            .unwrap();
+        assert_eq!(history_after.len(), 0);
+        assert!(
+            store
+                .list_checkpoints("session_123")
+                .await
+                .unwrap()
+                .is_empty()
+        );
+    }
+
+    /// Unit vector along one of four axes, with a little noise per entry
+    fn axis_embedding(axis: usize, i: usize) -> Vec<f32> {
+        let mut embedding = vec![0.01 * (i % 7) as f32; 4];
+        embedding[axis] = 1.0;
+        embedding
+    }
+
+    #[tokio::test]
+    async fn test_vector_index_trained_and_persisted() {
+        let temp_dir = tempdir().unwrap();
+        let db_path = temp_dir.path().join("test.db");
+
+        let store = ContextStore::new(&db_path, 4).await.unwrap();
+        for i in 0..MIN_TRAINING_VECTORS + 10 {
+            let entry = ContextEntry::new(
+                "test_agent".to_string(),
+                format!("session_{}", i % 2),
+                format!("Message {}", i),
+                if i % 2 == 0 { "user" } else { "assistant" }.to_string(),
+            );
+            store
+                .store_context(entry, axis_embedding(i % 4, i))
+                .await
+                .unwrap();
+        }
+
+        // The index is trained in the background
+        while store.vector_index.lock().unwrap().rebuilding {
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+        let stats = store.get_stats().await.unwrap();
+        assert!(stats.vector_index_lists > 0);
+
+        // Reopening the database loads the persisted index
+        drop(store);
+        let store = ContextStore::new(&db_path, 4).await.unwrap();
+        let reopened = store.get_stats().await.unwrap();
+        assert_eq!(reopened.vector_index_lists, stats.vector_index_lists);
+
+        let lists = store.rebuild_vector_index().await.unwrap();
+        assert!(lists > 0 && lists <= IvfIndex::num_lists_for(MIN_TRAINING_VECTORS + 10));
+
+        // Scoring only the nearest list still finds entries along the query axis
+        let query = axis_embedding(2, 0);
+        let nearest = {
+            let state = store.vector_index.lock().unwrap();
+            state.index.as_ref().unwrap().probe(&query, 1)
+        };
+        let conn = store.connection.lock().unwrap();
+        let candidates = store
+            .score_candidates(&conn, &query, &ContextFilter::default(), Some(&nearest))
+            .unwrap();
+        assert!(!candidates.is_empty());
+        assert!(candidates.len() < MIN_TRAINING_VECTORS + 10);
+        assert!(candidates.iter().all(|(_, score)| *score > 0.9));
+    }
+
+    #[tokio::test]
+    async fn test_search_similar_applies_filters() {
+        let temp_dir = tempdir().unwrap();
+        let db_path = temp_dir.path().join("test.db");
+
+        let store = ContextStore::new(&db_path, 4).await.unwrap();
+        for i in 0..8 {
+            let entry = ContextEntry::new(
+                if i < 4 { "chat" } else { "acp" }.to_string(),
+                "session_123".to_string(),
+                format!("Message {}", i),
+                if i % 2 == 0 { "user" } else { "assistant" }.to_string(),
+            );
+            store
+                .store_context(entry, axis_embedding(i % 4, i))
+                .await
+                .unwrap();
+        }
+
+        let filter = ContextFilter::session("session_123")
+            .with_agent("acp")
+            .with_role("user");
+        let results = store
+            .search_similar(&axis_embedding(0, 0), &filter, 10)
+            .await
+            .unwrap();
+
+        assert_eq!(results.len(), 2);
+        assert_eq!(results[0].0.content, "Message 4");
+        assert!(results[0].1 > results[1].1);
+        assert!(
+            results
+                .iter()
+                .all(|(entry, _)| entry.agent_name == "acp" && entry.role == "user")
+        );
+    }
+
+    async fn store_texts(store: &ContextStore, texts: &[&str]) {
+        for (i, text) in texts.iter().enumerate() {
+            let entry = ContextEntry::new(
+                "test_agent".to_string(),
+                "session_123".to_string(),
+                text.to_string(),
+                "user".to_string(),
+            );
+            store
+                .store_context(entry, axis_embedding(i % 4, i))
+                .await
+                .unwrap();
+        }
+    }
+
+    #[tokio::test]
+    async fn test_search_only_compares_same_model_embeddings() {
+        let temp_dir = tempdir().unwrap();
+        let db_path = temp_dir.path().join("test.db");
+
+        let service = EmbeddingProvider::Simple { dimension: 4 }.create_service();
+        let other = ContextStore::with_embedding_model(
+            &db_path,
+            EmbeddingModelInfo::new("ollama", "all-minilm", 4),
+        )
+        .await
+        .unwrap();
+        for i in 0..3 {
+            let entry = ContextEntry::new(
+                "test_agent".to_string(),
+                "session_123".to_string(),
+                format!("Entry {}", i),
+                "user".to_string(),
+            );
+            other
+                .store_context(entry, axis_embedding(i, i))
+                .await
+                .unwrap();
+        }
+
+        let store = ContextStore::with_embedding_model(&db_path, service.model_info())
+            .await
+            .unwrap();
+        let query = service.embed("Entry 0").await.unwrap();
+        assert!(
+            store
+                .search_similar(&query, &ContextFilter::default(), 10)
+                .await
+                .unwrap()
+                .is_empty()
+        );
+        assert_eq!(store.get_stats().await.unwrap().stale_embeddings, 3);
+
+        // A mismatched service is rejected
+        let wrong = EmbeddingProvider::Simple { dimension: 8 }.create_service();
+        assert!(store.reindex(&wrong, 2).await.is_err());
+
+        assert_eq!(store.reindex(&service, 2).await.unwrap(), 3);
+        assert_eq!(store.count_entries_needing_embedding().await.unwrap(), 0);
+        let results = store
+            .search_similar(&query, &ContextFilter::default(), 10)
+            .await
+            .unwrap();
+        assert_eq!(results.len(), 3);
+        assert_eq!(results[0].0.content, "Entry 0");
+
+        // The re-embedded entries are no longer comparable with the old model
+        assert!(
+            other
+                .search_similar(&axis_embedding(0, 0), &ContextFilter::default(), 10)
+                .await
+                .unwrap()
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn test_fts_match_expression() {
+        assert_eq!(
+            fts_match_expression("open src/context.rs"),
+            Some("\"open\" OR \"src context rs\"".to_string())
+        );
+        assert_eq!(fts_match_expression("  ?! "), None);
+    }
+
+    #[tokio::test]
+    async fn test_search_lexical_finds_identifiers() {
+        let temp_dir = tempdir().unwrap();
+        let db_path = temp_dir.path().join("test.db");
+
+        let store = ContextStore::new(&db_path, 4).await.unwrap();
+        store_texts(
+            &store,
+            &[
+                "The relevant context is loaded lazily",
+                "Call get_relevant_context from src/context.rs",
+                "Unrelated message about the weather",
+            ],
+        )
+        .await;
+
+        let results = store
+            .search_lexical("get_relevant_context", &ContextFilter::default(), 10)
+            .await
+            .unwrap();
+        assert_eq!(results.len(), 1);
+        assert!(results[0].0.content.contains("src/context.rs"));
+
+        let results = store
+            .search_lexical("context.rs", &ContextFilter::session("other_session"), 10)
+            .await
+            .unwrap();
+        assert!(results.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_search_lexical_survives_deletes_and_vacuum() {
+        let temp_dir = tempdir().unwrap();
+        let db_path = temp_dir.path().join("test.db");
+
+        let store = ContextStore::new(&db_path, 4).await.unwrap();
+        for (i, (session, text)) in [
+            ("old", "alpha release notes"),
+            ("new", "beta release notes"),
+        ]
+        .into_iter()
+        .enumerate()
+        {
+            let entry = ContextEntry::new(
+                "test_agent".to_string(),
+                session.to_string(),
+                text.to_string(),
+                "user".to_string(),
+            );
+            store
+                .store_context(entry, axis_embedding(i, i))
+                .await
+                .unwrap();
+        }
+        store.clear_session("old").await.unwrap();
+        store
+            .connection
+            .lock()
+            .unwrap()
+            .execute_batch("VACUUM")
+            .unwrap();
+
+        let results = store
+            .search_lexical("release", &ContextFilter::default(), 10)
+            .await
+            .unwrap();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].0.content, "beta release notes");
+    }
+
+    #[tokio::test]
+    async fn test_search_hybrid_fuses_rankings() {
+        let temp_dir = tempdir().unwrap();
+        let db_path = temp_dir.path().join("test.db");
+
+        let store = ContextStore::new(&db_path, 4).await.unwrap();
+        store_texts(
+            &store,
+            &[
+                "Deploy with deploy.sh",
+                "Semantic neighbour of the query",
+                "Nothing to see here",
+            ],
+        )
+        .await;
+
+        // Entry 0 matches lexically, entry 1 is the nearest embedding
+        let results = store
+            .search_hybrid(
+                "deploy.sh",
+                Some(&axis_embedding(1, 1)),
+                &ContextFilter::default(),
+                2,
+            )
+            .await
+            .unwrap();
+        let contents: Vec<&str> = results.iter().map(|(e, _)| e.content.as_str()).collect();
+        assert_eq!(contents.len(), 2);
+        assert!(contents.contains(&"Deploy with deploy.sh"));
+        assert!(contents.contains(&"Semantic neighbour of the query"));
+
+        // Lexical-only retrieval works without an embedding
+        let results = store
+            .search_hybrid("deploy.sh", None, &ContextFilter::default(), 5)
+            .await
+            .unwrap();
+        assert_eq!(results.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_cosine_similarity() {
+        let temp_dir = tempdir().unwrap();
+        let db_path = temp_dir.path().join("test.db");
+
+        let store = ContextStore::new(&db_path, 3).await.unwrap();
+
+        // Test identical vectors
+        let a = vec![1.0, 0.0, 0.0];
+        let b = vec![1.0, 0.0, 0.0];
+        let similarity = store.cosine_similarity(&a, &b);
+        assert!((similarity - 1.0).abs() < 1e-6);
+
+        // Test orthogonal vectors
+        let c = vec![1.0, 0.0, 0.0];
+        let d = vec![0.0, 1.0, 0.0];
+        let similarity = store.cosine_similarity(&c, &d);
+        assert!((similarity - 0.0).abs() < 1e-6);
+    }
+}
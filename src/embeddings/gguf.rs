@@ -0,0 +1,551 @@
+//! Minimal reader for GGUF model files
+//!
+//! GGUF is the single-file model format used by llama.cpp. A file holds typed
+//! key/value metadata (architecture, hyperparameters, vocabulary) followed by
+//! named tensors. Only the tensor types needed for small embedding models are
+//! decoded: `F32`, `F16`, `BF16` and `Q8_0`.
+
+use anyhow::{Context, Result, anyhow, bail};
+use std::collections::HashMap;
+use std::path::Path;
+
+/// File magic: "GGUF" in little-endian byte order
+const GGUF_MAGIC: u32 = 0x4655_4747;
+
+/// Default alignment of the tensor data section
+const DEFAULT_ALIGNMENT: usize = 32;
+
+/// Values per `Q8_0` block
+const Q8_0_BLOCK_SIZE: usize = 32;
+
+/// A typed metadata value
+#[derive(Debug, Clone, PartialEq)]
+pub enum MetadataValue {
+    UInt(u64),
+    Int(i64),
+    Float(f64),
+    Bool(bool),
+    String(String),
+    Array(Vec<MetadataValue>),
+}
+
+impl MetadataValue {
+    /// The value as an unsigned integer, if it is a non-negative integer
+    pub fn as_u64(&self) -> Option<u64> {
+        match self {
+            MetadataValue::UInt(value) => Some(*value),
+            MetadataValue::Int(value) => u64::try_from(*value).ok(),
+            _ => None,
+        }
+    }
+
+    /// The value as a float, if it is numeric
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            MetadataValue::Float(value) => Some(*value),
+            MetadataValue::UInt(value) => Some(*value as f64),
+            MetadataValue::Int(value) => Some(*value as f64),
+            _ => None,
+        }
+    }
+
+    /// The value as a string, if it is one
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            MetadataValue::String(value) => Some(value),
+            _ => None,
+        }
+    }
+}
+
+/// Element types of stored tensors
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TensorType {
+    F32,
+    F16,
+    BF16,
+    Q8_0,
+}
+
+impl TensorType {
+    fn from_id(id: u32) -> Result<Self> {
+        match id {
+            0 => Ok(TensorType::F32),
+            1 => Ok(TensorType::F16),
+            8 => Ok(TensorType::Q8_0),
+            30 => Ok(TensorType::BF16),
+            _ => bail!(
+                "Unsupported GGUF tensor type {} (supported: F32, F16, BF16, Q8_0)",
+                id
+            ),
+        }
+    }
+
+    /// Number of bytes used to store `elements` values
+    fn byte_len(self, elements: usize) -> Result<usize> {
+        let too_large = || anyhow!("Tensor of {} values is too large", elements);
+        match self {
+            TensorType::F32 => elements.checked_mul(4).ok_or_else(too_large),
+            TensorType::F16 | TensorType::BF16 => elements.checked_mul(2).ok_or_else(too_large),
+            TensorType::Q8_0 => {
+                if !elements.is_multiple_of(Q8_0_BLOCK_SIZE) {
+                    bail!("Q8_0 tensor size {} is not a multiple of 32", elements);
+                }
+                Ok(elements / Q8_0_BLOCK_SIZE * 34)
+            }
+        }
+    }
+}
+
+/// Location and shape of a tensor within the file
+#[derive(Debug, Clone)]
+struct TensorInfo {
+    dims: Vec<usize>,
+    tensor_type: TensorType,
+    offset: usize,
+}
+
+/// A tensor decoded to `f32` values.
+///
+/// `dims[0]` is the fastest-varying dimension, so a matrix with `dims = [n, m]`
+/// is stored as `m` contiguous rows of `n` values.
+#[derive(Debug, Clone)]
+pub struct Tensor {
+    pub dims: Vec<usize>,
+    pub data: Vec<f32>,
+}
+
+/// A parsed GGUF file
+pub struct GgufFile {
+    metadata: HashMap<String, MetadataValue>,
+    tensors: HashMap<String, TensorInfo>,
+    bytes: Vec<u8>,
+    data_start: usize,
+}
+
+impl GgufFile {
+    /// Read and parse a GGUF file from disk
+    pub fn read<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let bytes = std::fs::read(path)
+            .with_context(|| format!("Failed to read model file {}", path.display()))?;
+        Self::parse(bytes).with_context(|| format!("Invalid GGUF file {}", path.display()))
+    }
+
+    /// Parse a GGUF file from its bytes
+    pub fn parse(bytes: Vec<u8>) -> Result<Self> {
+        let mut reader = Reader::new(&bytes);
+
+        if reader.u32()? != GGUF_MAGIC {
+            bail!("Not a GGUF file (bad magic)");
+        }
+        let version = reader.u32()?;
+        if !(2..=3).contains(&version) {
+            bail!("Unsupported GGUF version {} (supported: 2, 3)", version);
+        }
+
+        let tensor_count = reader.u64()? as usize;
+        let metadata_count = reader.u64()? as usize;
+
+        let mut metadata = HashMap::new();
+        for _ in 0..metadata_count {
+            let key = reader.string()?;
+            let value_type = reader.u32()?;
+            let value = reader.value(value_type)?;
+            metadata.insert(key, value);
+        }
+
+        let mut tensors = HashMap::new();
+        for _ in 0..tensor_count {
+            let name = reader.string()?;
+            let n_dims = reader.u32()? as usize;
+            let dims = (0..n_dims)
+                .map(|_| reader.u64().map(|dim| dim as usize))
+                .collect::<Result<Vec<_>>>()?;
+            let tensor_type =
+                TensorType::from_id(reader.u32()?).with_context(|| format!("Tensor {}", name))?;
+            let offset = reader.u64()? as usize;
+            tensors.insert(
+                name,
+                TensorInfo {
+                    dims,
+                    tensor_type,
+                    offset,
+                },
+            );
+        }
+
+        let alignment = metadata
+            .get("general.alignment")
+            .and_then(MetadataValue::as_u64)
+            .map_or(DEFAULT_ALIGNMENT, |alignment| alignment as usize);
+        if !alignment.is_power_of_two() {
+            bail!(
+                "Invalid GGUF alignment {}, expected a power of two",
+                alignment
+            );
+        }
+        let data_start = reader.position.next_multiple_of(alignment);
+
+        let file = Self {
+            metadata,
+            tensors,
+            bytes,
+            data_start,
+        };
+
+        // Validate tensor bounds up front so lookups cannot read past the file
+        for (name, info) in &file.tensors {
+            let elements = info
+                .dims
+                .iter()
+                .try_fold(1usize, |elements, &dim| elements.checked_mul(dim))
+                .ok_or_else(|| anyhow!("Tensor {} is too large", name))?;
+            let len = info
+                .tensor_type
+                .byte_len(elements)
+                .with_context(|| format!("Tensor {}", name))?;
+            let end = file
+                .data_start
+                .checked_add(info.offset)
+                .and_then(|start| start.checked_add(len));
+            if end.is_none_or(|end| end > file.bytes.len()) {
+                bail!("Tensor {} extends past the end of the file", name);
+            }
+        }
+
+        Ok(file)
+    }
+
+    /// Look up a metadata value
+    pub fn metadata(&self, key: &str) -> Option<&MetadataValue> {
+        self.metadata.get(key)
+    }
+
+    /// Look up an unsigned integer metadata value
+    pub fn metadata_u64(&self, key: &str) -> Option<u64> {
+        self.metadata(key).and_then(MetadataValue::as_u64)
+    }
+
+    /// Look up a numeric metadata value as a float
+    pub fn metadata_f64(&self, key: &str) -> Option<f64> {
+        self.metadata(key).and_then(MetadataValue::as_f64)
+    }
+
+    /// Look up a string metadata value
+    pub fn metadata_str(&self, key: &str) -> Option<&str> {
+        self.metadata(key).and_then(MetadataValue::as_str)
+    }
+
+    /// Look up a string array metadata value
+    pub fn metadata_strings(&self, key: &str) -> Option<Vec<String>> {
+        match self.metadata(key)? {
+            MetadataValue::Array(values) => values
+                .iter()
+                .map(|value| value.as_str().map(str::to_string))
+                .collect(),
+            _ => None,
+        }
+    }
+
+    /// Whether the file contains a tensor
+    pub fn has_tensor(&self, name: &str) -> bool {
+        self.tensors.contains_key(name)
+    }
+
+    /// Decode a tensor to `f32` values
+    pub fn tensor(&self, name: &str) -> Result<Tensor> {
+        let info = self
+            .tensors
+            .get(name)
+            .ok_or_else(|| anyhow!("Tensor {} not found in model", name))?;
+
+        let elements: usize = info.dims.iter().product();
+        let start = self.data_start + info.offset;
+        let bytes = &self.bytes[start..start + info.tensor_type.byte_len(elements)?];
+
+        let data = match info.tensor_type {
+            TensorType::F32 => bytes
+                .chunks_exact(4)
+                .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+                .collect(),
+            TensorType::F16 => bytes
+                .chunks_exact(2)
+                .map(|b| f16_to_f32(u16::from_le_bytes([b[0], b[1]])))
+                .collect(),
+            TensorType::BF16 => bytes
+                .chunks_exact(2)
+                .map(|b| f32::from_bits((u16::from_le_bytes([b[0], b[1]]) as u32) << 16))
+                .collect(),
+            TensorType::Q8_0 => bytes
+                .chunks_exact(34)
+                .flat_map(|block| {
+                    let scale = f16_to_f32(u16::from_le_bytes([block[0], block[1]]));
+                    block[2..].iter().map(move |&q| scale * (q as i8) as f32)
+                })
+                .collect(),
+        };
+
+        Ok(Tensor {
+            dims: info.dims.clone(),
+            data,
+        })
+    }
+}
+
+/// Convert an IEEE 754 half-precision value to `f32`
+pub fn f16_to_f32(bits: u16) -> f32 {
+    let sign = ((bits >> 15) as u32) << 31;
+    let exponent = ((bits >> 10) & 0x1f) as u32;
+    let mantissa = (bits & 0x3ff) as u32;
+
+    let value = match (exponent, mantissa) {
+        (0, 0) => sign,
+        (0, _) => {
+            // Subnormal: renormalize the mantissa
+            let shift = mantissa.leading_zeros() - 21;
+            let mantissa = (mantissa << shift) & 0x3ff;
+            sign | ((113 - shift) << 23) | (mantissa << 13)
+        }
+        (0x1f, _) => sign | 0x7f80_0000 | (mantissa << 13),
+        _ => sign | ((exponent + 112) << 23) | (mantissa << 13),
+    };
+    f32::from_bits(value)
+}
+
+/// Cursor over the little-endian header of a GGUF file
+struct Reader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn new(bytes: &'a [u8]) -> Self {
+        Self { bytes, position: 0 }
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'a [u8]> {
+        let end = self
+            .position
+            .checked_add(len)
+            .filter(|&end| end <= self.bytes.len())
+            .ok_or_else(|| anyhow!("Unexpected end of file at byte {}", self.position))?;
+        let slice = &self.bytes[self.position..end];
+        self.position = end;
+        Ok(slice)
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N]> {
+        Ok(self.take(N)?.try_into().expect("slice has length N"))
+    }
+
+    fn u32(&mut self) -> Result<u32> {
+        Ok(u32::from_le_bytes(self.array()?))
+    }
+
+    fn u64(&mut self) -> Result<u64> {
+        Ok(u64::from_le_bytes(self.array()?))
+    }
+
+    fn string(&mut self) -> Result<String> {
+        let len = self.u64()? as usize;
+        Ok(String::from_utf8_lossy(self.take(len)?).into_owned())
+    }
+
+    fn value(&mut self, value_type: u32) -> Result<MetadataValue> {
+        Ok(match value_type {
+            0 => MetadataValue::UInt(self.array::<1>()?[0] as u64),
+            1 => MetadataValue::Int(i8::from_le_bytes(self.array()?) as i64),
+            2 => MetadataValue::UInt(u16::from_le_bytes(self.array()?) as u64),
+            3 => MetadataValue::Int(i16::from_le_bytes(self.array()?) as i64),
+            4 => MetadataValue::UInt(self.u32()? as u64),
+            5 => MetadataValue::Int(i32::from_le_bytes(self.array()?) as i64),
+            6 => MetadataValue::Float(f32::from_le_bytes(self.array()?) as f64),
+            7 => MetadataValue::Bool(self.array::<1>()?[0] != 0),
+            8 => MetadataValue::String(self.string()?),
+            9 => {
+                let element_type = self.u32()?;
+                let len = self.u64()? as usize;
+                let values = (0..len)
+                    .map(|_| self.value(element_type))
+                    .collect::<Result<Vec<_>>>()?;
+                MetadataValue::Array(values)
+            }
+            10 => MetadataValue::UInt(self.u64()?),
+            11 => MetadataValue::Int(i64::from_le_bytes(self.array()?)),
+            12 => MetadataValue::Float(f64::from_le_bytes(self.array()?)),
+            _ => bail!("Unknown GGUF metadata type {}", value_type),
+        })
+    }
+}
+
+/// Writer for small GGUF files in tests
+#[cfg(test)]
+pub(crate) mod writer {
+    /// Builds a GGUF (version 3) file with `F32` tensors
+    #[derive(Default)]
+    pub struct GgufWriter {
+        metadata: Vec<u8>,
+        metadata_count: u64,
+        tensors: Vec<(String, Vec<usize>, Vec<f32>)>,
+    }
+
+    fn push_string(buffer: &mut Vec<u8>, value: &str) {
+        buffer.extend((value.len() as u64).to_le_bytes());
+        buffer.extend(value.as_bytes());
+    }
+
+    impl GgufWriter {
+        fn key(&mut self, key: &str, value_type: u32) -> &mut Vec<u8> {
+            self.metadata_count += 1;
+            push_string(&mut self.metadata, key);
+            self.metadata.extend(value_type.to_le_bytes());
+            &mut self.metadata
+        }
+
+        pub fn u32(mut self, key: &str, value: u32) -> Self {
+            self.key(key, 4).extend(value.to_le_bytes());
+            self
+        }
+
+        pub fn f32(mut self, key: &str, value: f32) -> Self {
+            self.key(key, 6).extend(value.to_le_bytes());
+            self
+        }
+
+        pub fn string(mut self, key: &str, value: &str) -> Self {
+            push_string(self.key(key, 8), value);
+            self
+        }
+
+        pub fn strings(mut self, key: &str, values: &[&str]) -> Self {
+            let buffer = self.key(key, 9);
+            buffer.extend(8u32.to_le_bytes());
+            buffer.extend((values.len() as u64).to_le_bytes());
+            for value in values {
+                push_string(buffer, value);
+            }
+            self
+        }
+
+        pub fn tensor(mut self, name: &str, dims: &[usize], data: Vec<f32>) -> Self {
+            assert_eq!(dims.iter().product::<usize>(), data.len());
+            self.tensors.push((name.to_string(), dims.to_vec(), data));
+            self
+        }
+
+        pub fn build(self) -> Vec<u8> {
+            let mut bytes = Vec::new();
+            bytes.extend(super::GGUF_MAGIC.to_le_bytes());
+            bytes.extend(3u32.to_le_bytes());
+            bytes.extend((self.tensors.len() as u64).to_le_bytes());
+            bytes.extend(self.metadata_count.to_le_bytes());
+            bytes.extend(&self.metadata);
+
+            let mut offset = 0usize;
+            for (name, dims, data) in &self.tensors {
+                push_string(&mut bytes, name);
+                bytes.extend((dims.len() as u32).to_le_bytes());
+                for dim in dims {
+                    bytes.extend((*dim as u64).to_le_bytes());
+                }
+                bytes.extend(0u32.to_le_bytes());
+                bytes.extend((offset as u64).to_le_bytes());
+                offset = (offset + data.len() * 4).next_multiple_of(super::DEFAULT_ALIGNMENT);
+            }
+
+            for (_, _, data) in &self.tensors {
+                bytes.resize(bytes.len().next_multiple_of(super::DEFAULT_ALIGNMENT), 0);
+                for value in data {
+                    bytes.extend(value.to_le_bytes());
+                }
+            }
+            bytes
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::writer::GgufWriter;
+    use super::*;
+
+    #[test]
+    fn test_parse_metadata_and_tensors() {
+        let bytes = GgufWriter::default()
+            .string("general.architecture", "bert")
+            .u32("bert.block_count", 6)
+            .f32("bert.attention.layer_norm_epsilon", 1e-12)
+            .strings("tokenizer.ggml.tokens", &["[PAD]", "hello"])
+            .tensor("a", &[3], vec![1.0, 2.0, 3.0])
+            .tensor("b", &[2, 2], vec![0.5, -0.5, 1.5, -1.5])
+            .build();
+
+        let file = GgufFile::parse(bytes).unwrap();
+        assert_eq!(file.metadata_str("general.architecture"), Some("bert"));
+        assert_eq!(file.metadata_u64("bert.block_count"), Some(6));
+        assert!(
+            file.metadata_f64("bert.attention.layer_norm_epsilon")
+                .unwrap()
+                > 0.0
+        );
+        assert_eq!(
+            file.metadata_strings("tokenizer.ggml.tokens").unwrap(),
+            vec!["[PAD]", "hello"]
+        );
+
+        let b = file.tensor("b").unwrap();
+        assert_eq!(b.dims, vec![2, 2]);
+        assert_eq!(b.data, vec![0.5, -0.5, 1.5, -1.5]);
+        assert!(file.has_tensor("a"));
+        assert!(file.tensor("missing").is_err());
+    }
+
+    #[test]
+    fn test_rejects_invalid_files() {
+        assert!(GgufFile::parse(b"not a model".to_vec()).is_err());
+
+        let mut truncated = GgufWriter::default()
+            .tensor("a", &[8], vec![1.0; 8])
+            .build();
+        truncated.truncate(truncated.len() - 4);
+        assert!(GgufFile::parse(truncated).is_err());
+    }
+
+    #[test]
+    fn test_rejects_invalid_alignment() {
+        for alignment in [0, 24] {
+            let bytes = GgufWriter::default()
+                .u32("general.alignment", alignment)
+                .tensor("a", &[2], vec![1.0, 2.0])
+                .build();
+            let Err(error) = GgufFile::parse(bytes) else {
+                panic!("alignment {} accepted", alignment);
+            };
+            assert!(error.to_string().contains("alignment"), "{}", error);
+        }
+    }
+
+    #[test]
+    fn test_rejects_oversized_tensors() {
+        let mut bytes = GgufWriter::default()
+            .tensor("a", &[2], vec![1.0, 2.0])
+            .build();
+        // Make the only dimension so large its byte size overflows
+        let dim = bytes
+            .windows(8)
+            .position(|window| window == 2u64.to_le_bytes())
+            .unwrap();
+        bytes[dim..dim + 8].copy_from_slice(&(u64::MAX / 2).to_le_bytes());
+        assert!(GgufFile::parse(bytes).is_err());
+    }
+
+    #[test]
+    fn test_f16_conversion() {
+        assert_eq!(f16_to_f32(0x3c00), 1.0);
+        assert_eq!(f16_to_f32(0xc000), -2.0);
+        assert_eq!(f16_to_f32(0x3555), 0.333_251_95);
+        assert_eq!(f16_to_f32(0x0001), 2f32.powi(-24));
+        assert_eq!(f16_to_f32(0x7c00), f32::INFINITY);
+    }
+}
@@ -0,0 +1,584 @@
+//! In-process embedding model
+//!
+//! Runs a BERT-style sentence embedding model (such as all-MiniLM-L6-v2) loaded
+//! from a local GGUF file, so semantic retrieval works offline without any
+//! embedding service running. Models converted with llama.cpp's
+//! `convert_hf_to_gguf.py` in F32, F16, BF16 or Q8_0 precision are supported.
+//!
+//! The encoder is a straightforward CPU implementation: token, position and
+//! token-type embeddings, then self-attention and feed-forward blocks with
+//! post-layer normalization, followed by mean (or CLS) pooling and L2
+//! normalization.
+
+use anyhow::{Result, anyhow, bail};
+use std::fmt;
+use std::path::Path;
+
+use super::gguf::{GgufFile, Tensor};
+use super::utils::normalize_embedding;
+use super::wordpiece::WordPieceTokenizer;
+
+/// Layer normalization epsilon used when the model does not specify one
+const DEFAULT_LAYER_NORM_EPS: f32 = 1e-12;
+
+/// How token states are combined into a single embedding
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Pooling {
+    Mean,
+    Cls,
+}
+
+/// A fully connected layer: `y = W x + b`
+struct Linear {
+    weight: Vec<f32>,
+    bias: Vec<f32>,
+    inputs: usize,
+    outputs: usize,
+}
+
+impl Linear {
+    fn load(file: &GgufFile, name: &str, inputs: usize, outputs: usize) -> Result<Self> {
+        let weight = load_tensor(file, &format!("{}.weight", name), &[inputs, outputs])?;
+        let bias = if file.has_tensor(&format!("{}.bias", name)) {
+            load_tensor(file, &format!("{}.bias", name), &[outputs])?
+        } else {
+            vec![0.0; outputs]
+        };
+        Ok(Self {
+            weight,
+            bias,
+            inputs,
+            outputs,
+        })
+    }
+
+    /// Apply the layer to each row of `input` (`rows × inputs`)
+    fn forward(&self, input: &[f32]) -> Vec<f32> {
+        let rows = input.len() / self.inputs;
+        let mut output = Vec::with_capacity(rows * self.outputs);
+        for x in input.chunks_exact(self.inputs) {
+            for (w, b) in self.weight.chunks_exact(self.inputs).zip(&self.bias) {
+                output.push(dot(w, x) + b);
+            }
+        }
+        output
+    }
+}
+
+/// Layer normalization over the hidden dimension
+struct LayerNorm {
+    weight: Vec<f32>,
+    bias: Vec<f32>,
+    eps: f32,
+}
+
+impl LayerNorm {
+    fn load(file: &GgufFile, name: &str, hidden: usize, eps: f32) -> Result<Self> {
+        Ok(Self {
+            weight: load_tensor(file, &format!("{}.weight", name), &[hidden])?,
+            bias: load_tensor(file, &format!("{}.bias", name), &[hidden])?,
+            eps,
+        })
+    }
+
+    /// Normalize each row of `states` in place
+    fn forward(&self, states: &mut [f32]) {
+        let hidden = self.weight.len();
+        for row in states.chunks_exact_mut(hidden) {
+            let mean = row.iter().sum::<f32>() / hidden as f32;
+            let variance = row.iter().map(|x| (x - mean).powi(2)).sum::<f32>() / hidden as f32;
+            let scale = 1.0 / (variance + self.eps).sqrt();
+            for ((x, w), b) in row.iter_mut().zip(&self.weight).zip(&self.bias) {
+                *x = (*x - mean) * scale * w + b;
+            }
+        }
+    }
+}
+
+/// One transformer encoder block
+struct EncoderLayer {
+    query: Linear,
+    key: Linear,
+    value: Linear,
+    attention_output: Linear,
+    attention_norm: LayerNorm,
+    intermediate: Linear,
+    output: Linear,
+    output_norm: LayerNorm,
+}
+
+/// A BERT sentence embedding model loaded from a GGUF file
+pub struct LocalEmbeddingModel {
+    name: String,
+    tokenizer: WordPieceTokenizer,
+    hidden: usize,
+    heads: usize,
+    max_positions: usize,
+    pooling: Pooling,
+    token_embeddings: Vec<f32>,
+    position_embeddings: Vec<f32>,
+    token_type_embeddings: Option<Vec<f32>>,
+    embedding_norm: LayerNorm,
+    layers: Vec<EncoderLayer>,
+}
+
+impl fmt::Debug for LocalEmbeddingModel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("LocalEmbeddingModel")
+            .field("name", &self.name)
+            .field("hidden", &self.hidden)
+            .field("layers", &self.layers.len())
+            .field("heads", &self.heads)
+            .finish()
+    }
+}
+
+impl LocalEmbeddingModel {
+    /// Load a model from a GGUF file
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let file = GgufFile::read(path)?;
+        let name = path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_else(|| path.display().to_string());
+        Self::from_gguf(&file, name)
+    }
+
+    /// Build a model from a parsed GGUF file
+    pub fn from_gguf(file: &GgufFile, name: String) -> Result<Self> {
+        let architecture = file.metadata_str("general.architecture").unwrap_or("");
+        if architecture != "bert" {
+            bail!(
+                "Unsupported local embedding model architecture '{}' (only BERT models are supported)",
+                architecture
+            );
+        }
+
+        let required = |key: &str| {
+            file.metadata_u64(&format!("bert.{}", key))
+                .map(|value| value as usize)
+                .ok_or_else(|| anyhow!("Model is missing bert.{} metadata", key))
+        };
+        let hidden = required("embedding_length")?;
+        let block_count = required("block_count")?;
+        let heads = required("attention.head_count")?;
+        let intermediate = required("feed_forward_length")?;
+        let max_positions = required("context_length")?;
+        if heads == 0 || hidden % heads != 0 {
+            bail!(
+                "Hidden size {} is not divisible by {} attention heads",
+                hidden,
+                heads
+            );
+        }
+
+        let eps = file
+            .metadata_f64("bert.attention.layer_norm_epsilon")
+            .map_or(DEFAULT_LAYER_NORM_EPS, |eps| eps as f32);
+        let pooling = match file.metadata_u64("bert.pooling_type") {
+            Some(2) => Pooling::Cls,
+            _ => Pooling::Mean,
+        };
+
+        let tokens = file
+            .metadata_strings("tokenizer.ggml.tokens")
+            .ok_or_else(|| anyhow!("Model is missing its tokenizer vocabulary"))?;
+        let vocab_size = tokens.len();
+        let special = |keys: &[&str], token: &str| {
+            keys.iter()
+                .find_map(|key| file.metadata_u64(key))
+                .map(|id| id as u32)
+                .or_else(|| tokens.iter().position(|t| t == token).map(|id| id as u32))
+                .ok_or_else(|| anyhow!("Model vocabulary has no {} token", token))
+        };
+        let cls = special(
+            &["tokenizer.ggml.cls_token_id", "tokenizer.ggml.bos_token_id"],
+            "[CLS]",
+        )?;
+        let sep = special(
+            &[
+                "tokenizer.ggml.seperator_token_id",
+                "tokenizer.ggml.eos_token_id",
+            ],
+            "[SEP]",
+        )?;
+        let unk = special(&["tokenizer.ggml.unknown_token_id"], "[UNK]")?;
+        let tokenizer = WordPieceTokenizer::new(tokens, cls, sep, unk);
+
+        let token_embeddings = load_tensor(file, "token_embd.weight", &[hidden, vocab_size])?;
+        let position_embeddings =
+            load_tensor(file, "position_embd.weight", &[hidden, max_positions])?;
+        // Only the first token type is used, but the table is checked like the others
+        let token_type_embeddings = if file.has_tensor("token_types.weight") {
+            let Tensor { dims, data } = file.tensor("token_types.weight")?;
+            if dims.len() != 2 || dims[0] != hidden || dims[1] == 0 {
+                bail!(
+                    "Tensor token_types.weight has shape {:?}, expected [{}, <token types>]",
+                    dims,
+                    hidden
+                );
+            }
+            Some(data)
+        } else {
+            None
+        };
+        let embedding_norm = LayerNorm::load(file, "token_embd_norm", hidden, eps)?;
+
+        let layers = (0..block_count)
+            .map(|i| {
+                let block = |name: &str| format!("blk.{}.{}", i, name);
+                Ok(EncoderLayer {
+                    query: Linear::load(file, &block("attn_q"), hidden, hidden)?,
+                    key: Linear::load(file, &block("attn_k"), hidden, hidden)?,
+                    value: Linear::load(file, &block("attn_v"), hidden, hidden)?,
+                    attention_output: Linear::load(file, &block("attn_output"), hidden, hidden)?,
+                    attention_norm: LayerNorm::load(file, &block("attn_output_norm"), hidden, eps)?,
+                    intermediate: Linear::load(file, &block("ffn_up"), hidden, intermediate)?,
+                    output: Linear::load(file, &block("ffn_down"), intermediate, hidden)?,
+                    output_norm: LayerNorm::load(file, &block("layer_output_norm"), hidden, eps)?,
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(Self {
+            name,
+            tokenizer,
+            hidden,
+            heads,
+            max_positions,
+            pooling,
+            token_embeddings,
+            position_embeddings,
+            token_type_embeddings,
+            embedding_norm,
+            layers,
+        })
+    }
+
+    /// Name of the model (its file name)
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Dimension of the produced embeddings
+    pub fn dimension(&self) -> usize {
+        self.hidden
+    }
+
+    /// Compute the normalized embedding of `text`
+    pub fn embed(&self, text: &str) -> Vec<f32> {
+        let ids = self.tokenizer.encode(text, self.max_positions);
+        let hidden = self.hidden;
+
+        let mut states = Vec::with_capacity(ids.len() * hidden);
+        for (position, &id) in ids.iter().enumerate() {
+            let token = &self.token_embeddings[id as usize * hidden..(id as usize + 1) * hidden];
+            let position = &self.position_embeddings[position * hidden..(position + 1) * hidden];
+            for i in 0..hidden {
+                let token_type = self
+                    .token_type_embeddings
+                    .as_ref()
+                    .map_or(0.0, |embeddings| embeddings[i]);
+                states.push(token[i] + position[i] + token_type);
+            }
+        }
+        self.embedding_norm.forward(&mut states);
+
+        for layer in &self.layers {
+            states = self.encoder_layer(layer, states);
+        }
+
+        let mut embedding = match self.pooling {
+            Pooling::Cls => states[..hidden].to_vec(),
+            Pooling::Mean => {
+                let mut pooled = vec![0.0; hidden];
+                for row in states.chunks_exact(hidden) {
+                    for (sum, value) in pooled.iter_mut().zip(row) {
+                        *sum += value;
+                    }
+                }
+                let count = ids.len() as f32;
+                pooled.iter_mut().for_each(|value| *value /= count);
+                pooled
+            }
+        };
+        normalize_embedding(&mut embedding);
+        embedding
+    }
+
+    /// Run one encoder block over the token states
+    fn encoder_layer(&self, layer: &EncoderLayer, mut states: Vec<f32>) -> Vec<f32> {
+        let hidden = self.hidden;
+        let head_dim = hidden / self.heads;
+        let tokens = states.len() / hidden;
+        let scale = 1.0 / (head_dim as f32).sqrt();
+
+        let query = layer.query.forward(&states);
+        let key = layer.key.forward(&states);
+        let value = layer.value.forward(&states);
+
+        let mut context = vec![0.0; states.len()];
+        let mut scores = vec![0.0; tokens];
+        for head in 0..self.heads {
+            let range = head * head_dim..(head + 1) * head_dim;
+            for i in 0..tokens {
+                let q = &query[i * hidden..][range.clone()];
+                for (j, score) in scores.iter_mut().enumerate() {
+                    *score = dot(q, &key[j * hidden..][range.clone()]) * scale;
+                }
+                softmax(&mut scores);
+
+                let output = &mut context[i * hidden..][range.clone()];
+                for (j, weight) in scores.iter().enumerate() {
+                    for (out, v) in output.iter_mut().zip(&value[j * hidden..][range.clone()]) {
+                        *out += weight * v;
+                    }
+                }
+            }
+        }
+
+        let attention = layer.attention_output.forward(&context);
+        states.iter_mut().zip(&attention).for_each(|(x, a)| *x += a);
+        layer.attention_norm.forward(&mut states);
+
+        let mut intermediate = layer.intermediate.forward(&states);
+        intermediate.iter_mut().for_each(|x| *x = gelu(*x));
+        let output = layer.output.forward(&intermediate);
+        states.iter_mut().zip(&output).for_each(|(x, o)| *x += o);
+        layer.output_norm.forward(&mut states);
+
+        states
+    }
+}
+
+/// Load a tensor and check it has the expected shape
+fn load_tensor(file: &GgufFile, name: &str, dims: &[usize]) -> Result<Vec<f32>> {
+    let Tensor { dims: actual, data } = file.tensor(name)?;
+    if actual != dims {
+        bail!(
+            "Tensor {} has shape {:?}, expected {:?}",
+            name,
+            actual,
+            dims
+        );
+    }
+    Ok(data)
+}
+
+fn dot(a: &[f32], b: &[f32]) -> f32 {
+    a.iter().zip(b).map(|(x, y)| x * y).sum()
+}
+
+fn softmax(values: &mut [f32]) {
+    let max = values.iter().copied().fold(f32::NEG_INFINITY, f32::max);
+    let mut sum = 0.0;
+    for value in values.iter_mut() {
+        *value = (*value - max).exp();
+        sum += *value;
+    }
+    values.iter_mut().for_each(|value| *value /= sum);
+}
+
+/// Gaussian error linear unit (exact form, as used by BERT)
+fn gelu(x: f32) -> f32 {
+    0.5 * x * (1.0 + erf(x / std::f32::consts::SQRT_2))
+}
+
+/// Error function (Abramowitz and Stegun 7.1.26, maximum error 1.5e-7)
+fn erf(x: f32) -> f32 {
+    let sign = x.signum();
+    let x = x.abs();
+    let t = 1.0 / (1.0 + 0.327_591_1 * x);
+    let polynomial = t
+        * (0.254_829_6
+            + t * (-0.284_496_74 + t * (1.421_413_7 + t * (-1.453_152_1 + t * 1.061_405_4))));
+    sign * (1.0 - polynomial * (-x * x).exp())
+}
+
+/// Tiny randomly initialised BERT models for tests
+#[cfg(test)]
+pub(crate) mod test_model {
+    use super::super::gguf::writer::GgufWriter;
+
+    pub const HIDDEN: usize = 8;
+
+    const VOCAB: &[&str] = &[
+        "[PAD]", "[UNK]", "[CLS]", "[SEP]", "the", "cat", "dog", "sat", "on", "mat", "car", "##s",
+        ",", ".",
+    ];
+
+    /// Deterministic pseudo-random weights in [-0.5, 0.5)
+    fn weights(seed: &mut u64, len: usize) -> Vec<f32> {
+        (0..len)
+            .map(|_| {
+                *seed = seed
+                    .wrapping_mul(6_364_136_223_846_793_005)
+                    .wrapping_add(1_442_695_040_888_963_407);
+                (*seed >> 40) as f32 / (1u64 << 24) as f32 - 0.5
+            })
+            .collect()
+    }
+
+    /// Build the bytes of a two-layer BERT model in GGUF format
+    pub fn gguf_bytes() -> Vec<u8> {
+        gguf_bytes_with_token_types(&[HIDDEN, 2])
+    }
+
+    /// [`gguf_bytes`] with a token type table of the given shape
+    pub fn gguf_bytes_with_token_types(token_type_dims: &[usize]) -> Vec<u8> {
+        let intermediate = 16;
+        let positions = 16;
+        let mut seed = 42;
+
+        let mut writer = GgufWriter::default()
+            .string("general.architecture", "bert")
+            .u32("bert.embedding_length", HIDDEN as u32)
+            .u32("bert.block_count", 2)
+            .u32("bert.attention.head_count", 2)
+            .u32("bert.feed_forward_length", intermediate as u32)
+            .u32("bert.context_length", positions as u32)
+            .f32("bert.attention.layer_norm_epsilon", 1e-12)
+            .u32("bert.pooling_type", 1)
+            .strings("tokenizer.ggml.tokens", VOCAB)
+            .tensor(
+                "token_embd.weight",
+                &[HIDDEN, VOCAB.len()],
+                weights(&mut seed, HIDDEN * VOCAB.len()),
+            )
+            .tensor(
+                "position_embd.weight",
+                &[HIDDEN, positions],
+                weights(&mut seed, HIDDEN * positions),
+            )
+            .tensor(
+                "token_types.weight",
+                token_type_dims,
+                weights(&mut seed, token_type_dims.iter().product()),
+            )
+            .tensor("token_embd_norm.weight", &[HIDDEN], vec![1.0; HIDDEN])
+            .tensor("token_embd_norm.bias", &[HIDDEN], vec![0.0; HIDDEN]);
+
+        for block in 0..2 {
+            for (name, inputs, outputs) in [
+                ("attn_q", HIDDEN, HIDDEN),
+                ("attn_k", HIDDEN, HIDDEN),
+                ("attn_v", HIDDEN, HIDDEN),
+                ("attn_output", HIDDEN, HIDDEN),
+                ("ffn_up", HIDDEN, intermediate),
+                ("ffn_down", intermediate, HIDDEN),
+            ] {
+                writer = writer
+                    .tensor(
+                        &format!("blk.{}.{}.weight", block, name),
+                        &[inputs, outputs],
+                        weights(&mut seed, inputs * outputs),
+                    )
+                    .tensor(
+                        &format!("blk.{}.{}.bias", block, name),
+                        &[outputs],
+                        weights(&mut seed, outputs),
+                    );
+            }
+            for name in ["attn_output_norm", "layer_output_norm"] {
+                writer = writer
+                    .tensor(
+                        &format!("blk.{}.{}.weight", block, name),
+                        &[HIDDEN],
+                        vec![1.0; HIDDEN],
+                    )
+                    .tensor(
+                        &format!("blk.{}.{}.bias", block, name),
+                        &[HIDDEN],
+                        vec![0.0; HIDDEN],
+                    );
+            }
+        }
+
+        writer.build()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn model() -> LocalEmbeddingModel {
+        let file = GgufFile::parse(test_model::gguf_bytes()).unwrap();
+        LocalEmbeddingModel::from_gguf(&file, "tiny-bert.gguf".to_string()).unwrap()
+    }
+
+    #[test]
+    fn test_load_model() {
+        let model = model();
+        assert_eq!(model.name(), "tiny-bert.gguf");
+        assert_eq!(model.dimension(), test_model::HIDDEN);
+        assert_eq!(model.layers.len(), 2);
+        assert_eq!(model.pooling, Pooling::Mean);
+    }
+
+    #[test]
+    fn test_embed_is_normalized_and_deterministic() {
+        let model = model();
+        let embedding = model.embed("The cat sat on the mat.");
+        assert_eq!(embedding.len(), test_model::HIDDEN);
+        assert!(embedding.iter().all(|value| value.is_finite()));
+
+        let norm: f32 = embedding.iter().map(|x| x * x).sum::<f32>().sqrt();
+        assert!((norm - 1.0).abs() < 1e-5);
+
+        assert_eq!(embedding, model.embed("The cat sat on the mat."));
+        assert_ne!(embedding, model.embed("The dog sat on the cars."));
+    }
+
+    /// Embedding of "the cats sat" by the test model, computed in f64 by an
+    /// independent implementation of the BERT encoder
+    const REFERENCE_EMBEDDING: [f32; test_model::HIDDEN] = [
+        -0.289766, -0.121030, -0.583416, 0.275419, -0.277876, 0.478648, 0.109569, 0.408451,
+    ];
+
+    #[test]
+    fn test_embed_matches_reference() {
+        let embedding = model().embed("the cats sat");
+        for (actual, expected) in embedding.iter().zip(REFERENCE_EMBEDDING) {
+            assert!(
+                (actual - expected).abs() < 1e-4,
+                "{:?} differs from {:?}",
+                embedding,
+                REFERENCE_EMBEDDING
+            );
+        }
+    }
+
+    #[test]
+    fn test_rejects_malformed_token_types() {
+        let bytes = test_model::gguf_bytes_with_token_types(&[test_model::HIDDEN / 2, 1]);
+        let file = GgufFile::parse(bytes).unwrap();
+        let error = LocalEmbeddingModel::from_gguf(&file, "bad.gguf".to_string()).unwrap_err();
+        assert!(error.to_string().contains("token_types.weight"));
+    }
+
+    #[test]
+    fn test_embed_truncates_long_input() {
+        let model = model();
+        let long_text = "the cat sat on the mat ".repeat(20);
+        assert_eq!(model.embed(&long_text).len(), test_model::HIDDEN);
+    }
+
+    #[test]
+    fn test_rejects_other_architectures() {
+        let bytes = super::super::gguf::writer::GgufWriter::default()
+            .string("general.architecture", "llama")
+            .build();
+        let file = GgufFile::parse(bytes).unwrap();
+        let error = LocalEmbeddingModel::from_gguf(&file, "llama.gguf".to_string()).unwrap_err();
+        assert!(error.to_string().contains("only BERT"));
+    }
+
+    #[test]
+    fn test_gelu() {
+        assert!(gelu(0.0).abs() < 1e-7);
+        assert!((gelu(1.0) - 0.841_344_7).abs() < 1e-5);
+        assert!((gelu(-1.0) + 0.158_655_3).abs() < 1e-5);
+    }
+}
@@ -2,8 +2,16 @@ use anyhow::Result;
 use rig::client::EmbeddingsClient;
 use rig::embeddings::EmbeddingsBuilder;
 use rig::providers;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
 use tracing::{debug, warn};
 
+pub mod gguf;
+pub mod local;
+pub mod wordpiece;
+
+pub use local::LocalEmbeddingModel;
+
 /// Embedding service that generates embeddings for text using real models
 #[derive(Debug)]
 pub struct EmbeddingService {
@@ -25,6 +33,11 @@ impl EmbeddingService {
 
         match &self.provider {
             EmbeddingProvider::Simple { dimension } => self.embed_simple(text, *dimension).await,
+            EmbeddingProvider::Local { model } => {
+                let model = model.clone();
+                let text = text.to_string();
+                Ok(tokio::task::spawn_blocking(move || model.embed(&text)).await?)
+            }
             EmbeddingProvider::OpenAI { client, model } => {
                 let embedding_model = client.embedding_model(model);
                 let embeddings = EmbeddingsBuilder::new(embedding_model)
@@ -72,6 +85,14 @@ impl EmbeddingService {
                 }
                 Ok(embeddings)
             }
+            EmbeddingProvider::Local { model } => {
+                let model = model.clone();
+                let texts = texts.to_vec();
+                Ok(tokio::task::spawn_blocking(move || {
+                    texts.iter().map(|text| model.embed(text)).collect()
+                })
+                .await?)
+            }
             EmbeddingProvider::OpenAI { client, model } => {
                 let embedding_model = client.embedding_model(model);
                 let mut builder = EmbeddingsBuilder::new(embedding_model);
@@ -111,10 +132,20 @@ impl EmbeddingService {
         }
     }
 
+    /// Identify the model producing this service's embeddings
+    pub fn model_info(&self) -> EmbeddingModelInfo {
+        EmbeddingModelInfo::new(
+            self.provider.name(),
+            self.provider.model_name(),
+            self.dimension(),
+        )
+    }
+
     /// Get the embedding dimension
     pub fn dimension(&self) -> usize {
         match &self.provider {
             EmbeddingProvider::Simple { dimension } => *dimension,
+            EmbeddingProvider::Local { model } => model.dimension(),
             EmbeddingProvider::OpenAI { client: _, model } => {
                 // Common OpenAI embedding dimensions
                 match model.as_str() {
@@ -134,7 +165,7 @@ impl EmbeddingService {
                     _ => {
                         // For unknown models, default to 768 as it's more common for newer models
                         // Users can extend this match statement for other models
-                        // Note: If you change dimensions, you may need to delete existing context databases
+                        // Note: After changing models, run `vega reindex` to re-embed existing context
                         768
                     }
                 }
@@ -192,11 +223,69 @@ impl EmbeddingService {
     }
 }
 
+/// Identifies the provider, model and dimension that produced an embedding.
+///
+/// Embeddings are only comparable when they come from the same model, so the
+/// context store records this alongside every stored vector.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EmbeddingModelInfo {
+    pub provider: String,
+    pub model: String,
+    pub dimension: usize,
+}
+
+impl EmbeddingModelInfo {
+    /// Create model information for a provider and model
+    pub fn new(provider: impl Into<String>, model: impl Into<String>, dimension: usize) -> Self {
+        Self {
+            provider: provider.into(),
+            model: model.into(),
+            dimension,
+        }
+    }
+
+    /// Model information for embeddings of unknown origin, matched by dimension only
+    pub fn untagged(dimension: usize) -> Self {
+        Self::new("", "", dimension)
+    }
+
+    /// Whether the provider and model are unknown
+    pub fn is_untagged(&self) -> bool {
+        self.provider.is_empty()
+    }
+
+    /// Whether embeddings from the two models can be compared.
+    ///
+    /// Dimensions must match; untagged models are otherwise compatible with any model.
+    pub fn is_compatible_with(&self, other: &EmbeddingModelInfo) -> bool {
+        self.dimension == other.dimension
+            && (self.is_untagged()
+                || other.is_untagged()
+                || (self.provider == other.provider && self.model == other.model))
+    }
+}
+
+impl std::fmt::Display for EmbeddingModelInfo {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.is_untagged() {
+            write!(f, "untagged ({} dimensions)", self.dimension)
+        } else {
+            write!(
+                f,
+                "{}/{} ({} dimensions)",
+                self.provider, self.model, self.dimension
+            )
+        }
+    }
+}
+
 /// Configuration for different embedding providers
 #[derive(Clone, Debug)]
 pub enum EmbeddingProvider {
     /// Simple hash-based embeddings (for development/testing)
     Simple { dimension: usize },
+    /// In-process BERT model loaded from a local GGUF file (works offline)
+    Local { model: Arc<LocalEmbeddingModel> },
     /// OpenAI embeddings (requires API key)
     OpenAI {
         client: providers::openai::Client,
@@ -218,6 +307,16 @@ impl EmbeddingProvider {
     ) -> Result<Self> {
         match provider_name {
             "simple" => Ok(EmbeddingProvider::Simple { dimension: 384 }),
+            "local" => {
+                let path = model.ok_or_else(|| {
+                    anyhow::anyhow!("The local embedding provider requires --embedding-model <path to a GGUF model file>.")
+                })?;
+                let model = LocalEmbeddingModel::load(path)?;
+
+                Ok(EmbeddingProvider::Local {
+                    model: Arc::new(model),
+                })
+            }
             "openai" => {
                 let api_key = openai_api_key.ok_or_else(|| {
                     anyhow::anyhow!("OpenAI API key is required for OpenAI embedding provider. Set --openai-api-key or OPENAI_API_KEY environment variable.")
@@ -235,12 +334,33 @@ impl EmbeddingProvider {
                 Ok(EmbeddingProvider::Ollama { client, model })
             }
             _ => Err(anyhow::anyhow!(
-                "Unsupported embedding provider: {}. Supported providers: simple, openai, ollama",
+                "Unsupported embedding provider: {}. Supported providers: simple, local, openai, ollama",
                 provider_name
             )),
         }
     }
 
+    /// Name of the provider, as accepted by [`EmbeddingProvider::new`]
+    pub fn name(&self) -> &'static str {
+        match self {
+            EmbeddingProvider::Simple { .. } => "simple",
+            EmbeddingProvider::Local { .. } => "local",
+            EmbeddingProvider::OpenAI { .. } => "openai",
+            EmbeddingProvider::Ollama { .. } => "ollama",
+        }
+    }
+
+    /// Name of the embedding model
+    pub fn model_name(&self) -> &str {
+        match self {
+            EmbeddingProvider::Simple { .. } => "hash",
+            EmbeddingProvider::Local { model } => model.name(),
+            EmbeddingProvider::OpenAI { model, .. } | EmbeddingProvider::Ollama { model, .. } => {
+                model
+            }
+        }
+    }
+
     /// Create an embedding service from the provider configuration
     pub fn create_service(&self) -> EmbeddingService {
         EmbeddingService::new(self.clone())
@@ -362,6 +482,52 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_embedding_model_info() {
+        let service = EmbeddingService::new(EmbeddingProvider::Simple { dimension: 8 });
+        assert_eq!(
+            service.model_info(),
+            EmbeddingModelInfo::new("simple", "hash", 8)
+        );
+
+        let provider = EmbeddingProvider::new("ollama", Some("all-minilm"), None).unwrap();
+        let info = provider.create_service().model_info();
+        assert_eq!(info.to_string(), "ollama/all-minilm (384 dimensions)");
+        assert!(!info.is_untagged());
+        assert!(EmbeddingModelInfo::untagged(384).is_untagged());
+
+        assert!(info.is_compatible_with(&EmbeddingModelInfo::untagged(384)));
+        assert!(!info.is_compatible_with(&EmbeddingModelInfo::untagged(768)));
+        assert!(!info.is_compatible_with(&EmbeddingModelInfo::new("openai", "all-minilm", 384)));
+    }
+
+    #[tokio::test]
+    async fn test_local_embedding_provider() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let path = temp_dir.path().join("tiny-bert.gguf");
+        std::fs::write(&path, local::test_model::gguf_bytes()).unwrap();
+
+        let provider = EmbeddingProvider::new("local", path.to_str(), None).unwrap();
+        let service = provider.create_service();
+        assert_eq!(
+            service.model_info(),
+            EmbeddingModelInfo::new("local", "tiny-bert.gguf", local::test_model::HIDDEN)
+        );
+
+        let embedding = service.embed("the cat sat").await.unwrap();
+        assert_eq!(embedding.len(), local::test_model::HIDDEN);
+        let batch = service
+            .embed_batch(&["the cat sat".to_string(), "the dog".to_string()])
+            .await
+            .unwrap();
+        assert_eq!(batch.len(), 2);
+        assert_eq!(batch[0], embedding);
+
+        assert!(EmbeddingProvider::new("local", None, None).is_err());
+        let missing = temp_dir.path().join("missing.gguf");
+        assert!(EmbeddingProvider::new("local", missing.to_str(), None).is_err());
+    }
+
     #[test]
     fn test_ollama_embedding_dimensions() {
         // Test nomic-embed-text model returns 768 dimensions
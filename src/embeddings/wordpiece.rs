@@ -0,0 +1,195 @@
+//! WordPiece tokenizer for BERT-style embedding models
+//!
+//! Text is lowercased, split on whitespace and punctuation, and each word is
+//! broken into the longest vocabulary pieces available (greedy longest-match).
+//! Both vocabulary conventions are supported: Hugging Face `vocab.txt` style,
+//! where continuation pieces start with `##`, and the llama.cpp GGUF conversion,
+//! where word-initial pieces start with `▁` instead.
+
+use std::collections::HashMap;
+
+/// Longest word (in characters) that is split into pieces; longer words are unknown
+const MAX_WORD_CHARS: usize = 100;
+
+/// Marker the llama.cpp conversion puts in front of word-initial pieces
+const WORD_START_MARKER: char = '\u{2581}';
+
+/// How pieces after the start of a word are distinguished in the vocabulary
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum PieceStyle {
+    /// Continuation pieces start with `##`
+    ContinuationPrefix,
+    /// Word-initial pieces start with `▁`
+    WordStartMarker,
+}
+
+/// Greedy longest-match-first WordPiece tokenizer
+#[derive(Debug, Clone)]
+pub struct WordPieceTokenizer {
+    vocab: HashMap<String, u32>,
+    style: PieceStyle,
+    cls: u32,
+    sep: u32,
+    unk: u32,
+}
+
+impl WordPieceTokenizer {
+    /// Create a tokenizer from a vocabulary (token id = index) and special token ids
+    pub fn new(tokens: Vec<String>, cls: u32, sep: u32, unk: u32) -> Self {
+        let style = if tokens.iter().any(|token| token.starts_with("##")) {
+            PieceStyle::ContinuationPrefix
+        } else if tokens
+            .iter()
+            .any(|token| token.starts_with(WORD_START_MARKER))
+        {
+            PieceStyle::WordStartMarker
+        } else {
+            PieceStyle::ContinuationPrefix
+        };
+
+        let vocab = tokens
+            .into_iter()
+            .enumerate()
+            .map(|(id, token)| (token, id as u32))
+            .collect();
+
+        Self {
+            vocab,
+            style,
+            cls,
+            sep,
+            unk,
+        }
+    }
+
+    /// Find the id of a token in the vocabulary
+    pub fn token_id(&self, token: &str) -> Option<u32> {
+        self.vocab.get(token).copied()
+    }
+
+    /// Encode text as `[CLS] pieces... [SEP]`, truncated to at most `max_len` ids
+    pub fn encode(&self, text: &str, max_len: usize) -> Vec<u32> {
+        let mut ids = vec![self.cls];
+        for word in split_words(text) {
+            ids.extend(self.word_pieces(&word));
+        }
+        ids.truncate(max_len.saturating_sub(1).max(1));
+        ids.push(self.sep);
+        ids
+    }
+
+    /// Split one word into vocabulary pieces, or `[UNK]` if it cannot be covered
+    fn word_pieces(&self, word: &str) -> Vec<u32> {
+        let chars: Vec<char> = word.chars().collect();
+        if chars.len() > MAX_WORD_CHARS {
+            return vec![self.unk];
+        }
+
+        let mut pieces = Vec::new();
+        let mut start = 0;
+        while start < chars.len() {
+            let mut end = chars.len();
+            let mut found = None;
+            while start < end {
+                let piece: String = chars[start..end].iter().collect();
+                let candidate = match (self.style, start) {
+                    (PieceStyle::ContinuationPrefix, 0) => piece,
+                    (PieceStyle::ContinuationPrefix, _) => format!("##{}", piece),
+                    (PieceStyle::WordStartMarker, 0) => format!("{}{}", WORD_START_MARKER, piece),
+                    (PieceStyle::WordStartMarker, _) => piece,
+                };
+                if let Some(&id) = self.vocab.get(&candidate) {
+                    found = Some(id);
+                    break;
+                }
+                end -= 1;
+            }
+
+            match found {
+                Some(id) => pieces.push(id),
+                None => return vec![self.unk],
+            }
+            start = end;
+        }
+        pieces
+    }
+}
+
+/// Lowercase text and split it into words, with each punctuation mark its own word
+fn split_words(text: &str) -> Vec<String> {
+    let mut words = Vec::new();
+    let mut current = String::new();
+
+    for c in text.chars().flat_map(char::to_lowercase) {
+        if c.is_whitespace() || c.is_control() {
+            if !current.is_empty() {
+                words.push(std::mem::take(&mut current));
+            }
+        } else if c.is_alphanumeric() {
+            current.push(c);
+        } else {
+            if !current.is_empty() {
+                words.push(std::mem::take(&mut current));
+            }
+            words.push(c.to_string());
+        }
+    }
+
+    if !current.is_empty() {
+        words.push(current);
+    }
+    words
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tokenizer(tokens: &[&str]) -> WordPieceTokenizer {
+        WordPieceTokenizer::new(tokens.iter().map(|t| t.to_string()).collect(), 1, 2, 0)
+    }
+
+    #[test]
+    fn test_split_words() {
+        assert_eq!(
+            split_words("Hello, World!  run_tests"),
+            vec!["hello", ",", "world", "!", "run", "_", "tests"]
+        );
+    }
+
+    #[test]
+    fn test_encode_continuation_prefix_vocab() {
+        let tokenizer = tokenizer(&[
+            "[UNK]", "[CLS]", "[SEP]", "un", "##aff", "##able", "hello", ",",
+        ]);
+        assert_eq!(
+            tokenizer.encode("Hello, unaffable xyz", 512),
+            vec![1, 6, 7, 3, 4, 5, 0, 2]
+        );
+    }
+
+    #[test]
+    fn test_encode_word_start_marker_vocab() {
+        let tokenizer = tokenizer(&[
+            "[UNK]",
+            "[CLS]",
+            "[SEP]",
+            "\u{2581}un",
+            "aff",
+            "able",
+            "\u{2581}hello",
+            "\u{2581},",
+        ]);
+        assert_eq!(
+            tokenizer.encode("Hello, unaffable xyz", 512),
+            vec![1, 6, 7, 3, 4, 5, 0, 2]
+        );
+    }
+
+    #[test]
+    fn test_encode_truncates() {
+        let tokenizer = tokenizer(&["[UNK]", "[CLS]", "[SEP]", "a"]);
+        assert_eq!(tokenizer.encode("a a a a a", 4), vec![1, 3, 3, 2]);
+        assert_eq!(tokenizer.token_id("a"), Some(3));
+    }
+}
@@ -8,7 +8,7 @@
 //!
 //! - **Multiple LLM Providers**: Support for Ollama (local) and OpenRouter (cloud) providers
 //! - **Tool System**: Comprehensive set of tools for file operations, web search, code analysis
-//! - **Context Management**: Persistent conversation history with embedding-based retrieval
+//! - **Context Management**: Persistent conversation history with hybrid full-text and embedding retrieval
 //! - **Agent Instructions**: Flexible instruction system using AGENTS.md and VEGA.md files
 //! - **Web Interface**: Optional web interface for session management and monitoring
 //! - **ACP Support**: Compatible with Agent Client Protocol for editor integration
@@ -48,6 +48,7 @@
 //! - [`providers`] - LLM provider implementations (Ollama, OpenRouter)
 //! - [`streaming`] - Streaming progress indicators for LLM operations
 //! - [`tools`] - Tool system for file operations, web search, and system interaction
+//! - [`vector_index`] - Approximate nearest neighbour index for context embeddings
 //! - [`web`] - Web interface for session management and monitoring
 
 pub mod acp;
@@ -58,9 +59,11 @@ pub mod embeddings;
 pub mod input;
 pub mod logging;
 pub mod mcp;
+pub mod project;
 pub mod providers;
 pub mod streaming;
 pub mod tools;
+pub mod vector_index;
 pub mod web;
 
 // Re-export the Inter-Agent Communication Protocol crate
@@ -153,6 +153,44 @@ impl LogEntry {
         self
     }
 
+    /// Reconstruct a log entry written to the context store by the vector store output.
+    ///
+    /// Returns `None` for entries that are not logs or lack the log metadata.
+    pub fn from_context_entry(entry: &crate::context::ContextEntry) -> Option<Self> {
+        // Only include log entries (role = "log")
+        if entry.role != "log" {
+            return None;
+        }
+
+        let log_level = entry.metadata.get("log_level")?;
+        let log_id = entry.metadata.get("log_id")?;
+        let timestamp = DateTime::parse_from_rfc3339(entry.metadata.get("timestamp")?).ok()?;
+
+        let metadata = entry
+            .metadata
+            .iter()
+            .filter_map(|(k, v)| Some((k.strip_prefix("meta_")?.to_string(), v.clone())))
+            .collect();
+
+        Some(LogEntry {
+            id: log_id.clone(),
+            timestamp: timestamp.with_timezone(&Utc),
+            level: log_level.clone(),
+            message: entry
+                .content
+                .split(" | ")
+                .find_map(|part| part.strip_prefix("Message: "))
+                .map(str::to_string)
+                .unwrap_or_else(|| entry.content.clone()),
+            session_id: entry.session_id.clone(),
+            module: entry.metadata.get("module").cloned(),
+            file: entry.metadata.get("file").cloned(),
+            line: entry.metadata.get("line").and_then(|s| s.parse().ok()),
+            target: entry.metadata.get("target").cloned(),
+            metadata,
+        })
+    }
+
     /// Format as a human-readable string for console output
     pub fn format_console(&self) -> String {
         let timestamp = self.timestamp.format("%Y-%m-%d %H:%M:%S%.3f UTC");
@@ -213,6 +251,14 @@ pub struct Logger {
     embedding_service: Option<Arc<EmbeddingService>>,
 }
 
+impl std::fmt::Debug for Logger {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Logger")
+            .field("config", &self.config)
+            .finish_non_exhaustive()
+    }
+}
+
 impl Logger {
     /// Create a new logger with the given configuration
     pub fn new(config: LoggerConfig) -> Result<Self> {
@@ -416,54 +462,10 @@ impl Logger {
     ) -> Result<Vec<LogEntry>> {
         if let Some(ref context_store) = self.context_store {
             let entries = context_store.get_session_history(session_id, limit).await?;
-
-            let mut log_entries = Vec::new();
-            for entry in entries {
-                // Only include log entries (role = "log")
-                if entry.role == "log" {
-                    // Try to reconstruct the log entry from metadata
-                    if let (Some(log_level), Some(log_id), Some(timestamp_str)) = (
-                        entry.metadata.get("log_level"),
-                        entry.metadata.get("log_id"),
-                        entry.metadata.get("timestamp"),
-                    ) {
-                        if let Ok(timestamp) = DateTime::parse_from_rfc3339(timestamp_str) {
-                            let mut metadata = HashMap::new();
-                            for (k, v) in &entry.metadata {
-                                if k.starts_with("meta_") {
-                                    metadata.insert(
-                                        k.strip_prefix("meta_").unwrap().to_string(),
-                                        v.clone(),
-                                    );
-                                }
-                            }
-
-                            let log_entry = LogEntry {
-                                id: log_id.clone(),
-                                timestamp: timestamp.with_timezone(&Utc),
-                                level: log_level.clone(),
-                                message: entry
-                                    .content
-                                    .split(" | ")
-                                    .find(|part| part.starts_with("Message: "))
-                                    .map(|part| {
-                                        part.strip_prefix("Message: ").unwrap_or("").to_string()
-                                    })
-                                    .unwrap_or_else(|| entry.content.clone()),
-                                session_id: entry.session_id,
-                                module: entry.metadata.get("module").cloned(),
-                                file: entry.metadata.get("file").cloned(),
-                                line: entry.metadata.get("line").and_then(|s| s.parse().ok()),
-                                target: entry.metadata.get("target").cloned(),
-                                metadata,
-                            };
-                            log_entries.push(log_entry);
-                        }
-                    }
-                }
-            }
-
-            Ok(log_entries)
+            Ok(entries
+                .iter()
+                .filter_map(LogEntry::from_context_entry)
+                .collect())
         } else {
             Ok(Vec::new())
         }
@@ -1,7 +1,9 @@
-use anyhow::Result;
-use clap::Parser;
+use anyhow::{Context, Result};
+use clap::{Parser, Subcommand};
 
-use std::path::PathBuf;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
 // Main module - uses custom logger for all output
 use uuid::Uuid;
 
@@ -13,29 +15,36 @@ pub mod embeddings;
 pub mod input;
 pub mod logging;
 pub mod mcp;
+pub mod project;
 pub mod providers;
 pub mod streaming;
 pub mod tools;
 pub mod tui;
+pub mod vector_index;
 pub mod web;
 
 use crate::agent_instructions::AgentInstructionLoader;
 use crate::web::start_web_server_with_logger;
 use agents::chat::ChatAgent;
+use agents::history::TruncationPolicy;
 use agents::{Agent, AgentConfig};
-use context::ContextStore;
+use context::{ContextStore, ExportFormat, SessionExport};
+use embeddings::EmbeddingService;
 use logging::{LogLevel, Logger, LoggerConfig};
+use project::Project;
+use tools::output::{DEFAULT_HEAD_LINES, DEFAULT_TAIL_LINES};
+use tools::{OutputLimit, PermissionPolicy, Sandbox, Workspace};
 
 #[derive(Parser, Debug)]
 #[command(
     name = "vega",
     about = "An AI chat agent built with Rust and the Rig framework",
-    long_about = "Vega is a command-line AI chat agent that supports multiple LLM providers including Ollama and OpenRouter. \
+    long_about = "Vega is a command-line AI chat agent that supports multiple LLM providers including Ollama, OpenRouter, Anthropic and OpenAI. \
                   It provides an interactive chat interface with persistent context across sessions.\n\n\
                   Environment Variables:\n\
-                  - VEGA_PROVIDER: Set the LLM provider (ollama, openrouter)\n\
+                  - VEGA_PROVIDER: Set the LLM provider (ollama, openrouter, anthropic, openai)\n\
                   - VEGA_MODEL: Set the model name\n\
-                  - VEGA_EMBEDDING_PROVIDER: Set the embedding provider (openai, ollama, simple)\n\
+                  - VEGA_EMBEDDING_PROVIDER: Set the embedding provider (openai, ollama, local, simple)\n\
                   - VEGA_EMBEDDING_MODEL: Set the embedding model name\n\
                   - VEGA_CONTEXT_DB: Set the context database path\n\
                   - VEGA_SESSION_ID: Set the session ID for context sharing\n\
@@ -43,17 +52,20 @@ use logging::{LogLevel, Logger, LoggerConfig};
                   - VEGA_LOG_FILE: Set the log file path\n\
                   - VEGA_LOG_STRUCTURED: Enable structured JSON logging\n\
                   - VEGA_LOG_LEVEL: Set log level (error, warn, info, debug, trace)\n\
+                  - VEGA_HISTORY_TOKEN_BUDGET: Set the conversation history token budget (default: 4000)\n\
+                  - VEGA_HISTORY_TRUNCATION: Set the history truncation policy (drop-oldest, keep-first)\n\
+                  - VEGA_COMPACT_THRESHOLD: Set the history size in tokens that triggers summarization (default: 8000, 0 disables)\n\
                   - VEGA_COMMAND_HISTORY_LENGTH: Set command history length (default: 100)\n\
                   - OPENROUTER_API_KEY: Set the OpenRouter API key\n\
                   - ANTHROPIC_API_KEY: Set the Anthropic API key\n\
-                  - OPENAI_API_KEY: Set the OpenAI API key for embeddings"
+                  - OPENAI_API_KEY: Set the OpenAI API key for the openai provider and embeddings"
 )]
 struct Args {
     /// Enable verbose logging
     #[arg(short, long)]
     verbose: bool,
 
-    /// LLM provider to use (ollama or openrouter)
+    /// LLM provider to use (ollama, openrouter, anthropic, or openai)
     /// Can also be set via VEGA_PROVIDER environment variable
     #[arg(short, long, env = "VEGA_PROVIDER", default_value = "ollama")]
     provider: String,
@@ -73,17 +85,17 @@ struct Args {
     #[arg(long, env)]
     anthropic_api_key: Option<String>,
 
-    /// Embedding provider to use (openai, ollama, or simple)
+    /// Embedding provider to use (openai, ollama, local, or simple)
     /// Can also be set via VEGA_EMBEDDING_PROVIDER environment variable
     #[arg(long, env = "VEGA_EMBEDDING_PROVIDER", default_value = "simple")]
     embedding_provider: String,
 
-    /// Embedding model name to use
+    /// Embedding model name to use (a GGUF model file path for the local provider)
     /// Can also be set via VEGA_EMBEDDING_MODEL environment variable
     #[arg(long, env = "VEGA_EMBEDDING_MODEL")]
     embedding_model: Option<String>,
 
-    /// OpenAI API key (required if using openai embedding provider)
+    /// OpenAI API key (required if using the openai provider or embedding provider)
     /// Can also be set via OPENAI_API_KEY environment variable
     #[arg(long, env)]
     openai_api_key: Option<String>,
@@ -106,6 +118,31 @@ struct Args {
     #[arg(long)]
     yolo: bool,
 
+    /// Run the bash tool in one shell per session, keeping its working directory and environment
+    /// Can also be set via VEGA_PERSISTENT_SHELL environment variable
+    #[arg(long, env = "VEGA_PERSISTENT_SHELL")]
+    persistent_shell: bool,
+
+    /// Run bash commands in a sandbox confined to the project, even if .vega/settings.json does not enable it
+    /// Can also be set via VEGA_SANDBOX environment variable
+    #[arg(long, env = "VEGA_SANDBOX")]
+    sandbox: bool,
+
+    /// Directory besides the project root the file tools may access (repeatable)
+    /// Can also be set via VEGA_ALLOW_DIRS environment variable (comma-separated)
+    #[arg(long = "allow-dir", env = "VEGA_ALLOW_DIRS", value_delimiter = ',')]
+    allow_dirs: Vec<PathBuf>,
+
+    /// Lines from the start of each command output stream returned to the model
+    /// Can also be set via VEGA_OUTPUT_HEAD_LINES environment variable
+    #[arg(long, env = "VEGA_OUTPUT_HEAD_LINES", default_value_t = DEFAULT_HEAD_LINES)]
+    output_head_lines: usize,
+
+    /// Lines from the end of each command output stream returned to the model
+    /// Can also be set via VEGA_OUTPUT_TAIL_LINES environment variable
+    #[arg(long, env = "VEGA_OUTPUT_TAIL_LINES", default_value_t = DEFAULT_TAIL_LINES)]
+    output_tail_lines: usize,
+
     /// Log output destination (console, file, vector, or combinations like "console,file")
     /// Can also be set via VEGA_LOG_OUTPUT environment variable
     #[arg(long, env = "VEGA_LOG_OUTPUT", default_value = "console")]
@@ -125,6 +162,21 @@ struct Args {
     #[arg(long)]
     acp: bool,
 
+    /// Maximum tokens of session history sent to the model as conversation turns
+    /// Can also be set via VEGA_HISTORY_TOKEN_BUDGET environment variable
+    #[arg(long, env = "VEGA_HISTORY_TOKEN_BUDGET", default_value = "4000")]
+    history_token_budget: usize,
+
+    /// How history is trimmed to fit the budget (drop-oldest or keep-first)
+    /// Can also be set via VEGA_HISTORY_TRUNCATION environment variable
+    #[arg(long, env = "VEGA_HISTORY_TRUNCATION", default_value = "drop-oldest")]
+    history_truncation: TruncationPolicy,
+
+    /// Unsummarized history tokens after which older turns are summarized (0 disables)
+    /// Can also be set via VEGA_COMPACT_THRESHOLD environment variable
+    #[arg(long, env = "VEGA_COMPACT_THRESHOLD", default_value = "8000")]
+    compact_threshold: usize,
+
     /// Command history length (default: 100)
     /// Can also be set via VEGA_COMMAND_HISTORY_LENGTH environment variable
     #[arg(long, env = "VEGA_COMMAND_HISTORY_LENGTH", default_value = "100")]
@@ -138,11 +190,25 @@ struct Args {
     #[arg(long, default_value = "vega-mcp-server")]
     mcp_server_name: String,
 
+    /// Serve the MCP server over Streamable HTTP on this address instead of stdio
+    #[arg(long, value_name = "ADDR", requires = "mcp_server")]
+    mcp_listen: Option<SocketAddr>,
+
+    /// Bearer token clients must send to the MCP server over Streamable HTTP
+    /// Can also be set via VEGA_MCP_TOKEN environment variable
+    #[arg(
+        long,
+        value_name = "TOKEN",
+        env = "VEGA_MCP_TOKEN",
+        hide_env_values = true
+    )]
+    mcp_token: Option<String>,
+
     /// Enable MCP client connections
     #[arg(long)]
     mcp_client: bool,
 
-    /// MCP client configuration file path
+    /// MCP configuration file path
     #[arg(long)]
     mcp_config: Option<PathBuf>,
 
@@ -153,6 +219,204 @@ struct Args {
     /// Enable debug output for troubleshooting
     #[arg(long)]
     debug_startup: bool,
+
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Re-embed stored context with the configured embedding provider and model
+    Reindex {
+        /// Number of entries sent to the embedding provider per request
+        #[arg(long, default_value = "32")]
+        batch_size: usize,
+    },
+    /// Export or import conversation sessions
+    Session {
+        #[command(subcommand)]
+        command: SessionCommand,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum SessionCommand {
+    /// Export a session's entries, metadata and command history
+    Export {
+        /// Session to export
+        session_id: String,
+        /// Output format: json, markdown or jsonl (default: from the output file extension, else json)
+        #[arg(long)]
+        format: Option<ExportFormat>,
+        /// File to write the export to (default: standard output)
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+        /// Include stored embeddings so the session is searchable without re-embedding
+        #[arg(long)]
+        embeddings: bool,
+    },
+    /// Import a session from an export file
+    Import {
+        /// Export file to read
+        file: PathBuf,
+        /// Format of the file (default: detected from the extension or contents)
+        #[arg(long)]
+        format: Option<ExportFormat>,
+    },
+}
+
+/// Run a command-line subcommand instead of the chat
+async fn run_command(
+    command: Command,
+    context: &ContextStore,
+    embedding_service: &EmbeddingService,
+) -> Result<()> {
+    match command {
+        Command::Reindex { batch_size } => {
+            run_reindex(context, embedding_service, batch_size).await
+        }
+        Command::Session {
+            command:
+                SessionCommand::Export {
+                    session_id,
+                    format,
+                    output,
+                    embeddings,
+                },
+        } => {
+            let format = format
+                .or_else(|| output.as_deref().and_then(ExportFormat::from_path))
+                .unwrap_or_default();
+            let export = context.export_session(&session_id, embeddings).await?;
+            let rendered = export.render(format)?;
+            match output {
+                Some(path) => {
+                    tokio::fs::write(&path, rendered).await?;
+                    eprintln!(
+                        "Exported {} entries and {} commands from session {} to {}",
+                        export.entries.len(),
+                        export.commands.len(),
+                        session_id,
+                        path.display()
+                    );
+                }
+                None => print!("{}", rendered),
+            }
+            Ok(())
+        }
+        Command::Session {
+            command: SessionCommand::Import { file, format },
+        } => {
+            let text = tokio::fs::read_to_string(&file).await?;
+            let format = format.or_else(|| ExportFormat::from_path(&file));
+            let export = SessionExport::parse(&text, format)?;
+            let summary = context
+                .import_session(&export, Some(embedding_service))
+                .await?;
+            println!(
+                "Imported session {}: {} entries ({} already present), {} commands",
+                summary.session_id, summary.entries, summary.skipped, summary.commands
+            );
+            let pending = context.count_entries_needing_embedding().await?;
+            if pending > 0 {
+                println!(
+                    "{} context entries still need embedding; run `vega reindex` to embed them",
+                    pending
+                );
+            }
+            Ok(())
+        }
+    }
+}
+
+/// Re-embed stored context entries with the configured embedding model
+async fn run_reindex(
+    context: &ContextStore,
+    embedding_service: &EmbeddingService,
+    batch_size: usize,
+) -> Result<()> {
+    let model = context.embedding_model();
+    let pending = context.count_entries_needing_embedding().await?;
+    if pending == 0 {
+        println!("All context entries are already embedded with {}", model);
+        return Ok(());
+    }
+
+    println!("Re-embedding {} context entries with {}...", pending, model);
+    let reindexed = context.reindex(embedding_service, batch_size).await?;
+    println!("Re-embedded {} context entries", reindexed);
+    Ok(())
+}
+
+/// Serve Vega's tools over MCP instead of starting the chat
+///
+/// The `server` section of the MCP configuration file, if one is given,
+/// supplies the exposed tools and the HTTP settings. Tool calls are checked
+/// against the permission policy of the agent configuration. The context store
+/// and agent instructions are offered to clients as resources.
+async fn run_mcp_server(
+    name: String,
+    listen: Option<SocketAddr>,
+    token: Option<String>,
+    config_path: Option<&Path>,
+    context: Arc<ContextStore>,
+    agent_config: &AgentConfig,
+    logger: &Logger,
+) -> Result<()> {
+    use crate::mcp::config::TransportType;
+    use crate::mcp::{McpConfig, McpServer, McpServerConfig};
+
+    let mut config = match config_path {
+        Some(path) => McpConfig::from_file(&path.to_string_lossy())?
+            .server
+            .unwrap_or_default(),
+        None => McpServerConfig::default(),
+    };
+    config.name = name;
+    if let Some(listen) = listen {
+        config.listen = listen;
+        config.transport.transport_type = TransportType::Http;
+    }
+    if token.is_some() {
+        config.settings.auth_token = token;
+    }
+
+    match config.transport.transport_type {
+        TransportType::Http => {
+            logger
+                .info(format!(
+                    "Starting MCP server '{}' on http://{}/mcp",
+                    config.name, config.listen
+                ))
+                .await?;
+            if config.settings.auth_token.is_none() {
+                logger
+                    .warn(
+                        "The MCP server has no auth token, so any local process may call its tools; set one with --mcp-token"
+                            .to_string(),
+                    )
+                    .await?;
+            }
+        }
+        _ => {
+            logger
+                .info(format!("Starting MCP server '{}' on stdio", config.name))
+                .await?
+        }
+    }
+
+    let mut server = McpServer::new(config).await?.with_context(context);
+    if let Some(ref instructions) = agent_config.agent_instructions {
+        server = server.with_instructions(instructions.clone());
+    }
+    if let Some(ref sandbox) = agent_config.sandbox {
+        server = server.with_sandbox(sandbox.clone());
+    }
+    if let Some(ref workspace) = agent_config.workspace {
+        server = server.with_workspace(workspace.clone());
+    }
+    server = server.with_permissions(agent_config.permissions.clone());
+    server.run().await
 }
 
 /// Display the ASCII art splash screen
@@ -212,8 +476,11 @@ async fn main() -> Result<()> {
         );
     }
 
-    // Display ASCII art splash screen (unless in ACP mode or disabled)
-    if !args.acp && !args.no_splash {
+    // Stdout carries the protocol when serving MCP over stdio
+    let serves_mcp_stdio = args.mcp_server && args.mcp_listen.is_none();
+
+    // Display ASCII art splash screen (unless in ACP or MCP stdio mode, disabled or running a command)
+    if !args.acp && !serves_mcp_stdio && !args.no_splash && args.command.is_none() {
         if args.debug_startup {
             eprintln!("DEBUG: Displaying splash screen...");
         }
@@ -238,7 +505,12 @@ async fn main() -> Result<()> {
             // Check VEGA_LOG_LEVEL first, then default to info
             std::env::var("VEGA_LOG_LEVEL").unwrap_or_else(|_| "info".to_string())
         };
-        tracing_subscriber::fmt().with_env_filter(filter).init();
+        let subscriber = tracing_subscriber::fmt().with_env_filter(filter);
+        if serves_mcp_stdio {
+            subscriber.with_writer(std::io::stderr).init();
+        } else {
+            subscriber.init();
+        }
     } else {
         // Initialize a no-op subscriber to suppress tracing output
         use tracing_subscriber::filter::LevelFilter;
@@ -254,16 +526,23 @@ async fn main() -> Result<()> {
 
     // We'll log this information with our custom logger after it's initialized
 
-    // Create embedding provider to determine dimension
+    // Create embedding provider to determine the embedding model
     let embedding_provider = crate::embeddings::EmbeddingProvider::new(
         &args.embedding_provider,
         args.embedding_model.as_deref(),
         args.openai_api_key.as_deref(),
     )?;
-    let embedding_dimension = embedding_provider.create_service().dimension();
+    let embedding_service = std::sync::Arc::new(embedding_provider.create_service());
+
+    // Initialize context store for the configured embedding model
+    let context =
+        ContextStore::with_embedding_model(&args.context_db, embedding_service.model_info())
+            .await?;
+
+    if let Some(command) = args.command {
+        return run_command(command, &context, &embedding_service).await;
+    }
 
-    // Initialize context store with correct embedding dimension
-    let context = ContextStore::new(&args.context_db, embedding_dimension).await?;
     let context_arc = std::sync::Arc::new(context);
 
     // Initialize custom logger
@@ -286,7 +565,7 @@ async fn main() -> Result<()> {
     let mut logger_config = LoggerConfig::new(session_id.clone())
         .with_console_level(final_log_level)
         .with_structured(args.log_structured)
-        .with_console_output(log_outputs.contains(&"console"));
+        .with_console_output(log_outputs.contains(&"console") && !serves_mcp_stdio);
 
     // Configure file logging if requested
     if log_outputs.contains(&"file") {
@@ -308,10 +587,9 @@ async fn main() -> Result<()> {
 
     // Add context store and embedding service for vector logging
     if log_outputs.contains(&"vector") {
-        let embedding_service = std::sync::Arc::new(embedding_provider.create_service());
         logger = logger
             .with_context_store(context_arc.clone())
-            .with_embedding_service(embedding_service);
+            .with_embedding_service(embedding_service.clone());
     }
 
     let logger = std::sync::Arc::new(logger);
@@ -337,6 +615,17 @@ async fn main() -> Result<()> {
             .await?;
     }
 
+    let stale_embeddings = context_arc.count_entries_needing_embedding().await?;
+    if stale_embeddings > 0 {
+        logger
+            .warn(format!(
+                "{} context entries were not embedded with {}; semantic search may miss them. Run `vega reindex` to re-embed them.",
+                stale_embeddings,
+                context_arc.embedding_model()
+            ))
+            .await?;
+    }
+
     // Discover and load agent instructions
     let instruction_loader = AgentInstructionLoader::new()?;
     let agent_instructions = match instruction_loader.discover_instructions()? {
@@ -358,10 +647,49 @@ async fn main() -> Result<()> {
         }
     };
 
+    // Discover the project and set up the sandbox it asks for
+    let project = Project::discover_current()?;
+    let mut sandbox_config = project.settings.sandbox.clone();
+    sandbox_config.enabled |= args.sandbox;
+    let sandbox = if sandbox_config.enabled {
+        let sandbox = Sandbox::new(sandbox_config, &project.root);
+        if !sandbox.is_available() {
+            anyhow::bail!(
+                "The sandbox needs Linux with bubblewrap (bwrap) installed; install it or disable the sandbox"
+            );
+        }
+        logger
+            .info(format!(
+                "Running commands in a sandbox confined to {}",
+                project.root.display()
+            ))
+            .await?;
+        Some(sandbox)
+    } else {
+        None
+    };
+
+    // Confine the file tools to the project and the directories it allows
+    let workspace = Workspace::new(&project.root)
+        .with_context(|| format!("Failed to open the workspace {}", project.root.display()))?
+        .with_extra_roots(
+            project
+                .settings
+                .workspace
+                .allowed_roots
+                .iter()
+                .chain(&args.allow_dirs)
+                .map(|dir| project.resolve(dir)),
+        );
+
+    // Load the permission policy of the project and the user
+    let permissions = PermissionPolicy::load(&project.root)?;
+
     // Create agent configuration
     let api_key = match args.provider.as_str() {
         "openrouter" => args.openrouter_api_key,
         "anthropic" => args.anthropic_api_key,
+        "openai" => args.openai_api_key.clone(),
         _ => None,
     };
 
@@ -374,12 +702,24 @@ async fn main() -> Result<()> {
         args.embedding_model,
         args.openai_api_key,
         args.yolo,
-    );
+    )
+    .with_history(args.history_token_budget, args.history_truncation)
+    .with_compaction(args.compact_threshold)
+    .with_persistent_shell(args.persistent_shell)
+    .with_permissions(Arc::new(permissions))
+    .with_workspace(workspace)
+    .with_command_output(OutputLimit {
+        head_lines: args.output_head_lines,
+        tail_lines: args.output_tail_lines,
+    });
 
     // Add agent instructions if found
     if let Some(instructions) = agent_instructions {
         config = config.with_instructions(instructions);
     }
+    if let Some(sandbox) = sandbox {
+        config = config.with_sandbox(sandbox);
+    }
 
     // Check if running in ACP mode
     if args.acp {
@@ -388,15 +728,36 @@ async fn main() -> Result<()> {
             .await?;
 
         // Run the ACP server
-        return crate::acp::start_acp_server(config, context_arc, logger).await;
+        return crate::acp::start_acp_server(config, context_arc, embedding_service, logger).await;
+    }
+
+    // Check if running as an MCP server
+    if args.mcp_server {
+        return run_mcp_server(
+            args.mcp_server_name,
+            args.mcp_listen,
+            args.mcp_token,
+            args.mcp_config.as_deref(),
+            context_arc,
+            &config,
+            &logger,
+        )
+        .await;
     }
 
     // Start web server in background
     let web_context = context_arc.clone();
     let web_logger = logger.clone();
+    let web_embeddings = embedding_service.clone();
     let web_port = args.web_port;
     tokio::spawn(async move {
-        if let Err(e) = start_web_server_with_logger(web_context, Some(web_logger), web_port).await
+        if let Err(e) = start_web_server_with_logger(
+            web_context,
+            Some(web_logger),
+            Some(web_embeddings),
+            web_port,
+        )
+        .await
         {
             eprintln!("Web server error: {}", e);
         }
@@ -409,70 +770,58 @@ async fn main() -> Result<()> {
         ))
         .await?;
 
-    // Initialize MCP if requested
+    // Connect to the configured MCP servers so their tools reach the agent
     let mut mcp_manager = None;
-    if args.mcp_server || args.mcp_client {
-        use crate::mcp::{McpConfig, McpManager, SimpleMcpServerConfig};
+    if args.mcp_client {
+        use crate::mcp::{McpConfig, McpManager};
 
         let mut mcp_config = McpConfig::default();
 
-        // Configure MCP server if enabled
-        if args.mcp_server {
-            let server_config = SimpleMcpServerConfig {
-                name: args.mcp_server_name.clone(),
-                version: "0.1.0".to_string(),
-                enabled_tools: vec![
-                    "bash".to_string(),
-                    "read_file".to_string(),
-                    "edit_file".to_string(),
-                    "list_files".to_string(),
-                    "code_search".to_string(),
-                    "web_search".to_string(),
-                ],
-            };
-
-            let mut manager = McpManager::new();
-            manager.start_server(server_config);
-
-            logger
-                .info("MCP server started - Vega tools are now available via MCP".to_string())
-                .await?;
-            mcp_manager = Some(manager);
-        }
-
         // Load MCP client configuration if provided
-        if args.mcp_client {
-            if let Some(config_path) = &args.mcp_config {
-                match McpConfig::from_file(&config_path.to_string_lossy()) {
-                    Ok(loaded_config) => {
-                        mcp_config = loaded_config;
-                        logger
-                            .info(format!("Loaded MCP configuration from {:?}", config_path))
-                            .await?;
-                    }
-                    Err(e) => {
-                        logger
-                            .warn(format!(
-                                "Failed to load MCP config from {:?}: {}",
-                                config_path, e
-                            ))
-                            .await?;
-                    }
+        if let Some(config_path) = &args.mcp_config {
+            match McpConfig::from_file(&config_path.to_string_lossy()) {
+                Ok(loaded_config) => {
+                    mcp_config = loaded_config;
+                    logger
+                        .info(format!("Loaded MCP configuration from {:?}", config_path))
+                        .await?;
+                }
+                Err(e) => {
+                    logger
+                        .warn(format!(
+                            "Failed to load MCP config from {:?}: {}",
+                            config_path, e
+                        ))
+                        .await?;
                 }
             }
+        }
 
-            if mcp_manager.is_none() {
-                mcp_manager = Some(McpManager::with_config(mcp_config));
-            }
-
+        let mut manager = McpManager::with_config(mcp_config).with_logger(logger.clone());
+        for (name, e) in manager.connect_clients().await {
             logger
-                .info("MCP client enabled - can connect to external MCP servers".to_string())
+                .warn(format!("Failed to connect to MCP server '{}': {}", name, e))
                 .await?;
         }
+
+        logger
+            .info(format!(
+                "MCP client enabled - {} tools available from {} servers",
+                manager.tools().len(),
+                manager.clients.len()
+            ))
+            .await?;
+
+        mcp_manager = Some(std::sync::Arc::new(manager));
     }
 
     // Create the chat agent
-    let agent = ChatAgent::new(config)?.with_logger(logger.clone());
+    let mut agent = ChatAgent::with_embeddings(config, embedding_service.clone())
+        .with_logger(logger.clone())
+        .with_command_events(crate::streaming::print_command_output());
+    if let Some(manager) = &mcp_manager {
+        agent = agent.with_mcp(manager.clone());
+    }
 
     // Main session loop to handle session switching
     let mut current_session_id = session_id;
@@ -506,6 +855,10 @@ async fn main() -> Result<()> {
         }
     }
 
+    if let Some(manager) = mcp_manager {
+        manager.disconnect_all().await;
+    }
+
     Ok(())
 }
 
@@ -606,17 +959,28 @@ mod tests {
             session_id: Some("test_session".to_string()),
             web_port: 3000,
             yolo: false,
+            persistent_shell: false,
+            sandbox: false,
+            allow_dirs: vec![],
+            output_head_lines: 50,
+            output_tail_lines: 150,
             log_output: "console".to_string(),
             log_file: None,
             log_structured: false,
             acp: false,
+            history_token_budget: 4000,
+            history_truncation: TruncationPolicy::DropOldest,
+            compact_threshold: 8000,
             command_history_length: 100,
             mcp_server: false,
             mcp_server_name: "vega-mcp-server".to_string(),
+            mcp_listen: None,
+            mcp_token: None,
             mcp_client: false,
             mcp_config: None,
             no_splash: false,
             debug_startup: false,
+            command: None,
         };
 
         let config = AgentConfig::new(
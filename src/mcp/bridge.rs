@@ -3,39 +3,99 @@
 //! This module provides a bridge between MCP tools and Vega's existing tool system,
 //! allowing MCP tools to be used seamlessly within Vega's agent framework.
 
-use anyhow::{Result, anyhow};
+use anyhow::{Result, anyhow, bail};
+use rig::completion::ToolDefinition;
 use rig::tool::Tool as RigTool;
-use rust_mcp_schema::Tool as McpToolDef;
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
 use std::collections::HashMap;
 use std::sync::Arc;
-use tokio::sync::RwLock;
+use tokio::sync::mpsc;
 
 use super::McpTool;
-use super::transport::{MessageRouter, RequestBuilder};
-use crate::tools::ToolError;
+use super::client::McpClient;
+use super::protocol::Tool as McpToolDef;
+use crate::streaming::{self, ProgressPhase};
+use crate::tools::{
+    Decision, PermissionPolicy, PolicyCheck, ProgressSender, Sandbox, ToolError, Workspace,
+    forward_progress,
+};
+
+/// Separator between the server name and the tool name in namespaced tool names
+pub const TOOL_NAMESPACE_SEPARATOR: &str = "__";
+
+/// Longest tool name model providers accept
+const MAX_TOOL_NAME_LEN: usize = 64;
+
+/// Name under which a server's tool is registered on the agent, `server__tool`.
+///
+/// Characters providers reject in tool names are replaced with `_` and the
+/// result is cut to 64 characters.
+pub fn namespaced_tool_name(server: &str, tool: &str) -> String {
+    format!("{}{}{}", server, TOOL_NAMESPACE_SEPARATOR, tool)
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '_' || c == '-' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .take(MAX_TOOL_NAME_LEN)
+        .collect()
+}
 
-/// A wrapper that makes MCP tools compatible with Vega's tool system
-#[derive(Debug)]
+/// A tool advertised by an MCP server, callable by the agent under its namespaced name
+#[derive(Debug, Clone)]
 pub struct VegaMcpTool {
-    /// Tool name
+    /// Namespaced tool name
     name: String,
     /// Tool definition from MCP server
     definition: McpToolDef,
-    /// Message router for sending requests
-    router: Arc<RwLock<MessageRouter>>,
+    /// Client connected to the server providing the tool
+    client: McpClient,
 }
 
 impl VegaMcpTool {
-    /// Create a new Vega-compatible MCP tool
-    pub fn new(name: String, definition: McpToolDef, router: Arc<RwLock<MessageRouter>>) -> Self {
+    /// Create a Vega-compatible tool for a definition advertised through `client`
+    pub fn new(definition: McpToolDef, client: McpClient) -> Self {
         Self {
-            name,
+            name: namespaced_tool_name(client.name(), &definition.name),
             definition,
-            router,
+            client,
         }
     }
+
+    /// Name of the server providing the tool
+    pub fn server(&self) -> &str {
+        self.client.name()
+    }
+
+    /// Name of the tool on its server
+    pub fn remote_name(&self) -> &str {
+        &self.definition.name
+    }
+
+    /// Call the tool on its server
+    pub async fn invoke(&self, arguments: Value) -> Result<McpToolCallResponse> {
+        self.invoke_with_progress(arguments, None).await
+    }
+
+    /// Call the tool on its server, sending the progress it reports to `progress`
+    pub async fn invoke_with_progress(
+        &self,
+        arguments: Value,
+        progress: Option<ProgressSender>,
+    ) -> Result<McpToolCallResponse> {
+        // Tools without parameters are often called with no arguments at all
+        let arguments = match arguments {
+            Value::Null => Value::Object(Default::default()),
+            arguments => arguments,
+        };
+        self.client
+            .call_tool_with_progress(&self.definition.name, Some(arguments), progress)
+            .await
+    }
 }
 
 impl McpTool for VegaMcpTool {
@@ -51,33 +111,59 @@ impl McpTool for VegaMcpTool {
     }
 
     fn input_schema(&self) -> Value {
-        // Convert the schema to a serde_json::Value
-        serde_json::to_value(&self.definition.input_schema).unwrap_or(Value::Null)
+        self.definition.input_schema.clone()
     }
 
     fn call_boxed(
         &self,
         args: Value,
     ) -> std::pin::Pin<Box<dyn std::future::Future<Output = Result<Value>> + Send>> {
-        let router = self.router.clone();
-        let name = self.name.clone();
+        let tool = self.clone();
 
         Box::pin(async move {
-            let mut router = router.write().await;
-            let id = router.next_id();
-            let rx = router.register_request(id);
-            drop(router);
+            let response = tool.invoke(args).await?;
+            Ok(serde_json::to_value(response)?)
+        })
+    }
+}
 
-            let request = RequestBuilder::call_tool(id, &name, Some(args));
+impl RigTool for VegaMcpTool {
+    // Every instance is registered under its own namespaced name instead
+    const NAME: &'static str = "mcp_tool";
+    type Error = ToolError;
+    type Args = Value;
+    type Output = String;
 
-            // Note: In a real implementation, we would need access to the transport
-            // For now, this is a placeholder that shows the structure
-            // The actual implementation would need to be coordinated with the client
+    fn name(&self) -> String {
+        self.name.clone()
+    }
 
-            Err(anyhow!(
-                "Tool call not implemented in bridge - needs client transport"
-            ))
+    async fn definition(&self, _prompt: String) -> ToolDefinition {
+        ToolDefinition {
+            name: self.name.clone(),
+            description: McpTool::description(self).to_string(),
+            parameters: self.definition.input_schema.clone(),
+        }
+    }
+
+    async fn call(&self, args: Self::Args) -> Result<Self::Output, Self::Error> {
+        // Progress the server reports is shown on the chat's progress indicator
+        let (progress, updates) = mpsc::unbounded_channel();
+        let call = self.invoke_with_progress(args, Some(progress));
+        let response = forward_progress(call, updates, |update| {
+            streaming::report_progress(
+                ProgressPhase::ToolExecution(self.name.clone()),
+                format!("Using {}: {}", self.name, update),
+            )
         })
+        .await
+        .map_err(|e| ToolError::Command(e.to_string()))?;
+
+        if response.is_error.unwrap_or(false) {
+            Err(ToolError::Command(response.text()))
+        } else {
+            Ok(response.text())
+        }
     }
 }
 
@@ -86,6 +172,14 @@ impl McpTool for VegaMcpTool {
 pub struct VegaToMcpBridge {
     /// Map of tool names to their configurations for runtime instantiation
     tool_configs: HashMap<String, VegaToolConfig>,
+    /// Sandbox the bash tool runs commands in
+    sandbox: Option<Sandbox>,
+    /// Directories the file tools are confined to
+    workspace: Option<Workspace>,
+    /// Policy every call is checked against before the tool runs
+    permissions: Arc<PermissionPolicy>,
+    /// Whether calls the policy would ask about run, as nobody can be asked
+    allow_ask: bool,
 }
 
 /// Configuration for a Vega tool that can be instantiated when needed
@@ -105,9 +199,33 @@ impl VegaToMcpBridge {
     pub fn new() -> Self {
         Self {
             tool_configs: HashMap::new(),
+            sandbox: None,
+            workspace: None,
+            permissions: Arc::new(PermissionPolicy::default()),
+            allow_ask: false,
         }
     }
 
+    /// Run the commands of the bash tool in a sandbox
+    pub fn with_sandbox(mut self, sandbox: Sandbox) -> Self {
+        self.sandbox = Some(sandbox);
+        self
+    }
+
+    /// Confine the file tools to a workspace
+    pub fn with_workspace(mut self, workspace: Workspace) -> Self {
+        self.workspace = Some(workspace);
+        self
+    }
+
+    /// Check calls against a permission policy; those it would ask about are
+    /// refused unless `allow_ask` is set
+    pub fn with_permissions(mut self, permissions: Arc<PermissionPolicy>, allow_ask: bool) -> Self {
+        self.permissions = permissions;
+        self.allow_ask = allow_ask;
+        self
+    }
+
     /// Add a Vega tool to be exposed via MCP
     pub fn add_tool(&mut self, name: String, config: VegaToolConfig) {
         self.tool_configs.insert(name, config);
@@ -124,7 +242,7 @@ impl VegaToMcpBridge {
     }
 
     /// Convert a tool config to MCP definition
-    fn config_to_mcp_definition(&self, name: &str, config: &VegaToolConfig) -> Result<McpToolDef> {
+    fn config_to_mcp_definition(&self, name: &str, _config: &VegaToolConfig) -> Result<McpToolDef> {
         // Use the predefined tool definitions from McpToolFactory
         let definitions = McpToolFactory::create_mcp_tools()?;
 
@@ -148,12 +266,22 @@ impl VegaToMcpBridge {
 
     /// Call a Vega tool through the bridge
     pub async fn call_tool(&self, name: &str, arguments: Value) -> Result<Value> {
+        self.call_tool_with_progress(name, arguments, None).await
+    }
+
+    /// Call a Vega tool, sending the progress of long-running tools to `progress`
+    pub async fn call_tool_with_progress(
+        &self,
+        name: &str,
+        arguments: Value,
+        progress: Option<ProgressSender>,
+    ) -> Result<Value> {
         let config = self
             .tool_configs
             .get(name)
             .ok_or_else(|| anyhow!("Tool '{}' not found", name))?;
 
-        self.call_tool_by_config(config, arguments).await
+        self.call_tool_by_config(config, arguments, progress).await
     }
 
     /// Call a tool based on its configuration
@@ -161,49 +289,74 @@ impl VegaToMcpBridge {
         &self,
         config: &VegaToolConfig,
         arguments: Value,
+        progress: Option<ProgressSender>,
     ) -> Result<Value> {
         use crate::tools::*;
 
-        // Convert the arguments to the format expected by Rig tools
-        let args_str = serde_json::to_string(&arguments)?;
-
-        let result = match config {
+        match config {
             VegaToolConfig::Bash => {
-                let tool = BashTool::new();
-                tool.call(&args_str).await
+                let mut tool = BashTool::new();
+                if let Some(ref sandbox) = self.sandbox {
+                    tool = tool.with_sandbox(sandbox.clone());
+                }
+                if let Some(ref workspace) = self.workspace {
+                    tool = tool.with_workspace(workspace.clone());
+                }
+                if let Some(progress) = progress {
+                    tool = tool.with_progress(progress);
+                }
+                call_rig_tool(tool, arguments, &self.permissions, self.allow_ask).await
             }
             VegaToolConfig::ReadFile => {
-                let tool = ReadFileTool::new();
-                tool.call(&args_str).await
+                let mut tool = ReadFileTool::new();
+                if let Some(ref workspace) = self.workspace {
+                    tool = tool.with_workspace(workspace.clone());
+                }
+                call_rig_tool(tool, arguments, &self.permissions, self.allow_ask).await
             }
             VegaToolConfig::EditFile => {
-                let tool = EditFileTool::new();
-                tool.call(&args_str).await
+                let mut tool = EditFileTool::new();
+                if let Some(ref workspace) = self.workspace {
+                    tool = tool.with_workspace(workspace.clone());
+                }
+                call_rig_tool(tool, arguments, &self.permissions, self.allow_ask).await
             }
             VegaToolConfig::ListFiles => {
-                let tool = ListFilesTool::new();
-                tool.call(&args_str).await
+                let mut tool = ListFilesTool::new();
+                if let Some(ref workspace) = self.workspace {
+                    tool = tool.with_workspace(workspace.clone());
+                }
+                call_rig_tool(tool, arguments, &self.permissions, self.allow_ask).await
             }
             VegaToolConfig::CodeSearch => {
-                let tool = CodeSearchTool::new();
-                tool.call(&args_str).await
+                let mut tool = CodeSearchTool::new();
+                if let Some(ref workspace) = self.workspace {
+                    tool = tool.with_workspace(workspace.clone());
+                }
+                if let Some(progress) = progress {
+                    tool = tool.with_progress(progress);
+                }
+                call_rig_tool(tool, arguments, &self.permissions, self.allow_ask).await
             }
             VegaToolConfig::WebSearch => {
-                let tool = WebSearchTool::new();
-                tool.call(&args_str).await
+                call_rig_tool(
+                    WebSearchTool::new(),
+                    arguments,
+                    &self.permissions,
+                    self.allow_ask,
+                )
+                .await
             }
             VegaToolConfig::ReadLogs => {
-                let tool = ReadLogsTool::new();
-                tool.call(&args_str).await
+                call_rig_tool(
+                    ReadLogsTool::new(),
+                    arguments,
+                    &self.permissions,
+                    self.allow_ask,
+                )
+                .await
             }
         }
-        .map_err(|e| anyhow!("Tool call failed: {}", e))?;
-
-        // Parse the result back to JSON
-        let result_value: Value =
-            serde_json::from_str(&result).unwrap_or_else(|_| Value::String(result));
-
-        Ok(result_value)
     }
 
     /// List all available tool names
@@ -212,6 +365,41 @@ impl VegaToMcpBridge {
     }
 }
 
+impl Default for VegaToMcpBridge {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Call a Vega tool with JSON arguments once the policy allows it, and return
+/// its output as JSON
+async fn call_rig_tool<T: PolicyCheck>(
+    tool: T,
+    arguments: Value,
+    permissions: &PermissionPolicy,
+    allow_ask: bool,
+) -> Result<Value> {
+    let args: T::Args = serde_json::from_value(arguments)
+        .map_err(|e| anyhow!("Invalid arguments for '{}': {}", T::NAME, e))?;
+
+    let description = tool.describe(&args);
+    match permissions.decide(&tool.request(&args), T::DEFAULT_DECISION) {
+        Decision::Allow => {}
+        Decision::Ask if allow_ask => {}
+        Decision::Ask => bail!(
+            "This needs confirmation, which cannot be given over MCP: {}. Allow it in .vega/permissions.json",
+            description
+        ),
+        Decision::Deny => bail!("The permission policy does not allow this: {}", description),
+    }
+
+    let output = tool
+        .call(args)
+        .await
+        .map_err(|e| anyhow!("Tool call failed: {}", e))?;
+    Ok(serde_json::to_value(output)?)
+}
+
 /// MCP tool call request structure
 #[derive(Debug, Serialize, Deserialize)]
 pub struct McpToolCallRequest {
@@ -225,11 +413,27 @@ pub struct McpToolCallRequest {
 #[derive(Debug, Serialize, Deserialize)]
 pub struct McpToolCallResponse {
     /// Content returned by the tool
+    #[serde(default)]
     pub content: Vec<McpContent>,
     /// Whether the tool call was an error
+    #[serde(rename = "isError", default, skip_serializing_if = "Option::is_none")]
     pub is_error: Option<bool>,
 }
 
+impl McpToolCallResponse {
+    /// The response's text content, with other content kinds named by a placeholder
+    pub fn text(&self) -> String {
+        self.content
+            .iter()
+            .map(|content| match &content.text {
+                Some(text) => text.clone(),
+                None => format!("[{} content]", content.content_type),
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
+
 /// Content structure for MCP responses
 #[derive(Debug, Serialize, Deserialize)]
 pub struct McpContent {
@@ -237,6 +441,7 @@ pub struct McpContent {
     #[serde(rename = "type")]
     pub content_type: String,
     /// The actual content
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub text: Option<String>,
 }
 
@@ -313,7 +518,7 @@ impl McpToolFactory {
         tools.push(McpToolDef {
             name: "edit_file".to_string(),
             description: Some(
-                "Create or edit files with backup support and safety validation".to_string(),
+                "Create or edit files by writing content, replacing old_string with new_string, several edits, or applying a unified diff; returns a diff of the changes".to_string(),
             ),
             input_schema: serde_json::json!({
                 "type": "object",
@@ -340,9 +545,39 @@ impl McpToolFactory {
                         "type": "string",
                         "description": "Text encoding to use (default: UTF-8)",
                         "default": "utf-8"
+                    },
+                    "old_string": {
+                        "type": "string",
+                        "description": "Exact text to replace; must occur once unless replace_all is set"
+                    },
+                    "new_string": {
+                        "type": "string",
+                        "description": "Text replacing old_string"
+                    },
+                    "replace_all": {
+                        "type": "boolean",
+                        "description": "Replace every occurrence of old_string (default: false)",
+                        "default": false
+                    },
+                    "edits": {
+                        "type": "array",
+                        "description": "Several replacements, made all or none",
+                        "items": {
+                            "type": "object",
+                            "properties": {
+                                "old_string": { "type": "string" },
+                                "new_string": { "type": "string" },
+                                "replace_all": { "type": "boolean", "default": false }
+                            },
+                            "required": ["old_string", "new_string"]
+                        }
+                    },
+                    "diff": {
+                        "type": "string",
+                        "description": "Unified diff of the file to apply"
                     }
                 },
-                "required": ["path", "content"]
+                "required": ["path"]
             }),
         });
 
@@ -448,6 +683,33 @@ impl McpToolFactory {
             }),
         });
 
+        // Read Logs Tool
+        tools.push(McpToolDef {
+            name: "read_logs".to_string(),
+            description: Some(
+                "Read log messages for a specific session from file logs or the vector store"
+                    .to_string(),
+            ),
+            input_schema: serde_json::json!({
+                "type": "object",
+                "properties": {
+                    "session_id": {
+                        "type": "string",
+                        "description": "Session ID to read logs for"
+                    },
+                    "limit": {
+                        "type": "integer",
+                        "description": "Maximum number of log entries to return (default: 50)"
+                    },
+                    "level_filter": {
+                        "type": "string",
+                        "description": "Only show this level and more severe ones",
+                        "enum": ["error", "warn", "info", "debug", "trace"]
+                    }
+                }
+            }),
+        });
+
         Ok(tools)
     }
 }
@@ -472,6 +734,72 @@ mod tests {
         assert!(bridge.list_tools().is_empty());
     }
 
+    #[tokio::test]
+    async fn test_calls_are_checked_against_the_policy() {
+        use crate::tools::Rule;
+
+        let policy = Arc::new(
+            PermissionPolicy::new("/project").with_rules([Rule::new("read_file", Decision::Deny)]),
+        );
+        let mut bridge = VegaToMcpBridge::new().with_permissions(policy.clone(), false);
+        bridge.add_tool("bash".to_string(), VegaToolConfig::Bash);
+        bridge.add_tool("read_file".to_string(), VegaToolConfig::ReadFile);
+        bridge.add_tool("edit_file".to_string(), VegaToolConfig::EditFile);
+        let bash = serde_json::json!({ "command": "echo hi" });
+
+        // Nobody can confirm a call over MCP
+        let error = bridge.call_tool("bash", bash.clone()).await.unwrap_err();
+        assert!(error.to_string().contains("needs confirmation"));
+
+        let error = bridge
+            .call_tool("read_file", serde_json::json!({ "path": "/project/a.txt" }))
+            .await
+            .unwrap_err();
+        assert!(error.to_string().contains("does not allow"));
+        let error = bridge
+            .call_tool(
+                "edit_file",
+                serde_json::json!({ "path": "/etc/vega.conf", "content": "x" }),
+            )
+            .await
+            .unwrap_err();
+        assert!(error.to_string().contains("does not allow"));
+
+        let bridge = bridge.with_permissions(policy, true);
+        let output = bridge.call_tool("bash", bash).await.unwrap();
+        assert_eq!(output["stdout"], "hi\n");
+    }
+
+    #[test]
+    fn test_namespaced_tool_name() {
+        assert_eq!(
+            namespaced_tool_name("github", "create_issue"),
+            "github__create_issue"
+        );
+        assert_eq!(
+            namespaced_tool_name("my server", "fs.read"),
+            "my_server__fs_read"
+        );
+        assert_eq!(
+            namespaced_tool_name(&"s".repeat(40), &"t".repeat(40)).len(),
+            64
+        );
+    }
+
+    #[test]
+    fn test_tool_call_response() {
+        let response: McpToolCallResponse = serde_json::from_value(serde_json::json!({
+            "content": [
+                {"type": "text", "text": "first"},
+                {"type": "image", "data": "aGk=", "mimeType": "image/png"}
+            ],
+            "isError": true
+        }))
+        .unwrap();
+        assert_eq!(response.is_error, Some(true));
+        assert_eq!(response.text(), "first\n[image content]");
+    }
+
     #[test]
     fn test_mcp_content_serialization() {
         let content = McpContent {
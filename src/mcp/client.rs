@@ -2,327 +2,839 @@
 //!
 //! This module provides MCP client functionality for connecting to external MCP servers
 //! and accessing their tools and resources.
+//!
+//! A connected [`McpClient`] owns a background task reading messages from the
+//! server: responses are routed back to the waiting request, `ping` requests are
+//! answered, and `notifications/tools/list_changed` re-fetches the tool list so
+//! the next agent turn sees the server's current tools. Progress the server
+//! reports for a tool call goes to the caller of
+//! [`McpClient::call_tool_with_progress`], and its log messages
+//! (`notifications/message`) are logged. A request that times out or whose
+//! caller stops waiting is cancelled on the server with `notifications/cancelled`.
+//!
+//! The connection is supervised according to [`McpSettings`]: when it is lost,
+//! e.g. because a stdio server process crashed, the server is started again
+//! with exponential backoff, re-initialized and its tools listed again. Tool
+//! calls are limited to `max_concurrent_calls` at a time per server and time
+//! out after `default_timeout` seconds.
 
 use anyhow::{Result, anyhow};
-use rust_mcp_schema::{McpMessage, Request, Response, Tool as McpToolDef};
-use serde_json::Value;
-use std::collections::HashMap;
-use std::sync::Arc;
-use tokio::sync::RwLock;
+use chrono::{DateTime, Utc};
+use serde_json::{Value, json};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::sync::{Arc, Mutex, OnceLock, RwLock};
+use tokio::sync::{Semaphore, mpsc};
 use tokio::time::{Duration, timeout};
 
-use super::McpTool;
-use super::bridge::VegaMcpTool;
-use super::config::{McpClientConfig, TransportConfig};
+use super::bridge::{McpToolCallResponse, VegaMcpTool};
+use super::config::McpSettings;
+use super::protocol::{
+    LoggingLevel, McpError, McpMessage, Notification, PROTOCOL_VERSION, Request, Response,
+    Tool as McpToolDef,
+};
 use super::transport::{McpTransport, MessageRouter, RequestBuilder, TransportFactory};
+use crate::logging::Logger;
+use crate::tools::ProgressSender;
+
+/// Delay before the first reconnection attempt, doubled for every further attempt
+const RECONNECT_BASE_DELAY: Duration = Duration::from_millis(500);
+
+/// Longest delay between reconnection attempts
+const RECONNECT_MAX_DELAY: Duration = Duration::from_secs(30);
 
 /// Configuration for MCP client
 pub use super::config::McpClientConfig;
 
 /// MCP client for connecting to external servers
-#[derive(Debug)]
+///
+/// Clones share the same connection, so a client can be handed to every tool
+/// it provides.
+#[derive(Clone)]
 pub struct McpClient {
+    /// Name the server is configured under, used to namespace its tools
+    name: String,
     /// Client configuration
-    config: McpClientConfig,
-    /// Transport layer for communication
-    transport: Option<Box<dyn McpTransport>>,
+    config: Arc<McpClientConfig>,
+    /// Global MCP settings: reconnection, concurrency and timeouts
+    settings: Arc<McpSettings>,
+    /// Current connection, replaced when the server is reconnected
+    connection: Arc<RwLock<Connection>>,
+    /// Tells the supervisor that the connection of a generation was lost
+    lost: mpsc::UnboundedSender<u64>,
+    /// Health of the connection
+    health: Arc<RwLock<ConnectionHealth>>,
+    /// Permits for concurrent tool calls
+    calls: Arc<Semaphore>,
+    /// Logger connection health is reported to
+    logger: Arc<OnceLock<Arc<Logger>>>,
     /// Message router for request/response correlation
-    router: Arc<RwLock<MessageRouter>>,
-    /// Available tools from the server
-    tools: Arc<RwLock<HashMap<String, McpToolDef>>>,
-    /// Available resources from the server
-    resources: Arc<RwLock<HashMap<String, Value>>>,
-    /// Connection state
-    connected: bool,
-    /// Server information
-    server_info: Option<Value>,
+    router: Arc<Mutex<MessageRouter>>,
+    /// Available tools from the server, by name
+    tools: Arc<RwLock<BTreeMap<String, McpToolDef>>>,
+    /// Available resources from the server, by URI
+    resources: Arc<RwLock<BTreeMap<String, Value>>>,
+    /// Result of the initialize handshake
+    server_info: Arc<RwLock<Option<Value>>>,
+    /// How long to wait for a response
+    request_timeout: Duration,
+}
+
+/// An open transport and the generation it belongs to
+#[derive(Clone)]
+struct Connection {
+    generation: u64,
+    transport: Arc<dyn McpTransport>,
+}
+
+/// State of the connection to an MCP server
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConnectionState {
+    /// The initialize handshake is in progress
+    Connecting,
+    /// The server is ready for requests
+    Connected,
+    /// The connection was lost and is being re-established
+    Reconnecting {
+        /// Current attempt, starting at 1
+        attempt: usize,
+    },
+    /// The connection was lost and is not re-established
+    Failed,
+    /// The client disconnected on request
+    Disconnected,
+}
+
+impl fmt::Display for ConnectionState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Connecting => write!(f, "connecting"),
+            Self::Connected => write!(f, "connected"),
+            Self::Reconnecting { attempt } => write!(f, "reconnecting (attempt {})", attempt),
+            Self::Failed => write!(f, "failed"),
+            Self::Disconnected => write!(f, "disconnected"),
+        }
+    }
+}
+
+/// Health of the connection to an MCP server
+#[derive(Debug, Clone)]
+pub struct ConnectionHealth {
+    /// Current state of the connection
+    pub state: ConnectionState,
+    /// When the current connection was established
+    pub connected_at: Option<DateTime<Utc>>,
+    /// Number of times the connection was re-established
+    pub reconnects: usize,
+    /// Most recent reason the connection was lost or could not be established
+    pub last_error: Option<String>,
+    /// Tools the server currently advertises
+    pub tools: usize,
+    /// Tool calls in progress
+    pub active_calls: usize,
+    /// Tool calls allowed at the same time
+    pub max_concurrent_calls: usize,
+}
+
+impl ConnectionHealth {
+    /// Health of a server that could not be connected at all
+    pub fn failed(error: impl Into<String>) -> Self {
+        Self {
+            state: ConnectionState::Failed,
+            connected_at: None,
+            reconnects: 0,
+            last_error: Some(error.into()),
+            tools: 0,
+            active_calls: 0,
+            max_concurrent_calls: 0,
+        }
+    }
+}
+
+impl std::fmt::Debug for McpClient {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("McpClient")
+            .field("name", &self.name)
+            .field("command", &self.config.command)
+            .field("state", &self.state())
+            .finish()
+    }
 }
 
 impl McpClient {
-    /// Create a new MCP client with the given configuration
-    pub async fn new(config: McpClientConfig) -> Result<Self> {
-        let transport = TransportFactory::create(config.transport.clone())?;
-
-        let mut client = Self {
-            config,
-            transport: Some(transport),
-            router: Arc::new(RwLock::new(MessageRouter::new())),
-            tools: Arc::new(RwLock::new(HashMap::new())),
-            resources: Arc::new(RwLock::new(HashMap::new())),
-            connected: false,
-            server_info: None,
+    /// Start the configured server, perform the initialize handshake and load its tools
+    pub async fn connect(name: impl Into<String>, config: McpClientConfig) -> Result<Self> {
+        Self::connect_with_settings(name, config, McpSettings::default()).await
+    }
+
+    /// Connect like [`McpClient::connect`], supervising the connection with the given settings
+    pub async fn connect_with_settings(
+        name: impl Into<String>,
+        config: McpClientConfig,
+        settings: McpSettings,
+    ) -> Result<Self> {
+        let transport = TransportFactory::connect(&config).await?;
+        Self::with_transport(name, config, settings, transport).await
+    }
+
+    /// Initialize a session over an already open transport.
+    ///
+    /// When the connection is lost, new transports are opened from `config`.
+    pub async fn with_transport(
+        name: impl Into<String>,
+        config: McpClientConfig,
+        settings: McpSettings,
+        transport: Box<dyn McpTransport>,
+    ) -> Result<Self> {
+        let request_timeout = Duration::from_secs(config.transport.options.timeout.unwrap_or(30));
+        let max_concurrent_calls = settings.max_concurrent_calls.max(1);
+        let (lost, lost_receiver) = mpsc::unbounded_channel();
+
+        let client = Self {
+            name: name.into(),
+            config: Arc::new(config),
+            settings: Arc::new(settings),
+            connection: Arc::new(RwLock::new(Connection {
+                generation: 0,
+                transport: Arc::from(transport),
+            })),
+            lost,
+            health: Arc::new(RwLock::new(ConnectionHealth {
+                state: ConnectionState::Connecting,
+                connected_at: None,
+                reconnects: 0,
+                last_error: None,
+                tools: 0,
+                active_calls: 0,
+                max_concurrent_calls,
+            })),
+            calls: Arc::new(Semaphore::new(max_concurrent_calls)),
+            logger: Arc::new(OnceLock::new()),
+            router: Arc::new(Mutex::new(MessageRouter::new())),
+            tools: Arc::new(RwLock::new(BTreeMap::new())),
+            resources: Arc::new(RwLock::new(BTreeMap::new())),
+            server_info: Arc::new(RwLock::new(None)),
+            request_timeout,
         };
 
-        // Establish connection and initialize
-        client.connect().await?;
-        client.initialize().await?;
+        let connection = client.connection.read().unwrap().clone();
+        tokio::spawn(client.clone().dispatch_messages(connection));
+        tokio::spawn(client.clone().supervise(lost_receiver));
+
+        if let Err(e) = client.initialize().await {
+            client.set_state(ConnectionState::Disconnected);
+            let _ = client.transport().close().await;
+            return Err(e);
+        }
+        client.set_connected();
 
         Ok(client)
     }
 
-    /// Connect to the MCP server
-    async fn connect(&mut self) -> Result<()> {
-        if let Some(transport) = &mut self.transport {
-            // For stdio transport, we need to start the process
-            if let Some(stdio_transport) = transport
-                .as_any()
-                .downcast_mut::<crate::mcp::transport::StdioTransport>()
-            {
-                stdio_transport
-                    .connect(&self.config.command, &self.config.args)
-                    .await?;
+    /// Report connection health changes to a logger as well as to tracing
+    pub fn set_logger(&self, logger: Arc<Logger>) {
+        let _ = self.logger.set(logger);
+    }
+
+    /// The transport of the current connection
+    fn transport(&self) -> Arc<dyn McpTransport> {
+        self.connection.read().unwrap().transport.clone()
+    }
+
+    /// Current state of the connection
+    pub fn state(&self) -> ConnectionState {
+        self.health.read().unwrap().state.clone()
+    }
+
+    fn set_state(&self, state: ConnectionState) {
+        self.health.write().unwrap().state = state;
+    }
+
+    fn set_connected(&self) {
+        let mut health = self.health.write().unwrap();
+        health.state = ConnectionState::Connected;
+        health.connected_at = Some(Utc::now());
+    }
+
+    /// Health of the connection, for display
+    pub fn health(&self) -> ConnectionHealth {
+        let mut health = self.health.read().unwrap().clone();
+        health.tools = self.tools.read().unwrap().len();
+        health.active_calls = health
+            .max_concurrent_calls
+            .saturating_sub(self.calls.available_permits());
+        health
+    }
+
+    /// Log a connection health change
+    async fn report(&self, level: tracing::Level, message: String) {
+        match level {
+            tracing::Level::ERROR => tracing::error!("{}", message),
+            tracing::Level::WARN => tracing::warn!("{}", message),
+            _ => tracing::info!("{}", message),
+        }
+
+        if let Some(logger) = self.logger.get() {
+            let result = match level {
+                tracing::Level::ERROR => logger.error(message).await,
+                tracing::Level::WARN => logger.warn(message).await,
+                _ => logger.info(message).await,
+            };
+            if let Err(e) = result {
+                tracing::debug!("Failed to log MCP connection health: {}", e);
             }
-            self.connected = true;
+        }
+    }
+
+    /// Re-establish lost connections until the client is disconnected or gives up
+    async fn supervise(self, mut lost: mpsc::UnboundedReceiver<u64>) {
+        while let Some(generation) = lost.recv().await {
+            // Only the loss of the current connection matters
+            if generation != self.connection.read().unwrap().generation {
+                continue;
+            }
+            match self.state() {
+                ConnectionState::Disconnected => return,
+                ConnectionState::Connecting => continue,
+                _ => {}
+            }
+
+            if !self.reconnect().await {
+                return;
+            }
+        }
+    }
+
+    /// Start the server again with exponential backoff, returning whether it is connected
+    async fn reconnect(&self) -> bool {
+        let error = self
+            .health
+            .read()
+            .unwrap()
+            .last_error
+            .clone()
+            .unwrap_or_else(|| "connection closed".to_string());
+
+        let attempts = if self.settings.auto_reconnect {
+            self.settings.retry_attempts
+        } else {
+            0
+        };
+        if attempts == 0 {
+            self.set_state(ConnectionState::Failed);
+            self.report(
+                tracing::Level::ERROR,
+                format!("Lost connection to MCP server '{}': {}", self.name, error),
+            )
+            .await;
+            return false;
+        }
+
+        let mut delay = RECONNECT_BASE_DELAY;
+        for attempt in 1..=attempts {
+            self.set_state(ConnectionState::Reconnecting { attempt });
+            self.report(
+                tracing::Level::WARN,
+                format!(
+                    "Lost connection to MCP server '{}' ({}), reconnecting in {:.1}s (attempt {}/{})",
+                    self.name,
+                    error,
+                    delay.as_secs_f32(),
+                    attempt,
+                    attempts
+                ),
+            )
+            .await;
+            tokio::time::sleep(delay).await;
+            delay = (delay * 2).min(RECONNECT_MAX_DELAY);
+
+            if self.state() == ConnectionState::Disconnected {
+                return false;
+            }
+
+            match self.open_connection().await {
+                Ok(()) => {
+                    {
+                        let mut health = self.health.write().unwrap();
+                        health.reconnects += 1;
+                    }
+                    self.set_connected();
+                    self.report(
+                        tracing::Level::INFO,
+                        format!(
+                            "Reconnected to MCP server '{}', {} tools available",
+                            self.name,
+                            self.tools.read().unwrap().len()
+                        ),
+                    )
+                    .await;
+                    return true;
+                }
+                Err(e) => {
+                    tracing::debug!("Reconnecting to '{}' failed: {}", self.name, e);
+                    self.health.write().unwrap().last_error = Some(e.to_string());
+                }
+            }
+        }
+
+        self.set_state(ConnectionState::Failed);
+        self.report(
+            tracing::Level::ERROR,
+            format!(
+                "Giving up on MCP server '{}' after {} reconnection attempts",
+                self.name, attempts
+            ),
+        )
+        .await;
+        false
+    }
+
+    /// Open a new transport, replace the current connection with it and initialize the server
+    async fn open_connection(&self) -> Result<()> {
+        let transport: Arc<dyn McpTransport> =
+            Arc::from(TransportFactory::connect(&self.config).await?);
+        let connection = {
+            let mut current = self.connection.write().unwrap();
+            *current = Connection {
+                generation: current.generation + 1,
+                transport,
+            };
+            current.clone()
+        };
+        let dispatcher = tokio::spawn(self.clone().dispatch_messages(connection.clone()));
+
+        if let Err(e) = self.initialize().await {
+            // Stop routing for the failed connection before the next attempt
+            dispatcher.abort();
+            let _ = connection.transport.close().await;
+            return Err(e);
         }
         Ok(())
     }
 
     /// Initialize the MCP session
-    async fn initialize(&mut self) -> Result<()> {
-        let mut router = self.router.write().await;
-        let id = router.next_id();
-        let rx = router.register_request(id);
-        drop(router);
-
-        let client_info = serde_json::json!({
-            "protocolVersion": "2025-06-18",
-            "capabilities": {
-                "tools": {
-                    "list_changed": true
-                },
-                "resources": {
-                    "list_changed": true,
-                    "subscribe": false
-                }
-            },
+    async fn initialize(&self) -> Result<()> {
+        let params = json!({
+            "protocolVersion": PROTOCOL_VERSION,
+            "capabilities": {},
             "clientInfo": {
-                "name": "vega-mcp-client",
-                "version": "0.1.0"
+                "name": "vega",
+                "version": env!("CARGO_PKG_VERSION")
             }
         });
 
-        let request = RequestBuilder::initialize(id, client_info);
-        self.send_request(request).await?;
-
-        // Wait for initialize response
-        let response = timeout(Duration::from_secs(10), rx)
+        let result = self
+            .request("initialize", Some(params))
             .await
-            .map_err(|_| anyhow!("Timeout waiting for initialize response"))?
-            .map_err(|_| anyhow!("Initialize request cancelled"))?;
-
-        if let Some(result) = response.result {
-            self.server_info = Some(result);
-            tracing::info!("MCP client initialized successfully");
-        } else if let Some(error) = response.error {
-            return Err(anyhow!("Initialize failed: {:?}", error));
-        }
+            .map_err(|e| anyhow!("Initialize failed: {}", e))?;
+        *self.server_info.write().unwrap() = Some(result.clone());
+        self.notify("notifications/initialized", None).await?;
+        tracing::info!("MCP client '{}' initialized successfully", self.name);
 
         // Load available tools and resources
-        self.refresh_tools().await?;
-        self.refresh_resources().await?;
+        let capabilities = result.get("capabilities");
+        if capabilities.and_then(|c| c.get("tools")).is_some() {
+            self.refresh_tools().await?;
+        }
+        if capabilities.and_then(|c| c.get("resources")).is_some()
+            && let Err(e) = self.refresh_resources().await
+        {
+            tracing::warn!("Failed to list resources of '{}': {}", self.name, e);
+        }
 
         Ok(())
     }
 
-    /// Send a request to the server
-    async fn send_request(&mut self, request: Request) -> Result<()> {
-        if let Some(transport) = &mut self.transport {
-            let message = McpMessage::Request(request);
-            transport.send(message).await?;
-        } else {
-            return Err(anyhow!("No transport available"));
-        }
-        Ok(())
+    /// Send a request to the server and wait for its result
+    pub async fn request(&self, method: &str, params: Option<Value>) -> Result<Value> {
+        self.request_with_timeout(method, params, self.request_timeout, None)
+            .await
     }
 
-    /// Refresh the list of available tools
-    async fn refresh_tools(&mut self) -> Result<()> {
-        let mut router = self.router.write().await;
-        let id = router.next_id();
-        let rx = router.register_request(id);
-        drop(router);
+    /// Send a request and wait at most `limit` for its result, asking the
+    /// server to report its progress to `progress`
+    async fn request_with_timeout(
+        &self,
+        method: &str,
+        mut params: Option<Value>,
+        limit: Duration,
+        progress: Option<ProgressSender>,
+    ) -> Result<Value> {
+        match self.state() {
+            ConnectionState::Failed => {
+                let error = self.health.read().unwrap().last_error.clone();
+                return Err(anyhow!(
+                    "MCP server '{}' is unavailable: {}",
+                    self.name,
+                    error.unwrap_or_else(|| "connection lost".to_string())
+                ));
+            }
+            ConnectionState::Disconnected => {
+                return Err(anyhow!("MCP server '{}' is disconnected", self.name));
+            }
+            _ => {}
+        }
 
-        let request = RequestBuilder::list_tools(id);
-        self.send_request(request).await?;
+        let transport = self.transport();
+        let (id, rx) = {
+            let mut router = self.router.lock().unwrap();
+            let id = router.next_id();
+            if let Some(progress) = progress
+                && let Some(Value::Object(params)) = params.as_mut()
+            {
+                params.insert("_meta".to_string(), json!({ "progressToken": id }));
+                router.register_progress(id, progress);
+            }
+            (id, router.register_request(id))
+        };
+
+        // Cancels the request on the server if this future is dropped before it is answered
+        let mut pending = PendingRequest {
+            client: self,
+            transport: transport.clone(),
+            id,
+            // The initialize request must not be cancelled
+            cancellable: method != "initialize",
+        };
+
+        // HTTP transports may take a while just to accept the request
+        let request = RequestBuilder::request(id, method, params);
+        let exchange = async {
+            transport.send(McpMessage::Request(request)).await?;
+            rx.await
+                .map_err(|_| anyhow!("Connection to '{}' was lost", self.name))
+        };
+
+        let response = match timeout(limit, exchange).await {
+            Ok(Ok(response)) => response,
+            Ok(Err(e)) => {
+                // The server never got the request or the connection is gone
+                pending.cancellable = false;
+                self.router.lock().unwrap().cancel(id);
+                return Err(e);
+            }
+            Err(_) => {
+                pending.cancel(format!("Timed out after {}s", limit.as_secs_f32()));
+                return Err(anyhow!(
+                    "Timeout waiting for '{}' response from '{}'",
+                    method,
+                    self.name
+                ));
+            }
+        };
+
+        response.into_result().map_err(|e| anyhow!(e))
+    }
 
-        let response = timeout(Duration::from_secs(10), rx)
+    /// Send a notification to the server
+    pub async fn notify(&self, method: &str, params: Option<Value>) -> Result<()> {
+        self.transport()
+            .send(McpMessage::notification(method, params))
             .await
-            .map_err(|_| anyhow!("Timeout waiting for tools list"))?
-            .map_err(|_| anyhow!("Tools list request cancelled"))?;
+    }
 
-        if let Some(result) = response.result {
-            if let Some(tools_array) = result.get("tools").and_then(|t| t.as_array()) {
-                let mut tools = self.tools.write().await;
-                tools.clear();
+    /// Read messages of a connection until it closes, then tell the supervisor
+    async fn dispatch_messages(self, connection: Connection) {
+        loop {
+            let message = match connection.transport.receive().await {
+                Ok(message) => message,
+                Err(e) => {
+                    tracing::debug!("MCP server '{}' disconnected: {}", self.name, e);
+                    self.health.write().unwrap().last_error = Some(e.to_string());
+                    break;
+                }
+            };
 
-                for tool_value in tools_array {
-                    if let Ok(tool) = serde_json::from_value::<McpToolDef>(tool_value.clone()) {
-                        tools.insert(tool.name.clone(), tool);
+            match message {
+                McpMessage::Response(response) => {
+                    if !self.router.lock().unwrap().handle_response(response) {
+                        tracing::debug!("Ignoring unexpected response from '{}'", self.name);
                     }
                 }
-
-                tracing::info!("Loaded {} tools from MCP server", tools.len());
+                McpMessage::Request(request) => {
+                    self.handle_request(&*connection.transport, request).await
+                }
+                McpMessage::Notification(notification) => match notification.method.as_str() {
+                    "notifications/progress" => {
+                        let params = notification.params.unwrap_or_default();
+                        if !self.router.lock().unwrap().handle_progress(&params) {
+                            tracing::trace!("Ignoring progress of a finished request");
+                        }
+                    }
+                    "notifications/message" => self.log_server_message(notification),
+                    "notifications/tools/list_changed" => {
+                        // Refreshing waits for a response this task has to deliver
+                        let client = self.clone();
+                        tokio::spawn(async move {
+                            if let Err(e) = client.refresh_tools().await {
+                                tracing::warn!(
+                                    "Failed to refresh tools of '{}': {}",
+                                    client.name,
+                                    e
+                                );
+                            }
+                        });
+                    }
+                    "notifications/resources/list_changed" => {
+                        let client = self.clone();
+                        tokio::spawn(async move {
+                            if let Err(e) = client.refresh_resources().await {
+                                tracing::warn!(
+                                    "Failed to refresh resources of '{}': {}",
+                                    client.name,
+                                    e
+                                );
+                            }
+                        });
+                    }
+                    method => {
+                        tracing::trace!("Ignoring notification '{}' from '{}'", method, self.name)
+                    }
+                },
             }
-        } else if let Some(error) = response.error {
-            tracing::warn!("Failed to list tools: {:?}", error);
         }
 
-        Ok(())
+        self.router.lock().unwrap().fail_all();
+        let _ = self.lost.send(connection.generation);
     }
 
-    /// Refresh the list of available resources
-    async fn refresh_resources(&mut self) -> Result<()> {
-        let mut router = self.router.write().await;
-        let id = router.next_id();
-        let rx = router.register_request(id);
-        drop(router);
+    /// Log a message the server sent with `notifications/message`
+    fn log_server_message(&self, notification: Notification) {
+        let params = notification.params.unwrap_or_default();
+        let level = params
+            .get("level")
+            .cloned()
+            .and_then(|level| serde_json::from_value(level).ok())
+            .unwrap_or(LoggingLevel::Info);
+        let text = match params.get("data") {
+            Some(Value::String(text)) => text.clone(),
+            Some(data) => data.to_string(),
+            None => String::new(),
+        };
+        let message = match params.get("logger").and_then(Value::as_str) {
+            Some(logger) => format!("[{}/{}] {}", self.name, logger, text),
+            None => format!("[{}] {}", self.name, text),
+        };
 
-        let request = RequestBuilder::list_resources(id);
-        self.send_request(request).await?;
+        let level = match level {
+            LoggingLevel::Debug => {
+                tracing::debug!("{}", message);
+                return;
+            }
+            LoggingLevel::Info | LoggingLevel::Notice => tracing::Level::INFO,
+            LoggingLevel::Warning => tracing::Level::WARN,
+            _ => tracing::Level::ERROR,
+        };
+        // Writing to the logger must not hold up the messages behind this one
+        let client = self.clone();
+        tokio::spawn(async move { client.report(level, message).await });
+    }
 
-        let response = timeout(Duration::from_secs(10), rx)
-            .await
-            .map_err(|_| anyhow!("Timeout waiting for resources list"))?
-            .map_err(|_| anyhow!("Resources list request cancelled"))?;
+    /// Answer a request sent by the server
+    async fn handle_request(&self, transport: &dyn McpTransport, request: Request) {
+        let response = match request.method.as_str() {
+            "ping" => Response::success(request.id, json!({})),
+            method => Response::failure(request.id, McpError::method_not_found(method)),
+        };
+        if let Err(e) = transport.send(McpMessage::Response(response)).await {
+            tracing::warn!("Failed to answer request from '{}': {}", self.name, e);
+        }
+    }
+
+    /// Re-fetch the server's tools, returning how many it advertises
+    pub async fn refresh_tools(&self) -> Result<usize> {
+        let mut tools = BTreeMap::new();
+        let mut cursor: Option<String> = None;
 
-        if let Some(result) = response.result {
-            if let Some(resources_array) = result.get("resources").and_then(|r| r.as_array()) {
-                let mut resources = self.resources.write().await;
-                resources.clear();
+        loop {
+            let params = cursor.map(|cursor| json!({ "cursor": cursor }));
+            let result = self.request("tools/list", params).await?;
 
-                for resource_value in resources_array {
-                    if let Some(uri) = resource_value.get("uri").and_then(|u| u.as_str()) {
-                        resources.insert(uri.to_string(), resource_value.clone());
+            if let Some(tools_array) = result.get("tools").and_then(|t| t.as_array()) {
+                for tool_value in tools_array {
+                    match serde_json::from_value::<McpToolDef>(tool_value.clone()) {
+                        Ok(tool) => {
+                            tools.insert(tool.name.clone(), tool);
+                        }
+                        Err(e) => {
+                            tracing::warn!("Skipping invalid tool from '{}': {}", self.name, e)
+                        }
                     }
                 }
+            }
 
-                tracing::info!("Loaded {} resources from MCP server", resources.len());
+            cursor = result
+                .get("nextCursor")
+                .and_then(|c| c.as_str())
+                .map(String::from);
+            if cursor.is_none() {
+                break;
             }
-        } else if let Some(error) = response.error {
-            tracing::warn!("Failed to list resources: {:?}", error);
         }
 
-        Ok(())
+        let count = tools.len();
+        *self.tools.write().unwrap() = tools;
+        tracing::info!("Loaded {} tools from MCP server '{}'", count, self.name);
+        Ok(count)
     }
 
-    /// Get all available tools as Vega-compatible tools
-    pub async fn get_tools(&self) -> Result<Vec<Box<dyn McpTool>>> {
-        let tools = self.tools.read().await;
-        let mut vega_tools: Vec<Box<dyn McpTool>> = Vec::new();
+    /// Re-fetch the server's resources, returning how many it advertises
+    pub async fn refresh_resources(&self) -> Result<usize> {
+        let result = self.request("resources/list", None).await?;
+        let mut resources = BTreeMap::new();
 
-        for (name, tool_def) in tools.iter() {
-            let vega_tool = VegaMcpTool::new(name.clone(), tool_def.clone(), self.router.clone());
-            vega_tools.push(Box::new(vega_tool));
+        if let Some(resources_array) = result.get("resources").and_then(|r| r.as_array()) {
+            for resource_value in resources_array {
+                if let Some(uri) = resource_value.get("uri").and_then(|u| u.as_str()) {
+                    resources.insert(uri.to_string(), resource_value.clone());
+                }
+            }
         }
 
-        Ok(vega_tools)
+        let count = resources.len();
+        *self.resources.write().unwrap() = resources;
+        tracing::info!("Loaded {} resources from MCP server '{}'", count, self.name);
+        Ok(count)
     }
 
-    /// Call a tool on the remote server
-    pub async fn call_tool(&mut self, name: &str, arguments: Option<Value>) -> Result<Value> {
-        let mut router = self.router.write().await;
-        let id = router.next_id();
-        let rx = router.register_request(id);
-        drop(router);
-
-        let request = RequestBuilder::call_tool(id, name, arguments);
-        self.send_request(request).await?;
+    /// Get all available tools as Vega-compatible tools
+    pub fn get_tools(&self) -> Vec<VegaMcpTool> {
+        self.tools
+            .read()
+            .unwrap()
+            .values()
+            .map(|definition| VegaMcpTool::new(definition.clone(), self.clone()))
+            .collect()
+    }
 
-        let response = timeout(Duration::from_secs(30), rx)
-            .await
-            .map_err(|_| anyhow!("Timeout waiting for tool call response"))?
-            .map_err(|_| anyhow!("Tool call request cancelled"))?;
+    /// Call a tool on the remote server.
+    ///
+    /// Waits while `max_concurrent_calls` calls to the server are in progress,
+    /// and fails if the call takes longer than `default_timeout`.
+    pub async fn call_tool(
+        &self,
+        name: &str,
+        arguments: Option<Value>,
+    ) -> Result<McpToolCallResponse> {
+        self.call_tool_with_progress(name, arguments, None).await
+    }
 
-        if let Some(result) = response.result {
-            Ok(result)
-        } else if let Some(error) = response.error {
-            Err(anyhow!("Tool call failed: {:?}", error))
-        } else {
-            Err(anyhow!("No result or error in response"))
+    /// Call a tool like [`McpClient::call_tool`], sending the progress the
+    /// server reports to `progress`.
+    ///
+    /// Dropping the returned future cancels the call on the server.
+    pub async fn call_tool_with_progress(
+        &self,
+        name: &str,
+        arguments: Option<Value>,
+        progress: Option<ProgressSender>,
+    ) -> Result<McpToolCallResponse> {
+        let mut params = json!({ "name": name });
+        if let Some(arguments) = arguments {
+            params["arguments"] = arguments;
         }
+
+        let _permit = self
+            .calls
+            .acquire()
+            .await
+            .map_err(|_| anyhow!("MCP server '{}' is disconnected", self.name))?;
+        let call_timeout = Duration::from_secs(self.settings.default_timeout.max(1));
+        let result = self
+            .request_with_timeout("tools/call", Some(params), call_timeout, progress)
+            .await?;
+        serde_json::from_value(result).map_err(|e| anyhow!("Invalid tool call result: {}", e))
     }
 
     /// List available tools
-    pub async fn list_tools(&self) -> Result<Vec<String>> {
-        let tools = self.tools.read().await;
-        Ok(tools.keys().cloned().collect())
+    pub fn list_tools(&self) -> Vec<String> {
+        self.tools.read().unwrap().keys().cloned().collect()
     }
 
     /// Get tool definition
-    pub async fn get_tool_definition(&self, name: &str) -> Result<McpToolDef> {
-        let tools = self.tools.read().await;
-        tools
+    pub fn get_tool_definition(&self, name: &str) -> Result<McpToolDef> {
+        self.tools
+            .read()
+            .unwrap()
             .get(name)
             .cloned()
             .ok_or_else(|| anyhow!("Tool '{}' not found", name))
     }
 
     /// List available resources
-    pub async fn list_resources(&self) -> Result<Vec<String>> {
-        let resources = self.resources.read().await;
-        Ok(resources.keys().cloned().collect())
+    pub fn list_resources(&self) -> Vec<String> {
+        self.resources.read().unwrap().keys().cloned().collect()
     }
 
     /// Read a resource from the server
-    pub async fn read_resource(&mut self, uri: &str) -> Result<Value> {
-        let mut router = self.router.write().await;
-        let id = router.next_id();
-        let rx = router.register_request(id);
-        drop(router);
-
-        let request = RequestBuilder::read_resource(id, uri);
-        self.send_request(request).await?;
-
-        let response = timeout(Duration::from_secs(30), rx)
+    pub async fn read_resource(&self, uri: &str) -> Result<Value> {
+        self.request("resources/read", Some(json!({ "uri": uri })))
             .await
-            .map_err(|_| anyhow!("Timeout waiting for resource read response"))?
-            .map_err(|_| anyhow!("Resource read request cancelled"))?;
+    }
 
-        if let Some(result) = response.result {
-            Ok(result)
-        } else if let Some(error) = response.error {
-            Err(anyhow!("Resource read failed: {:?}", error))
-        } else {
-            Err(anyhow!("No result or error in response"))
-        }
+    /// Name the server is configured under
+    pub fn name(&self) -> &str {
+        &self.name
     }
 
-    /// Get server information
-    pub fn get_server_info(&self) -> Option<&Value> {
-        self.server_info.as_ref()
+    /// Get server information from the initialize handshake
+    pub fn get_server_info(&self) -> Option<Value> {
+        self.server_info.read().unwrap().clone()
     }
 
     /// Check if the client is connected
     pub fn is_connected(&self) -> bool {
-        self.connected && self.transport.as_ref().map_or(false, |t| t.is_connected())
+        self.state() == ConnectionState::Connected && self.transport().is_connected()
     }
 
     /// Disconnect from the server
-    pub async fn disconnect(&mut self) -> Result<()> {
-        if let Some(mut transport) = self.transport.take() {
-            transport.close().await?;
-        }
-        self.connected = false;
-        tracing::info!("Disconnected from MCP server");
+    pub async fn disconnect(&self) -> Result<()> {
+        self.set_state(ConnectionState::Disconnected);
+        self.transport().close().await?;
+        tracing::info!("Disconnected from MCP server '{}'", self.name);
         Ok(())
     }
 }
 
-// Helper trait to enable downcasting for transport
-trait AsAny {
-    fn as_any(&mut self) -> &mut dyn std::any::Any;
+/// A request awaiting its response, cancelled on the server when abandoned
+struct PendingRequest<'a> {
+    client: &'a McpClient,
+    transport: Arc<dyn McpTransport>,
+    id: u64,
+    cancellable: bool,
 }
 
-impl<T: 'static> AsAny for T {
-    fn as_any(&mut self) -> &mut dyn std::any::Any {
-        self
+impl PendingRequest<'_> {
+    /// Stop waiting for the response and tell the server, unless it already answered
+    fn cancel(&mut self, reason: String) {
+        let pending = self.client.router.lock().unwrap().cancel(self.id);
+        if !pending || !std::mem::take(&mut self.cancellable) {
+            return;
+        }
+
+        tracing::debug!(
+            "Cancelling request {} to '{}': {}",
+            self.id,
+            self.client.name,
+            reason
+        );
+        let transport = self.transport.clone();
+        let message = McpMessage::cancelled(&json!(self.id), &reason);
+        let name = self.client.name.clone();
+        // Sending may await an HTTP round trip, which a drop cannot wait for
+        if let Ok(runtime) = tokio::runtime::Handle::try_current() {
+            runtime.spawn(async move {
+                if let Err(e) = transport.send(message).await {
+                    tracing::debug!("Failed to cancel request to '{}': {}", name, e);
+                }
+            });
+        }
     }
 }
 
-// Extend McpTransport with AsAny
-impl dyn McpTransport {
-    fn as_any(&mut self) -> &mut dyn std::any::Any {
-        self as &mut dyn std::any::Any
+impl Drop for PendingRequest<'_> {
+    fn drop(&mut self) {
+        self.cancel("The request was abandoned".to_string());
     }
 }
 
@@ -330,6 +842,8 @@ impl dyn McpTransport {
 mod tests {
     use super::*;
     use crate::mcp::config::{TransportConfig, TransportType};
+    use std::collections::HashMap;
+    use tokio::sync::mpsc;
 
     #[test]
     fn test_mcp_client_config() {
@@ -339,6 +853,8 @@ mod tests {
             args: vec!["server.py".to_string()],
             env: HashMap::new(),
             cwd: None,
+            url: None,
+            headers: HashMap::new(),
             transport: TransportConfig {
                 transport_type: TransportType::Stdio,
                 ..Default::default()
@@ -353,14 +869,11 @@ mod tests {
 
     #[tokio::test]
     async fn test_client_tools_storage() {
-        let client_config = McpClientConfig::default();
-
         // Create a mock client (this won't actually connect in tests)
-        let router = Arc::new(RwLock::new(MessageRouter::new()));
         let tools = Arc::new(RwLock::new(HashMap::new()));
 
         // Simulate adding a tool
-        let mut tools_map = tools.write().await;
+        let mut tools_map = tools.write().unwrap();
         let mock_tool = McpToolDef {
             name: "test_tool".to_string(),
             description: Some("A test tool".to_string()),
@@ -375,8 +888,350 @@ mod tests {
         drop(tools_map);
 
         // Verify tool was stored
-        let tools_map = tools.read().await;
+        let tools_map = tools.read().unwrap();
         assert!(tools_map.contains_key("test_tool"));
         assert_eq!(tools_map.get("test_tool").unwrap().name, "test_tool");
     }
+
+    /// Transport connected to an in-process fake server
+    struct ChannelTransport {
+        outgoing: mpsc::UnboundedSender<McpMessage>,
+        incoming: tokio::sync::Mutex<mpsc::UnboundedReceiver<McpMessage>>,
+    }
+
+    #[async_trait::async_trait]
+    impl McpTransport for ChannelTransport {
+        async fn send(&self, message: McpMessage) -> Result<()> {
+            self.outgoing.send(message).map_err(|e| anyhow!("{}", e))
+        }
+
+        async fn receive(&self) -> Result<McpMessage> {
+            self.incoming
+                .lock()
+                .await
+                .recv()
+                .await
+                .ok_or_else(|| anyhow!("closed"))
+        }
+
+        async fn close(&self) -> Result<()> {
+            Ok(())
+        }
+
+        fn is_connected(&self) -> bool {
+            !self.outgoing.is_closed()
+        }
+    }
+
+    fn tool(name: &str) -> Value {
+        json!({"name": name, "description": name, "inputSchema": {"type": "object"}})
+    }
+
+    /// Serve `echo` until a `tools/call` of `add_reverse` adds a second tool.
+    ///
+    /// `count` reports progress before answering, and `cancelled` lists the
+    /// requests the client cancelled.
+    async fn fake_server(
+        mut requests: mpsc::UnboundedReceiver<McpMessage>,
+        replies: mpsc::UnboundedSender<McpMessage>,
+    ) {
+        let mut tools = vec![tool("echo")];
+        let mut cancelled = Vec::new();
+        while let Some(message) = requests.recv().await {
+            let request = match message {
+                McpMessage::Request(request) => request,
+                McpMessage::Notification(notification)
+                    if notification.method == "notifications/cancelled" =>
+                {
+                    cancelled.push(notification.params.unwrap()["requestId"].clone());
+                    continue;
+                }
+                _ => continue,
+            };
+            let params = request.params.unwrap_or_default();
+            let result = match request.method.as_str() {
+                "initialize" => json!({
+                    "protocolVersion": PROTOCOL_VERSION,
+                    "capabilities": {"tools": {"listChanged": true}},
+                    "serverInfo": {"name": "fake", "version": "1.0"}
+                }),
+                "tools/list" => json!({ "tools": tools }),
+                // Never answered, so the call times out
+                "tools/call" if params["name"] == "hang" => continue,
+                "tools/call" if params["name"] == "add_reverse" => {
+                    tools.push(tool("reverse"));
+                    let _ = replies.send(McpMessage::notification(
+                        "notifications/tools/list_changed",
+                        None,
+                    ));
+                    json!({"content": []})
+                }
+                "tools/call" if params["name"] == "count" => {
+                    let token = &params["_meta"]["progressToken"];
+                    for step in 1..=2 {
+                        let progress = crate::tools::ToolProgress {
+                            progress: step as f64,
+                            total: Some(2.0),
+                            message: None,
+                        };
+                        let _ = replies.send(McpMessage::progress(token, &progress));
+                    }
+                    let _ = replies.send(McpMessage::log(
+                        LoggingLevel::Warning,
+                        "fake",
+                        json!("counted"),
+                    ));
+                    json!({"content": [{"type": "text", "text": "2"}]})
+                }
+                "tools/call" if params["name"] == "cancelled" => json!({
+                    "content": [{"type": "text", "text": Value::from(cancelled.clone()).to_string()}]
+                }),
+                "tools/call" => json!({
+                    "content": [{"type": "text", "text": params["arguments"]["text"]}],
+                    "isError": params["arguments"]["text"] == "fail"
+                }),
+                method => {
+                    let error = McpError::method_not_found(method);
+                    let _ =
+                        replies.send(McpMessage::Response(Response::failure(request.id, error)));
+                    continue;
+                }
+            };
+            let _ = replies.send(McpMessage::Response(Response::success(request.id, result)));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_tools_reach_agent_and_refresh_on_list_changed() {
+        use crate::tools::RigTool;
+
+        let (client_tx, server_rx) = mpsc::unbounded_channel();
+        let (server_tx, client_rx) = mpsc::unbounded_channel();
+        tokio::spawn(fake_server(server_rx, server_tx));
+        let transport = ChannelTransport {
+            outgoing: client_tx,
+            incoming: tokio::sync::Mutex::new(client_rx),
+        };
+
+        let client = McpClient::with_transport(
+            "fake",
+            McpClientConfig::default(),
+            McpSettings::default(),
+            Box::new(transport),
+        )
+        .await
+        .unwrap();
+        assert_eq!(client.list_tools(), vec!["echo"]);
+        assert_eq!(
+            client.get_server_info().unwrap()["serverInfo"]["name"],
+            "fake"
+        );
+
+        let tools = client.get_tools();
+        assert_eq!(RigTool::name(&tools[0]), "fake__echo");
+        assert_eq!(tools[0].remote_name(), "echo");
+        let output = tools[0].call(json!({"text": "hello"})).await.unwrap();
+        assert_eq!(output, "hello");
+        let error = tools[0].call(json!({"text": "fail"})).await.unwrap_err();
+        assert!(error.to_string().contains("fail"));
+
+        client.call_tool("add_reverse", None).await.unwrap();
+        for _ in 0..100 {
+            if client.list_tools().len() == 2 {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        assert_eq!(client.list_tools(), vec!["echo", "reverse"]);
+
+        let error = client.request("prompts/list", None).await.unwrap_err();
+        assert!(error.to_string().contains("Method not found"));
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_calls_and_timeout() {
+        let (client_tx, server_rx) = mpsc::unbounded_channel();
+        let (server_tx, client_rx) = mpsc::unbounded_channel();
+        tokio::spawn(fake_server(server_rx, server_tx));
+        let transport = ChannelTransport {
+            outgoing: client_tx,
+            incoming: tokio::sync::Mutex::new(client_rx),
+        };
+        let settings = McpSettings {
+            max_concurrent_calls: 1,
+            default_timeout: 1,
+            ..Default::default()
+        };
+        let client = McpClient::with_transport(
+            "fake",
+            McpClientConfig::default(),
+            settings,
+            Box::new(transport),
+        )
+        .await
+        .unwrap();
+
+        let hanging = tokio::spawn({
+            let client = client.clone();
+            async move { client.call_tool("hang", None).await }
+        });
+        for _ in 0..100 {
+            if client.health().active_calls == 1 {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        assert_eq!(client.health().active_calls, 1);
+
+        // The second call waits for the first to time out
+        let started = std::time::Instant::now();
+        let response = client
+            .call_tool("echo", Some(json!({"text": "queued"})))
+            .await
+            .unwrap();
+        assert_eq!(response.text(), "queued");
+        assert!(started.elapsed() >= Duration::from_millis(500));
+
+        let error = hanging.await.unwrap().unwrap_err();
+        assert!(error.to_string().contains("Timeout"));
+        assert_eq!(client.health().active_calls, 0);
+    }
+
+    #[tokio::test]
+    async fn test_progress_and_cancellation() {
+        let (client_tx, server_rx) = mpsc::unbounded_channel();
+        let (server_tx, client_rx) = mpsc::unbounded_channel();
+        tokio::spawn(fake_server(server_rx, server_tx));
+        let transport = ChannelTransport {
+            outgoing: client_tx,
+            incoming: tokio::sync::Mutex::new(client_rx),
+        };
+        let settings = McpSettings {
+            default_timeout: 1,
+            ..Default::default()
+        };
+        let client = McpClient::with_transport(
+            "fake",
+            McpClientConfig::default(),
+            settings,
+            Box::new(transport),
+        )
+        .await
+        .unwrap();
+
+        let (progress, mut updates) = mpsc::unbounded_channel();
+        let response = client
+            .call_tool_with_progress("count", None, Some(progress))
+            .await
+            .unwrap();
+        assert_eq!(response.text(), "2");
+        assert_eq!(updates.recv().await.unwrap().progress, 1.0);
+        assert_eq!(updates.recv().await.unwrap().progress, 2.0);
+        assert!(updates.recv().await.is_none());
+
+        // A timed out call and an abandoned one are both cancelled on the server
+        client.call_tool("hang", None).await.unwrap_err();
+        let abandoned = tokio::spawn({
+            let client = client.clone();
+            async move { client.call_tool("hang", None).await }
+        });
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        abandoned.abort();
+
+        let mut cancelled = String::new();
+        for _ in 0..100 {
+            cancelled = client.call_tool("cancelled", None).await.unwrap().text();
+            if cancelled.matches(',').count() == 1 {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        let cancelled: Vec<u64> = serde_json::from_str(&cancelled).unwrap();
+        assert_eq!(cancelled.len(), 2);
+    }
+
+    /// Stdio server offering a `crash` tool that makes the process exit
+    fn crashing_server() -> McpClientConfig {
+        let script = r#"
+while IFS= read -r line; do
+  id=$(printf '%s\n' "$line" | sed -n 's/.*"id":\([0-9]*\).*/\1/p')
+  case "$line" in
+    *'"method":"initialize"'*) result='{"protocolVersion":"2025-06-18","capabilities":{"tools":{}},"serverInfo":{"name":"sh","version":"1"}}' ;;
+    *'"method":"tools/list"'*) result='{"tools":[{"name":"crash","inputSchema":{"type":"object"}}]}' ;;
+    *'"method":"tools/call"'*) exit 1 ;;
+    *) continue ;;
+  esac
+  printf '{"jsonrpc":"2.0","id":%s,"result":%s}\n' "$id" "$result"
+done
+"#;
+        McpClientConfig {
+            command: "sh".to_string(),
+            args: vec!["-c".to_string(), script.to_string()],
+            ..Default::default()
+        }
+    }
+
+    async fn wait_for_state(client: &McpClient, state: ConnectionState) {
+        for _ in 0..100 {
+            if client.state() == state {
+                return;
+            }
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+        panic!("{:?} never reached {:?}", client.health(), state);
+    }
+
+    #[tokio::test]
+    async fn test_crashed_server_is_restarted() {
+        let client = McpClient::connect("crashy", crashing_server())
+            .await
+            .unwrap();
+        assert_eq!(client.list_tools(), vec!["crash"]);
+
+        let error = client.call_tool("crash", None).await.unwrap_err();
+        assert!(error.to_string().contains("lost"));
+
+        wait_for_state(&client, ConnectionState::Connected).await;
+        let health = client.health();
+        assert_eq!(health.reconnects, 1);
+        assert_eq!(health.tools, 1);
+        assert!(health.last_error.is_some());
+        assert_eq!(client.refresh_tools().await.unwrap(), 1);
+
+        client.disconnect().await.unwrap();
+        assert_eq!(client.state(), ConnectionState::Disconnected);
+    }
+
+    #[tokio::test]
+    async fn test_crashed_server_without_reconnect() {
+        let settings = McpSettings {
+            auto_reconnect: false,
+            ..Default::default()
+        };
+        let client = McpClient::connect_with_settings("crashy", crashing_server(), settings)
+            .await
+            .unwrap();
+
+        client.call_tool("crash", None).await.unwrap_err();
+        wait_for_state(&client, ConnectionState::Failed).await;
+        assert!(!client.is_connected());
+
+        let error = client.refresh_tools().await.unwrap_err();
+        assert!(error.to_string().contains("unavailable"));
+    }
+
+    #[tokio::test]
+    async fn test_connect_fails_for_missing_command() {
+        let config = McpClientConfig {
+            command: "/nonexistent/mcp-server".to_string(),
+            args: vec![],
+            ..Default::default()
+        };
+        let error = McpClient::connect("missing", config).await.unwrap_err();
+        assert!(
+            error
+                .to_string()
+                .contains("Failed to start MCP server process")
+        );
+    }
 }
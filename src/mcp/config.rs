@@ -4,9 +4,13 @@
 
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
+use std::net::SocketAddr;
 
 /// Main configuration for MCP functionality
+///
+/// Every field may be left out of a configuration file and takes its default.
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
 pub struct McpConfig {
     /// Server configuration (if running as an MCP server)
     pub server: Option<McpServerConfig>,
@@ -28,6 +32,7 @@ impl Default for McpConfig {
 
 /// Configuration for an MCP server instance
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
 pub struct McpServerConfig {
     /// Name of the server
     pub name: String,
@@ -37,6 +42,8 @@ pub struct McpServerConfig {
     pub version: String,
     /// Transport configuration
     pub transport: TransportConfig,
+    /// Address the Streamable HTTP transport listens on
+    pub listen: SocketAddr,
     /// Which tools to expose via MCP
     pub exposed_tools: Vec<String>,
     /// Server-specific settings
@@ -50,6 +57,7 @@ impl Default for McpServerConfig {
             description: "Vega AI Agent MCP Server".to_string(),
             version: "0.1.0".to_string(),
             transport: TransportConfig::default(),
+            listen: SocketAddr::from(([127, 0, 0, 1], 8765)),
             exposed_tools: vec![
                 "bash".to_string(),
                 "read_file".to_string(),
@@ -66,10 +74,11 @@ impl Default for McpServerConfig {
 
 /// Configuration for an MCP client connection
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
 pub struct McpClientConfig {
     /// Name of the server to connect to
     pub server_name: String,
-    /// Command to start the MCP server
+    /// Command to start the MCP server (stdio transport)
     pub command: String,
     /// Arguments for the server command
     pub args: Vec<String>,
@@ -77,6 +86,10 @@ pub struct McpClientConfig {
     pub env: HashMap<String, String>,
     /// Working directory for the server process
     pub cwd: Option<String>,
+    /// URL of the server (HTTP and SSE transports)
+    pub url: Option<String>,
+    /// Extra HTTP headers sent with every request, e.g. `Authorization`
+    pub headers: HashMap<String, String>,
     /// Transport configuration
     pub transport: TransportConfig,
     /// Client-specific settings
@@ -91,6 +104,8 @@ impl Default for McpClientConfig {
             args: vec!["server.py".to_string()],
             env: HashMap::new(),
             cwd: None,
+            url: None,
+            headers: HashMap::new(),
             transport: TransportConfig::default(),
             settings: ClientSettings::default(),
         }
@@ -99,6 +114,7 @@ impl Default for McpClientConfig {
 
 /// Transport layer configuration
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
 pub struct TransportConfig {
     /// Transport type (stdio, sse, etc.)
     pub transport_type: TransportType,
@@ -120,14 +136,15 @@ impl Default for TransportConfig {
 pub enum TransportType {
     /// Standard input/output transport
     Stdio,
-    /// Server-Sent Events transport
+    /// Legacy HTTP+SSE transport: an event stream plus an endpoint for posting messages
     Sse,
-    /// HTTP transport (future)
+    /// Streamable HTTP transport
     Http,
 }
 
 /// Transport-specific configuration options
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
 pub struct TransportOptions {
     /// Timeout for operations (in seconds)
     pub timeout: Option<u64>,
@@ -152,16 +169,17 @@ impl Default for TransportOptions {
 
 /// Global MCP settings
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
 pub struct McpSettings {
     /// Enable debug logging for MCP operations
     pub debug: bool,
-    /// Maximum number of concurrent tool calls
+    /// Maximum number of concurrent tool calls per server
     pub max_concurrent_calls: usize,
     /// Default timeout for tool calls (in seconds)
     pub default_timeout: u64,
-    /// Enable automatic reconnection on connection loss
+    /// Restart or reconnect to a server when its connection is lost
     pub auto_reconnect: bool,
-    /// Retry attempts for failed operations
+    /// Reconnection attempts, with exponential backoff, before giving up on a server
     pub retry_attempts: usize,
 }
 
@@ -179,27 +197,45 @@ impl Default for McpSettings {
 
 /// Server-specific settings
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
 pub struct ServerSettings {
-    /// Enable CORS for HTTP transport
+    /// Answer CORS requests from `allowed_origins` on the HTTP transport
     pub enable_cors: bool,
+    /// Origins besides the local machine allowed to call the HTTP transport
+    pub allowed_origins: Vec<String>,
+    /// Bearer token HTTP requests must carry in their `Authorization` header
+    pub auth_token: Option<String>,
     /// Maximum request size (in bytes)
     pub max_request_size: usize,
     /// Rate limiting settings
     pub rate_limit: Option<RateLimit>,
+    /// Maximum number of HTTP sessions; the least recently used is ended to make room
+    pub max_sessions: usize,
+    /// Seconds after which an HTTP session without requests or an open event stream expires
+    pub session_idle_timeout: u64,
+    /// Run the tool calls the permission policy would ask about, as nobody
+    /// can confirm them; they are refused otherwise
+    pub allow_ask: bool,
 }
 
 impl Default for ServerSettings {
     fn default() -> Self {
         Self {
-            enable_cors: true,
+            enable_cors: false,
+            allowed_origins: Vec::new(),
+            auth_token: None,
             max_request_size: 10 * 1024 * 1024, // 10MB
             rate_limit: None,
+            max_sessions: 100,
+            session_idle_timeout: 3600,
+            allow_ask: false,
         }
     }
 }
 
 /// Client-specific settings
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
 pub struct ClientSettings {
     /// Enable connection pooling
     pub connection_pooling: bool,
@@ -365,6 +401,26 @@ mod tests {
         assert!(!config.clients.contains_key("test"));
     }
 
+    #[test]
+    fn test_partial_config_uses_defaults() {
+        let config: McpConfig = serde_json::from_str(
+            r#"{"clients": {"files": {"command": "mcp-files", "args": ["/tmp"]}}}"#,
+        )
+        .unwrap();
+        let client = &config.clients["files"];
+        assert_eq!(client.command, "mcp-files");
+        assert!(matches!(
+            client.transport.transport_type,
+            TransportType::Stdio
+        ));
+        assert_eq!(client.transport.options.timeout, Some(30));
+        assert!(config.server.is_none());
+
+        let example = include_str!("../../examples/mcp_config.json");
+        let config: McpConfig = serde_json::from_str(example).unwrap();
+        assert!(config.clients.contains_key("example_python_server"));
+    }
+
     #[test]
     fn test_mcp_config_server_management() {
         let mut config = McpConfig::default();
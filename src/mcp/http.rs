@@ -0,0 +1,671 @@
+//! # MCP HTTP Transports
+//!
+//! Transports for MCP servers that run as web services:
+//!
+//! - [`StreamableHttpTransport`] - the Streamable HTTP transport. Every message
+//!   is POSTed to a single endpoint, which answers with plain JSON or with an
+//!   SSE stream carrying the response. The `Mcp-Session-Id` header returned by
+//!   `initialize` is sent with every later message, a GET stream receives
+//!   server-initiated messages, and broken streams are resumed with
+//!   `Last-Event-ID`.
+//! - [`SseTransport`] - the legacy HTTP+SSE transport. A GET stream announces an
+//!   `endpoint` event naming the URL messages are POSTed to, and every message
+//!   from the server arrives on that stream.
+
+use anyhow::{Result, anyhow};
+use async_trait::async_trait;
+use reqwest::header::{ACCEPT, CONTENT_TYPE, HeaderMap, HeaderName, HeaderValue};
+use reqwest::{Method, StatusCode, Url};
+use serde_json::Value;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex as StdMutex};
+use std::time::Duration;
+use tokio::sync::{Mutex, Notify, mpsc};
+use tokio::task::JoinHandle;
+
+use super::config::McpClientConfig;
+use super::protocol::{McpError, McpMessage, PROTOCOL_VERSION_HEADER, Response, SESSION_HEADER};
+use super::sse::{SseParser, next_events};
+use super::transport::McpTransport;
+
+/// Header asking a server to replay a stream after the given event
+const LAST_EVENT_ID_HEADER: &str = "last-event-id";
+
+/// How often a broken stream is resumed before giving up
+const MAX_RESUME_ATTEMPTS: usize = 3;
+
+/// Delay before resuming a stream when the server did not ask for one
+const DEFAULT_RETRY: Duration = Duration::from_secs(1);
+
+/// State shared between an HTTP transport and the tasks reading its streams
+struct HttpShared {
+    http: reqwest::Client,
+    headers: HeaderMap,
+    session_id: StdMutex<Option<String>>,
+    protocol_version: StdMutex<Option<String>>,
+    /// Id of the `initialize` request waiting for its response
+    initialize_id: StdMutex<Option<Value>>,
+    incoming: mpsc::UnboundedSender<McpMessage>,
+    connected: AtomicBool,
+    closed: Notify,
+}
+
+impl HttpShared {
+    fn new(config: &McpClientConfig, incoming: mpsc::UnboundedSender<McpMessage>) -> Result<Self> {
+        let mut headers = HeaderMap::new();
+        for (name, value) in &config.headers {
+            headers.insert(
+                HeaderName::from_bytes(name.as_bytes())
+                    .map_err(|e| anyhow!("Invalid header name '{}': {}", name, e))?,
+                HeaderValue::from_str(value)
+                    .map_err(|e| anyhow!("Invalid value for header '{}': {}", name, e))?,
+            );
+        }
+
+        let http = reqwest::Client::builder()
+            .connect_timeout(Duration::from_secs(config.settings.connection_timeout))
+            .build()?;
+
+        Ok(Self {
+            http,
+            headers,
+            session_id: StdMutex::new(None),
+            protocol_version: StdMutex::new(None),
+            initialize_id: StdMutex::new(None),
+            incoming,
+            connected: AtomicBool::new(true),
+            closed: Notify::new(),
+        })
+    }
+
+    /// Start a request carrying the configured, session and protocol version headers
+    fn request(&self, method: Method, url: &Url) -> reqwest::RequestBuilder {
+        let mut request = self
+            .http
+            .request(method, url.clone())
+            .headers(self.headers.clone());
+        if let Some(session_id) = self.session_id.lock().unwrap().as_ref() {
+            request = request.header(SESSION_HEADER, session_id);
+        }
+        if let Some(version) = self.protocol_version.lock().unwrap().as_ref() {
+            request = request.header(PROTOCOL_VERSION_HEADER, version);
+        }
+        request
+    }
+
+    /// Remember the id of an `initialize` request, to recognize its response
+    fn note_sent(&self, message: &McpMessage) {
+        if let McpMessage::Request(request) = message
+            && request.method == "initialize"
+        {
+            *self.initialize_id.lock().unwrap() = Some(request.id.clone());
+        }
+    }
+
+    /// Forward a message from the server, noting the protocol version `initialize` negotiated
+    fn deliver(&self, message: McpMessage) {
+        if let McpMessage::Response(ref response) = message
+            && self.answers_initialize(response)
+            && let Some(version) = response
+                .result
+                .as_ref()
+                .and_then(|result| result.get("protocolVersion"))
+                .and_then(Value::as_str)
+        {
+            *self.protocol_version.lock().unwrap() = Some(version.to_string());
+        }
+        let _ = self.incoming.send(message);
+    }
+
+    /// Whether `response` answers the pending `initialize` request, which is then no longer pending
+    fn answers_initialize(&self, response: &Response) -> bool {
+        let mut initialize_id = self.initialize_id.lock().unwrap();
+        if initialize_id.as_ref() != Some(&response.id) {
+            return false;
+        }
+        initialize_id.take();
+        true
+    }
+
+    /// Forward a JSON body holding one message or a batch, returning the ids of its responses
+    fn deliver_json(&self, body: &str) -> Vec<Value> {
+        let messages = match serde_json::from_str::<Value>(body) {
+            Ok(Value::Array(batch)) => batch,
+            Ok(value) => vec![value],
+            Err(e) => {
+                tracing::warn!("Failed to parse MCP message: {}: {}", e, body);
+                return Vec::new();
+            }
+        };
+
+        let mut answered = Vec::new();
+        for value in messages {
+            match serde_json::from_value::<McpMessage>(value) {
+                Ok(message) => {
+                    if let McpMessage::Response(ref response) = message {
+                        answered.push(response.id.clone());
+                    }
+                    self.deliver(message);
+                }
+                Err(e) => tracing::warn!("Failed to parse MCP message: {}", e),
+            }
+        }
+        answered
+    }
+
+    fn is_connected(&self) -> bool {
+        self.connected.load(Ordering::SeqCst) && !self.incoming.is_closed()
+    }
+
+    /// Mark the transport closed, waking anyone waiting for a message
+    fn disconnect(&self) {
+        self.connected.store(false, Ordering::SeqCst);
+        self.closed.notify_waiters();
+    }
+
+    /// Receive the next message, failing once the transport is closed
+    async fn receive(
+        &self,
+        receiver: &Mutex<mpsc::UnboundedReceiver<McpMessage>>,
+    ) -> Result<McpMessage> {
+        let closed = self.closed.notified();
+        let mut receiver = receiver.lock().await;
+
+        // Messages that arrived before the connection closed are still delivered
+        if let Ok(message) = receiver.try_recv() {
+            return Ok(message);
+        }
+        if !self.connected.load(Ordering::SeqCst) {
+            return Err(anyhow!("MCP server closed the connection"));
+        }
+
+        tokio::select! {
+            biased;
+            message = receiver.recv() => message.ok_or_else(|| anyhow!("MCP server closed the connection")),
+            _ = closed => Err(anyhow!("MCP server closed the connection")),
+        }
+    }
+}
+
+/// How an SSE stream ended
+#[derive(Debug, Default)]
+struct StreamEnd {
+    /// Id of the last event received, to resume from
+    last_event_id: Option<String>,
+    /// Reconnection delay the server asked for
+    retry: Option<Duration>,
+    /// Whether the awaited response arrived
+    answered: bool,
+}
+
+/// Forward the messages of an SSE stream until it ends
+async fn read_stream(
+    shared: &HttpShared,
+    mut response: reqwest::Response,
+    awaiting: Option<&Value>,
+) -> StreamEnd {
+    let mut parser = SseParser::new();
+    let mut end = StreamEnd::default();
+
+    loop {
+        let events = match next_events(&mut response, &mut parser).await {
+            Ok(Some(events)) => events,
+            Ok(None) => break,
+            Err(e) => {
+                tracing::debug!("MCP event stream interrupted: {}", e);
+                break;
+            }
+        };
+
+        for event in events {
+            end.retry = event.retry.map(Duration::from_millis).or(end.retry);
+            if event.event != "message" || event.data.trim().is_empty() {
+                continue;
+            }
+            let answered = shared.deliver_json(&event.data);
+            if awaiting.is_some_and(|id| answered.contains(id)) {
+                end.answered = true;
+            }
+        }
+    }
+
+    end.last_event_id = parser.last_event_id().map(String::from);
+    end
+}
+
+/// Transport for servers implementing the MCP Streamable HTTP transport
+pub struct StreamableHttpTransport {
+    url: Url,
+    shared: Arc<HttpShared>,
+    receiver: Mutex<mpsc::UnboundedReceiver<McpMessage>>,
+    tasks: StdMutex<Vec<JoinHandle<()>>>,
+}
+
+impl StreamableHttpTransport {
+    /// Create a transport for the server at the configured `url`
+    pub fn new(config: &McpClientConfig) -> Result<Self> {
+        let url = configured_url(config)?;
+        let (tx, rx) = mpsc::unbounded_channel();
+
+        Ok(Self {
+            url,
+            shared: Arc::new(HttpShared::new(config, tx)?),
+            receiver: Mutex::new(rx),
+            tasks: StdMutex::new(Vec::new()),
+        })
+    }
+
+    /// Id of the session the server assigned, once initialized
+    pub fn session_id(&self) -> Option<String> {
+        self.shared.session_id.lock().unwrap().clone()
+    }
+
+    fn spawn(&self, task: impl Future<Output = ()> + Send + 'static) {
+        let mut tasks = self.tasks.lock().unwrap();
+        tasks.retain(|task| !task.is_finished());
+        tasks.push(tokio::spawn(task));
+    }
+}
+
+#[async_trait]
+impl McpTransport for StreamableHttpTransport {
+    async fn send(&self, message: McpMessage) -> Result<()> {
+        if !self.is_connected() {
+            return Err(anyhow!("Transport not connected"));
+        }
+
+        let request_id = match message {
+            McpMessage::Request(ref request) => Some(request.id.clone()),
+            _ => None,
+        };
+        // The server may push messages on a GET stream once the session is initialized
+        let opens_stream = matches!(
+            message,
+            McpMessage::Notification(ref notification)
+                if notification.method == "notifications/initialized"
+        );
+
+        self.shared.note_sent(&message);
+        let had_session = self.session_id().is_some();
+        let response = self
+            .shared
+            .request(Method::POST, &self.url)
+            .header(ACCEPT, "application/json, text/event-stream")
+            .json(&message)
+            .send()
+            .await
+            .map_err(|e| anyhow!("Failed to send message: {}", e))?;
+
+        if let Some(session_id) = response
+            .headers()
+            .get(SESSION_HEADER)
+            .and_then(|value| value.to_str().ok())
+        {
+            *self.shared.session_id.lock().unwrap() = Some(session_id.to_string());
+        }
+
+        let status = response.status();
+        if status == StatusCode::NOT_FOUND && had_session {
+            self.shared.session_id.lock().unwrap().take();
+            return Err(anyhow!(
+                "MCP session expired, the server must be initialized again"
+            ));
+        }
+        if !status.is_success() {
+            let body = response.text().await.unwrap_or_default();
+            return Err(anyhow!("MCP server returned {}: {}", status, body.trim()));
+        }
+
+        let content_type = response
+            .headers()
+            .get(CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .unwrap_or_default()
+            .to_string();
+
+        if content_type.starts_with("text/event-stream") {
+            self.spawn(follow_post_stream(
+                self.shared.clone(),
+                self.url.clone(),
+                response,
+                request_id,
+            ));
+        } else if status != StatusCode::ACCEPTED && content_type.starts_with("application/json") {
+            let body = response.text().await?;
+            self.shared.deliver_json(&body);
+        }
+
+        if opens_stream {
+            self.spawn(listen(self.shared.clone(), self.url.clone()));
+        }
+        Ok(())
+    }
+
+    async fn receive(&self) -> Result<McpMessage> {
+        self.shared.receive(&self.receiver).await
+    }
+
+    async fn close(&self) -> Result<()> {
+        self.shared.disconnect();
+        for task in self.tasks.lock().unwrap().drain(..) {
+            task.abort();
+        }
+
+        // Ending the session is a courtesy; servers may refuse it with 405
+        if self.session_id().is_some()
+            && let Err(e) = self.shared.request(Method::DELETE, &self.url).send().await
+        {
+            tracing::debug!("Failed to end MCP session: {}", e);
+        }
+        Ok(())
+    }
+
+    fn is_connected(&self) -> bool {
+        self.shared.is_connected()
+    }
+}
+
+/// Read the SSE stream answering a POST, resuming it if it breaks before the response
+async fn follow_post_stream(
+    shared: Arc<HttpShared>,
+    url: Url,
+    response: reqwest::Response,
+    request_id: Option<Value>,
+) {
+    let mut end = read_stream(&shared, response, request_id.as_ref()).await;
+    let Some(request_id) = request_id else {
+        return;
+    };
+
+    let mut attempts = 0;
+    while !end.answered && shared.is_connected() && attempts < MAX_RESUME_ATTEMPTS {
+        // Without an event id the server cannot tell where to resume
+        let Some(last_event_id) = end.last_event_id.clone() else {
+            break;
+        };
+        attempts += 1;
+        tokio::time::sleep(end.retry.unwrap_or(DEFAULT_RETRY)).await;
+
+        tracing::debug!("Resuming MCP event stream after event {}", last_event_id);
+        let resumed = shared
+            .request(Method::GET, &url)
+            .header(ACCEPT, "text/event-stream")
+            .header(LAST_EVENT_ID_HEADER, &last_event_id)
+            .send()
+            .await;
+        match resumed {
+            Ok(response) if response.status().is_success() => {
+                let next = read_stream(&shared, response, Some(&request_id)).await;
+                end = StreamEnd {
+                    last_event_id: next.last_event_id.or(end.last_event_id),
+                    retry: next.retry.or(end.retry),
+                    answered: next.answered,
+                };
+            }
+            Ok(response) => {
+                tracing::debug!("MCP server refused to resume stream: {}", response.status());
+                break;
+            }
+            Err(e) => tracing::debug!("Failed to resume MCP event stream: {}", e),
+        }
+    }
+
+    // Fail the request now rather than letting it run into its timeout
+    if !end.answered && shared.is_connected() {
+        let error = McpError::new(
+            McpError::INTERNAL_ERROR,
+            "Event stream ended before the server responded",
+        );
+        shared.deliver(McpMessage::Response(Response::failure(request_id, error)));
+    }
+}
+
+/// Receive server-initiated messages on the GET stream for as long as the transport is open
+async fn listen(shared: Arc<HttpShared>, url: Url) {
+    let mut last_event_id: Option<String> = None;
+    let mut retry = DEFAULT_RETRY;
+    let mut failures = 0;
+
+    while shared.is_connected() {
+        let mut request = shared
+            .request(Method::GET, &url)
+            .header(ACCEPT, "text/event-stream");
+        if let Some(id) = &last_event_id {
+            request = request.header(LAST_EVENT_ID_HEADER, id);
+        }
+
+        match request.send().await {
+            Ok(response) if response.status() == StatusCode::METHOD_NOT_ALLOWED => {
+                tracing::debug!("MCP server does not offer an event stream");
+                return;
+            }
+            Ok(response) if response.status().is_success() => {
+                failures = 0;
+                let end = read_stream(&shared, response, None).await;
+                last_event_id = end.last_event_id.or(last_event_id);
+                retry = end.retry.unwrap_or(retry);
+            }
+            Ok(response) => {
+                tracing::debug!("MCP event stream refused: {}", response.status());
+                failures += 1;
+            }
+            Err(e) => {
+                tracing::debug!("Failed to open MCP event stream: {}", e);
+                failures += 1;
+            }
+        }
+
+        if failures >= MAX_RESUME_ATTEMPTS {
+            tracing::warn!("Giving up on the MCP event stream of {}", url);
+            return;
+        }
+        tokio::time::sleep(retry).await;
+    }
+}
+
+/// Transport for servers implementing the legacy HTTP+SSE transport
+pub struct SseTransport {
+    endpoint: Url,
+    shared: Arc<HttpShared>,
+    receiver: Mutex<mpsc::UnboundedReceiver<McpMessage>>,
+    stream: JoinHandle<()>,
+}
+
+impl SseTransport {
+    /// Open the event stream at the configured `url` and wait for the message endpoint
+    pub async fn connect(config: &McpClientConfig) -> Result<Self> {
+        let url = configured_url(config)?;
+        let (tx, rx) = mpsc::unbounded_channel();
+        let shared = Arc::new(HttpShared::new(config, tx)?);
+
+        let mut response = shared
+            .request(Method::GET, &url)
+            .header(ACCEPT, "text/event-stream")
+            .send()
+            .await
+            .map_err(|e| anyhow!("Failed to open MCP event stream: {}", e))?;
+        if !response.status().is_success() {
+            return Err(anyhow!("MCP server returned {}", response.status()));
+        }
+
+        let mut parser = SseParser::new();
+        let wait = Duration::from_secs(config.settings.connection_timeout);
+        let endpoint = tokio::time::timeout(wait, async {
+            while let Some(events) = next_events(&mut response, &mut parser).await? {
+                let mut events = events.into_iter();
+                if let Some(event) = events.by_ref().find(|event| event.event == "endpoint") {
+                    // Messages sent right behind the endpoint belong to the session too
+                    for event in events.filter(|event| event.event == "message") {
+                        shared.deliver_json(&event.data);
+                    }
+                    return Ok(url.join(event.data.trim())?);
+                }
+            }
+            Err(anyhow!(
+                "MCP event stream ended before announcing an endpoint"
+            ))
+        })
+        .await
+        .map_err(|_| anyhow!("Timeout waiting for the MCP message endpoint"))??;
+
+        let stream_shared = shared.clone();
+        let stream = tokio::spawn(async move {
+            loop {
+                match next_events(&mut response, &mut parser).await {
+                    Ok(Some(events)) => {
+                        for event in events.into_iter().filter(|event| event.event == "message") {
+                            stream_shared.deliver_json(&event.data);
+                        }
+                    }
+                    Ok(None) => break,
+                    Err(e) => {
+                        tracing::debug!("MCP event stream interrupted: {}", e);
+                        break;
+                    }
+                }
+            }
+            stream_shared.disconnect();
+        });
+
+        Ok(Self {
+            endpoint,
+            shared,
+            receiver: Mutex::new(rx),
+            stream,
+        })
+    }
+
+    /// URL messages are posted to, as announced by the server
+    pub fn endpoint(&self) -> &Url {
+        &self.endpoint
+    }
+}
+
+#[async_trait]
+impl McpTransport for SseTransport {
+    async fn send(&self, message: McpMessage) -> Result<()> {
+        if !self.is_connected() {
+            return Err(anyhow!("Transport not connected"));
+        }
+
+        self.shared.note_sent(&message);
+        let response = self
+            .shared
+            .request(Method::POST, &self.endpoint)
+            .json(&message)
+            .send()
+            .await
+            .map_err(|e| anyhow!("Failed to send message: {}", e))?;
+
+        if !response.status().is_success() {
+            let status = response.status();
+            let body = response.text().await.unwrap_or_default();
+            return Err(anyhow!("MCP server returned {}: {}", status, body.trim()));
+        }
+        Ok(())
+    }
+
+    async fn receive(&self) -> Result<McpMessage> {
+        self.shared.receive(&self.receiver).await
+    }
+
+    async fn close(&self) -> Result<()> {
+        self.shared.disconnect();
+        self.stream.abort();
+        Ok(())
+    }
+
+    fn is_connected(&self) -> bool {
+        self.shared.is_connected()
+    }
+}
+
+/// URL of an HTTP-based server, which its configuration must name
+fn configured_url(config: &McpClientConfig) -> Result<Url> {
+    let url = config.url.as_deref().ok_or_else(|| {
+        anyhow!(
+            "MCP server '{}' uses the {:?} transport but has no url",
+            config.server_name,
+            config.transport.transport_type
+        )
+    })?;
+    Url::parse(url).map_err(|e| anyhow!("Invalid MCP server url '{}': {}", url, e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mcp::config::TransportType;
+    use crate::mcp::protocol::Request;
+
+    fn request(id: u64, method: &str) -> McpMessage {
+        McpMessage::Request(Request {
+            id: Value::from(id),
+            method: method.to_string(),
+            params: None,
+        })
+    }
+
+    #[test]
+    fn test_http_transport_requires_url() {
+        let mut config = McpClientConfig::default();
+        config.transport.transport_type = TransportType::Http;
+        let error = StreamableHttpTransport::new(&config).err().unwrap();
+        assert!(error.to_string().contains("has no url"));
+
+        config.url = Some("not a url".to_string());
+        assert!(StreamableHttpTransport::new(&config).is_err());
+
+        config.url = Some("http://127.0.0.1:8765/mcp".to_string());
+        config
+            .headers
+            .insert("Authorization".to_string(), "Bearer token".to_string());
+        let transport = StreamableHttpTransport::new(&config).unwrap();
+        assert!(transport.is_connected());
+        assert!(transport.session_id().is_none());
+    }
+
+    #[test]
+    fn test_deliver_json_reports_answered_requests() {
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let shared = HttpShared::new(&McpClientConfig::default(), tx).unwrap();
+        shared.note_sent(&request(1, "initialize"));
+
+        let answered = shared.deliver_json(
+            r#"[{"jsonrpc":"2.0","id":1,"result":{"protocolVersion":"2025-03-26"}},
+                {"jsonrpc":"2.0","method":"notifications/message","params":{}}]"#,
+        );
+        assert_eq!(answered, vec![Value::from(1)]);
+        assert_eq!(
+            shared.protocol_version.lock().unwrap().as_deref(),
+            Some("2025-03-26")
+        );
+        assert!(matches!(rx.try_recv().unwrap(), McpMessage::Response(_)));
+        assert!(matches!(
+            rx.try_recv().unwrap(),
+            McpMessage::Notification(_)
+        ));
+    }
+
+    #[test]
+    fn test_protocol_version_comes_from_the_initialize_response() {
+        let (tx, _rx) = mpsc::unbounded_channel();
+        let shared = HttpShared::new(&McpClientConfig::default(), tx).unwrap();
+        shared.note_sent(&request(1, "initialize"));
+        shared.note_sent(&request(2, "tools/call"));
+
+        // A tool result happening to hold a protocolVersion is not the negotiated one
+        shared
+            .deliver_json(r#"{"jsonrpc":"2.0","id":2,"result":{"protocolVersion":"1999-01-01"}}"#);
+        assert!(shared.protocol_version.lock().unwrap().is_none());
+
+        shared
+            .deliver_json(r#"{"jsonrpc":"2.0","id":1,"result":{"protocolVersion":"2025-03-26"}}"#);
+        shared
+            .deliver_json(r#"{"jsonrpc":"2.0","id":1,"result":{"protocolVersion":"1999-01-01"}}"#);
+        assert_eq!(
+            shared.protocol_version.lock().unwrap().as_deref(),
+            Some("2025-03-26")
+        );
+    }
+}
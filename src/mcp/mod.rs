@@ -16,10 +16,18 @@
 //! The MCP implementation is organized into several modules:
 //!
 //! - [`client`] - MCP client functionality for connecting to external servers
-//! - [`server`] - MCP server functionality for exposing Vega's tools
+//! - [`server`] - MCP server exposing Vega's tools over stdio or Streamable HTTP
+//! - [`resources`] - Vega's sessions, session logs and agent instructions as MCP resources
+//! - [`prompts`] - Prompt templates built from Vega's system prompt and stored sessions
 //! - [`bridge`] - Bridge layer that integrates MCP tools with Vega's existing tool system
 //! - [`config`] - Configuration structures for MCP clients and servers
 //! - [`transport`] - Transport layer implementations (stdio, SSE, etc.)
+//! - [`http`] - Streamable HTTP and legacy SSE transports for servers running as web services
+//! - [`sse`] - Server-Sent Events parsing for the HTTP transports
+//! - [`protocol`] - JSON-RPC message types shared by clients and servers
+//!
+//! Tools of connected servers reach the chat agent through [`McpManager::tools`],
+//! registered as `server__tool` so they cannot clash with Vega's own tools.
 //!
 //! ## Usage Examples
 //!
@@ -31,12 +39,13 @@
 //! #[tokio::main]
 //! async fn main() -> anyhow::Result<()> {
 //!     let config = McpClientConfig {
-//!         server_command: "python mcp_server.py".to_string(),
+//!         command: "python".to_string(),
+//!         args: vec!["mcp_server.py".to_string()],
 //!         ..Default::default()
 //!     };
 //!     
-//!     let mut client = McpClient::new(config).await?;
-//!     let tools = client.list_tools().await?;
+//!     let client = McpClient::connect("python", config).await?;
+//!     let tools = client.list_tools();
 //!     println!("Available tools: {:?}", tools);
 //!     
 //!     Ok(())
@@ -53,37 +62,41 @@
 //!     let config = McpServerConfig::default();
 //!     let server = McpServer::new(config).await?;
 //!     
-//!     // Server will expose Vega's tools via MCP
+//!     // Server will expose Vega's tools via MCP on stdio; set
+//!     // `transport.transport_type` to `Http` to serve them on `config.listen`
 //!     server.run().await?;
 //!     
 //!     Ok(())
 //! }
 //! ```
 
+pub mod bridge;
+pub mod client;
 pub mod config;
+pub mod http;
+pub mod prompts;
+pub mod protocol;
+pub mod resources;
+pub mod server;
 pub mod simple;
-
-// For now, we'll use the simple implementation
-// The more complex bridge, client, server, and transport modules
-// can be enabled later when we have time to fix all the type issues
-
-// pub mod bridge;
-// pub mod client;
-// pub mod server;
-// pub mod transport;
+pub mod sse;
+pub mod transport;
 
 // Re-export commonly used types
-pub use config::{McpConfig, McpServerInfo};
+pub use bridge::VegaMcpTool;
+pub use client::{ConnectionHealth, ConnectionState, McpClient};
+pub use config::{McpClientConfig, McpConfig, McpServerConfig, McpServerInfo, McpSettings};
+pub use server::McpServer;
 pub use simple::{
     SimpleMcpClient, SimpleMcpClientConfig, SimpleMcpManager, SimpleMcpServer,
     SimpleMcpServerConfig,
 };
 
-// These will be available when the full implementation is ready
-// pub use client::{McpClient, McpClientConfig};
-// pub use server::{McpServer, McpServerConfig};
-
 use anyhow::Result;
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+use crate::logging::Logger;
 
 /// Trait representing an MCP tool that can be called remotely
 /// Note: This trait cannot use async methods to remain object-safe
@@ -115,12 +128,31 @@ pub trait McpToolExt: McpTool {
 impl<T: McpTool> McpToolExt for T {}
 
 /// Manager for MCP functionality, coordinating clients and servers
-/// This is an alias to the simple manager for now
-pub type McpManager = SimpleMcpManager;
+#[derive(Debug, Default)]
+pub struct McpManager {
+    /// Server exposing Vega's tools, if enabled
+    pub server: Option<SimpleMcpServer>,
+    /// Connected clients, by the name their server is configured under
+    pub clients: BTreeMap<String, McpClient>,
+    /// Configured clients that have not been connected yet
+    pending_clients: BTreeMap<String, McpClientConfig>,
+    /// Configured clients that could not be connected, with the reason
+    failed_clients: BTreeMap<String, String>,
+    /// Reconnection, concurrency and timeout settings of the clients
+    settings: McpSettings,
+    /// Logger connection health is reported to
+    logger: Option<Arc<Logger>>,
+}
 
-// Extended functionality for McpManager using the simple implementation
 impl McpManager {
+    /// Create an MCP manager with no server and no clients
+    pub fn new() -> Self {
+        Self::default()
+    }
+
     /// Create a new MCP manager with the given configuration
+    ///
+    /// Configured clients are connected by [`McpManager::connect_clients`].
     pub fn with_config(config: McpConfig) -> Self {
         let mut manager = Self::new();
 
@@ -134,16 +166,80 @@ impl McpManager {
             manager.start_server(simple_config);
         }
 
-        // Add configured clients
-        for (name, client_config) in config.clients {
-            let simple_config = SimpleMcpClientConfig {
-                server_command: client_config.command,
-                server_args: client_config.args,
-            };
-            manager.add_client(name, simple_config);
+        manager.pending_clients = config.clients.into_iter().collect();
+        manager.settings = config.settings;
+        manager
+    }
+
+    /// Report the connection health of the clients to a logger
+    pub fn with_logger(mut self, logger: Arc<Logger>) -> Self {
+        self.logger = Some(logger);
+        self
+    }
+
+    /// Start an MCP server
+    pub fn start_server(&mut self, config: SimpleMcpServerConfig) {
+        self.server = Some(SimpleMcpServer::new(config));
+    }
+
+    /// Connect every configured client, returning the servers that could not be reached
+    pub async fn connect_clients(&mut self) -> Vec<(String, anyhow::Error)> {
+        let mut failures = Vec::new();
+
+        for (name, config) in std::mem::take(&mut self.pending_clients) {
+            match McpClient::connect_with_settings(name.clone(), config, self.settings.clone())
+                .await
+            {
+                Ok(client) => {
+                    if let Some(logger) = &self.logger {
+                        client.set_logger(logger.clone());
+                    }
+                    self.clients.insert(name, client);
+                }
+                Err(e) => {
+                    self.failed_clients.insert(name.clone(), e.to_string());
+                    failures.push((name, e));
+                }
+            }
         }
 
-        manager
+        failures
+    }
+
+    /// Get a connected client by name
+    pub fn client(&self, name: &str) -> Option<&McpClient> {
+        self.clients.get(name)
+    }
+
+    /// Connection health of every configured server, by name
+    pub fn health(&self) -> BTreeMap<String, ConnectionHealth> {
+        let mut health: BTreeMap<_, _> = self
+            .clients
+            .iter()
+            .map(|(name, client)| (name.clone(), client.health()))
+            .collect();
+        for (name, error) in &self.failed_clients {
+            health.insert(name.clone(), ConnectionHealth::failed(error.clone()));
+        }
+        health
+    }
+
+    /// Every tool currently advertised by the connected servers
+    pub fn tools(&self) -> Vec<VegaMcpTool> {
+        self.clients
+            .values()
+            .filter(|client| client.is_connected())
+            .flat_map(McpClient::get_tools)
+            .collect()
+    }
+
+    /// Disconnect from every server
+    pub async fn disconnect_all(&self) {
+        for (name, client) in &self.clients {
+            if let Err(e) = client.disconnect().await {
+                tracing::warn!("Failed to disconnect from MCP server '{}': {}", name, e);
+            }
+        }
     }
 }
 
@@ -158,4 +254,31 @@ mod tests {
         assert!(manager.clients.is_empty());
         assert!(manager.server.is_none());
     }
+
+    #[tokio::test]
+    async fn test_health_of_unreachable_server() {
+        let mut config = McpConfig::default();
+        config.clients.insert(
+            "missing".to_string(),
+            McpClientConfig {
+                command: "/nonexistent/mcp-server".to_string(),
+                ..Default::default()
+            },
+        );
+        let mut manager = McpManager::with_config(config);
+
+        let failures = manager.connect_clients().await;
+        assert_eq!(failures.len(), 1);
+
+        let health = manager.health();
+        assert_eq!(health["missing"].state, ConnectionState::Failed);
+        assert!(
+            health["missing"]
+                .last_error
+                .as_deref()
+                .unwrap()
+                .contains("Failed to start")
+        );
+        assert!(manager.tools().is_empty());
+    }
 }
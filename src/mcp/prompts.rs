@@ -0,0 +1,247 @@
+//! # Vega Prompts
+//!
+//! Prompt templates served by [`McpServer`](super::McpServer), so MCP clients
+//! can reuse Vega's system prompt and build on its stored sessions:
+//!
+//! - `vega_system` - the chat agent's system prompt with the agent instructions
+//! - `summarize_session` - asks for a summary of a stored session
+//! - `resume_session` - embeds a stored session so a conversation can continue it
+
+use serde_json::{Value, json};
+
+use super::protocol::McpError;
+use super::resources::{VegaResource, VegaResources};
+use crate::agent_instructions::format_instructions_for_prompt;
+use crate::agents::chat::SYSTEM_PROMPT;
+use crate::agents::render_prompt_template;
+use crate::agents::summary::{self, SUMMARY_PREAMBLE, SessionTranscript};
+use crate::context::ContextEntry;
+
+const SESSION_ID: &str = "session_id";
+
+/// The `prompts/list` result
+pub fn list_prompts(resources: &VegaResources) -> Value {
+    let mut prompts = vec![json!({
+        "name": "vega_system",
+        "title": "Vega system prompt",
+        "description": "Vega's system prompt, including the project's agent instructions"
+    })];
+
+    if resources.context().is_some() {
+        let session_argument = json!([{
+            "name": SESSION_ID,
+            "description": "Id of a session in Vega's context store",
+            "required": true
+        }]);
+        prompts.push(json!({
+            "name": "summarize_session",
+            "title": "Summarize a session",
+            "description": "Ask for a summary of a conversation Vega had",
+            "arguments": session_argument
+        }));
+        prompts.push(json!({
+            "name": "resume_session",
+            "title": "Resume a session",
+            "description": "Continue a conversation Vega had, with its transcript attached",
+            "arguments": session_argument
+        }));
+    }
+
+    json!({ "prompts": prompts })
+}
+
+/// The `prompts/get` result for a prompt and its arguments
+pub async fn get_prompt(
+    resources: &VegaResources,
+    name: &str,
+    arguments: &Value,
+) -> Result<Value, McpError> {
+    match name {
+        "vega_system" => {
+            let mut text = render_prompt_template(SYSTEM_PROMPT)
+                .map_err(|e| McpError::new(McpError::INTERNAL_ERROR, e.to_string()))?;
+            if let Some(instructions) = resources.instructions() {
+                text.push_str(&format_instructions_for_prompt(&instructions));
+            }
+            Ok(prompt("Vega's system prompt", vec![text_message(text)]))
+        }
+        "summarize_session" => {
+            let session_id = session_argument(resources, arguments)?;
+            let entries = session_entries(resources, session_id).await?;
+            let transcript = SessionTranscript::from_entries(&entries);
+
+            let text = format!(
+                "{}\n\n{}",
+                SUMMARY_PREAMBLE,
+                summary::summary_prompt(transcript.summary.as_ref(), &transcript.turns)
+            );
+            Ok(prompt(
+                &format!("Summarize session {}", session_id),
+                vec![text_message(text)],
+            ))
+        }
+        "resume_session" => {
+            let session_id = session_argument(resources, arguments)?;
+            let resource = VegaResource::Session(session_id.to_string());
+            let uri = resource.uri();
+            let contents = resources.read(&uri).await?;
+
+            Ok(prompt(
+                &format!("Resume session {}", session_id),
+                vec![
+                    json!({
+                        "role": "user",
+                        "content": { "type": "resource", "resource": contents["contents"][0] }
+                    }),
+                    text_message(
+                        "This is an earlier conversation with Vega. Continue it where it left off."
+                            .to_string(),
+                    ),
+                ],
+            ))
+        }
+        _ => Err(McpError::new(
+            McpError::INVALID_PARAMS,
+            format!("Unknown prompt: {}", name),
+        )),
+    }
+}
+
+fn prompt(description: &str, messages: Vec<Value>) -> Value {
+    json!({ "description": description, "messages": messages })
+}
+
+fn text_message(text: String) -> Value {
+    json!({ "role": "user", "content": { "type": "text", "text": text } })
+}
+
+/// The `session_id` argument of the session prompts
+fn session_argument<'a>(
+    resources: &VegaResources,
+    arguments: &'a Value,
+) -> Result<&'a str, McpError> {
+    if resources.context().is_none() {
+        return Err(McpError::new(
+            McpError::INVALID_PARAMS,
+            "No context store is available",
+        ));
+    }
+    arguments
+        .get(SESSION_ID)
+        .and_then(Value::as_str)
+        .filter(|id| !id.is_empty())
+        .ok_or_else(|| {
+            McpError::new(
+                McpError::INVALID_PARAMS,
+                format!("Missing '{}' argument", SESSION_ID),
+            )
+        })
+}
+
+async fn session_entries(
+    resources: &VegaResources,
+    session_id: &str,
+) -> Result<Vec<ContextEntry>, McpError> {
+    let entries = match resources.context() {
+        Some(context) => context
+            .get_session_history(session_id, None)
+            .await
+            .map_err(|e| McpError::new(McpError::INTERNAL_ERROR, e.to_string()))?,
+        None => Vec::new(),
+    };
+    if entries.is_empty() {
+        return Err(McpError::new(
+            McpError::INVALID_PARAMS,
+            format!("Unknown session: {}", session_id),
+        ));
+    }
+    Ok(entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::context::ContextStore;
+    use std::sync::Arc;
+    use tempfile::TempDir;
+
+    #[tokio::test]
+    async fn test_prompts() {
+        let resources = VegaResources::new();
+        let names: Vec<Value> = list_prompts(&resources)["prompts"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|prompt| prompt["name"].clone())
+            .collect();
+        assert_eq!(names, vec![json!("vega_system")]);
+
+        let system = get_prompt(&resources, "vega_system", &json!({}))
+            .await
+            .unwrap();
+        let text = system["messages"][0]["content"]["text"].as_str().unwrap();
+        assert!(text.starts_with("The assistant is Vega."));
+        assert!(!text.contains("{{currentDateTime}}"));
+
+        let error = get_prompt(&resources, "summarize_session", &json!({"session_id": "a"}))
+            .await
+            .unwrap_err();
+        assert_eq!(error.code, McpError::INVALID_PARAMS);
+        let error = get_prompt(&resources, "missing", &json!({}))
+            .await
+            .unwrap_err();
+        assert_eq!(error.code, McpError::INVALID_PARAMS);
+    }
+
+    #[tokio::test]
+    async fn test_session_prompts() {
+        let temp_dir = TempDir::new().unwrap();
+        let store = ContextStore::new(temp_dir.path().join("context.db"), 4)
+            .await
+            .unwrap();
+        for (role, content) in [("user", "Rename the crate"), ("assistant", "Renamed it")] {
+            let entry = ContextEntry::new(
+                "chat".to_string(),
+                "s1".to_string(),
+                content.to_string(),
+                role.to_string(),
+            );
+            store.store_context(entry, vec![0.0; 4]).await.unwrap();
+        }
+        let resources = VegaResources::new().with_context(Arc::new(store));
+        assert_eq!(
+            list_prompts(&resources)["prompts"]
+                .as_array()
+                .unwrap()
+                .len(),
+            3
+        );
+
+        let summary = get_prompt(
+            &resources,
+            "summarize_session",
+            &json!({"session_id": "s1"}),
+        )
+        .await
+        .unwrap();
+        let text = summary["messages"][0]["content"]["text"].as_str().unwrap();
+        assert!(text.starts_with(SUMMARY_PREAMBLE));
+        assert!(text.contains("user: Rename the crate"));
+
+        let resume = get_prompt(&resources, "resume_session", &json!({"session_id": "s1"}))
+            .await
+            .unwrap();
+        let resource = &resume["messages"][0]["content"]["resource"];
+        assert_eq!(resource["uri"], "vega://sessions/s1");
+        assert!(resource["text"].as_str().unwrap().contains("Renamed it"));
+
+        let error = get_prompt(&resources, "resume_session", &json!({"session_id": "s2"}))
+            .await
+            .unwrap_err();
+        assert_eq!(error.code, McpError::RESOURCE_NOT_FOUND);
+        let error = get_prompt(&resources, "summarize_session", &json!({}))
+            .await
+            .unwrap_err();
+        assert_eq!(error.code, McpError::INVALID_PARAMS);
+    }
+}
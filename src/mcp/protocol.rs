@@ -0,0 +1,445 @@
+//! # MCP Protocol Messages
+//!
+//! JSON-RPC 2.0 message types exchanged between MCP clients and servers, and
+//! the MCP payloads Vega reads out of them. Every message on the wire carries
+//! `"jsonrpc": "2.0"`; requests have an `id` and a `method`, notifications only
+//! a `method`, and responses an `id` with either a `result` or an `error`.
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::tools::ToolProgress;
+
+/// JSON-RPC version carried by every message
+pub const JSONRPC_VERSION: &str = "2.0";
+
+/// MCP protocol revision spoken by Vega
+pub const PROTOCOL_VERSION: &str = "2025-06-18";
+
+/// Earlier protocol revisions Vega's server still accepts, newest first
+pub const SUPPORTED_PROTOCOL_VERSIONS: &[&str] = &[PROTOCOL_VERSION, "2025-03-26", "2024-11-05"];
+
+/// HTTP header carrying the session assigned by a Streamable HTTP server
+pub const SESSION_HEADER: &str = "mcp-session-id";
+
+/// HTTP header carrying the negotiated protocol version
+pub const PROTOCOL_VERSION_HEADER: &str = "mcp-protocol-version";
+
+/// A request expecting a response with the same `id`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Request {
+    /// Request identifier, a number or a string
+    pub id: Value,
+    /// Method to invoke
+    pub method: String,
+    /// Method parameters
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub params: Option<Value>,
+}
+
+/// A one-way message that is never answered
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Notification {
+    /// Notification method
+    pub method: String,
+    /// Notification parameters
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub params: Option<Value>,
+}
+
+/// The answer to a request, holding either a result or an error
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Response {
+    /// Identifier of the request being answered
+    pub id: Value,
+    /// Result of a successful request
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub result: Option<Value>,
+    /// Error of a failed request
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<McpError>,
+}
+
+impl Response {
+    /// Create a successful response
+    pub fn success(id: Value, result: Value) -> Self {
+        Self {
+            id,
+            result: Some(result),
+            error: None,
+        }
+    }
+
+    /// Create an error response
+    pub fn failure(id: Value, error: McpError) -> Self {
+        Self {
+            id,
+            result: None,
+            error: Some(error),
+        }
+    }
+
+    /// Create the response for the outcome of handling a request
+    pub fn from_result(id: Value, result: Result<Value, McpError>) -> Self {
+        match result {
+            Ok(result) => Self::success(id, result),
+            Err(error) => Self::failure(id, error),
+        }
+    }
+
+    /// Turn the response into its result, or the error the peer reported
+    pub fn into_result(self) -> Result<Value, McpError> {
+        match (self.result, self.error) {
+            (_, Some(error)) => Err(error),
+            (Some(result), None) => Ok(result),
+            (None, None) => Ok(Value::Null),
+        }
+    }
+}
+
+/// Any JSON-RPC message
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(try_from = "RawMessage", into = "RawMessage")]
+pub enum McpMessage {
+    Request(Request),
+    Response(Response),
+    Notification(Notification),
+}
+
+impl McpMessage {
+    /// Create a notification message
+    pub fn notification(method: &str, params: Option<Value>) -> Self {
+        Self::Notification(Notification {
+            method: method.to_string(),
+            params,
+        })
+    }
+
+    /// Create a `notifications/progress` message for the request that sent `token`
+    pub fn progress(token: &Value, progress: &ToolProgress) -> Self {
+        let mut params = serde_json::json!({
+            "progressToken": token,
+            "progress": progress.progress
+        });
+        if let Some(total) = progress.total {
+            params["total"] = total.into();
+        }
+        if let Some(message) = &progress.message {
+            params["message"] = message.as_str().into();
+        }
+        Self::notification("notifications/progress", Some(params))
+    }
+
+    /// Create a `notifications/cancelled` message for a request
+    pub fn cancelled(request_id: &Value, reason: &str) -> Self {
+        Self::notification(
+            "notifications/cancelled",
+            Some(serde_json::json!({ "requestId": request_id, "reason": reason })),
+        )
+    }
+
+    /// Create a `notifications/message` log message
+    pub fn log(level: LoggingLevel, logger: &str, data: Value) -> Self {
+        Self::notification(
+            "notifications/message",
+            Some(serde_json::json!({ "level": level, "logger": logger, "data": data })),
+        )
+    }
+}
+
+/// The progress token and progress carried by `notifications/progress` parameters
+pub fn parse_progress(params: &Value) -> Option<(Value, ToolProgress)> {
+    let token = params.get("progressToken")?.clone();
+    let progress = ToolProgress {
+        progress: params.get("progress")?.as_f64()?,
+        total: params.get("total").and_then(Value::as_f64),
+        message: params
+            .get("message")
+            .and_then(Value::as_str)
+            .map(String::from),
+    };
+    Some((token, progress))
+}
+
+/// Severity of a `notifications/message` log message, from least to most severe
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LoggingLevel {
+    Debug,
+    Info,
+    Notice,
+    Warning,
+    Error,
+    Critical,
+    Alert,
+    Emergency,
+}
+
+/// Flat wire representation shared by all message kinds
+#[derive(Serialize, Deserialize)]
+struct RawMessage {
+    jsonrpc: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    id: Option<Value>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    method: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    params: Option<Value>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    result: Option<Value>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    error: Option<McpError>,
+}
+
+impl TryFrom<RawMessage> for McpMessage {
+    type Error = String;
+
+    fn try_from(raw: RawMessage) -> Result<Self, Self::Error> {
+        if raw.jsonrpc != JSONRPC_VERSION {
+            return Err(format!("unsupported JSON-RPC version '{}'", raw.jsonrpc));
+        }
+
+        match (raw.method, raw.id) {
+            (Some(method), Some(id)) => Ok(Self::Request(Request {
+                id,
+                method,
+                params: raw.params,
+            })),
+            (Some(method), None) => Ok(Self::Notification(Notification {
+                method,
+                params: raw.params,
+            })),
+            (None, id) if raw.result.is_some() || raw.error.is_some() => {
+                Ok(Self::Response(Response {
+                    // Errors for unparseable requests carry a null id
+                    id: id.unwrap_or(Value::Null),
+                    result: raw.result,
+                    error: raw.error,
+                }))
+            }
+            (None, _) => Err("message has neither a method nor a result".to_string()),
+        }
+    }
+}
+
+impl From<McpMessage> for RawMessage {
+    fn from(message: McpMessage) -> Self {
+        let mut raw = RawMessage {
+            jsonrpc: JSONRPC_VERSION.to_string(),
+            id: None,
+            method: None,
+            params: None,
+            result: None,
+            error: None,
+        };
+
+        match message {
+            McpMessage::Request(request) => {
+                raw.id = Some(request.id);
+                raw.method = Some(request.method);
+                raw.params = request.params;
+            }
+            McpMessage::Notification(notification) => {
+                raw.method = Some(notification.method);
+                raw.params = notification.params;
+            }
+            McpMessage::Response(response) => {
+                raw.id = Some(response.id);
+                raw.error = response.error;
+                // A successful response always carries a result
+                if raw.error.is_none() {
+                    raw.result = Some(response.result.unwrap_or_else(|| serde_json::json!({})));
+                }
+            }
+        }
+        raw
+    }
+}
+
+/// Error object of a failed request
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, thiserror::Error)]
+#[error("{message} (code {code})")]
+pub struct McpError {
+    /// JSON-RPC error code
+    pub code: i64,
+    /// Short description of the error
+    pub message: String,
+    /// Additional error details
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub data: Option<Value>,
+}
+
+impl McpError {
+    /// Invalid JSON was received
+    pub const PARSE_ERROR: i64 = -32700;
+    /// The JSON sent is not a valid request
+    pub const INVALID_REQUEST: i64 = -32600;
+    /// The method does not exist or is not available
+    pub const METHOD_NOT_FOUND: i64 = -32601;
+    /// Invalid method parameters
+    pub const INVALID_PARAMS: i64 = -32602;
+    /// Internal error while handling the request
+    pub const INTERNAL_ERROR: i64 = -32603;
+    /// The requested resource does not exist
+    pub const RESOURCE_NOT_FOUND: i64 = -32002;
+
+    /// Create an error with the given code and message
+    pub fn new(code: i64, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            message: message.into(),
+            data: None,
+        }
+    }
+
+    /// Error for a method the receiver does not implement
+    pub fn method_not_found(method: &str) -> Self {
+        Self::new(
+            Self::METHOD_NOT_FOUND,
+            format!("Method not found: {}", method),
+        )
+    }
+
+    /// Error for a resource URI the server does not know
+    pub fn resource_not_found(uri: &str) -> Self {
+        Self {
+            data: Some(serde_json::json!({ "uri": uri })),
+            ..Self::new(
+                Self::RESOURCE_NOT_FOUND,
+                format!("Resource not found: {}", uri),
+            )
+        }
+    }
+}
+
+/// A tool advertised by an MCP server
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Tool {
+    /// Name the tool is called by
+    pub name: String,
+    /// Human-readable description for the model
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    /// JSON Schema of the tool's arguments
+    #[serde(rename = "inputSchema")]
+    pub input_schema: Value,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn parse(value: Value) -> McpMessage {
+        serde_json::from_value(value).unwrap()
+    }
+
+    #[test]
+    fn test_message_kinds() {
+        let request = parse(json!({"jsonrpc": "2.0", "id": 1, "method": "tools/list"}));
+        assert!(matches!(request, McpMessage::Request(ref r) if r.method == "tools/list"));
+
+        let notification = parse(json!({
+            "jsonrpc": "2.0",
+            "method": "notifications/tools/list_changed"
+        }));
+        assert!(matches!(notification, McpMessage::Notification(_)));
+
+        let response = parse(json!({"jsonrpc": "2.0", "id": "a", "result": {"tools": []}}));
+        match response {
+            McpMessage::Response(response) => {
+                assert_eq!(response.id, json!("a"));
+                assert_eq!(response.into_result().unwrap(), json!({"tools": []}));
+            }
+            other => panic!("expected response, got {:?}", other),
+        }
+
+        let error = parse(json!({
+            "jsonrpc": "2.0",
+            "id": null,
+            "error": {"code": -32700, "message": "Parse error"}
+        }));
+        match error {
+            McpMessage::Response(response) => {
+                assert_eq!(response.id, Value::Null);
+                assert_eq!(
+                    response.into_result().unwrap_err().code,
+                    McpError::PARSE_ERROR
+                );
+            }
+            other => panic!("expected response, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_invalid_messages_are_rejected() {
+        assert!(
+            serde_json::from_value::<McpMessage>(json!({"jsonrpc": "1.0", "id": 1, "method": "x"}))
+                .is_err()
+        );
+        assert!(serde_json::from_value::<McpMessage>(json!({"jsonrpc": "2.0", "id": 1})).is_err());
+    }
+
+    #[test]
+    fn test_serialization_round_trip() {
+        let messages = vec![
+            McpMessage::Request(Request {
+                id: json!(7),
+                method: "tools/call".to_string(),
+                params: Some(json!({"name": "echo"})),
+            }),
+            McpMessage::notification("notifications/initialized", None),
+            McpMessage::Response(Response::success(json!(7), json!({}))),
+            McpMessage::Response(Response::failure(
+                json!(8),
+                McpError::method_not_found("foo"),
+            )),
+        ];
+
+        for message in messages {
+            let value = serde_json::to_value(&message).unwrap();
+            assert_eq!(value["jsonrpc"], "2.0");
+            assert_eq!(parse(value), message);
+        }
+    }
+
+    #[test]
+    fn test_progress_and_logging_messages() {
+        let progress = ToolProgress {
+            progress: 2.0,
+            total: Some(4.0),
+            message: Some("halfway".to_string()),
+        };
+        let McpMessage::Notification(notification) = McpMessage::progress(&json!("t1"), &progress)
+        else {
+            panic!("expected a notification");
+        };
+        assert_eq!(notification.method, "notifications/progress");
+        let (token, parsed) = parse_progress(&notification.params.unwrap()).unwrap();
+        assert_eq!(token, json!("t1"));
+        assert_eq!(parsed, progress);
+        assert!(parse_progress(&json!({"progress": 1})).is_none());
+
+        let level: LoggingLevel = serde_json::from_value(json!("warning")).unwrap();
+        assert!(level > LoggingLevel::Info && level < LoggingLevel::Error);
+        let message =
+            serde_json::to_value(McpMessage::log(level, "vega", json!("careful"))).unwrap();
+        assert_eq!(message["params"]["level"], "warning");
+    }
+
+    #[test]
+    fn test_tool_definition() {
+        let tool: Tool = serde_json::from_value(json!({
+            "name": "echo",
+            "inputSchema": {"type": "object"},
+            "annotations": {"readOnlyHint": true}
+        }))
+        .unwrap();
+        assert_eq!(tool.name, "echo");
+        assert!(tool.description.is_none());
+        assert_eq!(
+            serde_json::to_value(&tool).unwrap()["inputSchema"]["type"],
+            "object"
+        );
+    }
+}
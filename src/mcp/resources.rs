@@ -0,0 +1,428 @@
+//! # Vega Resources
+//!
+//! Vega's memory as MCP resources, served by [`McpServer`](super::McpServer):
+//!
+//! - `vega://sessions/{session_id}` - a session's conversation as Markdown
+//! - `vega://sessions/{session_id}/logs` - the log messages recorded for a session
+//! - `vega://instructions` - the VEGA.md or AGENTS.md file discovered at startup
+//!
+//! Sessions and logs are read from the context store on every request, so
+//! sessions written by other Vega processes sharing the database are included.
+
+use anyhow::Result;
+use serde_json::{Value, json};
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::sync::Arc;
+
+use super::protocol::McpError;
+use crate::agent_instructions::AgentInstructions;
+use crate::context::ContextStore;
+use crate::logging::LogEntry;
+
+/// URI prefix of session resources
+pub const SESSION_URI_PREFIX: &str = "vega://sessions/";
+
+/// URI suffix turning a session resource into its logs
+pub const LOGS_URI_SUFFIX: &str = "/logs";
+
+/// URI of the discovered agent instructions
+pub const INSTRUCTIONS_URI: &str = "vega://instructions";
+
+const MARKDOWN: &str = "text/markdown";
+const PLAIN_TEXT: &str = "text/plain";
+
+/// Number of resources returned by one `resources/list` request
+const PAGE_SIZE: usize = 100;
+
+/// A resource Vega serves, identified by its URI
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum VegaResource {
+    /// The conversation of a session
+    Session(String),
+    /// The log messages of a session
+    SessionLogs(String),
+    /// The discovered VEGA.md or AGENTS.md
+    Instructions,
+}
+
+impl VegaResource {
+    /// Parse a resource URI, returning `None` for URIs Vega does not serve
+    pub fn parse(uri: &str) -> Option<Self> {
+        if uri == INSTRUCTIONS_URI {
+            return Some(Self::Instructions);
+        }
+
+        let rest = uri.strip_prefix(SESSION_URI_PREFIX)?;
+        let (id, logs) = match rest.strip_suffix(LOGS_URI_SUFFIX) {
+            Some(id) => (id, true),
+            None => (rest, false),
+        };
+        if id.is_empty() || id.contains('/') {
+            return None;
+        }
+
+        let id = urlencoding::decode(id).ok()?.into_owned();
+        Some(if logs {
+            Self::SessionLogs(id)
+        } else {
+            Self::Session(id)
+        })
+    }
+
+    /// The resource's URI, with the session id percent-encoded
+    pub fn uri(&self) -> String {
+        match self {
+            Self::Session(id) => format!("{}{}", SESSION_URI_PREFIX, urlencoding::encode(id)),
+            Self::SessionLogs(id) => format!(
+                "{}{}{}",
+                SESSION_URI_PREFIX,
+                urlencoding::encode(id),
+                LOGS_URI_SUFFIX
+            ),
+            Self::Instructions => INSTRUCTIONS_URI.to_string(),
+        }
+    }
+
+    fn mime_type(&self) -> &'static str {
+        match self {
+            Self::SessionLogs(_) => PLAIN_TEXT,
+            Self::Session(_) | Self::Instructions => MARKDOWN,
+        }
+    }
+}
+
+/// Vega's sessions, session logs and agent instructions, read on demand
+#[derive(Clone, Default)]
+pub struct VegaResources {
+    context: Option<Arc<ContextStore>>,
+    instructions: Option<AgentInstructions>,
+}
+
+impl std::fmt::Debug for VegaResources {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("VegaResources")
+            .field("context", &self.context.is_some())
+            .field(
+                "instructions",
+                &self.instructions.as_ref().map(|i| &i.source_path),
+            )
+            .finish()
+    }
+}
+
+impl VegaResources {
+    /// Create resources with no context store and no instructions
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Serve the sessions and logs of a context store
+    pub fn with_context(mut self, context: Arc<ContextStore>) -> Self {
+        self.context = Some(context);
+        self
+    }
+
+    /// Serve the given agent instructions
+    pub fn with_instructions(mut self, instructions: AgentInstructions) -> Self {
+        self.instructions = Some(instructions);
+        self
+    }
+
+    /// The context store, if one is served
+    pub fn context(&self) -> Option<&ContextStore> {
+        self.context.as_deref()
+    }
+
+    /// The agent instructions, re-read so edits to the file are picked up
+    pub fn instructions(&self) -> Option<AgentInstructions> {
+        let mut instructions = self.instructions.clone()?;
+        match std::fs::read_to_string(&instructions.source_path) {
+            Ok(content) => instructions.content = content,
+            Err(e) => tracing::debug!(
+                "Serving cached instructions, failed to re-read {}: {}",
+                instructions.source_path.display(),
+                e
+            ),
+        }
+        Some(instructions)
+    }
+
+    /// The `resources/list` result for the page starting at `cursor`
+    pub async fn list(&self, cursor: Option<&str>) -> Result<Value, McpError> {
+        let mut resources = Vec::new();
+
+        if let Some(instructions) = &self.instructions {
+            resources.push(json!({
+                "uri": INSTRUCTIONS_URI,
+                "name": instructions.file_type.filename(),
+                "title": "Agent instructions",
+                "description": format!(
+                    "Project instructions from {}",
+                    instructions.source_path.display()
+                ),
+                "mimeType": MARKDOWN
+            }));
+        }
+
+        if let Some(context) = &self.context {
+            let sessions = context.list_sessions().await.map_err(internal_error)?;
+            for session in sessions {
+                let id = session.session_id;
+                let last_modified = session.last_entry.to_rfc3339();
+                resources.push(json!({
+                    "uri": VegaResource::Session(id.clone()).uri(),
+                    "name": id,
+                    "title": format!("Session {}", id),
+                    "description": format!(
+                        "{} entries from {} to {}",
+                        session.entry_count,
+                        session.first_entry.format("%Y-%m-%d %H:%M:%S UTC"),
+                        session.last_entry.format("%Y-%m-%d %H:%M:%S UTC")
+                    ),
+                    "mimeType": MARKDOWN,
+                    "annotations": { "lastModified": last_modified }
+                }));
+                resources.push(json!({
+                    "uri": VegaResource::SessionLogs(id.clone()).uri(),
+                    "name": format!("{}{}", id, LOGS_URI_SUFFIX),
+                    "title": format!("Logs of session {}", id),
+                    "mimeType": PLAIN_TEXT,
+                    "annotations": { "lastModified": last_modified }
+                }));
+            }
+        }
+
+        let start = match cursor {
+            Some(cursor) => cursor
+                .parse::<usize>()
+                .map_err(|_| McpError::new(McpError::INVALID_PARAMS, "Invalid cursor"))?,
+            None => 0,
+        };
+        let end = (start + PAGE_SIZE).min(resources.len());
+
+        let mut result = json!({ "resources": resources.get(start..end).unwrap_or_default() });
+        if end < resources.len() {
+            result["nextCursor"] = json!(end.to_string());
+        }
+        Ok(result)
+    }
+
+    /// The `resources/templates/list` result
+    pub fn templates(&self) -> Value {
+        let mut templates = Vec::new();
+        if self.context.is_some() {
+            templates.push(json!({
+                "uriTemplate": format!("{}{{session_id}}", SESSION_URI_PREFIX),
+                "name": "session",
+                "title": "Session conversation",
+                "mimeType": MARKDOWN
+            }));
+            templates.push(json!({
+                "uriTemplate": format!("{}{{session_id}}{}", SESSION_URI_PREFIX, LOGS_URI_SUFFIX),
+                "name": "session_logs",
+                "title": "Session logs",
+                "mimeType": PLAIN_TEXT
+            }));
+        }
+        json!({ "resourceTemplates": templates })
+    }
+
+    /// The `resources/read` result for a URI
+    pub async fn read(&self, uri: &str) -> Result<Value, McpError> {
+        let resource = VegaResource::parse(uri).ok_or_else(|| McpError::resource_not_found(uri))?;
+        let text = self
+            .read_text(&resource)
+            .await
+            .map_err(internal_error)?
+            .ok_or_else(|| McpError::resource_not_found(uri))?;
+
+        Ok(json!({
+            "contents": [{
+                "uri": uri,
+                "mimeType": resource.mime_type(),
+                "text": text
+            }]
+        }))
+    }
+
+    /// Fingerprint of a resource's content, `None` if it does not exist.
+    ///
+    /// Subscriptions compare fingerprints to find updated resources.
+    pub async fn fingerprint(&self, uri: &str) -> Option<u64> {
+        let resource = VegaResource::parse(uri)?;
+        let text = self.read_text(&resource).await.ok()??;
+
+        let mut hasher = DefaultHasher::new();
+        text.hash(&mut hasher);
+        Some(hasher.finish())
+    }
+
+    /// Render a resource, returning `None` if it does not exist
+    pub async fn read_text(&self, resource: &VegaResource) -> Result<Option<String>> {
+        match resource {
+            VegaResource::Instructions => {
+                Ok(self.instructions().map(|instructions| instructions.content))
+            }
+            VegaResource::Session(id) => {
+                let Some(context) = &self.context else {
+                    return Ok(None);
+                };
+                let entries = context.get_session_history(id, None).await?;
+                if entries.is_empty() {
+                    return Ok(None);
+                }
+
+                let mut text = format!("# Session {}\n", id);
+                for entry in entries.iter().filter(|entry| entry.role != "log") {
+                    text.push_str(&format!(
+                        "\n## {} - {}\n\n{}\n",
+                        entry.role,
+                        entry.timestamp.format("%Y-%m-%d %H:%M:%S UTC"),
+                        entry.content
+                    ));
+                }
+                Ok(Some(text))
+            }
+            VegaResource::SessionLogs(id) => {
+                let Some(context) = &self.context else {
+                    return Ok(None);
+                };
+                let entries = context.get_session_history(id, None).await?;
+                if entries.is_empty() {
+                    return Ok(None);
+                }
+
+                Ok(Some(
+                    entries
+                        .iter()
+                        .filter_map(LogEntry::from_context_entry)
+                        .map(|log| log.format_console() + "\n")
+                        .collect(),
+                ))
+            }
+        }
+    }
+}
+
+fn internal_error(error: anyhow::Error) -> McpError {
+    McpError::new(McpError::INTERNAL_ERROR, error.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::context::ContextEntry;
+    use std::collections::HashMap;
+    use tempfile::TempDir;
+
+    async fn store_with_session(temp_dir: &TempDir) -> Arc<ContextStore> {
+        let store = ContextStore::new(temp_dir.path().join("context.db"), 4)
+            .await
+            .unwrap();
+
+        for (role, content) in [("user", "Where are the logs?"), ("assistant", "In ./logs")] {
+            let entry = ContextEntry::new(
+                "chat".to_string(),
+                "session/1".to_string(),
+                content.to_string(),
+                role.to_string(),
+            );
+            store.store_context(entry, vec![0.0; 4]).await.unwrap();
+        }
+
+        let metadata = HashMap::from([
+            ("log_level".to_string(), "WARN".to_string()),
+            ("log_id".to_string(), "log-1".to_string()),
+            ("timestamp".to_string(), "2025-01-02T03:04:05Z".to_string()),
+        ]);
+        let log = ContextEntry::new(
+            "vega_logger".to_string(),
+            "session/1".to_string(),
+            "Level: WARN | Message: Disk almost full | Session: session/1".to_string(),
+            "log".to_string(),
+        )
+        .with_metadata(metadata);
+        store.store_context(log, vec![0.0; 4]).await.unwrap();
+
+        Arc::new(store)
+    }
+
+    #[test]
+    fn test_resource_uris() {
+        let session = VegaResource::Session("session/1".to_string());
+        assert_eq!(session.uri(), "vega://sessions/session%2F1");
+        assert_eq!(VegaResource::parse(&session.uri()), Some(session));
+
+        let logs = VegaResource::parse("vega://sessions/abc/logs");
+        assert_eq!(logs, Some(VegaResource::SessionLogs("abc".to_string())));
+        assert_eq!(
+            VegaResource::parse(INSTRUCTIONS_URI),
+            Some(VegaResource::Instructions)
+        );
+        assert_eq!(VegaResource::parse("vega://sessions/"), None);
+        assert_eq!(VegaResource::parse("vega://sessions/a/b"), None);
+        assert_eq!(VegaResource::parse("file:///etc/passwd"), None);
+    }
+
+    #[tokio::test]
+    async fn test_list_and_read_sessions() {
+        let temp_dir = TempDir::new().unwrap();
+        let resources = VegaResources::new().with_context(store_with_session(&temp_dir).await);
+
+        let list = resources.list(None).await.unwrap();
+        let uris: Vec<&str> = list["resources"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|resource| resource["uri"].as_str().unwrap())
+            .collect();
+        assert_eq!(
+            uris,
+            vec![
+                "vega://sessions/session%2F1",
+                "vega://sessions/session%2F1/logs"
+            ]
+        );
+        assert!(list.get("nextCursor").is_none());
+
+        let session = resources.read(uris[0]).await.unwrap();
+        let text = session["contents"][0]["text"].as_str().unwrap();
+        assert!(text.contains("## user - "));
+        assert!(text.contains("In ./logs"));
+        assert!(!text.contains("Disk almost full"));
+
+        let logs = resources.read(uris[1]).await.unwrap();
+        let text = logs["contents"][0]["text"].as_str().unwrap();
+        assert_eq!(
+            text,
+            "2025-01-02 03:04:05.000 UTC [WARN] Disk almost full\n"
+        );
+
+        let missing = resources.read("vega://sessions/none").await.unwrap_err();
+        assert_eq!(missing.code, McpError::RESOURCE_NOT_FOUND);
+        let missing = resources.read(INSTRUCTIONS_URI).await.unwrap_err();
+        assert_eq!(missing.code, McpError::RESOURCE_NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_fingerprint_changes_with_content() {
+        let temp_dir = TempDir::new().unwrap();
+        let store = store_with_session(&temp_dir).await;
+        let resources = VegaResources::new().with_context(store.clone());
+        let uri = VegaResource::Session("session/1".to_string()).uri();
+
+        let before = resources.fingerprint(&uri).await.unwrap();
+        assert_eq!(resources.fingerprint(&uri).await, Some(before));
+
+        let entry = ContextEntry::new(
+            "chat".to_string(),
+            "session/1".to_string(),
+            "Thanks".to_string(),
+            "user".to_string(),
+        );
+        store.store_context(entry, vec![0.0; 4]).await.unwrap();
+        assert_ne!(resources.fingerprint(&uri).await, Some(before));
+        assert_eq!(resources.fingerprint("vega://sessions/none").await, None);
+    }
+}
@@ -2,22 +2,68 @@
 //!
 //! This module provides MCP server functionality, allowing Vega to expose its tools
 //! as MCP tools for other AI systems to consume.
+//!
+//! The same request handlers are served over two transports:
+//!
+//! - **stdio** - newline-delimited JSON-RPC on stdin/stdout, for a single client
+//!   that starts Vega as a subprocess
+//! - **Streamable HTTP** - JSON-RPC POSTed to `/mcp`, so several clients on one
+//!   machine can share a long-running Vega. `initialize` assigns a session that
+//!   later requests carry in the `Mcp-Session-Id` header, and a DELETE ends it.
+//!   A GET opens the session's event stream for notifications.
+//!
+//! Besides tools, the server offers Vega's sessions, logs and agent instructions
+//! as [resources](super::resources) and its prompt templates as
+//! [prompts](super::prompts). Clients subscribed to a resource are notified
+//! when its content changes.
+//!
+//! Tool calls carrying a `progressToken` receive `notifications/progress` while
+//! long-running tools work, and `notifications/cancelled` stops a request in
+//! flight. Tool failures are logged to the client with `notifications/message`
+//! at or above the level chosen with `logging/setLevel`. Over HTTP, a tool call
+//! whose client accepts `text/event-stream` is answered with a stream carrying
+//! these notifications before the response.
 
-use anyhow::{Result, anyhow};
-use rust_mcp_schema::{ErrorCode, McpError, McpMessage, Request, Response, Tool as McpToolDef};
-use serde_json::Value;
+use anyhow::{Context, Result, bail};
+use axum::Router;
+use axum::body::{Body, Bytes};
+use axum::extract::{DefaultBodyLimit, Request as HttpRequest, State};
+use axum::http::{HeaderMap, HeaderName, HeaderValue, Method, StatusCode, header};
+use axum::middleware::{self, Next};
+use axum::response::{IntoResponse, Json, Response as HttpResponse};
+use axum::routing::post;
+use futures::future::{AbortHandle, Abortable};
+use serde_json::{Value, json};
 use std::collections::HashMap;
-use std::sync::Arc;
+use std::convert::Infallible;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, Weak};
+use std::time::{Duration, Instant};
 use tokio::io::{self, AsyncBufReadExt, AsyncWriteExt, BufReader};
-use tokio::sync::RwLock;
-use tokio::task::JoinHandle;
-
-use super::bridge::{McpToolFactory, VegaToMcpBridge};
-use super::config::McpServerConfig;
-use crate::tools::{
-    BashTool, CodeSearchTool, EditFileTool, ListFilesTool, ReadFileTool, ReadLogsTool, RigTool,
-    WebSearchTool,
+use tokio::net::TcpListener;
+use tokio::sync::mpsc;
+use tower_http::cors::{AllowOrigin, CorsLayer};
+
+use super::bridge::{VegaToMcpBridge, VegaToolConfig};
+use super::config::{McpServerConfig, RateLimit, TransportType};
+use super::prompts;
+use super::protocol::{
+    LoggingLevel, McpError, McpMessage, Notification, PROTOCOL_VERSION, PROTOCOL_VERSION_HEADER,
+    Request, Response, SESSION_HEADER, SUPPORTED_PROTOCOL_VERSIONS,
 };
+use super::resources::{VegaResource, VegaResources};
+use crate::agent_instructions::AgentInstructions;
+use crate::context::ContextStore;
+use crate::tools::{PermissionPolicy, Sandbox, Workspace, forward_progress};
+
+/// How often subscribed resources are checked for changes
+const SUBSCRIPTION_POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Name of the logger in `notifications/message` sent to clients
+const LOGGER_NAME: &str = "vega";
+
+/// Log level used until a client sets its own with `logging/setLevel`
+const DEFAULT_LOG_LEVEL: LoggingLevel = LoggingLevel::Warning;
 
 /// MCP server that exposes Vega's tools
 #[derive(Debug)]
@@ -25,13 +71,11 @@ pub struct McpServer {
     /// Server configuration
     config: McpServerConfig,
     /// Available tools bridge
-    bridge: Arc<RwLock<VegaToMcpBridge>>,
+    bridge: VegaToMcpBridge,
+    /// Sessions, logs and instructions offered as resources
+    resources: VegaResources,
     /// Server capabilities
     capabilities: ServerCapabilities,
-    /// Running state
-    running: Arc<RwLock<bool>>,
-    /// Server task handle
-    task_handle: Option<JoinHandle<Result<()>>>,
 }
 
 /// Server capabilities structure
@@ -39,9 +83,9 @@ pub struct McpServer {
 pub struct ServerCapabilities {
     /// Tools capabilities
     pub tools: Option<ToolsCapability>,
-    /// Resources capabilities (not implemented yet)
+    /// Resources capabilities
     pub resources: Option<ResourcesCapability>,
-    /// Prompts capabilities (not implemented yet)
+    /// Prompts capabilities
     pub prompts: Option<PromptsCapability>,
     /// Logging capabilities
     pub logging: Option<LoggingCapability>,
@@ -54,7 +98,7 @@ pub struct ToolsCapability {
     pub list_changed: bool,
 }
 
-/// Resources capability (placeholder)
+/// Resources capability
 #[derive(Debug, Clone)]
 pub struct ResourcesCapability {
     /// Supports subscribe to resource changes
@@ -63,7 +107,7 @@ pub struct ResourcesCapability {
     pub list_changed: bool,
 }
 
-/// Prompts capability (placeholder)
+/// Prompts capability
 #[derive(Debug, Clone)]
 pub struct PromptsCapability {
     /// Supports list_changed notifications
@@ -83,12 +127,17 @@ impl Default for ServerCapabilities {
             tools: Some(ToolsCapability {
                 list_changed: false, // We don't currently support dynamic tool changes
             }),
-            resources: None, // Not implemented yet
-            prompts: None,   // Not implemented yet
+            resources: Some(ResourcesCapability {
+                subscribe: true,
+                list_changed: false,
+            }),
+            prompts: Some(PromptsCapability {
+                list_changed: false,
+            }),
             logging: Some(LoggingCapability {
                 levels: vec![
                     "error".to_string(),
-                    "warn".to_string(),
+                    "warning".to_string(),
                     "info".to_string(),
                     "debug".to_string(),
                 ],
@@ -97,344 +146,1029 @@ impl Default for ServerCapabilities {
     }
 }
 
+impl ServerCapabilities {
+    /// The capabilities as announced in the `initialize` result
+    fn to_json(&self) -> Value {
+        let mut capabilities = json!({});
+        if let Some(tools) = &self.tools {
+            capabilities["tools"] = json!({ "listChanged": tools.list_changed });
+        }
+        if let Some(resources) = &self.resources {
+            capabilities["resources"] = json!({
+                "subscribe": resources.subscribe,
+                "listChanged": resources.list_changed
+            });
+        }
+        if let Some(prompts) = &self.prompts {
+            capabilities["prompts"] = json!({ "listChanged": prompts.list_changed });
+        }
+        if self.logging.is_some() {
+            capabilities["logging"] = json!({});
+        }
+        capabilities
+    }
+}
+
 impl McpServer {
     /// Create a new MCP server
     pub async fn new(config: McpServerConfig) -> Result<Self> {
-        let mut bridge = VegaToMcpBridge::new();
+        let mut bridge = VegaToMcpBridge::new().with_permissions(
+            Arc::new(PermissionPolicy::default()),
+            config.settings.allow_ask,
+        );
 
         // Add Vega tools to the bridge based on configuration
-        Self::setup_tools(&mut bridge, &config.exposed_tools).await?;
+        Self::setup_tools(&mut bridge, &config.exposed_tools);
 
         Ok(Self {
             config,
-            bridge: Arc::new(RwLock::new(bridge)),
+            bridge,
+            resources: VegaResources::new(),
             capabilities: ServerCapabilities::default(),
-            running: Arc::new(RwLock::new(false)),
-            task_handle: None,
         })
     }
 
+    /// Offer the sessions and logs of a context store as resources
+    pub fn with_context(mut self, context: Arc<ContextStore>) -> Self {
+        self.resources = std::mem::take(&mut self.resources).with_context(context);
+        self
+    }
+
+    /// Offer the discovered agent instructions as a resource
+    pub fn with_instructions(mut self, instructions: AgentInstructions) -> Self {
+        self.resources = std::mem::take(&mut self.resources).with_instructions(instructions);
+        self
+    }
+
+    /// Run the commands of the exposed bash tool in a sandbox
+    pub fn with_sandbox(mut self, sandbox: Sandbox) -> Self {
+        self.bridge = std::mem::take(&mut self.bridge).with_sandbox(sandbox);
+        self
+    }
+
+    /// Confine the exposed file tools to a workspace
+    pub fn with_workspace(mut self, workspace: Workspace) -> Self {
+        self.bridge = std::mem::take(&mut self.bridge).with_workspace(workspace);
+        self
+    }
+
+    /// Check every tool call against a permission policy before it runs
+    pub fn with_permissions(mut self, permissions: Arc<PermissionPolicy>) -> Self {
+        self.bridge = std::mem::take(&mut self.bridge)
+            .with_permissions(permissions, self.config.settings.allow_ask);
+        self
+    }
+
     /// Setup tools in the bridge based on configuration
-    async fn setup_tools(bridge: &mut VegaToMcpBridge, exposed_tools: &[String]) -> Result<()> {
+    fn setup_tools(bridge: &mut VegaToMcpBridge, exposed_tools: &[String]) {
         for tool_name in exposed_tools {
-            match tool_name.as_str() {
-                "bash" => {
-                    let tool = Box::new(BashTool::new()) as Box<dyn RigTool>;
-                    bridge.add_tool("bash".to_string(), tool);
-                }
-                "read_file" => {
-                    let tool = Box::new(ReadFileTool::new()) as Box<dyn RigTool>;
-                    bridge.add_tool("read_file".to_string(), tool);
-                }
-                "edit_file" => {
-                    let tool = Box::new(EditFileTool::new()) as Box<dyn RigTool>;
-                    bridge.add_tool("edit_file".to_string(), tool);
-                }
-                "list_files" => {
-                    let tool = Box::new(ListFilesTool::new()) as Box<dyn RigTool>;
-                    bridge.add_tool("list_files".to_string(), tool);
-                }
-                "code_search" => {
-                    let tool = Box::new(CodeSearchTool::new()) as Box<dyn RigTool>;
-                    bridge.add_tool("code_search".to_string(), tool);
-                }
-                "web_search" => {
-                    let tool = Box::new(WebSearchTool::new()) as Box<dyn RigTool>;
-                    bridge.add_tool("web_search".to_string(), tool);
-                }
-                "read_logs" => {
-                    let tool = Box::new(ReadLogsTool::new()) as Box<dyn RigTool>;
-                    bridge.add_tool("read_logs".to_string(), tool);
-                }
+            let config = match tool_name.as_str() {
+                "bash" => VegaToolConfig::Bash,
+                "read_file" => VegaToolConfig::ReadFile,
+                "edit_file" => VegaToolConfig::EditFile,
+                "list_files" => VegaToolConfig::ListFiles,
+                "code_search" => VegaToolConfig::CodeSearch,
+                "web_search" => VegaToolConfig::WebSearch,
+                "read_logs" => VegaToolConfig::ReadLogs,
                 _ => {
                     tracing::warn!("Unknown tool '{}' in configuration", tool_name);
+                    continue;
                 }
-            }
+            };
+            bridge.add_tool(tool_name.clone(), config);
         }
 
-        tracing::info!("Configured {} tools for MCP server", exposed_tools.len());
-        Ok(())
+        tracing::info!(
+            "Configured {} tools for MCP server",
+            bridge.list_tools().len()
+        );
     }
 
-    /// Start the MCP server
-    pub async fn run(mut self) -> Result<()> {
-        *self.running.write().await = true;
-
-        let bridge = self.bridge.clone();
-        let capabilities = self.capabilities.clone();
-        let running = self.running.clone();
-
-        let handle =
-            tokio::spawn(async move { Self::serve_stdio(bridge, capabilities, running).await });
-
-        self.task_handle = Some(handle);
-
-        // Wait for the server task to complete
-        if let Some(handle) = self.task_handle {
-            handle.await??;
+    /// Start the MCP server on the configured transport
+    pub async fn run(self) -> Result<()> {
+        match self.config.transport.transport_type {
+            TransportType::Stdio => self.serve_stdio().await,
+            TransportType::Http => {
+                let listener = TcpListener::bind(self.config.listen)
+                    .await
+                    .with_context(|| format!("Failed to listen on {}", self.config.listen))?;
+                self.serve_http(listener).await
+            }
+            TransportType::Sse => {
+                bail!("The MCP server does not support the legacy SSE transport, use Http")
+            }
         }
-
-        Ok(())
     }
 
-    /// Serve over stdio (JSON-RPC over stdin/stdout)
-    async fn serve_stdio(
-        bridge: Arc<RwLock<VegaToMcpBridge>>,
-        capabilities: ServerCapabilities,
-        running: Arc<RwLock<bool>>,
-    ) -> Result<()> {
-        let stdin = io::stdin();
-        let mut stdout = io::stdout();
-        let mut reader = BufReader::new(stdin);
-        let mut line = String::new();
+    /// Serve over stdio (JSON-RPC over stdin/stdout) until stdin is closed
+    ///
+    /// Requests are handled concurrently, so a long tool call can be cancelled
+    /// while it runs.
+    pub async fn serve_stdio(self) -> Result<()> {
+        let server = Arc::new(self);
+        let mut lines = BufReader::new(io::stdin()).lines();
 
-        tracing::info!("MCP server started, listening on stdio");
+        // Responses and notifications share stdout, so one task writes both
+        let peer = Peer::new();
+        let mut outgoing = peer
+            .take_messages()
+            .context("Peer messages already taken")?;
+        let writer = tokio::spawn(async move {
+            let mut stdout = io::stdout();
+            while let Some(message) = outgoing.recv().await {
+                let message_json = serde_json::to_string(&message)?;
+                stdout
+                    .write_all(format!("{}\n", message_json).as_bytes())
+                    .await?;
+                stdout.flush().await?;
+            }
+            Ok::<_, anyhow::Error>(())
+        });
 
-        while *running.read().await {
-            line.clear();
+        tracing::info!("MCP server '{}' listening on stdio", server.config.name);
 
-            match reader.read_line(&mut line).await {
-                Ok(0) => {
-                    // EOF reached
-                    tracing::info!("EOF reached, shutting down MCP server");
-                    break;
+        let mut requests = tokio::task::JoinSet::new();
+        while let Some(line) = lines.next_line().await? {
+            if line.trim().is_empty() {
+                continue;
+            }
+
+            match Self::parse_message(line.as_bytes()) {
+                Ok(McpMessage::Request(request)) => {
+                    let server = server.clone();
+                    let peer = peer.clone();
+                    requests.spawn(async move {
+                        let message = McpMessage::Request(request);
+                        if let Some(response) = server.handle_message(&peer, message).await {
+                            peer.send(McpMessage::Response(response));
+                        }
+                    });
                 }
-                Ok(_) => {
-                    // Process the line
-                    if let Err(e) =
-                        Self::process_message(&line, &mut stdout, &bridge, &capabilities).await
-                    {
-                        tracing::error!("Error processing message: {}", e);
+                // Notifications such as cancellations take effect right away
+                Ok(message) => {
+                    if let Some(response) = server.handle_message(&peer, message).await {
+                        peer.send(McpMessage::Response(response));
                     }
                 }
-                Err(e) => {
-                    tracing::error!("Error reading from stdin: {}", e);
-                    break;
+                Err(error) => {
+                    peer.send(McpMessage::Response(Response::failure(Value::Null, error)))
                 }
             }
         }
 
+        tracing::info!("EOF reached, shutting down MCP server");
+        while requests.join_next().await.is_some() {}
+        drop(peer);
+        writer.await?
+    }
+
+    /// Serve over Streamable HTTP on the given listener
+    pub async fn serve_http(self, listener: TcpListener) -> Result<()> {
+        tracing::info!(
+            "MCP server '{}' listening on http://{}/mcp",
+            self.config.name,
+            listener.local_addr()?
+        );
+
+        axum::serve(listener, self.http_router()).await?;
         Ok(())
     }
 
-    /// Process a single MCP message
-    async fn process_message(
-        line: &str,
-        stdout: &mut io::Stdout,
-        bridge: &Arc<RwLock<VegaToMcpBridge>>,
-        capabilities: &ServerCapabilities,
-    ) -> Result<()> {
-        let line = line.trim();
-        if line.is_empty() {
-            return Ok(());
+    /// Router serving the Streamable HTTP transport at `/mcp`, honouring the
+    /// origin, token, CORS, request size, rate limit and session settings
+    pub fn http_router(self) -> Router {
+        let settings = self.config.settings.clone();
+        let state = Arc::new(HttpState {
+            server: self,
+            sessions: Mutex::new(HashMap::new()),
+            max_sessions: settings.max_sessions.max(1),
+            idle_timeout: Duration::from_secs(settings.session_idle_timeout),
+        });
+
+        let mut router = Router::new()
+            .route(
+                "/mcp",
+                post(handle_post).get(handle_get).delete(handle_delete),
+            )
+            .layer(DefaultBodyLimit::max(settings.max_request_size))
+            .with_state(state.clone());
+
+        if let Some(token) = settings.auth_token {
+            router = router.layer(middleware::from_fn_with_state(
+                Arc::new(token),
+                require_token,
+            ));
+        }
+
+        if let Some(limit) = &settings.rate_limit {
+            let limiter = Arc::new(RateLimiter::new(limit));
+            router = router.layer(middleware::from_fn_with_state(limiter, rate_limit));
+        }
+
+        let origins = Arc::new(settings.allowed_origins.clone());
+        router = router.layer(middleware::from_fn_with_state(
+            origins,
+            reject_foreign_origins,
+        ));
+
+        // CORS is the outermost layer so preflight requests are answered
+        // before they count against the rate limit
+        if settings.enable_cors {
+            let origins: Vec<HeaderValue> = settings
+                .allowed_origins
+                .iter()
+                .filter_map(|origin| HeaderValue::from_str(origin.trim_end_matches('/')).ok())
+                .collect();
+            let session = HeaderName::from_static(SESSION_HEADER);
+            router = router.layer(
+                CorsLayer::new()
+                    .allow_origin(AllowOrigin::list(origins))
+                    .allow_methods([Method::GET, Method::POST, Method::DELETE])
+                    .allow_headers([
+                        header::ACCEPT,
+                        header::AUTHORIZATION,
+                        header::CONTENT_TYPE,
+                        session.clone(),
+                        HeaderName::from_static(PROTOCOL_VERSION_HEADER),
+                    ])
+                    .expose_headers([session]),
+            );
         }
+        router
+    }
 
-        // Parse the incoming message
-        let message: McpMessage = serde_json::from_str(line)
-            .map_err(|e| anyhow!("Failed to parse MCP message: {}", e))?;
+    /// Parse a single JSON-RPC message
+    fn parse_message(payload: &[u8]) -> std::result::Result<McpMessage, McpError> {
+        serde_json::from_slice(payload).map_err(|e| {
+            McpError::new(
+                McpError::PARSE_ERROR,
+                format!("Failed to parse MCP message: {}", e),
+            )
+        })
+    }
 
+    /// Handle a message from a client, returning the response to a request.
+    ///
+    /// Notifications about a request, like its progress, are sent to the peer.
+    /// No response is returned for a request the client cancelled.
+    pub async fn handle_message(&self, peer: &Arc<Peer>, message: McpMessage) -> Option<Response> {
         match message {
-            McpMessage::Request(request) => {
-                let response = Self::handle_request(request, bridge, capabilities).await;
-                let response_json = serde_json::to_string(&McpMessage::Response(response))?;
-                stdout
-                    .write_all(format!("{}\n", response_json).as_bytes())
-                    .await?;
-                stdout.flush().await?;
-            }
+            McpMessage::Request(request) => self.serve_request(peer, request, &peer.outgoing).await,
             McpMessage::Response(_) => {
                 // Servers don't typically handle responses
                 tracing::warn!("Received unexpected response message");
+                None
             }
-            McpMessage::Notification(_) => {
-                // Handle notifications (not implemented yet)
-                tracing::debug!("Received notification (not implemented)");
+            McpMessage::Notification(notification) => {
+                self.handle_notification(peer, notification);
+                None
             }
         }
+    }
 
-        Ok(())
+    /// Handle a request until it is answered or cancelled, sending notifications about it to `related`
+    async fn serve_request(
+        &self,
+        peer: &Arc<Peer>,
+        request: Request,
+        related: &mpsc::UnboundedSender<McpMessage>,
+    ) -> Option<Response> {
+        // The initialize request must not be cancelled
+        if request.method == "initialize" {
+            return Some(self.handle_request(peer, request, related).await);
+        }
+
+        let key = request.id.to_string();
+        let (abort, registration) = AbortHandle::new_pair();
+        peer.requests.lock().unwrap().insert(key.clone(), abort);
+        let response =
+            Abortable::new(self.handle_request(peer, request, related), registration).await;
+        peer.requests.lock().unwrap().remove(&key);
+        response.ok()
+    }
+
+    /// Handle a notification from a client
+    fn handle_notification(&self, peer: &Arc<Peer>, notification: Notification) {
+        let params = notification.params.unwrap_or(Value::Null);
+        match notification.method.as_str() {
+            "notifications/initialized" => tracing::info!("MCP client initialized"),
+            "notifications/cancelled" => {
+                let Some(request_id) = params.get("requestId") else {
+                    tracing::debug!("Ignoring cancellation without a request id");
+                    return;
+                };
+                let reason = params
+                    .get("reason")
+                    .and_then(Value::as_str)
+                    .unwrap_or("no reason given");
+
+                // Requests that already finished are not an error
+                let Some(abort) = peer
+                    .requests
+                    .lock()
+                    .unwrap()
+                    .remove(&request_id.to_string())
+                else {
+                    tracing::debug!("Ignoring cancellation of finished request {}", request_id);
+                    return;
+                };
+                abort.abort();
+                tracing::info!("Request {} cancelled by the client: {}", request_id, reason);
+                peer.log(
+                    &peer.outgoing,
+                    LoggingLevel::Info,
+                    json!(format!("Cancelled request {}: {}", request_id, reason)),
+                );
+            }
+            method => tracing::debug!("Ignoring notification '{}'", method),
+        }
     }
 
     /// Handle an MCP request
     async fn handle_request(
+        &self,
+        peer: &Arc<Peer>,
         request: Request,
-        bridge: &Arc<RwLock<VegaToMcpBridge>>,
-        capabilities: &ServerCapabilities,
+        related: &mpsc::UnboundedSender<McpMessage>,
     ) -> Response {
         match request.method.as_str() {
-            "initialize" => Self::handle_initialize(request, capabilities).await,
-            "tools/list" => Self::handle_list_tools(request, bridge).await,
-            "tools/call" => Self::handle_call_tool(request, bridge).await,
-            "notifications/initialized" => Self::handle_initialized(request).await,
-            _ => Self::create_error_response(
-                request.id,
-                ErrorCode::MethodNotFound,
-                &format!("Method '{}' not found", request.method),
-            ),
+            "initialize" => self.handle_initialize(request),
+            "ping" => Response::success(request.id, json!({})),
+            "tools/list" => self.handle_list_tools(request),
+            "tools/call" => self.handle_call_tool(peer, request, related).await,
+            "resources/list" => {
+                let cursor = request
+                    .params
+                    .as_ref()
+                    .and_then(|params| params.get("cursor"))
+                    .and_then(Value::as_str);
+                Response::from_result(request.id, self.resources.list(cursor).await)
+            }
+            "resources/templates/list" => Response::success(request.id, self.resources.templates()),
+            "resources/read" => {
+                let result = match string_param(&request, "uri") {
+                    Ok(uri) => self.resources.read(uri).await,
+                    Err(error) => Err(error),
+                };
+                Response::from_result(request.id, result)
+            }
+            "resources/subscribe" => {
+                let result = match string_param(&request, "uri") {
+                    Ok(uri) => self.subscribe(peer, uri).await,
+                    Err(error) => Err(error),
+                };
+                Response::from_result(request.id, result)
+            }
+            "resources/unsubscribe" => {
+                let result = string_param(&request, "uri").map(|uri| {
+                    peer.subscriptions.lock().unwrap().remove(uri);
+                    json!({})
+                });
+                Response::from_result(request.id, result)
+            }
+            "prompts/list" => Response::success(request.id, prompts::list_prompts(&self.resources)),
+            "prompts/get" => {
+                let result = match string_param(&request, "name") {
+                    Ok(name) => {
+                        let arguments = request
+                            .params
+                            .as_ref()
+                            .and_then(|params| params.get("arguments"))
+                            .cloned()
+                            .unwrap_or_else(|| json!({}));
+                        prompts::get_prompt(&self.resources, name, &arguments).await
+                    }
+                    Err(error) => Err(error),
+                };
+                Response::from_result(request.id, result)
+            }
+            "logging/setLevel" => {
+                let level = request
+                    .params
+                    .as_ref()
+                    .and_then(|params| params.get("level"))
+                    .cloned()
+                    .and_then(|level| serde_json::from_value::<LoggingLevel>(level).ok());
+                match level {
+                    Some(level) => {
+                        *peer.log_level.lock().unwrap() = level;
+                        Response::success(request.id, json!({}))
+                    }
+                    None => Response::failure(
+                        request.id,
+                        McpError::new(McpError::INVALID_PARAMS, "Missing or invalid 'level'"),
+                    ),
+                }
+            }
+            _ => Response::failure(request.id, McpError::method_not_found(&request.method)),
         }
     }
 
     /// Handle initialize request
-    async fn handle_initialize(request: Request, capabilities: &ServerCapabilities) -> Response {
-        let server_info = serde_json::json!({
-            "protocolVersion": "2025-06-18",
-            "capabilities": {
-                "tools": capabilities.tools.as_ref().map(|t| serde_json::json!({
-                    "listChanged": t.list_changed
-                })),
-                "logging": capabilities.logging.as_ref().map(|l| serde_json::json!({
-                    "levels": l.levels
-                }))
-            },
-            "serverInfo": {
-                "name": "vega-mcp-server",
-                "version": "0.1.0"
-            }
-        });
+    fn handle_initialize(&self, request: Request) -> Response {
+        // Answer with the client's revision if we speak it, otherwise with ours
+        let protocol_version = request
+            .params
+            .as_ref()
+            .and_then(|params| params.get("protocolVersion"))
+            .and_then(Value::as_str)
+            .filter(|version| SUPPORTED_PROTOCOL_VERSIONS.contains(version))
+            .unwrap_or(PROTOCOL_VERSION);
 
-        Response {
-            id: request.id,
-            result: Some(server_info),
-            error: None,
-        }
+        Response::success(
+            request.id,
+            json!({
+                "protocolVersion": protocol_version,
+                "capabilities": self.capabilities.to_json(),
+                "serverInfo": {
+                    "name": self.config.name,
+                    "version": self.config.version
+                },
+                "instructions": self.config.description
+            }),
+        )
     }
 
-    /// Handle initialized notification
-    async fn handle_initialized(request: Request) -> Response {
-        tracing::info!("MCP client initialized");
-
-        Response {
-            id: request.id,
-            result: Some(Value::Null),
-            error: None,
+    /// Handle list tools request
+    fn handle_list_tools(&self, request: Request) -> Response {
+        match self.bridge.get_all_tools() {
+            Ok(mut tools) => {
+                tools.sort_by(|a, b| a.name.cmp(&b.name));
+                Response::success(request.id, json!({ "tools": tools }))
+            }
+            Err(e) => Response::failure(
+                request.id,
+                McpError::new(
+                    McpError::INTERNAL_ERROR,
+                    format!("Failed to list tools: {}", e),
+                ),
+            ),
         }
     }
 
-    /// Handle list tools request
-    async fn handle_list_tools(
+    /// Handle call tool request, reporting progress if the client asked for it
+    async fn handle_call_tool(
+        &self,
+        peer: &Peer,
         request: Request,
-        bridge: &Arc<RwLock<VegaToMcpBridge>>,
+        related: &mpsc::UnboundedSender<McpMessage>,
     ) -> Response {
-        match bridge.read().await.get_all_tools() {
-            Ok(tools) => {
-                let tools_json = serde_json::json!({
-                    "tools": tools
-                });
+        let params = request.params.unwrap_or(Value::Null);
 
-                Response {
-                    id: request.id,
-                    result: Some(tools_json),
-                    error: None,
-                }
-            }
-            Err(e) => Self::create_error_response(
+        let Some(tool_name) = params.get("name").and_then(Value::as_str) else {
+            return Response::failure(
                 request.id,
-                ErrorCode::InternalError,
-                &format!("Failed to list tools: {}", e),
-            ),
+                McpError::new(McpError::INVALID_PARAMS, "Missing 'name' parameter"),
+            );
+        };
+
+        if !self
+            .bridge
+            .list_tools()
+            .iter()
+            .any(|name| name == tool_name)
+        {
+            return Response::failure(
+                request.id,
+                McpError::new(
+                    McpError::INVALID_PARAMS,
+                    format!("Unknown tool: {}", tool_name),
+                ),
+            );
         }
-    }
 
-    /// Handle call tool request
-    async fn handle_call_tool(request: Request, bridge: &Arc<RwLock<VegaToMcpBridge>>) -> Response {
-        let params = match request.params {
-            Some(params) => params,
-            None => {
-                return Self::create_error_response(
-                    request.id,
-                    ErrorCode::InvalidParams,
-                    "Missing parameters for tool call",
-                );
+        let arguments = params
+            .get("arguments")
+            .cloned()
+            .filter(|arguments| !arguments.is_null())
+            .unwrap_or_else(|| json!({}));
+
+        let progress_token = params
+            .get("_meta")
+            .and_then(|meta| meta.get("progressToken"))
+            .filter(|token| token.is_string() || token.is_number());
+
+        let result = match progress_token {
+            Some(token) => {
+                let (progress, updates) = mpsc::unbounded_channel();
+                let call =
+                    self.bridge
+                        .call_tool_with_progress(tool_name, arguments, Some(progress));
+                forward_progress(call, updates, |update| {
+                    let _ = related.send(McpMessage::progress(token, &update));
+                })
+                .await
             }
+            None => self.bridge.call_tool(tool_name, arguments).await,
         };
 
-        let tool_name = match params.get("name").and_then(|n| n.as_str()) {
-            Some(name) => name,
-            None => {
-                return Self::create_error_response(
-                    request.id,
-                    ErrorCode::InvalidParams,
-                    "Missing 'name' parameter",
-                );
+        let (text, is_error) = match result {
+            Ok(Value::String(text)) => (text, false),
+            Ok(result) => (
+                serde_json::to_string_pretty(&result).unwrap_or_else(|_| result.to_string()),
+                false,
+            ),
+            Err(e) => {
+                let text = format!("Error calling tool '{}': {}", tool_name, e);
+                peer.log(related, LoggingLevel::Error, json!(text));
+                (text, true)
             }
         };
 
-        let arguments = params.get("arguments").cloned().unwrap_or(Value::Null);
+        Response::success(
+            request.id,
+            json!({
+                "content": [{ "type": "text", "text": text }],
+                "isError": is_error
+            }),
+        )
+    }
 
-        match bridge.read().await.call_tool(tool_name, arguments).await {
-            Ok(result) => {
-                let response_content = serde_json::json!({
-                    "content": [{
-                        "type": "text",
-                        "text": serde_json::to_string_pretty(&result).unwrap_or_else(|_| result.to_string())
-                    }],
-                    "isError": false
-                });
+    /// Subscribe a peer to updates of a resource
+    async fn subscribe(&self, peer: &Arc<Peer>, uri: &str) -> std::result::Result<Value, McpError> {
+        if VegaResource::parse(uri).is_none() {
+            return Err(McpError::resource_not_found(uri));
+        }
 
-                Response {
-                    id: request.id,
-                    result: Some(response_content),
-                    error: None,
-                }
+        // Sessions may be subscribed to before their first entry is stored
+        let fingerprint = self.resources.fingerprint(uri).await;
+        peer.subscriptions
+            .lock()
+            .unwrap()
+            .insert(uri.to_string(), fingerprint);
+
+        if !peer.watching.swap(true, Ordering::SeqCst) {
+            tokio::spawn(watch_subscriptions(
+                Arc::downgrade(peer),
+                self.resources.clone(),
+            ));
+        }
+        Ok(json!({}))
+    }
+
+    /// Get server configuration
+    pub fn config(&self) -> &McpServerConfig {
+        &self.config
+    }
+
+    /// Get server capabilities
+    pub fn capabilities(&self) -> &ServerCapabilities {
+        &self.capabilities
+    }
+}
+
+/// A string parameter every request of a method carries
+fn string_param<'a>(request: &'a Request, name: &str) -> std::result::Result<&'a str, McpError> {
+    request
+        .params
+        .as_ref()
+        .and_then(|params| params.get(name))
+        .and_then(Value::as_str)
+        .ok_or_else(|| {
+            McpError::new(
+                McpError::INVALID_PARAMS,
+                format!("Missing '{}' parameter", name),
+            )
+        })
+}
+
+/// A connected client: the messages sent to it, the resources it subscribed
+/// to and its requests in flight
+pub struct Peer {
+    outgoing: mpsc::UnboundedSender<McpMessage>,
+    /// Receiving end of `outgoing` while no transport is draining it
+    messages: Mutex<Option<mpsc::UnboundedReceiver<McpMessage>>>,
+    /// Subscribed resource URIs and the fingerprint of their last notified content
+    subscriptions: Mutex<HashMap<String, Option<u64>>>,
+    /// Whether a task is watching the subscriptions
+    watching: AtomicBool,
+    /// Requests being handled, by their JSON id, so they can be cancelled
+    requests: Mutex<HashMap<String, AbortHandle>>,
+    /// Least severe level of the log messages sent to the client
+    log_level: Mutex<LoggingLevel>,
+}
+
+impl Peer {
+    /// Create a peer with no subscriptions
+    pub fn new() -> Arc<Self> {
+        let (outgoing, messages) = mpsc::unbounded_channel();
+        Arc::new(Self {
+            outgoing,
+            messages: Mutex::new(Some(messages)),
+            subscriptions: Mutex::new(HashMap::new()),
+            watching: AtomicBool::new(false),
+            requests: Mutex::new(HashMap::new()),
+            log_level: Mutex::new(DEFAULT_LOG_LEVEL),
+        })
+    }
+
+    /// Send a log message to `sink` if the client asked for its level
+    fn log(&self, sink: &mpsc::UnboundedSender<McpMessage>, level: LoggingLevel, data: Value) {
+        if level >= *self.log_level.lock().unwrap() {
+            let _ = sink.send(McpMessage::log(level, LOGGER_NAME, data));
+        }
+    }
+
+    /// Take the messages sent to the peer, `None` if a transport is already draining them.
+    ///
+    /// Messages are queued until they are taken; the receiver ends when the peer is dropped.
+    pub fn take_messages(&self) -> Option<mpsc::UnboundedReceiver<McpMessage>> {
+        self.messages.lock().unwrap().take()
+    }
+
+    /// Whether a transport is draining the messages sent to the peer
+    fn is_streaming(&self) -> bool {
+        self.messages.lock().unwrap().is_none()
+    }
+
+    /// Queue a message for the peer
+    pub fn send(&self, message: McpMessage) {
+        // The receiver lives as long as the peer, so sending only fails while shutting down
+        let _ = self.outgoing.send(message);
+    }
+}
+
+/// Notify a peer whenever a resource it subscribed to changes, until it is dropped
+async fn watch_subscriptions(peer: Weak<Peer>, resources: VegaResources) {
+    let mut interval = tokio::time::interval(SUBSCRIPTION_POLL_INTERVAL);
+    loop {
+        interval.tick().await;
+        let Some(peer) = peer.upgrade() else {
+            return;
+        };
+
+        let subscriptions = peer.subscriptions.lock().unwrap().clone();
+        for (uri, last) in subscriptions {
+            let current = resources.fingerprint(&uri).await;
+            if current == last {
+                continue;
             }
-            Err(e) => {
-                let error_content = serde_json::json!({
-                    "content": [{
-                        "type": "text",
-                        "text": format!("Error calling tool '{}': {}", tool_name, e)
-                    }],
-                    "isError": true
-                });
 
-                Response {
-                    id: request.id,
-                    result: Some(error_content),
-                    error: None,
-                }
+            // The peer may have unsubscribed while the resource was read
+            match peer.subscriptions.lock().unwrap().get_mut(&uri) {
+                Some(fingerprint) => *fingerprint = current,
+                None => continue,
             }
+            peer.send(McpMessage::notification(
+                "notifications/resources/updated",
+                Some(json!({ "uri": uri })),
+            ));
         }
     }
+}
 
-    /// Create an error response
-    fn create_error_response(id: Value, code: ErrorCode, message: &str) -> Response {
-        Response {
-            id,
-            result: None,
-            error: Some(McpError {
-                code,
-                message: message.to_string(),
-                data: None,
-            }),
+/// State of the Streamable HTTP transport
+struct HttpState {
+    server: McpServer,
+    /// Sessions assigned by `initialize` and not yet deleted or expired, by id
+    sessions: Mutex<HashMap<String, HttpSession>>,
+    max_sessions: usize,
+    idle_timeout: Duration,
+}
+
+/// A session of the HTTP transport
+struct HttpSession {
+    peer: Arc<Peer>,
+    /// When the session's last request arrived
+    last_used: Instant,
+}
+
+impl HttpSession {
+    /// Whether the session went unused for `timeout`; sessions whose event
+    /// stream is open are never idle
+    fn is_idle(&self, now: Instant, timeout: Duration) -> bool {
+        now.duration_since(self.last_used) >= timeout && !self.peer.is_streaming()
+    }
+}
+
+impl HttpState {
+    /// Check the session a request carries, returning the error status if it is missing or unknown
+    fn check_session(
+        &self,
+        headers: &HeaderMap,
+    ) -> std::result::Result<(String, Arc<Peer>), (StatusCode, &'static str)> {
+        let Some(session) = header_str(headers, SESSION_HEADER) else {
+            return Err((StatusCode::BAD_REQUEST, "Missing Mcp-Session-Id header"));
+        };
+
+        let now = Instant::now();
+        let mut sessions = self.sessions.lock().unwrap();
+        match sessions.get_mut(session) {
+            Some(entry) if !entry.is_idle(now, self.idle_timeout) => {
+                entry.last_used = now;
+                Ok((session.to_string(), entry.peer.clone()))
+            }
+            Some(_) => {
+                sessions.remove(session);
+                Err((StatusCode::NOT_FOUND, "Unknown or expired session"))
+            }
+            None => Err((StatusCode::NOT_FOUND, "Unknown or expired session")),
         }
     }
 
-    /// Stop the server
-    pub async fn stop(self) -> Result<()> {
-        *self.running.write().await = false;
+    /// Start a session for a peer, first ending the idle sessions and, if there
+    /// are still too many, the least recently used one
+    fn open_session(&self, peer: Arc<Peer>) -> String {
+        let now = Instant::now();
+        let mut sessions = self.sessions.lock().unwrap();
+        sessions.retain(|_, session| !session.is_idle(now, self.idle_timeout));
+        while sessions.len() >= self.max_sessions {
+            let Some(oldest) = sessions
+                .iter()
+                .min_by_key(|(_, session)| session.last_used)
+                .map(|(id, _)| id.clone())
+            else {
+                break;
+            };
+            tracing::info!(
+                "Ending MCP session {} to stay within {} sessions",
+                oldest,
+                self.max_sessions
+            );
+            sessions.remove(&oldest);
+        }
+
+        let id = uuid::Uuid::new_v4().to_string();
+        sessions.insert(
+            id.clone(),
+            HttpSession {
+                peer,
+                last_used: now,
+            },
+        );
+        id
+    }
+}
+
+fn header_str<'a>(headers: &'a HeaderMap, name: &str) -> Option<&'a str> {
+    headers.get(name).and_then(|value| value.to_str().ok())
+}
 
-        if let Some(handle) = self.task_handle {
-            handle.abort();
+/// Handle a message POSTed by a client
+async fn handle_post(
+    State(state): State<Arc<HttpState>>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> HttpResponse {
+    let message = match McpServer::parse_message(&body) {
+        Ok(message) => message,
+        Err(error) => {
+            let response = McpMessage::Response(Response::failure(Value::Null, error));
+            return (StatusCode::BAD_REQUEST, Json(response)).into_response();
         }
+    };
 
-        tracing::info!("MCP server stopped");
-        Ok(())
+    let initialize =
+        matches!(&message, McpMessage::Request(request) if request.method == "initialize");
+    let peer = if initialize {
+        Peer::new()
+    } else {
+        let peer = match state.check_session(&headers) {
+            Ok((_, peer)) => peer,
+            Err(rejection) => return rejection.into_response(),
+        };
+        if let Some(version) = header_str(&headers, PROTOCOL_VERSION_HEADER)
+            && !SUPPORTED_PROTOCOL_VERSIONS.contains(&version)
+        {
+            return (
+                StatusCode::BAD_REQUEST,
+                format!("Unsupported MCP protocol version: {}", version),
+            )
+                .into_response();
+        }
+        peer
+    };
+
+    // Tool calls stream their progress and log messages ahead of the response
+    let message = match message {
+        McpMessage::Request(request)
+            if request.method == "tools/call" && accepts_event_stream(&headers) =>
+        {
+            return stream_request(state, peer, request);
+        }
+        message => message,
+    };
+
+    // Notifications and responses are only acknowledged
+    let Some(response) = state.server.handle_message(&peer, message).await else {
+        return StatusCode::ACCEPTED.into_response();
+    };
+
+    let session = (initialize && response.error.is_none()).then(|| state.open_session(peer));
+
+    let mut http_response = Json(McpMessage::Response(response)).into_response();
+    if let Some(session) = session
+        && let Ok(value) = HeaderValue::from_str(&session)
+    {
+        http_response.headers_mut().insert(SESSION_HEADER, value);
     }
+    http_response
+}
 
-    /// Get server configuration
-    pub fn config(&self) -> &McpServerConfig {
-        &self.config
+fn accepts_event_stream(headers: &HeaderMap) -> bool {
+    header_str(headers, header::ACCEPT.as_str())
+        .is_some_and(|accept| accept.contains("text/event-stream"))
+}
+
+/// Answer a request with an event stream carrying the notifications about it and
+/// then its response. The stream ends without a response if the request is cancelled.
+fn stream_request(state: Arc<HttpState>, peer: Arc<Peer>, request: Request) -> HttpResponse {
+    let (related, messages) = mpsc::unbounded_channel();
+    // A client dropping the stream does not cancel the request
+    tokio::spawn(async move {
+        if let Some(response) = state.server.serve_request(&peer, request, &related).await {
+            let _ = related.send(McpMessage::Response(response));
+        }
+    });
+    event_stream(messages)
+}
+
+/// An SSE response sending every message until the channel closes
+fn event_stream(messages: mpsc::UnboundedReceiver<McpMessage>) -> HttpResponse {
+    let body = futures::stream::unfold(messages, |mut messages| async move {
+        let message = messages.recv().await?;
+        let event = format!("data: {}\n\n", serde_json::to_string(&message).ok()?);
+        Some((Ok::<_, Infallible>(event), messages))
+    });
+    sse_response(Body::from_stream(body))
+}
+
+fn sse_response(body: Body) -> HttpResponse {
+    (
+        [
+            (header::CONTENT_TYPE, "text/event-stream"),
+            (header::CACHE_CONTROL, "no-cache"),
+        ],
+        body,
+    )
+        .into_response()
+}
+
+/// Open the event stream carrying a session's notifications
+async fn handle_get(State(state): State<Arc<HttpState>>, headers: HeaderMap) -> HttpResponse {
+    let peer = match state.check_session(&headers) {
+        Ok((_, peer)) => peer,
+        Err(rejection) => return rejection.into_response(),
+    };
+    let Some(messages) = peer.take_messages() else {
+        return (
+            StatusCode::CONFLICT,
+            "The session's event stream is already open",
+        )
+            .into_response();
+    };
+
+    let events = EventStream {
+        messages: Some(messages),
+        peer: Arc::downgrade(&peer),
+    };
+    let body = futures::stream::unfold(events, |mut events| async move {
+        let message = events.messages.as_mut()?.recv().await?;
+        let event = format!("data: {}\n\n", serde_json::to_string(&message).ok()?);
+        Some((Ok::<_, Infallible>(event), events))
+    });
+    sse_response(Body::from_stream(body))
+}
+
+/// A session's open event stream. It ends when the session is deleted, and
+/// hands undelivered messages back to the session when the client goes away.
+struct EventStream {
+    messages: Option<mpsc::UnboundedReceiver<McpMessage>>,
+    peer: Weak<Peer>,
+}
+
+impl Drop for EventStream {
+    fn drop(&mut self) {
+        if let (Some(messages), Some(peer)) = (self.messages.take(), self.peer.upgrade()) {
+            *peer.messages.lock().unwrap() = Some(messages);
+        }
     }
+}
 
-    /// Get server capabilities
-    pub fn capabilities(&self) -> &ServerCapabilities {
-        &self.capabilities
+/// End a session
+async fn handle_delete(State(state): State<Arc<HttpState>>, headers: HeaderMap) -> HttpResponse {
+    match state.check_session(&headers) {
+        Ok((session, _)) => {
+            state.sessions.lock().unwrap().remove(&session);
+            StatusCode::OK.into_response()
+        }
+        Err(rejection) => rejection.into_response(),
     }
 }
 
+/// Fixed-window limit on the requests the HTTP transport accepts
+#[derive(Debug)]
+struct RateLimiter {
+    max_requests: usize,
+    window: Duration,
+    /// Start of the current window and the requests accepted in it
+    current: Mutex<(Instant, usize)>,
+}
+
+impl RateLimiter {
+    fn new(limit: &RateLimit) -> Self {
+        Self {
+            max_requests: limit.max_requests,
+            window: Duration::from_secs(limit.window_seconds.max(1)),
+            current: Mutex::new((Instant::now(), 0)),
+        }
+    }
+
+    /// Count a request, or return how long until the next window if it is over the limit
+    fn acquire(&self) -> std::result::Result<(), Duration> {
+        let mut current = self.current.lock().unwrap();
+        let now = Instant::now();
+        if now.duration_since(current.0) >= self.window {
+            *current = (now, 0);
+        }
+
+        if current.1 < self.max_requests {
+            current.1 += 1;
+            Ok(())
+        } else {
+            Err(self.window - now.duration_since(current.0))
+        }
+    }
+}
+
+async fn rate_limit(
+    State(limiter): State<Arc<RateLimiter>>,
+    request: HttpRequest,
+    next: Next,
+) -> HttpResponse {
+    match limiter.acquire() {
+        Ok(()) => next.run(request).await,
+        Err(retry_after) => (
+            StatusCode::TOO_MANY_REQUESTS,
+            [(
+                header::RETRY_AFTER,
+                retry_after.as_secs().max(1).to_string(),
+            )],
+        )
+            .into_response(),
+    }
+}
+
+/// Only pages served from the local machine or an allowed origin may call the
+/// server, which keeps DNS rebinding attacks away from Vega's tools
+async fn reject_foreign_origins(
+    State(allowed): State<Arc<Vec<String>>>,
+    request: HttpRequest,
+    next: Next,
+) -> HttpResponse {
+    if let Some(origin) = header_str(request.headers(), header::ORIGIN.as_str())
+        && !is_local_origin(origin)
+        && !is_allowed_origin(origin, &allowed)
+    {
+        return (StatusCode::FORBIDDEN, "Origin not allowed").into_response();
+    }
+    next.run(request).await
+}
+
+fn is_allowed_origin(origin: &str, allowed: &[String]) -> bool {
+    allowed
+        .iter()
+        .any(|allowed| allowed.trim_end_matches('/').eq_ignore_ascii_case(origin))
+}
+
+/// Reject requests that do not carry the configured bearer token
+async fn require_token(
+    State(token): State<Arc<String>>,
+    request: HttpRequest,
+    next: Next,
+) -> HttpResponse {
+    let presented = header_str(request.headers(), header::AUTHORIZATION.as_str())
+        .and_then(|value| value.strip_prefix("Bearer "));
+    if !presented.is_some_and(|presented| secrets_match(presented.as_bytes(), token.as_bytes())) {
+        return (
+            StatusCode::UNAUTHORIZED,
+            [(header::WWW_AUTHENTICATE, "Bearer")],
+            "Missing or invalid bearer token",
+        )
+            .into_response();
+    }
+    next.run(request).await
+}
+
+/// Compare secrets without stopping at the first byte that differs
+fn secrets_match(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+fn is_local_origin(origin: &str) -> bool {
+    let Ok(url) = reqwest::Url::parse(origin) else {
+        return false;
+    };
+    matches!(
+        url.host_str(),
+        Some("localhost") | Some("127.0.0.1") | Some("[::1]")
+    )
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::mcp::config::McpServerConfig;
+    use crate::mcp::config::{McpServerConfig, ServerSettings};
 
     #[tokio::test]
     async fn test_mcp_server_creation() {
@@ -451,20 +1185,211 @@ mod tests {
 
         assert!(capabilities.tools.is_some());
         assert!(capabilities.logging.is_some());
-        assert!(capabilities.resources.is_none());
-        assert!(capabilities.prompts.is_none());
+        assert!(capabilities.resources.unwrap().subscribe);
+        assert!(capabilities.prompts.is_some());
     }
 
-    #[test]
-    fn test_error_response_creation() {
-        let response = McpServer::create_error_response(
-            Value::Number(1.into()),
-            ErrorCode::InvalidParams,
-            "Test error",
+    #[tokio::test]
+    async fn test_handle_requests() {
+        let config = McpServerConfig {
+            exposed_tools: vec!["list_files".to_string(), "unknown".to_string()],
+            ..Default::default()
+        };
+        let server = McpServer::new(config).await.unwrap();
+        let peer = Peer::new();
+
+        let request = |method: &str, params: Value| {
+            McpMessage::Request(Request {
+                id: json!(1),
+                method: method.to_string(),
+                params: Some(params),
+            })
+        };
+
+        let response = server
+            .handle_message(
+                &peer,
+                request("initialize", json!({ "protocolVersion": "2025-03-26" })),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.result.unwrap()["protocolVersion"], "2025-03-26");
+
+        let response = server
+            .handle_message(&peer, request("tools/list", json!({})))
+            .await
+            .unwrap();
+        let tools = response.result.unwrap();
+        assert_eq!(tools["tools"].as_array().unwrap().len(), 1);
+        assert_eq!(tools["tools"][0]["name"], "list_files");
+
+        let response = server
+            .handle_message(&peer, request("tools/call", json!({ "name": "bash" })))
+            .await
+            .unwrap();
+        assert_eq!(response.error.unwrap().code, McpError::INVALID_PARAMS);
+
+        let response = server
+            .handle_message(&peer, request("resources/list", json!({})))
+            .await
+            .unwrap();
+        assert_eq!(response.result.unwrap()["resources"], json!([]));
+
+        let response = server
+            .handle_message(&peer, request("resources/read", json!({})))
+            .await
+            .unwrap();
+        assert_eq!(response.error.unwrap().code, McpError::INVALID_PARAMS);
+
+        let response = server
+            .handle_message(
+                &peer,
+                request("resources/subscribe", json!({ "uri": "file:///" })),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.error.unwrap().code, McpError::RESOURCE_NOT_FOUND);
+
+        let response = server
+            .handle_message(
+                &peer,
+                request("prompts/get", json!({ "name": "vega_system" })),
+            )
+            .await
+            .unwrap();
+        assert!(response.result.is_some());
+
+        let response = server
+            .handle_message(&peer, request("completion/complete", json!({})))
+            .await
+            .unwrap();
+        assert_eq!(response.error.unwrap().code, McpError::METHOD_NOT_FOUND);
+
+        let notification = McpMessage::notification("notifications/initialized", None);
+        assert!(server.handle_message(&peer, notification).await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_tool_progress_logging_and_cancellation() {
+        let config = McpServerConfig {
+            exposed_tools: vec!["bash".to_string()],
+            settings: ServerSettings {
+                allow_ask: true,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let server = Arc::new(McpServer::new(config).await.unwrap());
+        let peer = Peer::new();
+        let mut outgoing = peer.take_messages().unwrap();
+        let call = |id: i64, command: &str| {
+            McpMessage::Request(Request {
+                id: json!(id),
+                method: "tools/call".to_string(),
+                params: Some(json!({
+                    "name": "bash",
+                    "arguments": { "command": command },
+                    "_meta": { "progressToken": "p1" }
+                })),
+            })
+        };
+
+        let response = server
+            .handle_message(&peer, call(1, "echo one; sleep 0.3; echo two"))
+            .await
+            .unwrap();
+        assert!(
+            response.result.unwrap()["content"][0]["text"]
+                .as_str()
+                .unwrap()
+                .contains("two")
         );
+        let mut progress = Vec::new();
+        while let Ok(McpMessage::Notification(notification)) = outgoing.try_recv() {
+            assert_eq!(notification.method, "notifications/progress");
+            let params = notification.params.unwrap();
+            assert_eq!(params["progressToken"], "p1");
+            progress.push(params["progress"].as_f64().unwrap());
+        }
+        assert_eq!(progress, vec![1.0, 2.0]);
+
+        let set_level = |level: &str| {
+            McpMessage::Request(Request {
+                id: json!(3),
+                method: "logging/setLevel".to_string(),
+                params: Some(json!({ "level": level })),
+            })
+        };
+        let response = server
+            .handle_message(&peer, set_level("verbose"))
+            .await
+            .unwrap();
+        assert_eq!(response.error.unwrap().code, McpError::INVALID_PARAMS);
+        let response = server
+            .handle_message(&peer, set_level("info"))
+            .await
+            .unwrap();
+        assert!(response.error.is_none());
 
-        assert!(response.error.is_some());
-        assert!(response.result.is_none());
-        assert_eq!(response.error.unwrap().message, "Test error");
+        // Cancelling stops the command and the request is never answered
+        let running = tokio::spawn({
+            let server = server.clone();
+            let peer = peer.clone();
+            async move { server.handle_message(&peer, call(2, "sleep 30")).await }
+        });
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        let cancel = McpMessage::cancelled(&json!(2), "changed my mind");
+        assert!(server.handle_message(&peer, cancel).await.is_none());
+        let response = tokio::time::timeout(Duration::from_secs(5), running)
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(response.is_none());
+        assert!(peer.requests.lock().unwrap().is_empty());
+
+        // The cancellation is logged now that the client asked for info messages
+        let Ok(McpMessage::Notification(log)) = outgoing.try_recv() else {
+            panic!("expected a log message");
+        };
+        assert_eq!(log.method, "notifications/message");
+        assert_eq!(log.params.unwrap()["level"], "info");
+    }
+
+    #[test]
+    fn test_parse_error_response() {
+        let error = McpServer::parse_message(b"{not json").unwrap_err();
+        assert_eq!(error.code, McpError::PARSE_ERROR);
+    }
+
+    #[test]
+    fn test_rate_limiter() {
+        let limiter = RateLimiter::new(&RateLimit {
+            max_requests: 2,
+            window_seconds: 60,
+        });
+
+        assert!(limiter.acquire().is_ok());
+        assert!(limiter.acquire().is_ok());
+        let retry_after = limiter.acquire().unwrap_err();
+        assert!(retry_after <= Duration::from_secs(60));
+    }
+
+    #[test]
+    fn test_local_origins() {
+        assert!(is_local_origin("http://localhost:3000"));
+        assert!(is_local_origin("http://127.0.0.1"));
+        assert!(!is_local_origin("https://example.com"));
+        assert!(!is_local_origin("null"));
+
+        let allowed = vec!["https://editor.example/".to_string()];
+        assert!(is_allowed_origin("https://editor.example", &allowed));
+        assert!(!is_allowed_origin("https://editor.example.evil", &allowed));
+    }
+
+    #[test]
+    fn test_secrets_match() {
+        assert!(secrets_match(b"token", b"token"));
+        assert!(!secrets_match(b"token", b"tokem"));
+        assert!(!secrets_match(b"token", b"token2"));
     }
 }
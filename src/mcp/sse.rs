@@ -0,0 +1,150 @@
+//! # Server-Sent Events
+//!
+//! Incremental parser for `text/event-stream` bodies as used by the MCP HTTP
+//! transports. Bytes are fed in as they arrive and complete events come out;
+//! an event ends at a blank line and lines may end in `\n` or `\r\n`.
+
+use anyhow::Result;
+
+/// A dispatched server-sent event
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct SseEvent {
+    /// Event type, `message` when the stream did not name one
+    pub event: String,
+    /// Event payload, with multiple `data` lines joined by newlines
+    pub data: String,
+    /// Last event id seen on the stream, used to resume it
+    pub id: Option<String>,
+    /// Reconnection delay requested by the server, in milliseconds
+    pub retry: Option<u64>,
+}
+
+/// Incremental `text/event-stream` parser
+#[derive(Debug, Default)]
+pub struct SseParser {
+    buffer: Vec<u8>,
+    event: Option<String>,
+    data: Option<String>,
+    last_id: Option<String>,
+    retry: Option<u64>,
+}
+
+impl SseParser {
+    /// Create a parser for a new stream
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Id of the last event seen, which persists across events as the spec requires
+    pub fn last_event_id(&self) -> Option<&str> {
+        self.last_id.as_deref()
+    }
+
+    /// Feed a chunk of the stream, returning the events it completed
+    pub fn feed(&mut self, chunk: &[u8]) -> Vec<SseEvent> {
+        self.buffer.extend_from_slice(chunk);
+
+        let mut events = Vec::new();
+        while let Some(end) = self.buffer.iter().position(|&b| b == b'\n') {
+            let mut line: Vec<u8> = self.buffer.drain(..=end).collect();
+            line.pop();
+            if line.last() == Some(&b'\r') {
+                line.pop();
+            }
+
+            if let Some(event) = self.process_line(&String::from_utf8_lossy(&line)) {
+                events.push(event);
+            }
+        }
+        events
+    }
+
+    /// Handle one line, dispatching the pending event at a blank line
+    fn process_line(&mut self, line: &str) -> Option<SseEvent> {
+        if line.is_empty() {
+            let event = self.event.take();
+            // Events without data are not dispatched
+            let data = self.data.take()?;
+            return Some(SseEvent {
+                event: event.unwrap_or_else(|| "message".to_string()),
+                data,
+                id: self.last_id.clone(),
+                retry: self.retry,
+            });
+        }
+
+        if line.starts_with(':') {
+            return None; // Comment, often used as a keep-alive
+        }
+
+        let (field, value) = match line.split_once(':') {
+            Some((field, value)) => (field, value.strip_prefix(' ').unwrap_or(value)),
+            None => (line, ""),
+        };
+
+        match field {
+            "event" => self.event = Some(value.to_string()),
+            "data" => match self.data {
+                Some(ref mut data) => {
+                    data.push('\n');
+                    data.push_str(value);
+                }
+                None => self.data = Some(value.to_string()),
+            },
+            "id" if !value.contains('\0') => self.last_id = Some(value.to_string()),
+            "retry" => {
+                if let Ok(retry) = value.parse() {
+                    self.retry = Some(retry);
+                }
+            }
+            _ => {}
+        }
+        None
+    }
+}
+
+/// Read the next events from a streaming HTTP response.
+///
+/// Returns `None` once the body has ended.
+pub async fn next_events(
+    response: &mut reqwest::Response,
+    parser: &mut SseParser,
+) -> Result<Option<Vec<SseEvent>>> {
+    match response.chunk().await? {
+        Some(chunk) => Ok(Some(parser.feed(&chunk))),
+        None => Ok(None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_events_across_chunks() {
+        let mut parser = SseParser::new();
+        assert!(parser.feed(b"event: endpoint\r\nda").is_empty());
+        let events = parser
+            .feed(b"ta: /messages?id=1\r\n\r\n: keep-alive\n\nid: 7\ndata: {\"a\":\ndata: 1}\n\n");
+
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0].event, "endpoint");
+        assert_eq!(events[0].data, "/messages?id=1");
+        assert_eq!(events[0].id, None);
+        assert_eq!(events[1].event, "message");
+        assert_eq!(events[1].data, "{\"a\":\n1}");
+        assert_eq!(events[1].id.as_deref(), Some("7"));
+        assert_eq!(parser.last_event_id(), Some("7"));
+    }
+
+    #[test]
+    fn test_event_without_data_only_sets_id() {
+        let mut parser = SseParser::new();
+        let events = parser.feed(b"id: 3\nretry: 500\n\ndata\n\n");
+
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].data, "");
+        assert_eq!(events[0].id.as_deref(), Some("3"));
+        assert_eq!(events[0].retry, Some(500));
+    }
+}
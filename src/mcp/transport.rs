@@ -1,31 +1,39 @@
 //! # MCP Transport Layer
 //!
 //! This module provides transport layer abstractions for MCP communication.
-//! It supports various transport mechanisms including stdio and SSE.
+//! It supports various transport mechanisms including stdio, Streamable HTTP
+//! and SSE; the HTTP-based ones live in [`super::http`].
 
 use anyhow::{Result, anyhow};
 use async_trait::async_trait;
-use rust_mcp_schema::{JsonrpcMessage, JsonrpcRequest, JsonrpcResponse, RequestParams};
 use serde_json::Value;
-use std::process::{Child, Stdio};
+use std::collections::HashMap;
+use std::process::Stdio;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
 use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
-use tokio::process::Command;
-use tokio::sync::mpsc;
-use tokio::time::{Duration, timeout};
+use tokio::process::{Child, Command};
+use tokio::sync::{Mutex, mpsc, oneshot};
 
-use super::config::{TransportConfig, TransportType};
+use super::config::{McpClientConfig, TransportType};
+use super::http::{SseTransport, StreamableHttpTransport};
+use super::protocol::{McpMessage, Request, Response, parse_progress};
+use crate::tools::ProgressSender;
 
 /// Trait for MCP transport implementations
+///
+/// Transports are shared between the task reading incoming messages and the
+/// callers sending requests, so all methods take `&self`.
 #[async_trait]
 pub trait McpTransport: Send + Sync {
     /// Send a message to the remote endpoint
-    async fn send(&mut self, message: JsonrpcMessage) -> Result<()>;
+    async fn send(&self, message: McpMessage) -> Result<()>;
 
-    /// Receive a message from the remote endpoint
-    async fn receive(&mut self) -> Result<JsonrpcMessage>;
+    /// Receive the next message from the remote endpoint, failing once the connection is closed
+    async fn receive(&self) -> Result<McpMessage>;
 
     /// Close the transport connection
-    async fn close(&mut self) -> Result<()>;
+    async fn close(&self) -> Result<()>;
 
     /// Check if the transport is connected
     fn is_connected(&self) -> bool;
@@ -35,52 +43,45 @@ pub trait McpTransport: Send + Sync {
 pub struct TransportFactory;
 
 impl TransportFactory {
-    /// Create a new transport based on the configuration
-    pub fn create(config: TransportConfig) -> Result<Box<dyn McpTransport>> {
-        match config.transport_type {
-            TransportType::Stdio => Ok(Box::new(StdioTransport::new(config)?)),
-            TransportType::Sse => Err(anyhow!("SSE transport not yet implemented")),
-            TransportType::Http => Err(anyhow!("HTTP transport not yet implemented")),
+    /// Open a transport to the server described by a client configuration
+    pub async fn connect(config: &McpClientConfig) -> Result<Box<dyn McpTransport>> {
+        match config.transport.transport_type {
+            TransportType::Stdio => Ok(Box::new(StdioTransport::spawn(config)?)),
+            TransportType::Sse => Ok(Box::new(SseTransport::connect(config).await?)),
+            TransportType::Http => Ok(Box::new(StreamableHttpTransport::new(config)?)),
         }
     }
 }
 
-/// Stdio-based transport implementation
+/// Stdio-based transport talking newline-delimited JSON to a child process
 pub struct StdioTransport {
-    child: Option<Child>,
-    sender: Option<mpsc::UnboundedSender<McpMessage>>,
-    receiver: Option<mpsc::UnboundedReceiver<McpMessage>>,
-    connected: bool,
-    timeout_duration: Duration,
+    child: Mutex<Option<Child>>,
+    sender: std::sync::Mutex<Option<mpsc::UnboundedSender<McpMessage>>>,
+    receiver: Mutex<mpsc::UnboundedReceiver<McpMessage>>,
+    connected: Arc<AtomicBool>,
 }
 
 impl StdioTransport {
-    /// Create a new stdio transport
-    pub fn new(config: TransportConfig) -> Result<Self> {
-        let timeout_duration = Duration::from_secs(config.options.timeout.unwrap_or(30));
-
-        Ok(Self {
-            child: None,
-            sender: None,
-            receiver: None,
-            connected: false,
-            timeout_duration,
-        })
-    }
-
-    /// Start a child process and establish stdio communication
-    pub async fn connect(&mut self, command: &str, args: &[String]) -> Result<()> {
-        // Start the child process
-        let mut child = Command::new(command)
-            .args(args)
+    /// Start the configured server process and establish stdio communication
+    pub fn spawn(config: &McpClientConfig) -> Result<Self> {
+        let mut command = Command::new(&config.command);
+        command
+            .args(&config.args)
+            .envs(&config.env)
             .stdin(Stdio::piped())
             .stdout(Stdio::piped())
             .stderr(Stdio::piped())
+            .kill_on_drop(true);
+        if let Some(cwd) = &config.cwd {
+            command.current_dir(cwd);
+        }
+
+        let mut child = command
             .spawn()
             .map_err(|e| anyhow!("Failed to start MCP server process: {}", e))?;
 
         // Get stdin and stdout handles
-        let stdin = child
+        let mut stdin = child
             .stdin
             .take()
             .ok_or_else(|| anyhow!("Failed to get stdin handle"))?;
@@ -88,118 +89,129 @@ impl StdioTransport {
             .stdout
             .take()
             .ok_or_else(|| anyhow!("Failed to get stdout handle"))?;
+        let stderr = child.stderr.take();
 
         // Create channels for communication
-        let (tx, rx) = mpsc::unbounded_channel();
+        let (tx, mut rx) = mpsc::unbounded_channel::<McpMessage>();
         let (response_tx, response_rx) = mpsc::unbounded_channel();
+        let connected = Arc::new(AtomicBool::new(true));
 
         // Start the writer task
-        let timeout_duration = self.timeout_duration;
+        let writer_connected = connected.clone();
         tokio::spawn(async move {
-            let mut stdin = stdin;
-            let mut rx = rx;
-
             while let Some(message) = rx.recv().await {
-                if let Ok(json) = serde_json::to_string(&message) {
-                    let line = format!("{}\n", json);
-                    if let Err(e) = stdin.write_all(line.as_bytes()).await {
-                        tracing::error!("Failed to write to MCP server stdin: {}", e);
-                        break;
-                    }
-                    if let Err(e) = stdin.flush().await {
-                        tracing::error!("Failed to flush MCP server stdin: {}", e);
-                        break;
-                    }
+                let Ok(mut line) = serde_json::to_string(&message) else {
+                    continue;
+                };
+                line.push('\n');
+                if let Err(e) = stdin.write_all(line.as_bytes()).await {
+                    tracing::error!("Failed to write to MCP server stdin: {}", e);
+                    break;
+                }
+                if let Err(e) = stdin.flush().await {
+                    tracing::error!("Failed to flush MCP server stdin: {}", e);
+                    break;
                 }
             }
+            writer_connected.store(false, Ordering::SeqCst);
         });
 
-        // Start the reader task
+        // Start the reader task; dropping the sender at EOF closes the receiver
+        let reader_connected = connected.clone();
         tokio::spawn(async move {
-            let reader = BufReader::new(stdout);
-            let mut lines = reader.lines();
+            let mut lines = BufReader::new(stdout).lines();
 
             while let Ok(Some(line)) = lines.next_line().await {
-                if let Ok(message) = serde_json::from_str::<McpMessage>(&line) {
-                    if let Err(_) = response_tx.send(message) {
-                        break; // Channel closed
+                if line.trim().is_empty() {
+                    continue;
+                }
+                match serde_json::from_str::<McpMessage>(&line) {
+                    Ok(message) => {
+                        if response_tx.send(message).is_err() {
+                            break; // Channel closed
+                        }
                     }
-                } else {
-                    tracing::warn!("Failed to parse MCP message: {}", line);
+                    Err(_) => tracing::warn!("Failed to parse MCP message: {}", line),
                 }
             }
+            reader_connected.store(false, Ordering::SeqCst);
         });
 
-        self.child = Some(child);
-        self.sender = Some(tx);
-        self.receiver = Some(response_rx);
-        self.connected = true;
+        // Servers log to stderr; keep the pipe drained so they never block on it
+        if let Some(stderr) = stderr {
+            let server = config.server_name.clone();
+            tokio::spawn(async move {
+                let mut lines = BufReader::new(stderr).lines();
+                while let Ok(Some(line)) = lines.next_line().await {
+                    tracing::debug!("[{}] {}", server, line);
+                }
+            });
+        }
 
-        Ok(())
+        Ok(Self {
+            child: Mutex::new(Some(child)),
+            sender: std::sync::Mutex::new(Some(tx)),
+            receiver: Mutex::new(response_rx),
+            connected,
+        })
     }
 }
 
 #[async_trait]
 impl McpTransport for StdioTransport {
-    async fn send(&mut self, message: McpMessage) -> Result<()> {
-        if !self.connected {
+    async fn send(&self, message: McpMessage) -> Result<()> {
+        if !self.is_connected() {
             return Err(anyhow!("Transport not connected"));
         }
 
-        if let Some(sender) = &self.sender {
-            sender
+        let sender = self.sender.lock().unwrap();
+        match sender.as_ref() {
+            Some(sender) => sender
                 .send(message)
-                .map_err(|e| anyhow!("Failed to send message: {}", e))?;
-            Ok(())
-        } else {
-            Err(anyhow!("Sender not available"))
+                .map_err(|e| anyhow!("Failed to send message: {}", e)),
+            None => Err(anyhow!("Sender not available")),
         }
     }
 
-    async fn receive(&mut self) -> Result<McpMessage> {
-        if !self.connected {
-            return Err(anyhow!("Transport not connected"));
-        }
-
-        if let Some(receiver) = &mut self.receiver {
-            timeout(self.timeout_duration, receiver.recv())
-                .await
-                .map_err(|_| anyhow!("Timeout waiting for message"))?
-                .ok_or_else(|| anyhow!("Channel closed"))
-        } else {
-            Err(anyhow!("Receiver not available"))
-        }
+    async fn receive(&self) -> Result<McpMessage> {
+        self.receiver
+            .lock()
+            .await
+            .recv()
+            .await
+            .ok_or_else(|| anyhow!("MCP server closed the connection"))
     }
 
-    async fn close(&mut self) -> Result<()> {
-        self.connected = false;
+    async fn close(&self) -> Result<()> {
+        self.connected.store(false, Ordering::SeqCst);
 
-        // Close channels
-        self.sender = None;
-        self.receiver = None;
+        // Closing stdin lets a well-behaved server exit on its own
+        self.sender.lock().unwrap().take();
 
         // Terminate child process
-        if let Some(mut child) = self.child.take() {
-            if let Err(e) = child.kill().await {
-                tracing::warn!("Failed to kill child process: {}", e);
-            }
-            if let Err(e) = child.wait().await {
-                tracing::warn!("Failed to wait for child process: {}", e);
-            }
+        if let Some(mut child) = self.child.lock().await.take()
+            && let Err(e) = child.kill().await
+        {
+            tracing::warn!("Failed to kill child process: {}", e);
         }
 
         Ok(())
     }
 
     fn is_connected(&self) -> bool {
-        self.connected
+        self.connected.load(Ordering::SeqCst)
     }
 }
 
 /// Message router for handling MCP request/response correlation
+///
+/// A request registered with a progress receiver uses its id as progress
+/// token, and `notifications/progress` carrying that token are routed to it
+/// until the request is answered or cancelled.
 #[derive(Debug)]
 pub struct MessageRouter {
-    pending_requests: std::collections::HashMap<u64, tokio::sync::oneshot::Sender<JsonrpcResponse>>,
+    pending_requests: HashMap<u64, oneshot::Sender<Response>>,
+    progress: HashMap<u64, ProgressSender>,
     next_id: u64,
 }
 
@@ -207,7 +219,8 @@ impl MessageRouter {
     /// Create a new message router
     pub fn new() -> Self {
         Self {
-            pending_requests: std::collections::HashMap::new(),
+            pending_requests: HashMap::new(),
+            progress: HashMap::new(),
             next_id: 1,
         }
     }
@@ -220,23 +233,68 @@ impl MessageRouter {
     }
 
     /// Register a pending request
-    pub fn register_request(&mut self, id: u64) -> tokio::sync::oneshot::Receiver<JsonrpcResponse> {
-        let (tx, rx) = tokio::sync::oneshot::channel();
+    pub fn register_request(&mut self, id: u64) -> oneshot::Receiver<Response> {
+        let (tx, rx) = oneshot::channel();
         self.pending_requests.insert(id, tx);
         rx
     }
 
-    /// Handle an incoming response
-    pub fn handle_response(&mut self, response: JsonrpcResponse) {
-        // Extract ID from the response and handle appropriately
-        if let Some(tx) = self.pending_requests.remove(&id) {
-            let _ = tx.send(response);
+    /// Send the progress of a pending request to `sender`
+    pub fn register_progress(&mut self, id: u64, sender: ProgressSender) {
+        self.progress.insert(id, sender);
+    }
+
+    /// Hand the parameters of a `notifications/progress` to the request they belong to.
+    ///
+    /// Returns `false` when no pending request uses the notification's token.
+    pub fn handle_progress(&mut self, params: &Value) -> bool {
+        let Some((token, progress)) = parse_progress(params) else {
+            return false;
+        };
+        let Some(sender) = token.as_u64().and_then(|id| self.progress.get(&id)) else {
+            return false;
+        };
+        // The caller may have stopped listening without cancelling the request
+        let _ = sender.send(progress);
+        true
+    }
+
+    /// Hand an incoming response to the request waiting for it.
+    ///
+    /// Returns `false` when no pending request has the response's id.
+    pub fn handle_response(&mut self, response: Response) -> bool {
+        let Some(id) = response.id.as_u64() else {
+            return false;
+        };
+        self.progress.remove(&id);
+        match self.pending_requests.remove(&id) {
+            Some(tx) => {
+                let _ = tx.send(response);
+                true
+            }
+            None => false,
         }
     }
 
-    /// Clean up expired requests
-    pub fn cleanup_expired(&mut self) {
-        // TODO: Implement cleanup based on timestamps
+    /// Stop waiting for a request, e.g. after it timed out.
+    ///
+    /// Returns whether the request was still pending, in which case the server
+    /// should be told with `notifications/cancelled`.
+    pub fn cancel(&mut self, id: u64) -> bool {
+        self.progress.remove(&id);
+        self.pending_requests.remove(&id).is_some()
+    }
+
+    /// Fail every pending request, e.g. after the connection was lost
+    pub fn fail_all(&mut self) {
+        self.progress.clear();
+        self.pending_requests.clear();
+    }
+}
+
+impl Default for MessageRouter {
+    fn default() -> Self {
+        Self::new()
     }
 }
 
@@ -244,15 +302,21 @@ impl MessageRouter {
 pub struct RequestBuilder;
 
 impl RequestBuilder {
-    /// Build a list_tools request
-    pub fn list_tools(id: u64) -> Request {
+    /// Build a request for any method
+    pub fn request(id: u64, method: &str, params: Option<Value>) -> Request {
         Request {
             id: Value::Number(id.into()),
-            method: "tools/list".to_string(),
-            params: None,
+            method: method.to_string(),
+            params,
         }
     }
 
+    /// Build a list_tools request, optionally continuing from a pagination cursor
+    pub fn list_tools(id: u64, cursor: Option<&str>) -> Request {
+        let params = cursor.map(|cursor| serde_json::json!({ "cursor": cursor }));
+        Self::request(id, "tools/list", params)
+    }
+
     /// Build a call_tool request
     pub fn call_tool(id: u64, name: &str, arguments: Option<Value>) -> Request {
         let mut params = serde_json::Map::new();
@@ -261,41 +325,23 @@ impl RequestBuilder {
             params.insert("arguments".to_string(), args);
         }
 
-        Request {
-            id: Value::Number(id.into()),
-            method: "tools/call".to_string(),
-            params: Some(Value::Object(params)),
-        }
+        Self::request(id, "tools/call", Some(Value::Object(params)))
     }
 
     /// Build a list_resources request
     pub fn list_resources(id: u64) -> Request {
-        Request {
-            id: Value::Number(id.into()),
-            method: "resources/list".to_string(),
-            params: None,
-        }
+        Self::request(id, "resources/list", None)
     }
 
     /// Build a read_resource request
     pub fn read_resource(id: u64, uri: &str) -> Request {
-        let mut params = serde_json::Map::new();
-        params.insert("uri".to_string(), Value::String(uri.to_string()));
-
-        Request {
-            id: Value::Number(id.into()),
-            method: "resources/read".to_string(),
-            params: Some(Value::Object(params)),
-        }
+        let params = serde_json::json!({ "uri": uri });
+        Self::request(id, "resources/read", Some(params))
     }
 
     /// Build an initialize request
     pub fn initialize(id: u64, client_info: Value) -> Request {
-        Request {
-            id: Value::Number(id.into()),
-            method: "initialize".to_string(),
-            params: Some(client_info),
-        }
+        Self::request(id, "initialize", Some(client_info))
     }
 }
 
@@ -319,9 +365,48 @@ mod tests {
         assert_eq!(router.pending_requests.len(), 2);
     }
 
+    #[test]
+    fn test_router_delivers_response_by_id() {
+        let mut router = MessageRouter::new();
+        let id = router.next_id();
+        let mut rx = router.register_request(id);
+
+        let unknown = Response::success(Value::from(99), Value::Null);
+        assert!(!router.handle_response(unknown));
+
+        let response = Response::success(Value::from(id), serde_json::json!({"ok": true}));
+        assert!(router.handle_response(response.clone()));
+        assert_eq!(rx.try_recv().unwrap(), response);
+        assert!(router.pending_requests.is_empty());
+
+        let id = router.next_id();
+        let rx = router.register_request(id);
+        router.fail_all();
+        assert!(rx.blocking_recv().is_err());
+    }
+
+    #[test]
+    fn test_router_routes_progress_and_cancels() {
+        let mut router = MessageRouter::new();
+        let id = router.next_id();
+        let _rx = router.register_request(id);
+        let (progress, mut updates) = tokio::sync::mpsc::unbounded_channel();
+        router.register_progress(id, progress);
+
+        let params = serde_json::json!({"progressToken": id, "progress": 1, "total": 2});
+        assert!(router.handle_progress(&params));
+        let update = updates.try_recv().unwrap();
+        assert_eq!((update.progress, update.total), (1.0, Some(2.0)));
+        assert!(!router.handle_progress(&serde_json::json!({"progressToken": 99, "progress": 1})));
+
+        assert!(router.cancel(id));
+        assert!(!router.cancel(id));
+        assert!(!router.handle_progress(&params));
+    }
+
     #[test]
     fn test_request_builder() {
-        let request = RequestBuilder::list_tools(1);
+        let request = RequestBuilder::list_tools(1, None);
         assert_eq!(request.method, "tools/list");
         assert_eq!(request.id, Value::Number(1.into()));
 
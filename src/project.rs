@@ -0,0 +1,189 @@
+//! # Project Settings
+//!
+//! Settings that belong to a project rather than to a user live in
+//! `.vega/settings.json`, found by walking up from the current working
+//! directory like the agent instruction files. The directory holding `.vega`
+//! is the project root. Without one, the directory holding the agent
+//! instructions (`VEGA.md` or `AGENTS.md`) is the root, and otherwise the
+//! current directory; every setting then has its default.
+//!
+//! ```json
+//! {
+//!   "workspace": {
+//!     "allowed_roots": ["../shared", "~/.cargo/registry"]
+//!   },
+//!   "sandbox": {
+//!     "enabled": true,
+//!     "network": false,
+//!     "writable_paths": ["~/.cargo/registry"],
+//!     "max_memory_mb": 4096,
+//!     "max_cpu_seconds": 600
+//!   }
+//! }
+//! ```
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use tracing::debug;
+
+use crate::agent_instructions::InstructionFileType;
+use crate::tools::SandboxConfig;
+
+/// Directory holding the settings of a project
+pub const SETTINGS_DIRECTORY: &str = ".vega";
+
+/// File in [`SETTINGS_DIRECTORY`] holding the settings
+pub const SETTINGS_FILE: &str = "settings.json";
+
+/// Settings of a project
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct ProjectSettings {
+    /// Directories the file tools may access
+    pub workspace: WorkspaceConfig,
+    /// Sandbox the bash tool runs commands in
+    pub sandbox: SandboxConfig,
+}
+
+/// Workspace settings of a project
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct WorkspaceConfig {
+    /// Directories besides the project root the file tools may access,
+    /// relative to the project root or starting with `~/`
+    pub allowed_roots: Vec<PathBuf>,
+}
+
+/// The project Vega works in
+#[derive(Debug, Clone)]
+pub struct Project {
+    /// Directory holding `.vega` or the agent instructions, or the starting
+    /// directory outside a project
+    pub root: PathBuf,
+    /// Settings read from `.vega/settings.json`
+    pub settings: ProjectSettings,
+}
+
+impl Project {
+    /// Find the project containing the current working directory
+    pub fn discover_current() -> Result<Self> {
+        let current_dir =
+            std::env::current_dir().context("Failed to get current working directory")?;
+        Self::discover(&current_dir)
+    }
+
+    /// Find the project containing `start`, walking up the directory tree
+    pub fn discover(start: &Path) -> Result<Self> {
+        for dir in start.ancestors() {
+            let settings_dir = dir.join(SETTINGS_DIRECTORY);
+            if settings_dir.is_dir() {
+                debug!("Found project settings in {}", settings_dir.display());
+                return Ok(Self {
+                    root: dir.to_path_buf(),
+                    settings: Self::load_settings(&settings_dir.join(SETTINGS_FILE))?,
+                });
+            }
+        }
+
+        let instruction_files = [InstructionFileType::Vega, InstructionFileType::Agents];
+        let root = start
+            .ancestors()
+            .find(|dir| {
+                instruction_files
+                    .iter()
+                    .any(|file| dir.join(file.filename()).is_file())
+            })
+            .unwrap_or(start);
+        Ok(Self {
+            root: root.to_path_buf(),
+            settings: ProjectSettings::default(),
+        })
+    }
+
+    /// A path from the settings: `~/` is the home directory and relative
+    /// paths are relative to the project root
+    pub fn resolve(&self, path: &Path) -> PathBuf {
+        resolve_setting_path(&self.root, path)
+    }
+
+    /// Read the settings file, which may be missing
+    fn load_settings(path: &Path) -> Result<ProjectSettings> {
+        if !path.exists() {
+            return Ok(ProjectSettings::default());
+        }
+        let content = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        serde_json::from_str(&content)
+            .with_context(|| format!("Invalid project settings in {}", path.display()))
+    }
+}
+
+/// A path from the settings of the project at `root`
+pub(crate) fn resolve_setting_path(root: &Path, path: &Path) -> PathBuf {
+    match (path.strip_prefix("~"), std::env::var_os("HOME")) {
+        (Ok(relative), Some(home)) => PathBuf::from(home).join(relative),
+        _ => root.join(path),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_discover_without_project() {
+        let temp_dir = TempDir::new().unwrap();
+        let project = Project::discover(temp_dir.path()).unwrap();
+
+        assert_eq!(project.root, temp_dir.path());
+        assert_eq!(project.settings, ProjectSettings::default());
+    }
+
+    #[test]
+    fn test_discover_settings_in_parent_directory() {
+        let temp_dir = TempDir::new().unwrap();
+        let settings_dir = temp_dir.path().join(SETTINGS_DIRECTORY);
+        fs::create_dir(&settings_dir).unwrap();
+        fs::write(
+            settings_dir.join(SETTINGS_FILE),
+            r#"{"sandbox": {"enabled": true, "network": false, "max_memory_mb": 1024}}"#,
+        )
+        .unwrap();
+        let sub_dir = temp_dir.path().join("src");
+        fs::create_dir(&sub_dir).unwrap();
+
+        let project = Project::discover(&sub_dir).unwrap();
+        assert_eq!(project.root, temp_dir.path());
+        assert!(project.settings.sandbox.enabled);
+        assert!(!project.settings.sandbox.network);
+        assert_eq!(project.settings.sandbox.max_memory_mb, Some(1024));
+    }
+
+    #[test]
+    fn test_invalid_settings_are_an_error() {
+        let temp_dir = TempDir::new().unwrap();
+        let settings_dir = temp_dir.path().join(SETTINGS_DIRECTORY);
+        fs::create_dir(&settings_dir).unwrap();
+        fs::write(settings_dir.join(SETTINGS_FILE), "{\"sandbox\": true}").unwrap();
+
+        assert!(Project::discover(temp_dir.path()).is_err());
+    }
+
+    #[test]
+    fn test_instructions_mark_the_root_without_settings() {
+        let temp_dir = TempDir::new().unwrap();
+        fs::write(temp_dir.path().join("VEGA.md"), "# Project").unwrap();
+        let sub_dir = temp_dir.path().join("src");
+        fs::create_dir(&sub_dir).unwrap();
+
+        let project = Project::discover(&sub_dir).unwrap();
+        assert_eq!(project.root, temp_dir.path());
+        assert_eq!(
+            project.resolve(Path::new("../shared")),
+            temp_dir.path().join("../shared")
+        );
+    }
+}
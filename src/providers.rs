@@ -8,6 +8,11 @@
 //! - **Ollama**: Local model execution with privacy and no API costs
 //! - **OpenRouter**: Cloud-based access to multiple model providers
 //! - **Anthropic**: Direct access to Claude models via Anthropic API
+//! - **OpenAI**: Direct access to GPT models via the OpenAI API
+//!
+//! Every backend is registered once in [`LLMProvider::new`] and exposes a
+//! type-erased [`AgentBuilder`] through [`LLMProvider::agent`], so callers can
+//! attach preambles and tools without matching on the provider.
 //!
 //! ## Example Usage
 //!
@@ -38,13 +43,21 @@
 //! ```
 
 use anyhow::Result;
-use rig::{client::CompletionClient, completion::Prompt, providers};
+use rig::agent::AgentBuilder;
+use rig::client::completion::{CompletionClientDyn, CompletionModelHandle};
+use rig::{completion::Prompt, providers};
 use std::fmt;
 
+/// Names of the providers accepted by [`LLMProvider::new`]
+pub const SUPPORTED_PROVIDERS: &[&str] = &["ollama", "openrouter", "anthropic", "openai"];
+
+/// Completion model type shared by agents built from any [`LLMProvider`]
+pub type ProviderModel = CompletionModelHandle<'static>;
+
 /// Enumeration of supported Large Language Model providers.
 ///
 /// This enum abstracts over different LLM providers, allowing the application
-/// to work with both local (Ollama) and cloud-based (OpenRouter, Anthropic) models
+/// to work with both local (Ollama) and cloud-based (OpenRouter, Anthropic, OpenAI) models
 /// through a unified interface.
 #[derive(Clone)]
 pub enum LLMProvider {
@@ -78,6 +91,15 @@ pub enum LLMProvider {
         /// The model name (e.g., "claude-3-5-sonnet-20241022", "claude-3-haiku-20240307")
         model: String,
     },
+    /// OpenAI provider for direct GPT model access.
+    ///
+    /// Provides direct access to OpenAI models through the OpenAI API.
+    OpenAI {
+        /// The OpenAI client instance
+        client: providers::openai::Client,
+        /// The model name (e.g., "gpt-4o", "gpt-4-turbo")
+        model: String,
+    },
 }
 
 impl LLMProvider {
@@ -85,9 +107,9 @@ impl LLMProvider {
     ///
     /// # Arguments
     ///
-    /// * `provider_name` - The name of the provider ("ollama", "openrouter", or "anthropic")
+    /// * `provider_name` - The name of the provider ("ollama", "openrouter", "anthropic", or "openai")
     /// * `model` - The model name to use
-    /// * `api_key` - Optional API key (required for OpenRouter and Anthropic, ignored for Ollama)
+    /// * `api_key` - Optional API key (required for OpenRouter, Anthropic and OpenAI, ignored for Ollama)
     ///
     /// # Returns
     ///
@@ -97,7 +119,7 @@ impl LLMProvider {
     ///
     /// Returns an error if:
     /// - The provider name is not supported
-    /// - OpenRouter or Anthropic is specified but no API key is provided
+    /// - OpenRouter, Anthropic or OpenAI is specified but no API key is provided
     /// - The provider client cannot be initialized
     ///
     /// # Examples
@@ -146,9 +168,21 @@ impl LLMProvider {
                     model: model.to_string(),
                 })
             }
+            "openai" => {
+                let api_key = api_key.ok_or_else(|| {
+                    anyhow::anyhow!("OpenAI API key is required for openai provider. Set --openai-api-key or OPENAI_API_KEY environment variable.")
+                })?;
+
+                let client = providers::openai::Client::new(api_key);
+                Ok(LLMProvider::OpenAI {
+                    client,
+                    model: model.to_string(),
+                })
+            }
             _ => Err(anyhow::anyhow!(
-                "Unsupported provider: {}. Supported providers: ollama, openrouter, anthropic",
-                provider_name
+                "Unsupported provider: {}. Supported providers: {}",
+                provider_name,
+                SUPPORTED_PROVIDERS.join(", ")
             )),
         }
     }
@@ -173,6 +207,30 @@ impl LLMProvider {
             LLMProvider::Ollama { model, .. } => model,
             LLMProvider::OpenRouter { model, .. } => model,
             LLMProvider::Anthropic { model, .. } => model,
+            LLMProvider::OpenAI { model, .. } => model,
+        }
+    }
+
+    /// Creates an agent builder for this provider's model.
+    ///
+    /// The returned builder is the same type for every provider, so preambles,
+    /// token limits and tools can be attached in one place.
+    ///
+    /// # Examples
+    ///
+    /// ```rust,no_run
+    /// use vega::providers::LLMProvider;
+    ///
+    /// let provider = LLMProvider::new("ollama", "llama3.1", None)?;
+    /// let agent = provider.agent().preamble("You are a helpful assistant.").build();
+    /// # Ok::<(), anyhow::Error>(())
+    /// ```
+    pub fn agent(&self) -> AgentBuilder<ProviderModel> {
+        match self {
+            LLMProvider::Ollama { client, model } => CompletionClientDyn::agent(client, model),
+            LLMProvider::OpenRouter { client, model } => CompletionClientDyn::agent(client, model),
+            LLMProvider::Anthropic { client, model } => CompletionClientDyn::agent(client, model),
+            LLMProvider::OpenAI { client, model } => CompletionClientDyn::agent(client, model),
         }
     }
 
@@ -219,32 +277,12 @@ impl LLMProvider {
     /// }
     /// ```
     pub async fn prompt(&self, prompt: &str, preamble: &str, max_tokens: u64) -> Result<String> {
-        let response = match self {
-            LLMProvider::Ollama { client, model } => {
-                let agent = client
-                    .agent(model)
-                    .preamble(preamble)
-                    .max_tokens(max_tokens)
-                    .build();
-                agent.prompt(prompt).await?
-            }
-            LLMProvider::OpenRouter { client, model } => {
-                let agent = client
-                    .agent(model)
-                    .preamble(preamble)
-                    .max_tokens(max_tokens)
-                    .build();
-                agent.prompt(prompt).await?
-            }
-            LLMProvider::Anthropic { client, model } => {
-                let agent = client
-                    .agent(model)
-                    .preamble(preamble)
-                    .max_tokens(max_tokens)
-                    .build();
-                agent.prompt(prompt).await?
-            }
-        };
+        let agent = self
+            .agent()
+            .preamble(preamble)
+            .max_tokens(max_tokens)
+            .build();
+        let response = agent.prompt(prompt).await?;
 
         Ok(response)
     }
@@ -265,6 +303,10 @@ impl fmt::Debug for LLMProvider {
                 .debug_struct("Anthropic")
                 .field("model", model)
                 .finish_non_exhaustive(),
+            LLMProvider::OpenAI { model, .. } => f
+                .debug_struct("OpenAI")
+                .field("model", model)
+                .finish_non_exhaustive(),
         }
     }
 }
@@ -331,6 +373,23 @@ mod tests {
         assert!(error.to_string().contains("Anthropic API key is required"));
     }
 
+    #[test]
+    fn test_openai_provider_creation() {
+        let provider = LLMProvider::new("openai", "gpt-4o", Some("test-api-key"));
+        assert!(matches!(provider, Ok(LLMProvider::OpenAI { .. })));
+
+        let error = LLMProvider::new("openai", "gpt-4o", None).unwrap_err();
+        assert!(error.to_string().contains("OpenAI API key is required"));
+    }
+
+    #[test]
+    fn test_every_supported_provider_builds_an_agent() {
+        for name in SUPPORTED_PROVIDERS {
+            let provider = LLMProvider::new(name, "model", Some("test-key")).unwrap();
+            let _agent = provider.agent().preamble("test").build();
+        }
+    }
+
     #[test]
     fn test_unsupported_provider() {
         let provider = LLMProvider::new("unsupported", "model", None);
@@ -1,8 +1,14 @@
 use std::io::{self, Write};
 use std::sync::{Arc, Mutex as StdMutex};
-use tokio::sync::{Mutex, broadcast};
+use tokio::sync::{Mutex, broadcast, mpsc};
+use tokio::task::JoinHandle;
 use tokio::time::{Duration, Instant};
 
+use crate::tools::{CommandEvent, CommandEventSender, OutputStream};
+
+/// Sender for incremental response text as it arrives from the LLM
+pub type TextDeltaSender = mpsc::UnboundedSender<String>;
+
 /// Progress phases for LLM operations
 #[derive(Debug, Clone)]
 pub enum ProgressPhase {
@@ -48,6 +54,9 @@ pub struct ProgressUpdate {
 /// Global pause state for streaming progress
 static PROGRESS_PAUSED: StdMutex<bool> = StdMutex::new(false);
 
+/// Updates of the progress indicator currently displayed, for tools reporting their progress
+static ACTIVE_PROGRESS: StdMutex<Option<broadcast::Sender<ProgressUpdate>>> = StdMutex::new(None);
+
 /// Streaming progress indicator for LLM operations
 pub struct StreamingProgress {
     sender: broadcast::Sender<ProgressUpdate>,
@@ -73,9 +82,14 @@ impl StreamingProgress {
     }
 
     /// Start the visual progress indicator
+    ///
+    /// Until it is stopped, progress reported with [`report_progress`] is shown by it.
     pub async fn start_indicator(&self) -> tokio::task::JoinHandle<()> {
         let mut receiver = self.sender.subscribe();
         let start_time = self.start_time;
+        if let Ok(mut active) = ACTIVE_PROGRESS.lock() {
+            *active = Some(self.sender.clone());
+        }
 
         tokio::spawn(async move {
             let frames = ["⠋", "⠙", "⠹", "⠸", "⠼", "⠴", "⠦", "⠧", "⠇", "⠏"];
@@ -129,8 +143,11 @@ impl StreamingProgress {
                 // Check if we should stop (no more phases)
                 tokio::select! {
                     _ = tokio::time::sleep(Duration::from_millis(100)) => {},
-                    _ = receiver.recv() => {
-                        // Continue with new update
+                    update = receiver.recv() => {
+                        if let Ok(update) = update {
+                            current_display_phase = update.phase;
+                            custom_message = update.message;
+                        }
                     }
                 }
             }
@@ -139,6 +156,13 @@ impl StreamingProgress {
 
     /// Stop the progress indicator and clear the line
     pub fn stop(&self) {
+        if let Ok(mut active) = ACTIVE_PROGRESS.lock()
+            && active
+                .as_ref()
+                .is_some_and(|sender| sender.same_channel(&self.sender))
+        {
+            *active = None;
+        }
         print!("\r\x1b[K"); // Clear the current line
         io::stdout().flush().unwrap();
     }
@@ -150,6 +174,66 @@ impl Default for StreamingProgress {
     }
 }
 
+/// Hands a running progress indicator off to live response text.
+///
+/// Phase updates are shown until the first text delta arrives; from then on the
+/// indicator is stopped and deltas are forwarded to the optional sender.
+pub struct ProgressHandoff<'a> {
+    progress: &'a StreamingProgress,
+    indicator: Option<JoinHandle<()>>,
+    deltas: Option<TextDeltaSender>,
+}
+
+impl<'a> ProgressHandoff<'a> {
+    /// Create a handoff for a progress indicator started with `start_indicator`
+    pub fn new(
+        progress: &'a StreamingProgress,
+        indicator: JoinHandle<()>,
+        deltas: Option<TextDeltaSender>,
+    ) -> Self {
+        Self {
+            progress,
+            indicator: Some(indicator),
+            deltas,
+        }
+    }
+
+    /// Whether the progress indicator is still being displayed
+    pub fn is_active(&self) -> bool {
+        self.indicator.is_some()
+    }
+
+    /// Update the progress phase if no text has been streamed yet
+    pub async fn update_phase(&self, phase: ProgressPhase) {
+        if self.is_active() {
+            self.progress.update_phase(phase, None).await;
+        }
+    }
+
+    /// Forward a text delta, stopping the progress indicator on the first one
+    pub fn push_text(&mut self, text: String) {
+        self.stop_indicator();
+        if let Some(ref deltas) = self.deltas {
+            // The receiver going away only means nobody is listening anymore
+            let _ = deltas.send(text);
+        }
+    }
+
+    /// Stop the progress indicator if it is still running
+    pub fn stop_indicator(&mut self) {
+        if let Some(indicator) = self.indicator.take() {
+            indicator.abort();
+            self.progress.stop();
+        }
+    }
+}
+
+impl Drop for ProgressHandoff<'_> {
+    fn drop(&mut self) {
+        self.stop_indicator();
+    }
+}
+
 /// Convenience function to show a simple progress indicator
 pub async fn show_simple_progress(message: &str, emoji: &str) -> tokio::task::JoinHandle<()> {
     let message = message.to_string();
@@ -201,6 +285,21 @@ pub fn stop_progress() {
     io::stdout().flush().unwrap();
 }
 
+/// Show a message on the progress indicator currently displayed, if any
+///
+/// Tools use this to surface their progress while they run, e.g. the progress
+/// an MCP server reports for a long tool call.
+pub fn report_progress(phase: ProgressPhase, message: String) {
+    if let Ok(active) = ACTIVE_PROGRESS.lock()
+        && let Some(sender) = active.as_ref()
+    {
+        let _ = sender.send(ProgressUpdate {
+            phase,
+            message: Some(message),
+        });
+    }
+}
+
 /// Pause progress indicators for user interaction
 pub fn pause_progress() {
     if let Ok(mut paused) = PROGRESS_PAUSED.lock() {
@@ -216,3 +315,47 @@ pub fn resume_progress() {
         *paused = false;
     }
 }
+
+/// Print the output of the commands the agent runs as it arrives
+///
+/// The progress indicator is paused while a command runs, so its lines are
+/// not mixed with the output.
+pub fn print_command_output() -> CommandEventSender {
+    let (sender, mut events) = mpsc::unbounded_channel();
+    tokio::spawn(async move {
+        while let Some(event) = events.recv().await {
+            match event {
+                CommandEvent::Started { command, .. } => {
+                    pause_progress();
+                    println!("\x1b[2m$ {}\x1b[0m", command);
+                }
+                CommandEvent::Output {
+                    stream: OutputStream::Stdout,
+                    line,
+                    ..
+                } => println!("\x1b[2m{}\x1b[0m", line),
+                CommandEvent::Output {
+                    stream: OutputStream::Stderr,
+                    line,
+                    ..
+                } => println!("\x1b[2;31m{}\x1b[0m", line),
+                CommandEvent::Finished {
+                    exit_code,
+                    timed_out,
+                    ..
+                } => {
+                    if timed_out {
+                        println!("\x1b[91mTimed out\x1b[0m");
+                    } else if exit_code.is_none() {
+                        println!("\x1b[91mCancelled\x1b[0m");
+                    } else if let Some(code) = exit_code.filter(|code| *code != 0) {
+                        println!("\x1b[91mExit code {}\x1b[0m", code);
+                    }
+                    resume_progress();
+                }
+            }
+            let _ = io::stdout().flush();
+        }
+    });
+    sender
+}
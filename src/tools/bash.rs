@@ -3,9 +3,25 @@ use rig::completion::ToolDefinition;
 use rig::tool::Tool;
 use serde::{Deserialize, Serialize};
 use serde_json::json;
-use std::process::Command;
+use std::process::Stdio;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::process::{Child, Command};
+use tokio::sync::mpsc;
 
-use super::ToolError;
+use super::output::{
+    CapturedOutput, CommandEventSender, OutputLimit, OutputStream, log_directory, read_lines,
+};
+use super::sandbox::Sandbox;
+use super::shell::PersistentShell;
+use super::workspace::Workspace;
+use super::{ProgressSender, ToolError};
+
+/// How long a command may take to exit after SIGTERM before it is killed
+const KILL_GRACE_PERIOD: Duration = Duration::from_secs(2);
+
+/// How long output still arriving after a command exited is waited for
+pub(super) const EXIT_DRAIN_PERIOD: Duration = Duration::from_millis(100);
 
 #[derive(Deserialize)]
 pub struct BashArgs {
@@ -14,6 +30,9 @@ pub struct BashArgs {
     pub timeout_seconds: u64,
     #[serde(default, deserialize_with = "deserialize_optional_string")]
     pub working_directory: Option<String>,
+    /// Start a fresh persistent shell before running the command
+    #[serde(default)]
+    pub reset_shell: bool,
 }
 
 fn deserialize_optional_string<'de, D>(deserializer: D) -> Result<Option<String>, D::Error>
@@ -35,43 +54,139 @@ pub struct BashOutput {
     pub exit_code: i32,
     pub command: String,
     pub success: bool,
+    /// Whether the command was killed for running longer than its timeout
+    pub timed_out: bool,
+    /// Whether the persistent shell ended with the command, so the next
+    /// command starts in a fresh one
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub shell_exited: bool,
+    /// File holding the complete output when `stdout` or `stderr` was truncated
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub full_output: Option<String>,
 }
 
 #[derive(Deserialize, Serialize)]
-pub struct BashTool;
+pub struct BashTool {
+    /// Receives a report for every line of output while the command runs
+    #[serde(skip)]
+    progress: Option<ProgressSender>,
+    /// Shell the commands run in, keeping its directory and environment between calls
+    #[serde(skip)]
+    shell: Option<Arc<PersistentShell>>,
+    /// Receives the output of the commands as they run, to show it live
+    #[serde(skip)]
+    events: Option<CommandEventSender>,
+    /// How much of the output is returned
+    #[serde(skip)]
+    output_limit: OutputLimit,
+    /// Confines the commands to the project
+    #[serde(skip)]
+    sandbox: Option<Sandbox>,
+    /// Workspace the full output logs are kept in
+    #[serde(skip)]
+    workspace: Option<Workspace>,
+}
 
 impl BashTool {
     pub fn new() -> Self {
-        Self
+        Self {
+            progress: None,
+            shell: None,
+            events: None,
+            output_limit: OutputLimit::default(),
+            sandbox: None,
+            workspace: None,
+        }
+    }
+
+    /// Run the commands in a sandbox
+    pub fn with_sandbox(mut self, sandbox: Sandbox) -> Self {
+        self.sandbox = Some(sandbox);
+        self
+    }
+
+    /// Send the output of the commands to a channel as they run
+    pub fn with_events(mut self, events: CommandEventSender) -> Self {
+        self.events = Some(events);
+        self
+    }
+
+    /// Cap the output returned for each command, saving the rest to a file
+    pub fn with_output_limit(mut self, limit: OutputLimit) -> Self {
+        self.output_limit = limit;
+        self
+    }
+
+    /// Keep the full output logs in a workspace, where the file tools can read them
+    pub fn with_workspace(mut self, workspace: Workspace) -> Self {
+        self.workspace = Some(workspace);
+        self
+    }
+
+    /// Start capturing the output of a command
+    pub(super) fn capture(&self, command: &str) -> CapturedOutput<'_> {
+        CapturedOutput::new(
+            command,
+            self.output_limit,
+            &log_directory(self.workspace.as_ref()),
+            self.progress.as_ref(),
+            self.events.as_ref(),
+        )
+    }
+
+    /// Run the commands in a persistent shell instead of a new one per call
+    pub fn with_shell(mut self, shell: Arc<PersistentShell>) -> Self {
+        self.shell = Some(shell);
+        self
+    }
+
+    /// Report the lines the command prints as progress
+    pub fn with_progress(mut self, progress: ProgressSender) -> Self {
+        self.progress = Some(progress);
+        self
+    }
+
+    /// Description of the tool, depending on whether the shell persists
+    fn description(&self) -> String {
+        let mut description = "Executes shell commands and returns the output. Includes basic safety checks to prevent dangerous operations. Commands running longer than the timeout are killed and return their output so far with timed_out set. Long output is cut to its first and last lines; full_output then names a file holding all of it, which read_file can read with a line_range.".to_string();
+        if self.shell.is_some() {
+            description.push_str(" Commands run in a persistent shell, so the working directory and environment carry over between calls; set reset_shell to start over.");
+        }
+        if let Some(ref sandbox) = self.sandbox {
+            description.push_str(&format!(
+                " Commands run in a sandbox: they can only write inside {} and /tmp, which is private to each command.",
+                sandbox.root().display()
+            ));
+        }
+        description
     }
 
     /// Execute a shell command with timeout and safety checks
     async fn execute_command(&self, args: &BashArgs) -> Result<BashOutput, ToolError> {
         // Basic safety checks - prevent obviously dangerous commands
-        let dangerous_patterns = [
-            "rm -rf /",
-            ":(){ :|:& };:", // fork bomb
-            "dd if=/dev/zero",
-            "mkfs",
-            "format",
-            "> /dev/",
-            "shutdown",
-            "reboot",
-            "halt",
-        ];
-
-        let command_lower = args.command.to_lowercase();
-        for pattern in &dangerous_patterns {
-            if command_lower.contains(pattern) {
-                return Err(ToolError::InvalidInput(format!(
-                    "Command contains potentially dangerous pattern: {}",
-                    pattern
-                )));
+        if let Some(pattern) = dangerous_pattern(&args.command) {
+            return Err(ToolError::InvalidInput(format!(
+                "Command contains potentially dangerous pattern: {}",
+                pattern
+            )));
+        }
+
+        if let Some(ref shell) = self.shell {
+            if args.reset_shell {
+                shell.reset().await;
             }
+            let output = self.capture(&args.command);
+            return shell.run(args, self.sandbox.as_ref(), output).await;
         }
 
         // Create the command
-        let mut cmd = if cfg!(target_os = "windows") {
+        let mut cmd = if let Some(ref sandbox) = self.sandbox {
+            sandbox.command(
+                "/bin/sh",
+                &["-c", &args.command],
+                args.working_directory.as_deref(),
+            )?
+        } else if cfg!(target_os = "windows") {
             let mut cmd = Command::new("cmd");
             cmd.args(["/C", &args.command]);
             cmd
@@ -89,24 +204,295 @@ impl BashTool {
             }
         }
 
-        // Execute the command
-        let output = tokio::task::spawn_blocking(move || cmd.output())
-            .await
-            .map_err(|e| ToolError::Command(format!("Failed to spawn command: {}", e)))?
-            .map_err(|e| ToolError::Command(format!("Command execution failed: {}", e)))?;
+        // Run the command in its own process group so everything it starts
+        // can be stopped together
+        #[cfg(unix)]
+        cmd.process_group(0);
 
-        let stdout = String::from_utf8_lossy(&output.stdout).to_string();
-        let stderr = String::from_utf8_lossy(&output.stderr).to_string();
-        let exit_code = output.status.code().unwrap_or(-1);
-        let success = output.status.success();
+        // Execute the command; dropping the call (e.g. when the user or an MCP
+        // client cancels it) kills its process group
+        let mut child = cmd
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true)
+            .spawn()
+            .map_err(|e| ToolError::Command(format!("Failed to spawn command: {}", e)))?;
+        let mut group = ProcessGroup::new(&child);
 
-        Ok(BashOutput {
-            stdout,
-            stderr,
-            exit_code,
-            command: args.command.clone(),
-            success,
+        // Both pipes are drained at once so the command never blocks on a full one
+        let (lines_tx, mut lines) = mpsc::unbounded_channel();
+        if let Some(stdout) = child.stdout.take() {
+            tokio::spawn(read_lines(stdout, OutputStream::Stdout, lines_tx.clone()));
+        }
+        if let Some(stderr) = child.stderr.take() {
+            tokio::spawn(read_lines(stderr, OutputStream::Stderr, lines_tx));
+        }
+
+        // The timeout applies to the command exiting rather than to its pipes
+        // closing, which processes it left in the background may hold open
+        let mut output = self.capture(&args.command);
+        let limit = Duration::from_secs(args.timeout_seconds);
+        let exited = tokio::time::timeout(limit, async {
+            let mut exited = std::pin::pin!(child.wait());
+            loop {
+                tokio::select! {
+                    status = &mut exited => break status,
+                    Some((stream, line)) = lines.recv() => output.push(stream, &line),
+                }
+            }
         })
+        .await;
+
+        let timed_out = exited.is_err();
+        let status = match exited {
+            Ok(status) => {
+                // Stop whatever still holds the pipes once their output is read
+                let drained =
+                    tokio::time::timeout(EXIT_DRAIN_PERIOD, output.collect(&mut lines)).await;
+                if drained.is_err() {
+                    group
+                        .terminate(&mut child, output.collect(&mut lines))
+                        .await;
+                }
+                status
+            }
+            Err(_) => {
+                // Keep whatever the command prints while it shuts down
+                group
+                    .terminate(&mut child, output.collect(&mut lines))
+                    .await;
+                child.wait().await
+            }
+        }
+        .map_err(|e| ToolError::Command(format!("Command execution failed: {}", e)))?;
+        group.release();
+
+        Ok(output.finish(&args.command, status.code().unwrap_or(-1), timed_out, false))
+    }
+}
+
+/// Programs refused outright, whatever their arguments
+const DANGEROUS_PROGRAMS: [&str; 6] = ["shutdown", "reboot", "halt", "poweroff", "mkfs", "format"];
+
+/// Words that run the rest of a command as another command
+const COMMAND_PREFIXES: [&str; 6] = ["sudo", "env", "exec", "nohup", "command", "time"];
+
+/// Devices output may be redirected to
+const HARMLESS_DEVICES: [&str; 4] = ["/dev/null", "/dev/stdout", "/dev/stderr", "/dev/tty"];
+
+/// The dangerous pattern a command contains, if any
+///
+/// The command is split into simple commands which are checked word by word,
+/// so that e.g. `cargo fmt` or `echo reboot` are not mistaken for `format` or
+/// `reboot`.
+fn dangerous_pattern(command: &str) -> Option<&'static str> {
+    let compact: String = command.split_whitespace().collect();
+    if compact.contains(":(){:|:&};:") {
+        return Some("fork bomb");
+    }
+
+    for simple in simple_commands(command) {
+        if redirects_to_device(simple) {
+            return Some("> /dev/");
+        }
+
+        let mut words = simple
+            .split_whitespace()
+            .map(|word| word.trim_matches(['\'', '"']))
+            .skip_while(|word| {
+                COMMAND_PREFIXES.contains(word)
+                    || word.starts_with('-')
+                    || (word.contains('=') && !word.starts_with('='))
+            });
+        let Some(program) = words.next() else {
+            continue;
+        };
+        let program = program.rsplit('/').next().unwrap_or(program);
+        let args: Vec<&str> = words.collect();
+
+        if let Some(dangerous) = DANGEROUS_PROGRAMS.into_iter().find(|name| {
+            program == *name
+                || program
+                    .strip_prefix(name)
+                    .is_some_and(|rest| rest.starts_with('.'))
+        }) {
+            return Some(dangerous);
+        }
+        match program {
+            "rm" => {
+                let recursive = args.iter().any(|arg| {
+                    *arg == "--recursive"
+                        || (arg.starts_with('-')
+                            && !arg.starts_with("--")
+                            && arg.contains(['r', 'R']))
+                });
+                if recursive && args.iter().any(|arg| matches!(*arg, "/" | "/*")) {
+                    return Some("rm -rf /");
+                }
+            }
+            "dd" if args.iter().any(|arg| arg.starts_with("of=/dev/")) => {
+                return Some("dd of=/dev/");
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+/// The simple commands a command line is made of, split at `;`, `&&`, `||`,
+/// `|`, `&`, newlines, subshells and command substitutions outside quotes
+pub(super) fn simple_commands(command: &str) -> Vec<&str> {
+    let mut commands = Vec::new();
+    let mut start = 0;
+    // Open quotes and `$(` substitutions, innermost last
+    let mut nesting = Vec::new();
+    let mut previous = None;
+    let mut chars = command.char_indices().peekable();
+    while let Some((index, c)) = chars.next() {
+        let separates = match (nesting.last().copied(), c) {
+            (Some('\''), '\'') | (Some('"'), '"') => {
+                nesting.pop();
+                false
+            }
+            (Some('\''), _) => false,
+            // Command substitutions still run inside double quotes
+            (Some('"'), '`') => true,
+            (_, '(') if previous == Some('$') => {
+                nesting.push('(');
+                true
+            }
+            (Some('"'), _) => false,
+            (_, '\'' | '"') => {
+                nesting.push(c);
+                false
+            }
+            (Some('('), ')') => {
+                nesting.pop();
+                true
+            }
+            // `2>&1` and `&>file` are redirections, not background jobs
+            (_, '&') => {
+                !matches!(previous, Some('>' | '<'))
+                    && chars.peek().is_none_or(|(_, next)| *next != '>')
+            }
+            (_, ';' | '|' | '\n' | '(' | ')' | '`') => true,
+            _ => false,
+        };
+        if separates {
+            commands.push(&command[start..index]);
+            start = index + c.len_utf8();
+        }
+        previous = Some(c);
+    }
+    commands.push(&command[start..]);
+    commands
+        .into_iter()
+        .map(str::trim)
+        .filter(|command| !command.is_empty())
+        .collect()
+}
+
+/// Whether a simple command redirects its output to a device such as a disk
+fn redirects_to_device(command: &str) -> bool {
+    command.match_indices('>').any(|(index, _)| {
+        let target = command[index + 1..].trim_start_matches('>').trim_start();
+        let target = target.split_whitespace().next().unwrap_or_default();
+        let target = target.trim_matches(['\'', '"']);
+        target.starts_with("/dev/")
+            && !HARMLESS_DEVICES.contains(&target)
+            && !target.starts_with("/dev/fd/")
+    })
+}
+
+/// The process group a command runs in, stopped when dropped before the
+/// command has exited
+#[derive(Debug)]
+pub(super) struct ProcessGroup {
+    #[cfg(unix)]
+    id: Option<libc::pid_t>,
+}
+
+impl ProcessGroup {
+    /// The group led by a freshly spawned command
+    pub(super) fn new(child: &Child) -> Self {
+        #[cfg(unix)]
+        {
+            Self {
+                id: child.id().map(|id| id as libc::pid_t),
+            }
+        }
+        #[cfg(not(unix))]
+        {
+            let _ = child;
+            Self {}
+        }
+    }
+
+    /// Ask every process in the group to stop, killing those still running
+    /// once `exited` completes or the grace period is over
+    pub(super) async fn terminate(&mut self, child: &mut Child, exited: impl Future<Output = ()>) {
+        self.signal(Signal::Terminate);
+        let _ = tokio::time::timeout(KILL_GRACE_PERIOD, exited).await;
+        self.signal(Signal::Kill);
+        let _ = child.start_kill();
+    }
+
+    /// Forget the group once its leader has been reaped, as its id may be reused
+    pub(super) fn release(&mut self) {
+        #[cfg(unix)]
+        {
+            self.id = None;
+        }
+    }
+
+    #[cfg(unix)]
+    fn signal(&self, signal: Signal) {
+        if let Some(id) = self.id {
+            signal.send(id);
+        }
+    }
+
+    #[cfg(not(unix))]
+    fn signal(&self, _signal: Signal) {}
+}
+
+impl Drop for ProcessGroup {
+    fn drop(&mut self) {
+        #[cfg(unix)]
+        if let Some(id) = self.id.take() {
+            Signal::Terminate.send(id);
+            match tokio::runtime::Handle::try_current() {
+                Ok(runtime) => {
+                    runtime.spawn(async move {
+                        tokio::time::sleep(KILL_GRACE_PERIOD).await;
+                        Signal::Kill.send(id);
+                    });
+                }
+                Err(_) => Signal::Kill.send(id),
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Signal {
+    Terminate,
+    Kill,
+}
+
+impl Signal {
+    /// Send the signal to every process in a group
+    #[cfg(unix)]
+    fn send(self, group: libc::pid_t) {
+        let signal = match self {
+            Signal::Terminate => libc::SIGTERM,
+            Signal::Kill => libc::SIGKILL,
+        };
+        // SAFETY: kill only takes plain integers; a negative pid addresses a group
+        unsafe {
+            libc::kill(-group, signal);
+        }
     }
 }
 
@@ -125,7 +511,7 @@ impl Tool for BashTool {
     async fn definition(&self, _prompt: String) -> ToolDefinition {
         ToolDefinition {
             name: Self::NAME.to_string(),
-            description: "Executes shell commands and returns the output. Includes basic safety checks to prevent dangerous operations.".to_string(),
+            description: self.description(),
             parameters: json!({
                 "type": "object",
                 "properties": {
@@ -141,6 +527,11 @@ impl Tool for BashTool {
                     "working_directory": {
                         "type": "string",
                         "description": "Working directory for the command (optional)"
+                    },
+                    "reset_shell": {
+                        "type": "boolean",
+                        "description": "Start a fresh shell before running the command, discarding the directory and environment left by earlier commands (default: false)",
+                        "default": false
                     }
                 },
                 "required": ["command"]
@@ -184,6 +575,7 @@ mod tests {
             command: "echo 'hello world'".to_string(),
             timeout_seconds: 5,
             working_directory: None,
+            reset_shell: false,
         };
 
         let result = tool.call(args).await;
@@ -201,6 +593,7 @@ mod tests {
             command: "rm -rf /".to_string(),
             timeout_seconds: 5,
             working_directory: None,
+            reset_shell: false,
         };
 
         let result = tool.call(args).await;
@@ -212,4 +605,134 @@ mod tests {
             panic!("Expected InvalidInput error");
         }
     }
+
+    #[test]
+    fn test_dangerous_patterns() {
+        for command in [
+            "rm -rf /",
+            "sudo rm -fr /*",
+            "cd /tmp && rm --recursive --force /",
+            ":(){ :|:& };:",
+            "dd if=/dev/zero of=/dev/sda bs=1M",
+            "echo x > /dev/sda",
+            "/sbin/shutdown -h now",
+            "sleep 1; reboot",
+            "mkfs.ext4 /dev/sdb1",
+        ] {
+            assert!(
+                dangerous_pattern(command).is_some(),
+                "{command} was allowed"
+            );
+        }
+
+        for command in [
+            "cargo fmt --check --message-format short",
+            "echo reboot; git log --format=%H",
+            "rm -rf ./target /tmp/build",
+            "dd if=/dev/zero of=disk.img count=1",
+            "make > /dev/null 2>&1",
+            "grep -r halting src/ 2> /dev/null",
+            "systemctl status --no-pager | head",
+        ] {
+            assert_eq!(dangerous_pattern(command), None, "{command} was refused");
+        }
+    }
+
+    #[test]
+    fn test_simple_commands() {
+        assert_eq!(
+            simple_commands("cd src && cargo test 2>&1 | tail -n 5; echo 'a; b' &"),
+            ["cd src", "cargo test 2>&1", "tail -n 5", "echo 'a; b'"]
+        );
+        assert_eq!(
+            simple_commands("echo \"$(git rev-parse HEAD)\" &>/dev/null"),
+            ["echo \"$", "git rev-parse HEAD", "\" &>/dev/null"]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_output_is_reported_as_progress() {
+        let (progress, mut updates) = mpsc::unbounded_channel();
+        let tool = BashTool::new().with_progress(progress);
+        let args = BashArgs {
+            command: "echo first; echo oops >&2".to_string(),
+            timeout_seconds: 5,
+            working_directory: None,
+            reset_shell: false,
+        };
+
+        let output = tool.call(args).await.unwrap();
+        assert_eq!(output.stdout, "first\n");
+        assert_eq!(output.stderr, "oops\n");
+
+        let first = updates.recv().await.unwrap();
+        assert_eq!(first.progress, 1.0);
+        assert!(first.message.is_some());
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_timeout_kills_process_group() {
+        let dir = tempfile::tempdir().unwrap();
+        let marker = dir.path().join("marker");
+        let tool = BashTool::new();
+        let args = BashArgs {
+            command: format!(
+                "(sleep 2; touch {}) & echo partial; sleep 30",
+                marker.display()
+            ),
+            timeout_seconds: 1,
+            working_directory: None,
+            reset_shell: false,
+        };
+
+        let started = std::time::Instant::now();
+        let output = tool.call(args).await.unwrap();
+        assert!(started.elapsed() < Duration::from_secs(10));
+        assert!(output.timed_out);
+        assert!(!output.success);
+        assert_eq!(output.stdout, "partial\n");
+
+        tokio::time::sleep(Duration::from_secs(3)).await;
+        assert!(!marker.exists(), "background process survived the timeout");
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_background_process_does_not_hold_the_command() {
+        let tool = BashTool::new();
+        let args = BashArgs {
+            command: "sleep 30 & echo started".to_string(),
+            timeout_seconds: 10,
+            working_directory: None,
+            reset_shell: false,
+        };
+
+        let started = std::time::Instant::now();
+        let output = tool.call(args).await.unwrap();
+        assert!(started.elapsed() < Duration::from_secs(5));
+        assert!(!output.timed_out);
+        assert!(output.success);
+        assert_eq!(output.stdout, "started\n");
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_cancelled_command_is_killed() {
+        let dir = tempfile::tempdir().unwrap();
+        let marker = dir.path().join("marker");
+        let tool = BashTool::new();
+        let args = BashArgs {
+            command: format!("(sleep 1; touch {}) & wait", marker.display()),
+            timeout_seconds: 30,
+            working_directory: None,
+            reset_shell: false,
+        };
+
+        let cancelled = tokio::time::timeout(Duration::from_millis(300), tool.call(args)).await;
+        assert!(cancelled.is_err());
+
+        tokio::time::sleep(Duration::from_secs(2)).await;
+        assert!(!marker.exists(), "background process survived cancellation");
+    }
 }
@@ -0,0 +1,221 @@
+//! Checkpoints of the files the agent changes
+//!
+//! A [`TurnCheckpoint`] given to [`EditFileTool::with_checkpoint`] records each
+//! file in the context store before the tool first changes it during a turn.
+//! [`restore_checkpoint`] rolls the files back to how they were before a
+//! checkpoint, removing the files the agent created since along with the
+//! directories created for them, unless something else was put there.
+//!
+//! Only changes made through `edit_file` are recorded; files changed by the
+//! commands the agent runs are not.
+//!
+//! [`EditFileTool::with_checkpoint`]: super::EditFileTool::with_checkpoint
+
+use anyhow::{Context, Result};
+use std::io;
+use std::path::{Path, PathBuf};
+use tokio::fs;
+use uuid::Uuid;
+
+use super::ToolError;
+use crate::context::{ContextStore, FileSnapshot};
+
+/// Records the files changed during one turn of a session
+#[derive(Clone)]
+pub struct TurnCheckpoint {
+    context: ContextStore,
+    session_id: String,
+    /// Identifies the turn, whose checkpoint is created with its first change
+    turn_id: String,
+    prompt: String,
+}
+
+impl TurnCheckpoint {
+    /// A checkpoint for a new turn answering `prompt`
+    pub fn new(context: ContextStore, session_id: &str, prompt: &str) -> Self {
+        Self {
+            context,
+            session_id: session_id.to_string(),
+            turn_id: Uuid::new_v4().to_string(),
+            prompt: prompt.to_string(),
+        }
+    }
+
+    /// Record the current content of a file about to be changed, and the
+    /// missing directories that will be created for it
+    pub(super) async fn record(&self, path: &Path) -> Result<(), ToolError> {
+        let path = std::path::absolute(path)?;
+        let content = match fs::read(&path).await {
+            Ok(content) => Some(content),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => None,
+            Err(e) => return Err(ToolError::Io(e)),
+        };
+
+        if content.is_none() {
+            for directory in path.ancestors().skip(1) {
+                if fs::try_exists(directory).await? {
+                    break;
+                }
+                self.save(FileSnapshot {
+                    path: directory.to_path_buf(),
+                    content: None,
+                    directory: true,
+                })
+                .await?;
+            }
+        }
+        self.save(FileSnapshot {
+            path,
+            content,
+            directory: false,
+        })
+        .await
+    }
+
+    async fn save(&self, snapshot: FileSnapshot) -> Result<(), ToolError> {
+        self.context
+            .record_file_snapshot(&self.session_id, &self.turn_id, &self.prompt, &snapshot)
+            .await
+            .map_err(|e| {
+                ToolError::Io(io::Error::other(format!(
+                    "Failed to record checkpoint: {e}"
+                )))
+            })
+    }
+}
+
+/// A file put back by [`restore_checkpoint`]
+#[derive(Debug, Clone, PartialEq)]
+pub enum RestoredFile {
+    /// The file was given its earlier content
+    Written(PathBuf),
+    /// The file or directory did not exist before and was removed
+    Removed(PathBuf),
+}
+
+/// Roll the files back to how they were before checkpoint `id` of a session,
+/// then delete that checkpoint and the ones after it
+pub async fn restore_checkpoint(
+    context: &ContextStore,
+    session_id: &str,
+    id: i64,
+) -> Result<Vec<RestoredFile>> {
+    let mut restored = Vec::new();
+    let mut directories = Vec::new();
+    for snapshot in context.checkpoint_snapshots(session_id, id).await? {
+        let path = snapshot.path;
+        if snapshot.directory {
+            directories.push(path);
+            continue;
+        }
+        match snapshot.content {
+            Some(content) => {
+                if let Some(parent) = path.parent() {
+                    fs::create_dir_all(parent).await?;
+                }
+                fs::write(&path, content)
+                    .await
+                    .with_context(|| format!("Failed to restore {}", path.display()))?;
+                restored.push(RestoredFile::Written(path));
+            }
+            None => match fs::remove_file(&path).await {
+                Ok(()) => restored.push(RestoredFile::Removed(path)),
+                Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+                Err(e) => {
+                    return Err(e).with_context(|| format!("Failed to remove {}", path.display()));
+                }
+            },
+        }
+    }
+
+    // Created directories are removed once emptied, the deepest first; those
+    // holding anything else are kept
+    directories.sort_by_key(|path| std::cmp::Reverse(path.components().count()));
+    for path in directories {
+        match fs::remove_dir(&path).await {
+            Ok(()) => restored.push(RestoredFile::Removed(path)),
+            Err(e)
+                if matches!(
+                    e.kind(),
+                    io::ErrorKind::NotFound
+                        | io::ErrorKind::DirectoryNotEmpty
+                        | io::ErrorKind::NotADirectory
+                ) => {}
+            Err(e) => {
+                return Err(e).with_context(|| format!("Failed to remove {}", path.display()));
+            }
+        }
+    }
+
+    context.delete_checkpoints(session_id, id).await?;
+    Ok(restored)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tools::EditFileTool;
+    use crate::tools::edit_file::EditFileArgs;
+    use rig::tool::Tool;
+    use tempfile::TempDir;
+
+    fn write(path: &Path, content: &str) -> EditFileArgs {
+        EditFileArgs {
+            path: path.to_string_lossy().into_owned(),
+            content: Some(content.to_string()),
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_restore_rolls_back_later_turns() {
+        let temp_dir = TempDir::new().unwrap();
+        let context = ContextStore::new(temp_dir.path().join("context.db"), 4)
+            .await
+            .unwrap();
+        let existing = temp_dir.path().join("main.rs");
+        let created = temp_dir.path().join("src/bin/new.rs");
+        std::fs::write(&existing, "fn main() {}\n").unwrap();
+
+        let turn = TurnCheckpoint::new(context.clone(), "s1", "rewrite main");
+        let tool = EditFileTool::new().with_checkpoint(turn);
+        tool.call(write(&existing, "fn main() { one() }\n"))
+            .await
+            .unwrap();
+        tool.call(write(&existing, "fn main() { two() }\n"))
+            .await
+            .unwrap();
+
+        let turn = TurnCheckpoint::new(context.clone(), "s1", "add a module");
+        let tool = EditFileTool::new().with_checkpoint(turn);
+        tool.call(write(&created, "pub fn new() {}\n"))
+            .await
+            .unwrap();
+        tool.call(write(&existing, "mod new;\n")).await.unwrap();
+
+        let checkpoints = context.list_checkpoints("s1").await.unwrap();
+        assert_eq!(checkpoints.len(), 2);
+        assert_eq!(checkpoints[1].files, 2);
+
+        // Undoing the last turn removes the new file and keeps the first turn's edit
+        let restored = restore_checkpoint(&context, "s1", checkpoints[1].id)
+            .await
+            .unwrap();
+        assert!(restored.contains(&RestoredFile::Removed(created.clone())));
+        assert!(!created.exists());
+        assert!(!temp_dir.path().join("src").exists());
+        assert_eq!(
+            std::fs::read_to_string(&existing).unwrap(),
+            "fn main() { two() }\n"
+        );
+
+        restore_checkpoint(&context, "s1", checkpoints[0].id)
+            .await
+            .unwrap();
+        assert_eq!(
+            std::fs::read_to_string(&existing).unwrap(),
+            "fn main() {}\n"
+        );
+        assert!(context.list_checkpoints("s1").await.unwrap().is_empty());
+    }
+}
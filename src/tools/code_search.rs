@@ -3,9 +3,12 @@ use rig::completion::ToolDefinition;
 use rig::tool::Tool;
 use serde::{Deserialize, Serialize};
 use serde_json::json;
-use std::process::Command;
+use std::process::{Command as StdCommand, Stdio};
+use tokio::io::{AsyncBufReadExt, BufReader};
+use tokio::process::Command;
 
-use super::ToolError;
+use super::workspace::Workspace;
+use super::{ProgressSender, ProgressThrottle, ToolError, ToolProgress};
 
 #[derive(Deserialize)]
 pub struct CodeSearchArgs {
@@ -45,11 +48,38 @@ pub struct CodeSearchOutput {
 }
 
 #[derive(Deserialize, Serialize)]
-pub struct CodeSearchTool;
+pub struct CodeSearchTool {
+    /// Receives the number of matches found while the search runs
+    #[serde(skip)]
+    progress: Option<ProgressSender>,
+    /// Directories the searched path must stay in
+    #[serde(skip)]
+    workspace: Option<Workspace>,
+}
 
 impl CodeSearchTool {
     pub fn new() -> Self {
-        Self
+        Self {
+            progress: None,
+            workspace: None,
+        }
+    }
+
+    /// Search from a workspace's root and refuse paths outside of it
+    pub fn with_workspace(mut self, workspace: Workspace) -> Self {
+        self.workspace = Some(workspace);
+        self
+    }
+
+    /// Workspace the searched path is resolved against
+    pub(super) fn workspace(&self) -> Option<&Workspace> {
+        self.workspace.as_ref()
+    }
+
+    /// Report the matches found so far as progress
+    pub fn with_progress(mut self, progress: ProgressSender) -> Self {
+        self.progress = Some(progress);
+        self
     }
 
     /// Execute ripgrep search with the given parameters
@@ -58,7 +88,7 @@ impl CodeSearchTool {
         args: &CodeSearchArgs,
     ) -> Result<CodeSearchOutput, ToolError> {
         // Check if ripgrep is available
-        let rg_available = Command::new("rg").arg("--version").output().is_ok();
+        let rg_available = StdCommand::new("rg").arg("--version").output().is_ok();
 
         if !rg_available {
             return Err(ToolError::Command(
@@ -69,6 +99,12 @@ impl CodeSearchTool {
         // Build the ripgrep command
         let mut cmd = Command::new("rg");
 
+        // Run from the workspace root, so matches keep the path as given
+        if let Some(ref workspace) = self.workspace {
+            workspace.resolve(&args.path)?;
+            cmd.current_dir(workspace.root());
+        }
+
         // Add the pattern
         cmd.arg(&args.pattern);
 
@@ -104,27 +140,50 @@ impl CodeSearchTool {
         // Max count (approximate, ripgrep doesn't have exact match limit)
         cmd.arg("--max-count").arg(args.max_results.to_string());
 
-        // Execute the command
-        let output = tokio::task::spawn_blocking(move || cmd.output())
-            .await
-            .map_err(|e| ToolError::Command(format!("Failed to spawn ripgrep: {}", e)))?
-            .map_err(|e| ToolError::Command(format!("Ripgrep execution failed: {}", e)))?;
-
-        // Parse the output
-        let stdout = String::from_utf8_lossy(&output.stdout);
+        // Execute the command; ripgrep is killed once enough matches were read
+        // or when the call is dropped
+        let mut child = cmd
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .kill_on_drop(true)
+            .spawn()
+            .map_err(|e| ToolError::Command(format!("Failed to spawn ripgrep: {}", e)))?;
+        let stdout = child
+            .stdout
+            .take()
+            .ok_or_else(|| ToolError::Command("Failed to read ripgrep output".to_string()))?;
+
+        // Parse the output as it arrives
+        let mut reader = BufReader::new(stdout);
         let mut matches = Vec::new();
         let mut files_searched = std::collections::HashSet::new();
+        let mut throttle = ProgressThrottle::new(self.progress.as_ref());
+        let mut line = Vec::new();
+
+        while matches.len() < args.max_results {
+            line.clear();
+            let read = reader
+                .read_until(b'\n', &mut line)
+                .await
+                .map_err(|e| ToolError::Command(format!("Ripgrep execution failed: {}", e)))?;
+            if read == 0 {
+                break;
+            }
 
-        for line in stdout.lines() {
-            if let Some(search_match) = self.parse_ripgrep_line(line) {
+            let text = String::from_utf8_lossy(&line);
+            if let Some(search_match) = self.parse_ripgrep_line(text.trim_end_matches(['\n', '\r']))
+            {
                 files_searched.insert(search_match.file_path.clone());
+                throttle.report(|| ToolProgress {
+                    progress: (matches.len() + 1) as f64,
+                    total: Some(args.max_results as f64),
+                    message: Some(search_match.file_path.clone()),
+                });
                 matches.push(search_match);
-
-                if matches.len() >= args.max_results {
-                    break;
-                }
             }
         }
+        let _ = child.kill().await;
 
         Ok(CodeSearchOutput {
             total_matches: matches.len(),
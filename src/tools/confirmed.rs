@@ -1,43 +1,168 @@
 use anyhow::Result;
 use rig::completion::ToolDefinition;
 use rig::tool::Tool;
+use serde_json::Value;
+use std::sync::Arc;
+use tokio::sync::{mpsc, oneshot};
 use tracing::trace;
+use uuid::Uuid;
 
 use std::io::{self, Write};
 
+use super::diff::FileChange;
+use super::policy::{Decision, PermissionPolicy, PolicyCheck, Rule, ToolRequest};
+use super::workspace::requested_path;
 use super::{
-    BashTool, EditFileTool, ToolError,
-    bash::{BashArgs, BashOutput},
-    edit_file::{EditFileArgs, EditFileOutput},
+    BashTool, CodeSearchTool, EditFileTool, ListFilesTool, ReadFileTool, ReadLogsTool, ToolError,
+    WebSearchTool, bash::BashArgs, code_search::CodeSearchArgs, edit_file::EditFileArgs,
+    list_files::ListFilesArgs, read_file::ReadFileArgs, read_logs::ReadLogsArgs,
+    web_search::WebSearchArgs,
 };
+use crate::mcp::VegaMcpTool;
 
-/// Wrapper for tools that require user confirmation
+/// Host the web search tool queries
+const WEB_SEARCH_URL: &str = "https://api.duckduckgo.com/";
+
+/// Most lines of a diff shown in the confirmation prompt
+const PREVIEW_LINES: usize = 200;
+
+/// Editor used to change a proposed edit when neither `VISUAL` nor `EDITOR` is set
+const DEFAULT_EDITOR: &str = "vi";
+
+/// Wrapper checking every call of a tool against the permission policy,
+/// asking the user for confirmation when the policy says so
 pub struct ConfirmedTool<T> {
     inner: T,
+    policy: Arc<PermissionPolicy>,
     yolo: bool,
+    /// Where confirmations are asked instead of the terminal
+    approvals: Option<ApprovalSender>,
+}
+
+/// Confirmed Bash Tool
+pub type ConfirmedBashTool = ConfirmedTool<BashTool>;
+
+/// Confirmed Edit File Tool
+pub type ConfirmedEditFileTool = ConfirmedTool<EditFileTool>;
+
+/// Confirmed tool provided by an external MCP server
+pub type ConfirmedMcpTool = ConfirmedTool<VegaMcpTool>;
+
+/// The user's answer to a confirmation prompt
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Approval {
+    /// Run this call
+    Once,
+    /// Run this call and allow the like for the rest of the session
+    Session,
+    /// Run this call and allow the like in the project's policy file
+    Always,
+    /// Change the proposed file content in an editor, then ask again
+    Edit,
+    /// Do not run this call
+    Denied,
 }
 
+/// A tool call waiting for the user's confirmation, for front ends that ask
+/// elsewhere than on the terminal, e.g. an ACP client
+#[derive(Debug)]
+pub struct ApprovalRequest {
+    pub tool: String,
+    pub description: String,
+    /// Rules the session and always answers add
+    pub rules: Vec<Rule>,
+    /// Change the call makes to a file
+    pub change: Option<FileChange>,
+    /// Where the answer goes; dropping it denies the call
+    pub reply: oneshot::Sender<Approval>,
+}
+
+/// Channel confirmations are asked on
+pub type ApprovalSender = mpsc::UnboundedSender<ApprovalRequest>;
+
 impl<T> ConfirmedTool<T> {
-    pub fn new(inner: T, yolo: bool) -> Self {
-        Self { inner, yolo }
+    /// Check the calls of `inner` against `policy`; with `yolo`, calls the
+    /// policy would ask about run without asking, denied ones still do not
+    pub fn new(inner: T, policy: Arc<PermissionPolicy>, yolo: bool) -> Self {
+        Self {
+            inner,
+            policy,
+            yolo,
+            approvals: None,
+        }
     }
 
-    /// Prompt user for confirmation
-    fn confirm_execution(&self, tool_name: &str, description: &str) -> Result<bool, ToolError> {
-        trace!("Tool execution requested: {} - {}", tool_name, description);
+    /// Ask for confirmations on a channel instead of the terminal
+    pub fn with_approvals(mut self, approvals: ApprovalSender) -> Self {
+        self.approvals = Some(approvals);
+        self
+    }
+
+    /// Ask the user whether a call may run
+    async fn ask(
+        &self,
+        tool_name: &str,
+        description: &str,
+        rules: &[Rule],
+        change: Option<&FileChange>,
+    ) -> Result<Approval, ToolError> {
+        let Some(ref approvals) = self.approvals else {
+            return self.confirm_execution(tool_name, description, rules, change);
+        };
 
-        if self.yolo {
-            trace!("YOLO mode enabled, auto-confirming tool execution");
-            return Ok(true);
+        let (reply, answer) = oneshot::channel();
+        let request = ApprovalRequest {
+            tool: tool_name.to_string(),
+            description: description.to_string(),
+            rules: rules.to_vec(),
+            change: change.cloned(),
+            reply,
+        };
+        if approvals.send(request).is_err() {
+            return Ok(Approval::Denied);
         }
+        Ok(answer.await.unwrap_or(Approval::Denied))
+    }
+
+    /// Prompt user for confirmation, offering to allow calls matching `rules` from now on
+    fn confirm_execution(
+        &self,
+        tool_name: &str,
+        description: &str,
+        rules: &[Rule],
+        change: Option<&FileChange>,
+    ) -> Result<Approval, ToolError> {
+        trace!("Tool execution requested: {} - {}", tool_name, description);
 
         // Pause any streaming progress indicators to avoid interference
         crate::streaming::pause_progress();
 
+        let rules_text = rules
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>()
+            .join(", ");
         println!("\n🔧 Tool Execution Request:");
         println!("Tool: {}", tool_name);
         println!("Action: {}", description);
-        print!("Do you want to proceed? (y/N): ");
+        if let Some(change) = change {
+            println!("{}", colored_diff(&change.diff()));
+        }
+        println!("  y - yes");
+        println!("  s - yes, and allow {} for this session", rules_text);
+        println!(
+            "  a - yes, and always allow {} (saved to {})",
+            rules_text,
+            self.policy.project_file().display()
+        );
+        if change.is_some() {
+            println!("  e - edit the change in your editor first");
+            println!("  n - no");
+            print!("Do you want to proceed? (y/s/a/e/N): ");
+        } else {
+            println!("  n - no");
+            print!("Do you want to proceed? (y/s/a/N): ");
+        }
         io::stdout().flush().map_err(|e| ToolError::Io(e))?;
 
         let mut input = String::new();
@@ -46,106 +171,408 @@ impl<T> ConfirmedTool<T> {
             .map_err(|e| ToolError::Io(e))?;
 
         let response = input.trim().to_lowercase();
-        let confirmed = response == "y" || response == "yes";
+        let approval = match response.as_str() {
+            "y" | "yes" => Approval::Once,
+            "s" | "session" => Approval::Session,
+            "a" | "always" => Approval::Always,
+            "e" | "edit" if change.is_some() => Approval::Edit,
+            _ => Approval::Denied,
+        };
 
         trace!(
-            "User response to tool confirmation: '{}' -> {}",
-            response, confirmed
+            "User response to tool confirmation: '{}' -> {:?}",
+            response, approval
         );
 
         // Resume streaming progress indicators after user interaction
         crate::streaming::resume_progress();
 
-        Ok(confirmed)
+        Ok(approval)
     }
 }
 
-/// Confirmed Bash Tool
-pub struct ConfirmedBashTool {
-    inner: ConfirmedTool<BashTool>,
-}
+impl<T: PolicyCheck> Tool for ConfirmedTool<T> {
+    const NAME: &'static str = T::NAME;
+    type Error = ToolError;
+    type Args = T::Args;
+    type Output = T::Output;
 
-impl ConfirmedBashTool {
-    pub fn new(yolo: bool) -> Self {
-        Self {
-            inner: ConfirmedTool::new(BashTool::new(), yolo),
-        }
+    fn name(&self) -> String {
+        self.inner.name()
     }
-}
-
-impl Tool for ConfirmedBashTool {
-    const NAME: &'static str = "bash";
-    type Error = ToolError;
-    type Args = BashArgs;
-    type Output = BashOutput;
 
     async fn definition(&self, prompt: String) -> ToolDefinition {
-        self.inner.inner.definition(prompt).await
+        self.inner.definition(prompt).await
     }
 
-    async fn call(&self, args: Self::Args) -> Result<Self::Output, Self::Error> {
-        let description = format!("Execute command: {}", args.command);
+    async fn call(&self, mut args: Self::Args) -> Result<Self::Output, Self::Error> {
+        let name = self.name();
+        let description = self.inner.describe(&args);
+        let request = self.inner.request(&args);
 
-        if !self.inner.confirm_execution(Self::NAME, &description)? {
-            trace!("Bash tool execution denied by user");
-            return Err(ToolError::PermissionDenied(
-                "User denied tool execution".to_string(),
-            ));
+        match self.policy.decide(&request, T::DEFAULT_DECISION) {
+            Decision::Deny => {
+                trace!("Tool execution denied by policy: {}", description);
+                return Err(ToolError::PermissionDenied(format!(
+                    "The permission policy does not allow this: {}",
+                    description
+                )));
+            }
+            Decision::Ask if self.yolo => {
+                trace!("YOLO mode enabled, auto-confirming tool execution");
+            }
+            Decision::Ask => {
+                let rules = self.policy.rules_allowing(&request, T::DEFAULT_DECISION);
+                let mut change = self.inner.preview(&args)?;
+                loop {
+                    match self
+                        .ask(&name, &description, &rules, change.as_ref())
+                        .await?
+                    {
+                        Approval::Once => {}
+                        Approval::Session => self.policy.allow_for_session(&rules),
+                        Approval::Always => match self.policy.allow_always(&rules) {
+                            Ok(path) => trace!("Saved permission rules to {}", path.display()),
+                            Err(e) => {
+                                println!("\x1b[91mCould not save the rules: {:#}\x1b[0m", e)
+                            }
+                        },
+                        Approval::Edit => {
+                            if let Some(ref mut change) = change {
+                                match edit_in_editor(change) {
+                                    Ok(content) if content != change.new => {
+                                        change.new = content.clone();
+                                        args = self.inner.revise(args, content);
+                                    }
+                                    Ok(_) => {}
+                                    Err(e) => {
+                                        println!("\x1b[91mCould not edit the change: {}\x1b[0m", e)
+                                    }
+                                }
+                            }
+                            continue;
+                        }
+                        Approval::Denied => {
+                            trace!("Tool execution denied by user: {}", name);
+                            return Err(ToolError::PermissionDenied(
+                                "User denied tool execution".to_string(),
+                            ));
+                        }
+                    }
+                    break;
+                }
+            }
+            Decision::Allow => {}
         }
 
-        trace!("Executing bash command: {}", args.command);
-        let result = self.inner.inner.call(args).await;
+        trace!("Executing {}: {}", name, description);
+        let result = self.inner.call(args).await;
 
         match &result {
-            Ok(_) => trace!("Bash command completed successfully"),
-            Err(e) => trace!("Bash command failed: {}", e),
+            Ok(_) => trace!("{} completed successfully", name),
+            Err(e) => trace!("{} failed: {}", name, e),
         }
 
         result
     }
 }
 
-/// Confirmed Edit File Tool
-pub struct ConfirmedEditFileTool {
-    inner: ConfirmedTool<EditFileTool>,
+/// A unified diff with colored additions, removals and hunk headers, cut
+/// short after [`PREVIEW_LINES`] lines
+fn colored_diff(diff: &str) -> String {
+    if diff.is_empty() {
+        return "(no changes)".to_string();
+    }
+    let mut colored: Vec<String> = diff
+        .lines()
+        .take(PREVIEW_LINES)
+        .map(|line| {
+            let color = if line.starts_with("+++") || line.starts_with("---") {
+                "\x1b[1m"
+            } else if line.starts_with('+') {
+                "\x1b[32m"
+            } else if line.starts_with('-') {
+                "\x1b[31m"
+            } else if line.starts_with("@@") {
+                "\x1b[36m"
+            } else {
+                return line.to_string();
+            };
+            format!("{}{}\x1b[0m", color, line)
+        })
+        .collect();
+    let hidden = diff.lines().count().saturating_sub(PREVIEW_LINES);
+    if hidden > 0 {
+        colored.push(format!("... {} more lines of the diff", hidden));
+    }
+    colored.join("\n")
 }
 
-impl ConfirmedEditFileTool {
-    pub fn new(yolo: bool) -> Self {
-        Self {
-            inner: ConfirmedTool::new(EditFileTool::new(), yolo),
+/// Let the user change the proposed content of a file in their editor,
+/// returning what they saved
+fn edit_in_editor(change: &FileChange) -> Result<String, ToolError> {
+    let editor = std::env::var("VISUAL")
+        .or_else(|_| std::env::var("EDITOR"))
+        .unwrap_or_else(|_| DEFAULT_EDITOR.to_string());
+    // Keep the extension so the editor highlights the file
+    let extension = change
+        .path
+        .extension()
+        .map(|extension| format!(".{}", extension.to_string_lossy()))
+        .unwrap_or_default();
+    let file = std::env::temp_dir().join(format!("vega-edit-{}{}", Uuid::new_v4(), extension));
+    std::fs::write(&file, &change.new).map_err(ToolError::Io)?;
+
+    // The editor may come with arguments, e.g. `code --wait`
+    let mut words = editor.split_whitespace();
+    let program = words.next().unwrap_or(DEFAULT_EDITOR);
+    let status = std::process::Command::new(program)
+        .args(words)
+        .arg(&file)
+        .status();
+    let content = std::fs::read_to_string(&file);
+    std::fs::remove_file(&file).ok();
+
+    let status = status.map_err(|e| ToolError::Command(format!("{}: {}", editor, e)))?;
+    if !status.success() {
+        return Err(ToolError::Command(format!(
+            "{} exited with {}",
+            editor, status
+        )));
+    }
+    content.map_err(ToolError::Io)
+}
+
+impl PolicyCheck for BashTool {
+    const DEFAULT_DECISION: Decision = Decision::Ask;
+
+    fn request(&self, args: &BashArgs) -> ToolRequest {
+        ToolRequest::new(Self::NAME).with_command(&args.command)
+    }
+
+    fn describe(&self, args: &BashArgs) -> String {
+        format!("Execute command: {}", args.command)
+    }
+}
+
+impl PolicyCheck for EditFileTool {
+    const DEFAULT_DECISION: Decision = Decision::Ask;
+
+    fn request(&self, args: &EditFileArgs) -> ToolRequest {
+        ToolRequest::new(Self::NAME).with_path(requested_path(self.workspace(), &args.path))
+    }
+
+    fn describe(&self, args: &EditFileArgs) -> String {
+        format!("Edit/create file: {}", args.path)
+    }
+
+    fn preview(&self, args: &EditFileArgs) -> Result<Option<FileChange>, ToolError> {
+        EditFileTool::preview(self, args).map(Some)
+    }
+
+    /// The whole file is written with the content the user saved
+    fn revise(&self, args: EditFileArgs, content: String) -> EditFileArgs {
+        EditFileArgs {
+            path: args.path,
+            content: Some(content),
+            create_if_missing: args.create_if_missing,
+            backup: args.backup,
+            encoding: args.encoding,
+            ..Default::default()
         }
     }
 }
 
-impl Tool for ConfirmedEditFileTool {
-    const NAME: &'static str = "edit_file";
-    type Error = ToolError;
-    type Args = EditFileArgs;
-    type Output = EditFileOutput;
+impl PolicyCheck for VegaMcpTool {
+    const DEFAULT_DECISION: Decision = Decision::Ask;
 
-    async fn definition(&self, prompt: String) -> ToolDefinition {
-        self.inner.inner.definition(prompt).await
+    /// The URL is the first http(s) URL among the arguments, e.g. of a fetch tool
+    fn request(&self, args: &Value) -> ToolRequest {
+        let mut request = ToolRequest::new(Tool::name(self));
+        let url = args
+            .as_object()
+            .into_iter()
+            .flat_map(|arguments| arguments.values())
+            .filter_map(Value::as_str)
+            .find(|value| value.starts_with("http://") || value.starts_with("https://"));
+        if let Some(url) = url {
+            request = request.with_url(url);
+        }
+        request
+    }
+
+    fn describe(&self, args: &Value) -> String {
+        format!(
+            "Call '{}' on MCP server '{}' with arguments: {}",
+            self.remote_name(),
+            self.server(),
+            args
+        )
+    }
+}
+
+impl PolicyCheck for ReadFileTool {
+    const DEFAULT_DECISION: Decision = Decision::Allow;
+
+    fn request(&self, args: &ReadFileArgs) -> ToolRequest {
+        ToolRequest::new(Self::NAME).with_path(requested_path(self.workspace(), &args.path))
     }
 
-    async fn call(&self, args: Self::Args) -> Result<Self::Output, Self::Error> {
-        let description = format!("Edit/create file: {}", args.path);
+    fn describe(&self, args: &ReadFileArgs) -> String {
+        format!("Read file: {}", args.path)
+    }
+}
+
+impl PolicyCheck for ListFilesTool {
+    const DEFAULT_DECISION: Decision = Decision::Allow;
+
+    fn request(&self, args: &ListFilesArgs) -> ToolRequest {
+        ToolRequest::new(Self::NAME).with_path(requested_path(self.workspace(), &args.directory))
+    }
+
+    fn describe(&self, args: &ListFilesArgs) -> String {
+        format!("List files in: {}", args.directory)
+    }
+}
+
+impl PolicyCheck for CodeSearchTool {
+    const DEFAULT_DECISION: Decision = Decision::Allow;
+
+    fn request(&self, args: &CodeSearchArgs) -> ToolRequest {
+        ToolRequest::new(Self::NAME).with_path(requested_path(self.workspace(), &args.path))
+    }
+
+    fn describe(&self, args: &CodeSearchArgs) -> String {
+        format!("Search for '{}' in: {}", args.pattern, args.path)
+    }
+}
+
+impl PolicyCheck for WebSearchTool {
+    const DEFAULT_DECISION: Decision = Decision::Allow;
+
+    fn request(&self, _args: &WebSearchArgs) -> ToolRequest {
+        ToolRequest::new(Self::NAME).with_url(WEB_SEARCH_URL)
+    }
 
-        if !self.inner.confirm_execution(Self::NAME, &description)? {
-            trace!("Edit file tool execution denied by user");
-            return Err(ToolError::PermissionDenied(
-                "User denied tool execution".to_string(),
-            ));
+    fn describe(&self, args: &WebSearchArgs) -> String {
+        format!("Search the web for: {}", args.query)
+    }
+}
+
+impl PolicyCheck for ReadLogsTool {
+    const DEFAULT_DECISION: Decision = Decision::Allow;
+
+    fn request(&self, _args: &ReadLogsArgs) -> ToolRequest {
+        ToolRequest::new(Self::NAME)
+    }
+
+    fn describe(&self, args: &ReadLogsArgs) -> String {
+        match args.session_id {
+            Some(ref session_id) => format!("Read the logs of session {}", session_id),
+            None => "Read the logs of this session".to_string(),
         }
+    }
+}
 
-        trace!("Editing/creating file: {}", args.path);
-        let result = self.inner.inner.call(args).await;
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-        match &result {
-            Ok(_) => trace!("File edit completed successfully"),
-            Err(e) => trace!("File edit failed: {}", e),
+    fn bash_args(command: &str) -> BashArgs {
+        BashArgs {
+            command: command.to_string(),
+            timeout_seconds: 5,
+            working_directory: None,
+            reset_shell: false,
         }
+    }
 
-        result
+    #[tokio::test]
+    async fn test_policy_decides_before_the_tool_runs() {
+        let policy = PermissionPolicy::new("/project").with_rules([
+            Rule::new("bash", Decision::Allow).with_command("echo"),
+            Rule::new("bash", Decision::Deny).with_command("echo secret"),
+        ]);
+        let tool = ConfirmedTool::new(BashTool::new(), Arc::new(policy), false);
+
+        let output = tool.call(bash_args("echo hello")).await.unwrap();
+        assert_eq!(output.stdout, "hello\n");
+
+        let denied = tool.call(bash_args("echo secret")).await;
+        assert!(matches!(denied, Err(ToolError::PermissionDenied(_))));
+    }
+
+    #[tokio::test]
+    async fn test_edits_are_confirmed_with_their_diff() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let path = temp_dir.path().join("notes.txt");
+        std::fs::write(&path, "one\ntwo\n").unwrap();
+        let edit = |old: &str, new: &str| EditFileArgs {
+            path: path.to_string_lossy().to_string(),
+            old_string: Some(old.to_string()),
+            new_string: Some(new.to_string()),
+            ..Default::default()
+        };
+        let (approvals, mut requests) = mpsc::unbounded_channel();
+        let policy = PermissionPolicy::new(temp_dir.path());
+        let tool = ConfirmedTool::new(EditFileTool::new(), Arc::new(policy), false)
+            .with_approvals(approvals);
+
+        // Answer the first request with a rejection and the second with an approval
+        let answers = tokio::spawn(async move {
+            let mut diffs = Vec::new();
+            for answer in [Approval::Denied, Approval::Once] {
+                let request: ApprovalRequest = requests.recv().await.unwrap();
+                diffs.push(request.change.unwrap().diff());
+                request.reply.send(answer).unwrap();
+            }
+            diffs
+        });
+
+        let rejected = tool.call(edit("two", "2")).await;
+        assert!(matches!(rejected, Err(ToolError::PermissionDenied(_))));
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "one\ntwo\n");
+
+        // An edit that cannot apply fails without asking
+        let missing = tool.call(edit("three", "3")).await;
+        assert!(matches!(missing, Err(ToolError::InvalidInput(_))));
+
+        tool.call(edit("one", "1")).await.unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "1\ntwo\n");
+
+        let diffs = answers.await.unwrap();
+        assert!(diffs[0].contains("-two\n+2\n"));
+        assert!(diffs[1].contains("-one\n+1\n"));
+    }
+
+    #[test]
+    fn test_revised_edits_write_the_saved_content() {
+        let tool = EditFileTool::new();
+        let args = EditFileArgs {
+            path: "notes.txt".to_string(),
+            diff: Some("@@ -1 +1 @@\n-one\n+1\n".to_string()),
+            backup: true,
+            ..Default::default()
+        };
+        let revised = tool.revise(args, "uno\n".to_string());
+        assert_eq!(revised.content.as_deref(), Some("uno\n"));
+        assert!(revised.diff.is_none());
+        assert!(revised.backup);
+
+        let colored = colored_diff("--- a/x\n+++ b/x\n@@ -1 +1 @@\n-one\n+1\n");
+        assert!(colored.contains("\x1b[31m-one\x1b[0m"));
+        assert!(colored.contains("\x1b[32m+1\x1b[0m"));
+    }
+
+    #[tokio::test]
+    async fn test_yolo_does_not_override_deny() {
+        let policy =
+            PermissionPolicy::new("/project")
+                .with_rules([Rule::new("bash", Decision::Deny).with_command("touch")]);
+        let tool = ConfirmedTool::new(BashTool::new(), Arc::new(policy), true);
+
+        assert!(tool.call(bash_args("echo asked")).await.is_ok());
+        let denied = tool.call(bash_args("true && touch /tmp/x")).await;
+        assert!(matches!(denied, Err(ToolError::PermissionDenied(_))));
     }
 }
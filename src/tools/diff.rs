@@ -0,0 +1,510 @@
+//! Line diffs of file edits
+//!
+//! [`unified_diff`] shows what an edit changed in the unified format of
+//! `diff -u`, and [`apply_unified_diff`] applies such a diff written by the
+//! model. Models often get the line numbers and the whitespace of the context
+//! wrong, so each hunk is looked for near the line its header gives, first
+//! exactly, then ignoring whitespace, then with fewer context lines.
+
+use std::path::{Path, PathBuf};
+
+use super::ToolError;
+
+/// Lines of unchanged context shown around each change
+const CONTEXT_LINES: usize = 3;
+
+/// Most line pairs compared when diffing; beyond it, the changed region is
+/// shown as one replacement
+const MAX_COMPARED_LINES: usize = 4_000_000;
+
+/// Context lines a hunk may drop at each end to match
+const MAX_FUZZ: usize = 2;
+
+/// Marker following a line that does not end with a newline
+const NO_NEWLINE_MARKER: &str = "\\ No newline at end of file";
+
+/// What happened to a line
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Change {
+    Equal,
+    Delete,
+    Insert,
+}
+
+/// A change a tool is about to make to a file
+#[derive(Debug, Clone, PartialEq)]
+pub struct FileChange {
+    pub path: PathBuf,
+    /// Current content, `None` if the file does not exist yet
+    pub old: Option<String>,
+    /// Content after the change
+    pub new: String,
+}
+
+impl FileChange {
+    /// The change as a unified diff
+    pub fn diff(&self) -> String {
+        unified_diff(
+            self.old.as_deref().unwrap_or(""),
+            &self.new,
+            &self.path.to_string_lossy(),
+        )
+    }
+}
+
+/// A unified diff turning `old` into `new`, empty if they are the same
+pub fn unified_diff(old: &str, new: &str, path: &str) -> String {
+    if old == new {
+        return String::new();
+    }
+    let old_lines = split_lines(old);
+    let new_lines = split_lines(new);
+    let changes = diff_lines(&old_lines, &new_lines);
+
+    // Lines of the old and new text before each change
+    let mut positions = Vec::with_capacity(changes.len() + 1);
+    let (mut old_line, mut new_line) = (0, 0);
+    for (change, _) in &changes {
+        positions.push((old_line, new_line));
+        match change {
+            Change::Equal => {
+                old_line += 1;
+                new_line += 1;
+            }
+            Change::Delete => old_line += 1,
+            Change::Insert => new_line += 1,
+        }
+    }
+    positions.push((old_line, new_line));
+
+    let changed: Vec<usize> = changes
+        .iter()
+        .enumerate()
+        .filter(|(_, (change, _))| *change != Change::Equal)
+        .map(|(index, _)| index)
+        .collect();
+
+    let mut diff = if Path::new(path).is_absolute() {
+        format!("--- {path}\n+++ {path}\n")
+    } else {
+        format!("--- a/{path}\n+++ b/{path}\n")
+    };
+    let mut start = 0;
+    while start < changed.len() {
+        // Changes closer than twice the context share a hunk
+        let mut end = start;
+        while end + 1 < changed.len() && changed[end + 1] - changed[end] <= 2 * CONTEXT_LINES + 1 {
+            end += 1;
+        }
+        let first = changed[start].saturating_sub(CONTEXT_LINES);
+        let last = (changed[end] + CONTEXT_LINES + 1).min(changes.len());
+
+        let (old_start, new_start) = positions[first];
+        let (old_end, new_end) = positions[last];
+        diff.push_str(&format!(
+            "@@ -{} +{} @@\n",
+            hunk_range(old_start, old_end - old_start),
+            hunk_range(new_start, new_end - new_start)
+        ));
+        for (change, line) in &changes[first..last] {
+            diff.push(match change {
+                Change::Equal => ' ',
+                Change::Delete => '-',
+                Change::Insert => '+',
+            });
+            diff.push_str(line);
+            if !line.ends_with('\n') {
+                diff.push('\n');
+                diff.push_str(NO_NEWLINE_MARKER);
+                diff.push('\n');
+            }
+        }
+        start = end + 1;
+    }
+    diff
+}
+
+/// Apply a unified diff to `original`
+///
+/// File headers are optional and the line counts of hunk headers are
+/// ignored; a hunk ends where the next one starts. Hunks must come in the
+/// order of the file and are applied all or none.
+pub fn apply_unified_diff(original: &str, diff: &str) -> Result<String, ToolError> {
+    let hunks = parse_hunks(diff)?;
+    let lines = split_lines(original);
+    let line_ending = if original.contains("\r\n") {
+        "\r\n"
+    } else {
+        "\n"
+    };
+
+    let mut patched = String::with_capacity(original.len());
+    let mut cursor = 0;
+    for (number, hunk) in hunks.iter().enumerate() {
+        let Some(found) = hunk.find(&lines, cursor) else {
+            return Err(ToolError::InvalidInput(format!(
+                "Hunk {} of the diff does not match the file; these lines were not found after line {}:\n{}",
+                number + 1,
+                cursor,
+                hunk.before().collect::<Vec<_>>().join("\n")
+            )));
+        };
+
+        for line in &lines[cursor..found.position] {
+            push_line(&mut patched, line, line_ending);
+        }
+        let mut position = found.position;
+        for line in &hunk.lines[found.skip_start..hunk.lines.len() - found.skip_end] {
+            match line {
+                HunkLine::Context(_) => {
+                    // Keep the file's version of the line
+                    push_line(&mut patched, lines[position], line_ending);
+                    position += 1;
+                }
+                HunkLine::Remove(_) => position += 1,
+                HunkLine::Add(text) => {
+                    // Added lines take the line endings of the file
+                    push_line(&mut patched, trim_line_ending(text), line_ending);
+                    if line_ends(text) {
+                        patched.push_str(line_ending);
+                    }
+                }
+            }
+        }
+        cursor = position;
+    }
+    for line in &lines[cursor..] {
+        push_line(&mut patched, line, line_ending);
+    }
+    Ok(patched)
+}
+
+/// The lines of a text, each with its line ending
+fn split_lines(text: &str) -> Vec<&str> {
+    text.split_inclusive('\n').collect()
+}
+
+/// A line without its line ending
+fn trim_line_ending(line: &str) -> &str {
+    line.trim_end_matches(['\n', '\r'])
+}
+
+fn line_ends(line: &str) -> bool {
+    line.ends_with('\n')
+}
+
+/// Append a line, ending the previous one if it had no line ending
+fn push_line(text: &mut String, line: &str, line_ending: &str) {
+    if !text.is_empty() && !text.ends_with('\n') {
+        text.push_str(line_ending);
+    }
+    text.push_str(line);
+}
+
+/// The start and length of a hunk in the format of its header
+fn hunk_range(start: usize, length: usize) -> String {
+    match length {
+        // An empty range names the line before it
+        0 => format!("{start},0"),
+        1 => format!("{}", start + 1),
+        _ => format!("{},{}", start + 1, length),
+    }
+}
+
+/// The changes turning `old` into `new`, by the longest common subsequence
+fn diff_lines<'a>(old: &[&'a str], new: &[&'a str]) -> Vec<(Change, &'a str)> {
+    let prefix = old.iter().zip(new).take_while(|(a, b)| a == b).count();
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let old_middle = &old[prefix..old.len() - suffix];
+    let new_middle = &new[prefix..new.len() - suffix];
+
+    let mut changes: Vec<(Change, &str)> = old[..prefix]
+        .iter()
+        .map(|line| (Change::Equal, *line))
+        .collect();
+
+    let (n, m) = (old_middle.len(), new_middle.len());
+    if n.saturating_mul(m) > MAX_COMPARED_LINES {
+        changes.extend(old_middle.iter().map(|line| (Change::Delete, *line)));
+        changes.extend(new_middle.iter().map(|line| (Change::Insert, *line)));
+    } else {
+        // common[i][j] is the longest common subsequence of old[i..] and new[j..]
+        let mut common = vec![vec![0u32; m + 1]; n + 1];
+        for i in (0..n).rev() {
+            for j in (0..m).rev() {
+                common[i][j] = if old_middle[i] == new_middle[j] {
+                    common[i + 1][j + 1] + 1
+                } else {
+                    common[i + 1][j].max(common[i][j + 1])
+                };
+            }
+        }
+        let (mut i, mut j) = (0, 0);
+        while i < n || j < m {
+            if i < n && j < m && old_middle[i] == new_middle[j] {
+                changes.push((Change::Equal, old_middle[i]));
+                i += 1;
+                j += 1;
+            } else if j == m || (i < n && common[i + 1][j] >= common[i][j + 1]) {
+                changes.push((Change::Delete, old_middle[i]));
+                i += 1;
+            } else {
+                changes.push((Change::Insert, new_middle[j]));
+                j += 1;
+            }
+        }
+    }
+
+    changes.extend(
+        old[old.len() - suffix..]
+            .iter()
+            .map(|line| (Change::Equal, *line)),
+    );
+    changes
+}
+
+/// A line of a hunk, with its line ending unless the diff says it has none
+#[derive(Debug, Clone, PartialEq)]
+enum HunkLine {
+    Context(String),
+    Remove(String),
+    Add(String),
+}
+
+/// A hunk of a unified diff
+#[derive(Debug, Default)]
+struct Hunk {
+    /// Line of the original the hunk starts at according to its header
+    old_start: Option<usize>,
+    lines: Vec<HunkLine>,
+}
+
+/// Where a hunk matches the file
+struct Match {
+    /// Line of the file the matched lines start at
+    position: usize,
+    /// Context lines left out at the start of the hunk
+    skip_start: usize,
+    /// Context lines left out at the end of the hunk
+    skip_end: usize,
+}
+
+impl Hunk {
+    /// The lines the hunk expects in the file, without line endings
+    fn before(&self) -> impl Iterator<Item = &str> {
+        self.lines.iter().filter_map(|line| match line {
+            HunkLine::Context(text) | HunkLine::Remove(text) => Some(trim_line_ending(text)),
+            HunkLine::Add(_) => None,
+        })
+    }
+
+    /// Find the hunk in `lines` at or after `cursor`, closest to the line its header gives
+    fn find(&self, lines: &[&str], cursor: usize) -> Option<Match> {
+        let leading_context = self
+            .lines
+            .iter()
+            .take_while(|line| matches!(line, HunkLine::Context(_)))
+            .count();
+        let trailing_context = self
+            .lines
+            .iter()
+            .rev()
+            .take_while(|line| matches!(line, HunkLine::Context(_)))
+            .count()
+            .min(self.lines.len() - leading_context);
+
+        for fuzz in 0..=MAX_FUZZ {
+            let skip_start = fuzz.min(leading_context);
+            let skip_end = fuzz.min(trailing_context);
+            if fuzz > 0 && skip_start + skip_end == 0 {
+                break;
+            }
+            let expected: Vec<&str> = self.before().collect();
+            let expected = &expected[skip_start..expected.len() - skip_end];
+
+            let exact = |a: &str, b: &str| trim_line_ending(a) == b;
+            let loose = |a: &str, b: &str| a.split_whitespace().eq(b.split_whitespace());
+            for same in [&exact as &dyn Fn(&str, &str) -> bool, &loose] {
+                if let Some(position) = self.closest(lines, cursor, expected, skip_start, same) {
+                    return Some(Match {
+                        position,
+                        skip_start,
+                        skip_end,
+                    });
+                }
+            }
+        }
+        None
+    }
+
+    /// The position at or after `cursor` where `expected` matches, closest to the header's line
+    fn closest(
+        &self,
+        lines: &[&str],
+        cursor: usize,
+        expected: &[&str],
+        skipped: usize,
+        same: &dyn Fn(&str, &str) -> bool,
+    ) -> Option<usize> {
+        if lines.len() < cursor + expected.len() {
+            return None;
+        }
+        // An empty range in a header names the line after which to insert
+        let target = match self.old_start {
+            Some(start) if expected.is_empty() => start,
+            Some(start) => start.saturating_sub(1) + skipped,
+            None => cursor,
+        };
+        (cursor..=lines.len() - expected.len())
+            .filter(|&position| {
+                expected
+                    .iter()
+                    .enumerate()
+                    .all(|(offset, line)| same(lines[position + offset], line))
+            })
+            .min_by_key(|&position| position.abs_diff(target))
+    }
+}
+
+/// Parse the hunks of a unified diff
+fn parse_hunks(diff: &str) -> Result<Vec<Hunk>, ToolError> {
+    let mut hunks: Vec<Hunk> = Vec::new();
+    let mut lines = diff.split_inclusive('\n').peekable();
+    while let Some(line) = lines.next() {
+        let content = trim_line_ending(line);
+        if let Some(header) = content.strip_prefix("@@") {
+            hunks.push(Hunk {
+                old_start: parse_old_start(header),
+                lines: Vec::new(),
+            });
+            continue;
+        }
+        let Some(hunk) = hunks.last_mut() else {
+            // File headers and anything else before the first hunk
+            continue;
+        };
+        let is_file_header = content.starts_with("--- ")
+            && lines.peek().is_some_and(|next| next.starts_with("+++ "));
+        if is_file_header {
+            return Err(ToolError::InvalidInput(
+                "The diff changes more than one file; give a diff of the file at path only"
+                    .to_string(),
+            ));
+        }
+
+        let line = if line_ends(line) {
+            format!("{}\n", content.get(1..).unwrap_or(""))
+        } else {
+            content.get(1..).unwrap_or("").to_string()
+        };
+        match content.chars().next() {
+            Some(' ') => hunk.lines.push(HunkLine::Context(line)),
+            Some('-') => hunk.lines.push(HunkLine::Remove(line)),
+            Some('+') => hunk.lines.push(HunkLine::Add(line)),
+            // The previous line has no line ending
+            Some('\\') => {
+                if let Some(
+                    HunkLine::Context(text) | HunkLine::Remove(text) | HunkLine::Add(text),
+                ) = hunk.lines.last_mut()
+                {
+                    text.truncate(trim_line_ending(text).len());
+                }
+            }
+            // Models often drop the space of empty context lines
+            None => hunk.lines.push(HunkLine::Context("\n".to_string())),
+            Some(_) => {
+                return Err(ToolError::InvalidInput(format!(
+                    "Invalid line in hunk {} of the diff, lines must start with ' ', '-' or '+': {}",
+                    hunks.len(),
+                    content
+                )));
+            }
+        }
+    }
+
+    // Trailing empty lines are the end of the diff rather than context
+    for hunk in &mut hunks {
+        while hunk.lines.last() == Some(&HunkLine::Context("\n".to_string())) {
+            hunk.lines.pop();
+        }
+    }
+    if hunks.is_empty() {
+        return Err(ToolError::InvalidInput(
+            "The diff has no hunks; each must start with an @@ header".to_string(),
+        ));
+    }
+    Ok(hunks)
+}
+
+/// The first line of the original in a hunk header like ` -12,7 +12,8 @@`
+fn parse_old_start(header: &str) -> Option<usize> {
+    let range = header.trim_start().strip_prefix('-')?;
+    let end = range
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(range.len());
+    range[..end].parse().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ORIGINAL: &str =
+        "fn main() {\n    let a = 1;\n    let b = 2;\n    println!(\"{}\", a + b);\n}\n";
+
+    #[test]
+    fn test_unified_diff() {
+        let new = ORIGINAL.replace("let b = 2;", "let b = 3;");
+        let diff = unified_diff(ORIGINAL, &new, "src/main.rs");
+        assert_eq!(
+            diff,
+            "--- a/src/main.rs\n+++ b/src/main.rs\n@@ -1,5 +1,5 @@\n fn main() {\n     let a = 1;\n-    let b = 2;\n+    let b = 3;\n     println!(\"{}\", a + b);\n }\n"
+        );
+        assert_eq!(unified_diff(ORIGINAL, ORIGINAL, "src/main.rs"), "");
+
+        let diff = unified_diff("", "one\ntwo", "new.txt");
+        assert!(diff.contains("@@ -0,0 +1,2 @@\n+one\n+two\n\\ No newline at end of file\n"));
+    }
+
+    #[test]
+    fn test_generated_diffs_apply() {
+        let new = "fn main() {\n    let a = 1;\n    println!(\"{}\", a);\n}\n// end";
+        let diff = unified_diff(ORIGINAL, new, "src/main.rs");
+        assert_eq!(apply_unified_diff(ORIGINAL, &diff).unwrap(), new);
+        assert_eq!(
+            apply_unified_diff("", &unified_diff("", ORIGINAL, "x")).unwrap(),
+            ORIGINAL
+        );
+    }
+
+    #[test]
+    fn test_apply_with_wrong_line_numbers_and_whitespace() {
+        // The header is off and the context is indented with tabs
+        let diff = "@@ -40,3 +40,3 @@\n \tlet a = 1;\n-\tlet b = 2;\n+    let b = 20;\n \tprintln!(\"{}\", a + b);\n";
+        let patched = apply_unified_diff(ORIGINAL, diff).unwrap();
+        assert_eq!(patched, ORIGINAL.replace("let b = 2;", "let b = 20;"));
+    }
+
+    #[test]
+    fn test_apply_with_stale_context() {
+        // The first and last context lines no longer match the file
+        let diff = "@@ -1,5 +1,5 @@\n fn start() {\n     let a = 1;\n-    let b = 2;\n+    let b = 4;\n     println!(\"{}\", a + b);\n } // main\n";
+        let patched = apply_unified_diff(ORIGINAL, diff).unwrap();
+        assert_eq!(patched, ORIGINAL.replace("let b = 2;", "let b = 4;"));
+    }
+
+    #[test]
+    fn test_apply_refuses_hunks_that_do_not_match() {
+        let diff = "@@ -2,1 +2,1 @@\n-    let c = 2;\n+    let c = 3;\n";
+        match apply_unified_diff(ORIGINAL, diff) {
+            Err(ToolError::InvalidInput(msg)) => assert!(msg.contains("let c = 2;")),
+            other => panic!("Expected a mismatch, got {:?}", other),
+        }
+        assert!(apply_unified_diff(ORIGINAL, "no hunks here").is_err());
+    }
+}
@@ -3,15 +3,20 @@ use rig::completion::ToolDefinition;
 use rig::tool::Tool;
 use serde::{Deserialize, Serialize};
 use serde_json::json;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use tokio::fs;
 
 use super::ToolError;
+use super::checkpoint::TurnCheckpoint;
+use super::diff::{FileChange, apply_unified_diff, unified_diff};
+use super::policy::{Decision, PermissionPolicy, ToolRequest};
+use super::workspace::{Workspace, resolve_path};
 
-#[derive(Deserialize)]
+#[derive(Deserialize, Default)]
 pub struct EditFileArgs {
     pub path: String,
-    pub content: String,
+    #[serde(default)]
+    pub content: Option<String>,
     #[serde(default)]
     pub create_if_missing: bool,
     #[serde(default)]
@@ -20,6 +25,79 @@ pub struct EditFileArgs {
     pub encoding: Option<String>,
     #[serde(default)]
     pub line_range: Option<(usize, usize)>, // (start_line, end_line) for partial edits
+    /// Exact text to replace, which must occur once unless `replace_all` is set
+    #[serde(default)]
+    pub old_string: Option<String>,
+    #[serde(default)]
+    pub new_string: Option<String>,
+    #[serde(default)]
+    pub replace_all: bool,
+    /// Replacements made one after the other, all or none
+    #[serde(default)]
+    pub edits: Vec<Replacement>,
+    /// Unified diff to apply
+    #[serde(default)]
+    pub diff: Option<String>,
+}
+
+/// One replacement of an edit
+#[derive(Deserialize, Serialize, Debug, Clone, Default)]
+pub struct Replacement {
+    pub old_string: String,
+    pub new_string: String,
+    #[serde(default)]
+    pub replace_all: bool,
+}
+
+impl EditFileArgs {
+    /// The replacements to make, `old_string` and `new_string` first
+    fn replacements(&self) -> Result<Vec<Replacement>, ToolError> {
+        let mut replacements = Vec::with_capacity(self.edits.len() + 1);
+        match (&self.old_string, &self.new_string) {
+            (Some(old_string), Some(new_string)) => replacements.push(Replacement {
+                old_string: old_string.clone(),
+                new_string: new_string.clone(),
+                replace_all: self.replace_all,
+            }),
+            (None, None) => {}
+            _ => {
+                return Err(ToolError::InvalidInput(
+                    "old_string and new_string must be given together".to_string(),
+                ));
+            }
+        }
+        replacements.extend(self.edits.iter().cloned());
+        Ok(replacements)
+    }
+}
+
+impl Replacement {
+    /// Make the replacement in `content`
+    fn apply(&self, content: &str) -> Result<String, ToolError> {
+        if self.old_string.is_empty() {
+            return Err(ToolError::InvalidInput(
+                "old_string must not be empty".to_string(),
+            ));
+        }
+        if self.old_string == self.new_string {
+            return Err(ToolError::InvalidInput(
+                "old_string and new_string are the same".to_string(),
+            ));
+        }
+
+        match content.matches(&self.old_string).count() {
+            0 => Err(ToolError::InvalidInput(
+                "old_string was not found; it must match the file exactly, including whitespace and indentation"
+                    .to_string(),
+            )),
+            1 => Ok(content.replacen(&self.old_string, &self.new_string, 1)),
+            _ if self.replace_all => Ok(content.replace(&self.old_string, &self.new_string)),
+            count => Err(ToolError::InvalidInput(format!(
+                "old_string occurs {} times; include more surrounding lines to make it unique, or set replace_all",
+                count
+            ))),
+        }
+    }
 }
 
 #[derive(Serialize, Debug)]
@@ -30,14 +108,66 @@ pub struct EditFileOutput {
     pub backup_path: Option<String>,
     pub created_new_file: bool,
     pub lines_modified: Option<(usize, usize)>, // (start_line, end_line) if partial edit
+    /// Unified diff of the changes, empty if the file did not change
+    pub diff: String,
 }
 
 #[derive(Deserialize, Serialize)]
-pub struct EditFileTool;
+pub struct EditFileTool {
+    /// Directories the paths must stay in
+    #[serde(skip)]
+    workspace: Option<Workspace>,
+    /// Records the files before they are changed
+    #[serde(skip)]
+    checkpoint: Option<TurnCheckpoint>,
+}
 
 impl EditFileTool {
     pub fn new() -> Self {
-        Self
+        Self {
+            workspace: None,
+            checkpoint: None,
+        }
+    }
+
+    /// Resolve paths against a workspace and refuse those outside of it
+    pub fn with_workspace(mut self, workspace: Workspace) -> Self {
+        self.workspace = Some(workspace);
+        self
+    }
+
+    /// Record every file in a checkpoint of the turn before changing it
+    pub fn with_checkpoint(mut self, checkpoint: TurnCheckpoint) -> Self {
+        self.checkpoint = Some(checkpoint);
+        self
+    }
+
+    /// Workspace the paths are resolved against
+    pub(super) fn workspace(&self) -> Option<&Workspace> {
+        self.workspace.as_ref()
+    }
+
+    /// The path to use for a path given by the model
+    fn resolve(&self, path: &str) -> Result<String, ToolError> {
+        let path = resolve_path(self.workspace.as_ref(), path)?;
+        Ok(path.to_string_lossy().into_owned())
+    }
+
+    /// The change a call would make, without making it
+    pub(super) fn preview(&self, args: &EditFileArgs) -> Result<FileChange, ToolError> {
+        let path = self.resolve(&args.path)?;
+        self.validate_path(&path)?;
+        let old = match std::fs::read_to_string(&path) {
+            Ok(content) => Some(content),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => None,
+            Err(e) => return Err(ToolError::Io(e)),
+        };
+        let (new, _) = Self::edited_content(args, old.as_deref())?;
+        Ok(FileChange {
+            path: PathBuf::from(path),
+            old,
+            new,
+        })
     }
 
     /// Edit file with safety checks and optional backup
@@ -45,11 +175,6 @@ impl EditFileTool {
         let path = Path::new(&args.path);
         let file_exists = path.exists();
 
-        // Auto-create file if it doesn't exist (always allow creation for better UX)
-        if !file_exists {
-            println!("File '{}' does not exist, creating it...", args.path);
-        }
-
         // If file exists, check if it's actually a file
         if file_exists && !path.is_file() {
             return Err(ToolError::InvalidInput(format!(
@@ -58,7 +183,26 @@ impl EditFileTool {
             )));
         }
 
-        // Create parent directories if needed
+        // Read existing content if file exists
+        let original_content = if file_exists {
+            Some(fs::read_to_string(&path).await.map_err(ToolError::Io)?)
+        } else {
+            None
+        };
+
+        // Work out the new content before touching the file, so an edit that
+        // does not apply changes nothing
+        let (final_content, lines_modified) =
+            Self::edited_content(args, original_content.as_deref())?;
+
+        // Auto-create file if it doesn't exist (always allow creation for better UX)
+        if !file_exists {
+            println!("File '{}' does not exist, creating it...", args.path);
+        }
+
+        // Create parent directories if needed, once the checkpoint knows which
+        // ones are new
+        self.record(path).await?;
         if let Some(parent) = path.parent() {
             if !parent.exists() {
                 fs::create_dir_all(parent)
@@ -67,69 +211,17 @@ impl EditFileTool {
             }
         }
 
+        // Create backup if requested
         let mut backup_path = None;
-        let mut original_content = String::new();
-
-        // Read existing content if file exists
-        if file_exists {
-            original_content = fs::read_to_string(&path)
+        if file_exists && args.backup {
+            let backup_file_path = format!("{}.backup", args.path);
+            self.record(Path::new(&backup_file_path)).await?;
+            fs::copy(&path, &backup_file_path)
                 .await
                 .map_err(|e| ToolError::Io(e))?;
-
-            // Create backup if requested
-            if args.backup {
-                let backup_file_path = format!("{}.backup", args.path);
-                fs::copy(&path, &backup_file_path)
-                    .await
-                    .map_err(|e| ToolError::Io(e))?;
-                backup_path = Some(backup_file_path);
-            }
+            backup_path = Some(backup_file_path);
         }
 
-        // Determine final content based on whether this is a partial edit
-        let (final_content, lines_modified) = if let Some((start_line, end_line)) = args.line_range
-        {
-            // Partial edit: replace specific lines
-            // For non-existent files, treat as empty file (0 lines)
-            let lines: Vec<&str> = if file_exists {
-                original_content.lines().collect()
-            } else {
-                Vec::new()
-            };
-            let total_lines = lines.len();
-
-            if start_line == 0 || start_line > total_lines + 1 {
-                return Err(ToolError::InvalidInput(format!(
-                    "Invalid start line: {}. File has {} lines (1-indexed)",
-                    start_line, total_lines
-                )));
-            }
-
-            // Convert to 0-indexed
-            let start_idx = start_line - 1;
-            let end_idx = std::cmp::min(end_line, total_lines);
-
-            // Split new content into lines
-            let new_lines: Vec<&str> = args.content.lines().collect();
-
-            // Replace the specified range
-            let mut result_lines = Vec::new();
-            result_lines.extend_from_slice(&lines[..start_idx]);
-            result_lines.extend_from_slice(&new_lines);
-            if end_idx < lines.len() {
-                result_lines.extend_from_slice(&lines[end_idx..]);
-            }
-
-            let final_content = result_lines.join("\n");
-            (
-                final_content,
-                Some((start_line, start_line + new_lines.len() - 1)),
-            )
-        } else {
-            // Full file replacement
-            (args.content.clone(), None)
-        };
-
         // Write the content
         fs::write(&path, &final_content)
             .await
@@ -146,9 +238,124 @@ impl EditFileTool {
             backup_path,
             created_new_file: !file_exists,
             lines_modified,
+            diff: unified_diff(
+                original_content.as_deref().unwrap_or(""),
+                &final_content,
+                &args.path,
+            ),
         })
     }
 
+    /// Record a file in the checkpoint, if there is one, before changing it
+    async fn record(&self, path: &Path) -> Result<(), ToolError> {
+        match self.checkpoint {
+            Some(ref checkpoint) => checkpoint.record(path).await,
+            None => Ok(()),
+        }
+    }
+
+    /// The content of the file after the edit, and the lines a partial edit replaced
+    ///
+    /// `original` is the current content, `None` if the file does not exist.
+    fn edited_content(
+        args: &EditFileArgs,
+        original: Option<&str>,
+    ) -> Result<(String, Option<(usize, usize)>), ToolError> {
+        let replacements = args.replacements()?;
+        let modes = [
+            args.content.is_some(),
+            !replacements.is_empty(),
+            args.diff.is_some(),
+        ];
+        match modes.iter().filter(|mode| **mode).count() {
+            0 => {
+                return Err(ToolError::InvalidInput(
+                    "Give content, old_string and new_string, edits or a diff".to_string(),
+                ));
+            }
+            1 => {}
+            _ => {
+                return Err(ToolError::InvalidInput(
+                    "Give only one kind of edit: content, old_string and new_string or edits, or a diff"
+                        .to_string(),
+                ));
+            }
+        }
+        if args.line_range.is_some() && args.content.is_none() {
+            return Err(ToolError::InvalidInput(
+                "line_range needs the content replacing the lines".to_string(),
+            ));
+        }
+
+        if let Some(ref diff) = args.diff {
+            let patched = apply_unified_diff(original.unwrap_or(""), diff)?;
+            return Ok((patched, None));
+        }
+
+        if !replacements.is_empty() {
+            let Some(original) = original else {
+                return Err(ToolError::FileNotFound(args.path.clone()));
+            };
+            let mut content = original.to_string();
+            for (index, replacement) in replacements.iter().enumerate() {
+                content = replacement.apply(&content).map_err(|e| match e {
+                    ToolError::InvalidInput(msg) if replacements.len() > 1 => {
+                        ToolError::InvalidInput(format!(
+                            "Edit {} of {} in {}: {}; no edits were made",
+                            index + 1,
+                            replacements.len(),
+                            args.path,
+                            msg
+                        ))
+                    }
+                    ToolError::InvalidInput(msg) => {
+                        ToolError::InvalidInput(format!("{} in {}", msg, args.path))
+                    }
+                    e => e,
+                })?;
+            }
+            return Ok((content, None));
+        }
+
+        let content = args.content.as_deref().unwrap_or_default();
+        let Some((start_line, end_line)) = args.line_range else {
+            // Full file replacement
+            return Ok((content.to_string(), None));
+        };
+
+        // Partial edit: replace specific lines
+        // For non-existent files, treat as empty file (0 lines)
+        let lines: Vec<&str> = original.unwrap_or("").lines().collect();
+        let total_lines = lines.len();
+
+        if start_line == 0 || start_line > total_lines + 1 {
+            return Err(ToolError::InvalidInput(format!(
+                "Invalid start line: {}. File has {} lines (1-indexed)",
+                start_line, total_lines
+            )));
+        }
+
+        // Convert to 0-indexed
+        let start_idx = start_line - 1;
+        let end_idx = std::cmp::min(end_line, total_lines);
+
+        // Split new content into lines
+        let new_lines: Vec<&str> = content.lines().collect();
+
+        // Replace the specified range
+        let mut result_lines = Vec::new();
+        result_lines.extend_from_slice(&lines[..start_idx]);
+        result_lines.extend_from_slice(&new_lines);
+        if end_idx < lines.len() {
+            result_lines.extend_from_slice(&lines[end_idx..]);
+        }
+
+        Ok((
+            result_lines.join("\n"),
+            Some((start_line, start_line + new_lines.len() - 1)),
+        ))
+    }
+
     /// Validate file path for security
     fn validate_path(&self, path: &str) -> Result<(), ToolError> {
         let path = Path::new(path);
@@ -160,26 +367,14 @@ impl EditFileTool {
             ));
         }
 
-        // Check for absolute paths to sensitive directories
-        let sensitive_paths = [
-            "/etc",
-            "/usr/bin",
-            "/usr/sbin",
-            "/bin",
-            "/sbin",
-            "/System",
-            "/Library",
-            "/Applications",
-        ];
-
-        let path_str = path.to_string_lossy();
-        for sensitive in &sensitive_paths {
-            if path_str.starts_with(sensitive) {
-                return Err(ToolError::PermissionDenied(format!(
-                    "Access to {} is not allowed",
-                    sensitive
-                )));
-            }
+        // Check for sensitive system directories, which the built-in rules
+        // of the permission policy deny even without a confirmation wrapper
+        let request = ToolRequest::new(Self::NAME).with_path(path);
+        if PermissionPolicy::default().decide(&request, Decision::Allow) == Decision::Deny {
+            return Err(ToolError::PermissionDenied(format!(
+                "Access to {} is not allowed",
+                path.display()
+            )));
         }
 
         Ok(())
@@ -201,7 +396,7 @@ impl Tool for EditFileTool {
     async fn definition(&self, _prompt: String) -> ToolDefinition {
         ToolDefinition {
             name: Self::NAME.to_string(),
-            description: "Edits or creates a file with the specified content. Automatically creates the file if it doesn't exist. Supports full file replacement or line range editing with optional backup.".to_string(),
+            description: "Edits or creates a file and returns a unified diff of the changes. Give exactly one of: content to write the whole file (or only the lines in line_range); old_string and new_string to replace text that occurs exactly once in the file (or everywhere with replace_all); edits for several such replacements, made all or none; or diff, a unified diff to apply. Prefer old_string/new_string or edits for changes to existing files. Automatically creates the file if it doesn't exist, with optional backup.".to_string(),
             parameters: json!({
                 "type": "object",
                 "properties": {
@@ -235,15 +430,46 @@ impl Tool for EditFileTool {
                         },
                         "minItems": 2,
                         "maxItems": 2
+                    },
+                    "old_string": {
+                        "type": "string",
+                        "description": "Exact text to replace, including whitespace and indentation; must occur once in the file unless replace_all is set"
+                    },
+                    "new_string": {
+                        "type": "string",
+                        "description": "Text replacing old_string"
+                    },
+                    "replace_all": {
+                        "type": "boolean",
+                        "description": "Replace every occurrence of old_string (default: false)",
+                        "default": false
+                    },
+                    "edits": {
+                        "type": "array",
+                        "description": "Replacements made one after the other; if any does not apply, none are made",
+                        "items": {
+                            "type": "object",
+                            "properties": {
+                                "old_string": { "type": "string" },
+                                "new_string": { "type": "string" },
+                                "replace_all": { "type": "boolean", "default": false }
+                            },
+                            "required": ["old_string", "new_string"]
+                        }
+                    },
+                    "diff": {
+                        "type": "string",
+                        "description": "Unified diff of this file to apply, with @@ hunk headers; context lines are matched near the given line numbers"
                     }
                 },
-                "required": ["path", "content"]
+                "required": ["path"]
             }),
         }
     }
 
-    async fn call(&self, args: Self::Args) -> Result<Self::Output, Self::Error> {
+    async fn call(&self, mut args: Self::Args) -> Result<Self::Output, Self::Error> {
         // Validate path for security
+        args.path = self.resolve(&args.path)?;
         self.validate_path(&args.path)?;
 
         self.edit_file_safe(&args).await
@@ -279,11 +505,12 @@ mod tests {
         let tool = EditFileTool::new();
         let args = EditFileArgs {
             path: file_path.to_string_lossy().to_string(),
-            content: "Hello, World!".to_string(),
+            content: Some("Hello, World!".to_string()),
             create_if_missing: true,
             backup: false,
             encoding: None,
             line_range: None,
+            ..Default::default()
         };
 
         let result = tool.call(args).await;
@@ -307,11 +534,12 @@ mod tests {
         let tool = EditFileTool::new();
         let args = EditFileArgs {
             path: temp_file.path().to_string_lossy().to_string(),
-            content: "New content".to_string(),
+            content: Some("New content".to_string()),
             create_if_missing: false,
             backup: true,
             encoding: None,
             line_range: None,
+            ..Default::default()
         };
 
         let result = tool.call(args).await;
@@ -343,11 +571,12 @@ mod tests {
         let tool = EditFileTool::new();
         let args = EditFileArgs {
             path: temp_file.path().to_string_lossy().to_string(),
-            content: "New Line 2\nNew Line 3".to_string(),
+            content: Some("New Line 2\nNew Line 3".to_string()),
             create_if_missing: false,
             backup: false,
             encoding: None,
             line_range: Some((2, 3)),
+            ..Default::default()
         };
 
         let result = tool.call(args).await;
@@ -392,11 +621,12 @@ mod tests {
         let tool = EditFileTool::new();
         let args = EditFileArgs {
             path: file_path.to_string_lossy().to_string(),
-            content: "Line 1\nLine 2".to_string(),
+            content: Some("Line 1\nLine 2".to_string()),
             create_if_missing: true,
             backup: false,
             encoding: None,
             line_range: Some((1, 1)), // Insert at line 1 of empty file
+            ..Default::default()
         };
 
         let result = tool.call(args).await;
@@ -417,11 +647,12 @@ mod tests {
         let tool = EditFileTool::new();
         let args = EditFileArgs {
             path: "/nonexistent/path/file.txt".to_string(),
-            content: "test".to_string(),
+            content: Some("test".to_string()),
             create_if_missing: false,
             backup: false,
             encoding: None,
             line_range: None,
+            ..Default::default()
         };
 
         let result = tool.call(args).await;
@@ -438,4 +669,140 @@ mod tests {
             );
         }
     }
+
+    #[tokio::test]
+    async fn test_replace_unique_string() {
+        let temp_dir = TempDir::new().unwrap();
+        let file_path = temp_dir.path().join("lib.rs");
+        std::fs::write(&file_path, "let a = 1;\nlet b = 1;\n").unwrap();
+        let path = file_path.to_string_lossy().to_string();
+        let tool = EditFileTool::new();
+
+        // Ambiguous and missing strings change nothing
+        let ambiguous = tool
+            .call(EditFileArgs {
+                path: path.clone(),
+                old_string: Some("= 1;".to_string()),
+                new_string: Some("= 2;".to_string()),
+                ..Default::default()
+            })
+            .await;
+        assert!(matches!(ambiguous, Err(ToolError::InvalidInput(msg)) if msg.contains("2 times")));
+        let missing = tool
+            .call(EditFileArgs {
+                path: path.clone(),
+                old_string: Some("let c".to_string()),
+                new_string: Some("let d".to_string()),
+                ..Default::default()
+            })
+            .await;
+        assert!(matches!(missing, Err(ToolError::InvalidInput(_))));
+
+        let output = tool
+            .call(EditFileArgs {
+                path: path.clone(),
+                old_string: Some("let b = 1;".to_string()),
+                new_string: Some("let b = 2;".to_string()),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        assert!(output.diff.contains("-let b = 1;\n+let b = 2;\n"));
+        assert_eq!(
+            fs::read_to_string(&file_path).await.unwrap(),
+            "let a = 1;\nlet b = 2;\n"
+        );
+
+        tool.call(EditFileArgs {
+            path,
+            old_string: Some("let".to_string()),
+            new_string: Some("const".to_string()),
+            replace_all: true,
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+        assert_eq!(
+            fs::read_to_string(&file_path).await.unwrap(),
+            "const a = 1;\nconst b = 2;\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_multiple_edits_are_all_or_none() {
+        let temp_dir = TempDir::new().unwrap();
+        let file_path = temp_dir.path().join("config.toml");
+        std::fs::write(&file_path, "name = \"vega\"\nversion = \"0.1.0\"\n").unwrap();
+        let path = file_path.to_string_lossy().to_string();
+        let edit = |old: &str, new: &str| Replacement {
+            old_string: old.to_string(),
+            new_string: new.to_string(),
+            replace_all: false,
+        };
+        let tool = EditFileTool::new();
+
+        let result = tool
+            .call(EditFileArgs {
+                path: path.clone(),
+                edits: vec![edit("0.1.0", "0.2.0"), edit("edition", "edition = 2024")],
+                ..Default::default()
+            })
+            .await;
+        assert!(
+            matches!(result, Err(ToolError::InvalidInput(msg)) if msg.starts_with("Edit 2 of 2"))
+        );
+        assert!(
+            fs::read_to_string(&file_path)
+                .await
+                .unwrap()
+                .contains("0.1.0")
+        );
+
+        let output = tool
+            .call(EditFileArgs {
+                path,
+                edits: vec![edit("0.1.0", "0.2.0"), edit("vega", "vega-agent")],
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        assert!(
+            output
+                .diff
+                .contains("+name = \"vega-agent\"\n+version = \"0.2.0\"\n")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_apply_unified_diff() {
+        let temp_dir = TempDir::new().unwrap();
+        let file_path = temp_dir.path().join("notes.txt");
+        std::fs::write(&file_path, "one\ntwo\nthree\n").unwrap();
+        let tool = EditFileTool::new();
+
+        let output = tool
+            .call(EditFileArgs {
+                path: file_path.to_string_lossy().to_string(),
+                diff: Some("@@ -2,2 +2,2 @@\n two\n-three\n+3\n".to_string()),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        assert!(output.diff.contains("-three\n+3\n"));
+        assert_eq!(
+            fs::read_to_string(&file_path).await.unwrap(),
+            "one\ntwo\n3\n"
+        );
+
+        // Several kinds of edit at once are refused
+        let result = tool
+            .call(EditFileArgs {
+                path: file_path.to_string_lossy().to_string(),
+                content: Some("replaced".to_string()),
+                diff: Some("@@ -1 +1 @@\n-one\n+1\n".to_string()),
+                ..Default::default()
+            })
+            .await;
+        assert!(matches!(result, Err(ToolError::InvalidInput(_))));
+    }
 }
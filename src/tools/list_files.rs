@@ -7,6 +7,7 @@ use std::path::Path;
 use tokio::fs;
 
 use super::ToolError;
+use super::workspace::{Workspace, resolve_path};
 
 #[derive(Deserialize)]
 pub struct ListFilesArgs {
@@ -49,11 +50,32 @@ pub struct ListFilesOutput {
 }
 
 #[derive(Deserialize, Serialize)]
-pub struct ListFilesTool;
+pub struct ListFilesTool {
+    /// Directories the paths must stay in
+    #[serde(skip)]
+    workspace: Option<Workspace>,
+}
 
 impl ListFilesTool {
     pub fn new() -> Self {
-        Self
+        Self { workspace: None }
+    }
+
+    /// Resolve paths against a workspace and refuse those outside of it
+    pub fn with_workspace(mut self, workspace: Workspace) -> Self {
+        self.workspace = Some(workspace);
+        self
+    }
+
+    /// Workspace the paths are resolved against
+    pub(super) fn workspace(&self) -> Option<&Workspace> {
+        self.workspace.as_ref()
+    }
+
+    /// The path to use for a path given by the model
+    fn resolve(&self, path: &str) -> Result<String, ToolError> {
+        let path = resolve_path(self.workspace.as_ref(), path)?;
+        Ok(path.to_string_lossy().into_owned())
     }
 
     /// List files in directory with filtering options
@@ -344,7 +366,8 @@ impl Tool for ListFilesTool {
         }
     }
 
-    async fn call(&self, args: Self::Args) -> Result<Self::Output, Self::Error> {
+    async fn call(&self, mut args: Self::Args) -> Result<Self::Output, Self::Error> {
+        args.directory = self.resolve(&args.directory)?;
         self.list_files_recursive(&args).await
     }
 }
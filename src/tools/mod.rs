@@ -15,11 +15,35 @@
 //! - [`ListFilesTool`] - List directory contents with filtering
 //! - [`ReadLogsTool`] - Read and filter log entries
 //!
+//! The file tools take a [`Workspace`] with `with_workspace`, which resolves the
+//! paths they are given and refuses those leading outside of it.
+//! [`EditFileTool::with_checkpoint`] records the files it changes in a
+//! [`TurnCheckpoint`], which [`restore_checkpoint`] rolls back.
+//!
+//! [`BashTool::with_shell`] runs commands in a [`PersistentShell`] that keeps its
+//! working directory and environment between calls; [`ShellSessions`] holds one
+//! per session. [`BashTool::with_sandbox`] confines the commands with a
+//! [`Sandbox`] configured by the project's [`SandboxConfig`].
+//!
 //! ## Confirmed Tools
 //!
-//! For potentially destructive operations, confirmed versions are available:
+//! [`ConfirmedTool`] checks every call of a tool against the [`PermissionPolicy`],
+//! whose allow, ask and deny [`Rule`]s come from the project's and the user's
+//! policy files. Calls the policy asks about need user confirmation:
 //! - [`ConfirmedBashTool`] - Bash tool with user confirmation
 //! - [`ConfirmedEditFileTool`] - Edit tool with user confirmation
+//! - [`ConfirmedMcpTool`] - Tools of external MCP servers with user confirmation
+//!
+//! Edits are confirmed with a diff of the [`FileChange`] they make, which the
+//! user can change in their editor first. Front ends other than the terminal
+//! answer [`ApprovalRequest`]s sent to the [`ApprovalSender`] given with
+//! `with_approvals`.
+//!
+//! ## Progress
+//!
+//! Long-running tools (`bash`, `code_search`) report their [`ToolProgress`] to a
+//! [`ProgressSender`] given with `with_progress`; the MCP server forwards it to
+//! its clients.
 //!
 //! ## Safety Features
 //!
@@ -51,25 +75,46 @@
 // Re-export the rig Tool trait for convenience
 pub use rig::tool::Tool as RigTool;
 
+use std::fmt;
+use std::time::{Duration, Instant};
+use tokio::sync::mpsc;
+
 // Tool modules
 pub mod bash;
+pub mod checkpoint;
 pub mod code_search;
 pub mod confirmed;
+pub mod diff;
 pub mod edit_file;
 pub mod list_files;
+pub mod output;
+pub mod policy;
 pub mod read_file;
 pub mod read_logs;
+pub mod sandbox;
+pub mod shell;
 pub mod web_search;
+pub mod workspace;
 
 // Re-export all tools
 pub use bash::BashTool;
+pub use checkpoint::{RestoredFile, TurnCheckpoint, restore_checkpoint};
 pub use code_search::CodeSearchTool;
-pub use confirmed::{ConfirmedBashTool, ConfirmedEditFileTool};
+pub use confirmed::{
+    Approval, ApprovalRequest, ApprovalSender, ConfirmedBashTool, ConfirmedEditFileTool,
+    ConfirmedMcpTool, ConfirmedTool,
+};
+pub use diff::FileChange;
 pub use edit_file::EditFileTool;
 pub use list_files::ListFilesTool;
+pub use output::{CommandEvent, CommandEventSender, OutputLimit, OutputStream};
+pub use policy::{Decision, PermissionPolicy, PolicyCheck, Rule, ToolRequest};
 pub use read_file::ReadFileTool;
 pub use read_logs::ReadLogsTool;
+pub use sandbox::{Sandbox, SandboxConfig};
+pub use shell::{PersistentShell, ShellSessions};
 pub use web_search::WebSearchTool;
+pub use workspace::Workspace;
 
 /// Common error types for all tools in the system.
 ///
@@ -100,6 +145,99 @@ pub enum ToolError {
     InvalidInput(String),
 }
 
+/// Shortest time between two progress reports of a tool call
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(200);
+
+/// Progress of a long-running tool call, as reported to MCP clients.
+///
+/// `progress` increases with every report; `total` is known only for some tools.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ToolProgress {
+    /// Work done so far
+    pub progress: f64,
+    /// Total work, if known
+    pub total: Option<f64>,
+    /// What the tool is currently doing
+    pub message: Option<String>,
+}
+
+impl fmt::Display for ToolProgress {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // Counts are whole numbers for every Vega tool; show them without a fraction
+        let number = |n: f64| {
+            if n.fract() == 0.0 {
+                format!("{}", n as i64)
+            } else {
+                format!("{:.1}", n)
+            }
+        };
+        let count = match self.total {
+            Some(total) => format!("{}/{}", number(self.progress), number(total)),
+            None => number(self.progress),
+        };
+        match &self.message {
+            Some(message) => write!(f, "{} ({})", message, count),
+            None => write!(f, "{}", count),
+        }
+    }
+}
+
+/// Receives the progress of a tool call
+pub type ProgressSender = mpsc::UnboundedSender<ToolProgress>;
+
+/// Forwards a tool's progress to an optional [`ProgressSender`] without flooding it
+pub(crate) struct ProgressThrottle<'a> {
+    sender: Option<&'a ProgressSender>,
+    last_report: Option<Instant>,
+}
+
+impl<'a> ProgressThrottle<'a> {
+    pub(crate) fn new(sender: Option<&'a ProgressSender>) -> Self {
+        Self {
+            sender,
+            last_report: None,
+        }
+    }
+
+    /// Report progress unless the previous report was less than `PROGRESS_INTERVAL` ago
+    pub(crate) fn report(&mut self, progress: impl FnOnce() -> ToolProgress) {
+        let Some(sender) = self.sender else {
+            return;
+        };
+        if self
+            .last_report
+            .is_some_and(|last| last.elapsed() < PROGRESS_INTERVAL)
+        {
+            return;
+        }
+        self.last_report = Some(Instant::now());
+        // Nobody listening anymore is not an error for the tool
+        let _ = sender.send(progress());
+    }
+}
+
+/// Await a tool call, handing every progress update it sends to `forward`.
+///
+/// Updates sent just before the call finished are forwarded before returning,
+/// so they always precede the call's result.
+pub async fn forward_progress<F: Future>(
+    call: F,
+    mut updates: mpsc::UnboundedReceiver<ToolProgress>,
+    mut forward: impl FnMut(ToolProgress),
+) -> F::Output {
+    tokio::pin!(call);
+    let output = loop {
+        tokio::select! {
+            output = &mut call => break output,
+            Some(update) = updates.recv() => forward(update),
+        }
+    };
+    while let Ok(update) = updates.try_recv() {
+        forward(update);
+    }
+    output
+}
+
 /// Creates a collection of all available tools for use by agents.
 ///
 /// This function instantiates all available tools and returns them in a format
@@ -140,4 +278,21 @@ mod tests {
         let tools = create_all_tools();
         assert_eq!(tools.len(), 7);
     }
+
+    #[test]
+    fn test_tool_progress_display() {
+        let progress = ToolProgress {
+            progress: 3.0,
+            total: Some(50.0),
+            message: Some("src/main.rs".to_string()),
+        };
+        assert_eq!(progress.to_string(), "src/main.rs (3/50)");
+
+        let progress = ToolProgress {
+            progress: 2.5,
+            total: None,
+            message: None,
+        };
+        assert_eq!(progress.to_string(), "2.5");
+    }
 }
@@ -0,0 +1,619 @@
+//! Output of the commands run by the bash tool
+//!
+//! While a command runs, its lines are reported as [`ToolProgress`] and, to
+//! whoever shows the output live, as [`CommandEvent`]s. What is returned to the
+//! model is capped by an [`OutputLimit`]: only the first and last lines of each
+//! stream are kept, and the complete output is written to a log file the model
+//! can read with the `read_file` tool. The logs are kept in the workspace's
+//! settings directory, readable only by the user, and deleted after a day.
+
+use std::borrow::Cow;
+use std::collections::VecDeque;
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncRead, BufReader};
+use tokio::sync::mpsc;
+use uuid::Uuid;
+
+use super::bash::BashOutput;
+use super::workspace::Workspace;
+use super::{ProgressSender, ProgressThrottle, ToolProgress};
+use crate::project::SETTINGS_DIRECTORY;
+
+/// Longest output line quoted in a progress report
+const PROGRESS_LINE_LIMIT: usize = 120;
+
+/// Longest output line returned to the model, in bytes
+const LINE_BYTE_LIMIT: usize = 2000;
+
+/// Longest output line read from a command, in bytes; the rest of a longer
+/// line is dropped as it is read
+const LINE_READ_LIMIT: usize = 64 * 1024;
+
+/// Lines at the start of each stream returned to the model by default
+pub const DEFAULT_HEAD_LINES: usize = 50;
+
+/// Lines at the end of each stream returned to the model by default
+pub const DEFAULT_TAIL_LINES: usize = 150;
+
+/// Directory, in the workspace's settings directory, holding full output logs
+const LOG_DIRECTORY: &str = "output";
+
+/// Directory, under the system's temporary directory, holding the full output
+/// logs of commands run without a workspace
+const TEMP_LOG_DIRECTORY: &str = "vega-output";
+
+/// Age after which a full output log is deleted
+const LOG_MAX_AGE: Duration = Duration::from_secs(24 * 60 * 60);
+
+static NEXT_COMMAND_ID: AtomicU64 = AtomicU64::new(1);
+
+/// The stream a command printed a line on
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputStream {
+    Stdout,
+    Stderr,
+}
+
+/// A line of output and the stream it was printed on
+pub(super) type OutputLine = (OutputStream, Vec<u8>);
+
+/// What a running command reports to whoever shows its output live
+#[derive(Debug, Clone, PartialEq)]
+pub enum CommandEvent {
+    /// A command started
+    Started { id: u64, command: String },
+    /// A command printed a line, given without its line ending
+    Output {
+        id: u64,
+        stream: OutputStream,
+        line: String,
+    },
+    /// A command finished; `exit_code` is `None` when it was cancelled
+    Finished {
+        id: u64,
+        exit_code: Option<i32>,
+        timed_out: bool,
+    },
+}
+
+/// Channel receiving the [`CommandEvent`]s of running commands
+pub type CommandEventSender = mpsc::UnboundedSender<CommandEvent>;
+
+/// How much of each output stream is returned to the model
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OutputLimit {
+    /// Lines kept from the start of the stream
+    pub head_lines: usize,
+    /// Lines kept from the end of the stream
+    pub tail_lines: usize,
+}
+
+impl Default for OutputLimit {
+    fn default() -> Self {
+        Self {
+            head_lines: DEFAULT_HEAD_LINES,
+            tail_lines: DEFAULT_TAIL_LINES,
+        }
+    }
+}
+
+/// Directory the full output logs are written to, inside `workspace` so the
+/// file tools confined to it can read them
+pub(super) fn log_directory(workspace: Option<&Workspace>) -> PathBuf {
+    match workspace {
+        Some(workspace) => workspace
+            .root()
+            .join(SETTINGS_DIRECTORY)
+            .join(LOG_DIRECTORY),
+        None => std::env::temp_dir().join(TEMP_LOG_DIRECTORY),
+    }
+}
+
+/// Send every line of a pipe, including its newline, until it is closed
+///
+/// Lines are cut to [`LINE_READ_LIMIT`] while they are read, so a command
+/// printing without newlines does not fill the memory.
+pub(super) async fn read_lines(
+    pipe: impl AsyncRead + Unpin,
+    stream: OutputStream,
+    lines: mpsc::UnboundedSender<OutputLine>,
+) {
+    let mut reader = BufReader::new(pipe);
+    let mut line = Vec::new();
+    let mut cut = false;
+    loop {
+        let (read, complete) = match reader.fill_buf().await {
+            Ok([]) | Err(_) => break,
+            Ok(chunk) => {
+                let (text, read, complete) = match chunk.iter().position(|&b| b == b'\n') {
+                    Some(newline) => (&chunk[..newline], newline + 1, true),
+                    None => (chunk, chunk.len(), false),
+                };
+                let room = LINE_READ_LIMIT - line.len();
+                line.extend_from_slice(&text[..text.len().min(room)]);
+                cut |= text.len() > room;
+                (read, complete)
+            }
+        };
+        reader.consume(read);
+
+        if complete {
+            let line = end_line(std::mem::take(&mut line), &mut cut, b"\n");
+            if lines.send((stream, line)).is_err() {
+                return;
+            }
+        }
+    }
+    if !line.is_empty() || cut {
+        let _ = lines.send((stream, end_line(line, &mut cut, b"")));
+    }
+}
+
+/// A line read by [`read_lines`], marking where it was cut
+fn end_line(mut line: Vec<u8>, cut: &mut bool, ending: &[u8]) -> Vec<u8> {
+    if std::mem::take(cut) {
+        line.extend_from_slice(b"...");
+    }
+    line.extend_from_slice(ending);
+    line
+}
+
+/// Output read from a running command so far
+pub(super) struct CapturedOutput<'a> {
+    id: u64,
+    stdout: KeptLines,
+    stderr: KeptLines,
+    limit: OutputLimit,
+    log: Option<OutputLog>,
+    line_count: usize,
+    throttle: ProgressThrottle<'a>,
+    events: Option<&'a CommandEventSender>,
+    finished: bool,
+}
+
+impl<'a> CapturedOutput<'a> {
+    /// Start capturing the output of a command
+    pub(super) fn new(
+        command: &str,
+        limit: OutputLimit,
+        log_directory: &Path,
+        progress: Option<&'a ProgressSender>,
+        events: Option<&'a CommandEventSender>,
+    ) -> Self {
+        let output = Self {
+            id: NEXT_COMMAND_ID.fetch_add(1, Ordering::Relaxed),
+            stdout: KeptLines::default(),
+            stderr: KeptLines::default(),
+            limit,
+            log: OutputLog::create(log_directory),
+            line_count: 0,
+            throttle: ProgressThrottle::new(progress),
+            events,
+            finished: false,
+        };
+        output.send(CommandEvent::Started {
+            id: output.id,
+            command: command.to_string(),
+        });
+        output
+    }
+
+    /// Read lines until both pipes are closed
+    pub(super) async fn collect(&mut self, lines: &mut mpsc::UnboundedReceiver<OutputLine>) {
+        while let Some((stream, line)) = lines.recv().await {
+            self.push(stream, &line);
+        }
+    }
+
+    /// Record a line of output, reporting it as progress and to the event channel
+    pub(super) fn push(&mut self, stream: OutputStream, line: &[u8]) {
+        self.line_count += 1;
+        let line_count = self.line_count;
+        self.throttle.report(|| ToolProgress {
+            progress: line_count as f64,
+            total: None,
+            message: Some(progress_line(line)),
+        });
+        self.send(CommandEvent::Output {
+            id: self.id,
+            stream,
+            line: String::from_utf8_lossy(line)
+                .trim_end_matches(['\n', '\r'])
+                .to_string(),
+        });
+
+        // A log that cannot be written is given up rather than left incomplete
+        if let Some(mut log) = self.log.take() {
+            match log.file.write_all(line) {
+                Ok(()) => self.log = Some(log),
+                Err(_) => {
+                    log.close(false);
+                }
+            }
+        }
+        match stream {
+            OutputStream::Stdout => self.stdout.push(line, self.limit),
+            OutputStream::Stderr => self.stderr.push(line, self.limit),
+        }
+    }
+
+    /// The result of the command, with its output capped for the model
+    pub(super) fn finish(
+        &mut self,
+        command: &str,
+        exit_code: i32,
+        timed_out: bool,
+        shell_exited: bool,
+    ) -> BashOutput {
+        self.finished = true;
+        self.send(CommandEvent::Finished {
+            id: self.id,
+            exit_code: Some(exit_code),
+            timed_out,
+        });
+
+        let truncated = self.stdout.is_truncated() || self.stderr.is_truncated();
+        let full_output = self.log.take().and_then(|log| log.close(truncated));
+        BashOutput {
+            stdout: std::mem::take(&mut self.stdout).render(full_output.as_deref()),
+            stderr: std::mem::take(&mut self.stderr).render(full_output.as_deref()),
+            exit_code,
+            command: command.to_string(),
+            success: exit_code == 0 && !timed_out,
+            timed_out,
+            shell_exited,
+            full_output: full_output.map(|path| path.display().to_string()),
+        }
+    }
+
+    fn send(&self, event: CommandEvent) {
+        if let Some(events) = self.events {
+            // Nobody showing the output anymore is not an error
+            let _ = events.send(event);
+        }
+    }
+}
+
+impl Drop for CapturedOutput<'_> {
+    fn drop(&mut self) {
+        if !self.finished {
+            self.send(CommandEvent::Finished {
+                id: self.id,
+                exit_code: None,
+                timed_out: false,
+            });
+        }
+        if let Some(log) = self.log.take() {
+            log.close(false);
+        }
+    }
+}
+
+/// The lines of one stream returned to the model: the first and the last ones
+#[derive(Debug, Default)]
+struct KeptLines {
+    head: Vec<u8>,
+    head_lines: usize,
+    tail: VecDeque<Vec<u8>>,
+    omitted: usize,
+    shortened: bool,
+}
+
+impl KeptLines {
+    fn push(&mut self, line: &[u8], limit: OutputLimit) {
+        let line = shorten_line(line);
+        self.shortened |= matches!(line, Cow::Owned(_));
+
+        if self.head_lines < limit.head_lines {
+            self.head.extend_from_slice(&line);
+            self.head_lines += 1;
+        } else {
+            self.tail.push_back(line.into_owned());
+            if self.tail.len() > limit.tail_lines {
+                self.tail.pop_front();
+                self.omitted += 1;
+            }
+        }
+    }
+
+    fn is_truncated(&self) -> bool {
+        self.omitted > 0 || self.shortened
+    }
+
+    /// The kept lines, noting where lines were left out
+    fn render(self, full_output: Option<&Path>) -> String {
+        let mut text = self.head;
+        if self.omitted > 0 {
+            if !text.is_empty() && !text.ends_with(b"\n") {
+                text.push(b'\n');
+            }
+            let note = match full_output {
+                Some(path) => format!(
+                    "[... {} lines omitted, the full output is in {} ...]\n",
+                    self.omitted,
+                    path.display()
+                ),
+                None => format!("[... {} lines omitted ...]\n", self.omitted),
+            };
+            text.extend_from_slice(note.as_bytes());
+        }
+        for line in self.tail {
+            text.extend_from_slice(&line);
+        }
+        String::from_utf8_lossy(&text).to_string()
+    }
+}
+
+/// A line cut to [`LINE_BYTE_LIMIT`], keeping its line ending
+fn shorten_line(line: &[u8]) -> Cow<'_, [u8]> {
+    if line.len() <= LINE_BYTE_LIMIT {
+        return Cow::Borrowed(line);
+    }
+    let mut short = line[..LINE_BYTE_LIMIT].to_vec();
+    short.extend_from_slice(b"...");
+    if line.ends_with(b"\n") {
+        short.push(b'\n');
+    }
+    Cow::Owned(short)
+}
+
+/// An output line as quoted in a progress report
+fn progress_line(line: &[u8]) -> String {
+    let line = String::from_utf8_lossy(line);
+    let line = line.trim();
+    match line.char_indices().nth(PROGRESS_LINE_LIMIT) {
+        Some((end, _)) => format!("{}...", &line[..end]),
+        None => line.to_string(),
+    }
+}
+
+/// File receiving every line a command prints, in the order it was printed
+struct OutputLog {
+    path: PathBuf,
+    file: BufWriter<File>,
+}
+
+impl OutputLog {
+    /// Create a log in `directory`, if it can be written, deleting old ones
+    fn create(directory: &Path) -> Option<Self> {
+        private_directory(directory).ok()?;
+        remove_old_logs(directory);
+        let path = directory.join(format!("{}.log", Uuid::new_v4().simple()));
+        let mut options = OpenOptions::new();
+        options.write(true).create_new(true);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+            options.mode(0o600);
+        }
+        let file = options.open(&path).ok()?;
+        Some(Self {
+            path,
+            file: BufWriter::new(file),
+        })
+    }
+
+    /// Close the log, returning its path if it is kept
+    fn close(mut self, keep: bool) -> Option<PathBuf> {
+        if keep && self.file.flush().is_ok() {
+            return Some(self.path);
+        }
+        drop(self.file);
+        let _ = std::fs::remove_file(&self.path);
+        None
+    }
+}
+
+/// Create `directory` accessible only by the user, refusing one owned by
+/// someone else, e.g. planted in the shared temporary directory
+fn private_directory(directory: &Path) -> io::Result<()> {
+    if let Some(parent) = directory.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    let mut builder = std::fs::DirBuilder::new();
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::DirBuilderExt;
+        builder.mode(0o700);
+    }
+    match builder.create(directory) {
+        // Keep the logs out of version control when they are in the project
+        Ok(()) => std::fs::write(directory.join(".gitignore"), "*\n")?,
+        Err(e) if e.kind() == io::ErrorKind::AlreadyExists => {}
+        Err(e) => return Err(e),
+    }
+
+    let metadata = std::fs::symlink_metadata(directory)?;
+    if !metadata.is_dir() {
+        return Err(io::Error::other("the log directory is not a directory"));
+    }
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::{MetadataExt, PermissionsExt};
+        // SAFETY: geteuid has no preconditions and cannot fail
+        if metadata.uid() != unsafe { libc::geteuid() } {
+            return Err(io::Error::other(
+                "the log directory belongs to another user",
+            ));
+        }
+        if metadata.mode() & 0o077 != 0 {
+            std::fs::set_permissions(directory, std::fs::Permissions::from_mode(0o700))?;
+        }
+    }
+    Ok(())
+}
+
+/// Delete the logs in `directory` older than [`LOG_MAX_AGE`]
+fn remove_old_logs(directory: &Path) {
+    let Ok(entries) = std::fs::read_dir(directory) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if path.extension().is_none_or(|extension| extension != "log") {
+            continue;
+        }
+        let expired = entry
+            .metadata()
+            .and_then(|metadata| metadata.modified())
+            .ok()
+            .and_then(|modified| modified.elapsed().ok())
+            .is_some_and(|age| age > LOG_MAX_AGE);
+        if expired {
+            let _ = std::fs::remove_file(path);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn limit(head_lines: usize, tail_lines: usize) -> OutputLimit {
+        OutputLimit {
+            head_lines,
+            tail_lines,
+        }
+    }
+
+    #[test]
+    fn test_output_within_limit_is_returned_whole() {
+        let logs = tempfile::tempdir().unwrap();
+        let mut output = CapturedOutput::new("true", limit(2, 2), logs.path(), None, None);
+        output.push(OutputStream::Stdout, b"one\n");
+        output.push(OutputStream::Stderr, b"oops\n");
+        output.push(OutputStream::Stdout, b"two\n");
+
+        let result = output.finish("true", 0, false, false);
+        assert_eq!(result.stdout, "one\ntwo\n");
+        assert_eq!(result.stderr, "oops\n");
+        assert!(result.full_output.is_none());
+    }
+
+    #[test]
+    fn test_long_output_keeps_head_and_tail() {
+        let logs = tempfile::tempdir().unwrap();
+        let mut output = CapturedOutput::new("seq 10", limit(2, 3), logs.path(), None, None);
+        for n in 1..=10 {
+            output.push(OutputStream::Stdout, format!("{}\n", n).as_bytes());
+        }
+
+        let result = output.finish("seq 10", 0, false, false);
+        let path = result.full_output.expect("full output saved");
+        assert_eq!(
+            result.stdout,
+            format!(
+                "1\n2\n[... 5 lines omitted, the full output is in {} ...]\n8\n9\n10\n",
+                path
+            )
+        );
+        let full = std::fs::read_to_string(&path).unwrap();
+        assert_eq!(full.lines().count(), 10);
+    }
+
+    #[test]
+    fn test_logs_are_kept_in_the_workspace() {
+        let project = tempfile::tempdir().unwrap();
+        let workspace = Workspace::new(project.path()).unwrap();
+        let directory = log_directory(Some(&workspace));
+        assert!(directory.starts_with(workspace.root()));
+
+        let log = OutputLog::create(&directory).expect("log created");
+        let path = log.close(true).unwrap();
+        assert!(workspace.resolve(&path.to_string_lossy()).is_ok());
+        assert_eq!(
+            std::fs::read_to_string(directory.join(".gitignore")).unwrap(),
+            "*\n"
+        );
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = |path: &Path| std::fs::metadata(path).unwrap().permissions().mode() & 0o777;
+            assert_eq!(mode(&directory), 0o700);
+            assert_eq!(mode(&path), 0o600);
+        }
+    }
+
+    #[test]
+    fn test_old_logs_are_deleted() {
+        let logs = tempfile::tempdir().unwrap();
+        let old = logs.path().join("old.log");
+        let other = logs.path().join("notes.txt");
+        std::fs::write(&old, "old").unwrap();
+        std::fs::write(&other, "notes").unwrap();
+        let long_ago = std::time::SystemTime::now() - LOG_MAX_AGE * 2;
+        for path in [&old, &other] {
+            File::options()
+                .write(true)
+                .open(path)
+                .unwrap()
+                .set_modified(long_ago)
+                .unwrap();
+        }
+
+        let log = OutputLog::create(logs.path()).expect("log created");
+        let path = log.close(true).unwrap();
+        assert!(!old.exists());
+        assert!(other.exists());
+        assert!(path.exists());
+    }
+
+    #[test]
+    fn test_events_follow_the_command() {
+        let (events, mut received) = mpsc::unbounded_channel();
+        let logs = tempfile::tempdir().unwrap();
+        let mut output =
+            CapturedOutput::new("echo hi", limit(2, 2), logs.path(), None, Some(&events));
+        output.push(OutputStream::Stdout, b"hi\r\n");
+        output.finish("echo hi", 0, false, false);
+
+        let Some(CommandEvent::Started { id, command }) = received.try_recv().ok() else {
+            panic!("expected the command to start");
+        };
+        assert_eq!(command, "echo hi");
+        assert_eq!(
+            received.try_recv().unwrap(),
+            CommandEvent::Output {
+                id,
+                stream: OutputStream::Stdout,
+                line: "hi".to_string()
+            }
+        );
+        assert_eq!(
+            received.try_recv().unwrap(),
+            CommandEvent::Finished {
+                id,
+                exit_code: Some(0),
+                timed_out: false
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn test_lines_are_cut_while_read() {
+        let mut pipe = "x".repeat(LINE_READ_LIMIT * 3).into_bytes();
+        pipe.extend_from_slice(b"\nshort\nlast");
+        let (sender, mut received) = mpsc::unbounded_channel();
+        read_lines(pipe.as_slice(), OutputStream::Stdout, sender).await;
+
+        let (_, long) = received.recv().await.unwrap();
+        assert_eq!(long.len(), LINE_READ_LIMIT + 4);
+        assert!(long.ends_with(b"...\n"));
+        assert_eq!(received.recv().await.unwrap().1, b"short\n");
+        assert_eq!(received.recv().await.unwrap().1, b"last");
+        assert!(received.recv().await.is_none());
+    }
+
+    #[test]
+    fn test_overlong_lines_are_shortened() {
+        let line = format!("{}\n", "x".repeat(LINE_BYTE_LIMIT + 10));
+        let short = shorten_line(line.as_bytes());
+        assert_eq!(short.len(), LINE_BYTE_LIMIT + 4);
+        assert!(short.ends_with(b"...\n"));
+    }
+}
@@ -0,0 +1,569 @@
+//! Permission policy for tool calls
+//!
+//! Before a tool runs, its call is checked against allow, ask and deny rules.
+//! Rules come from Vega's built-in list, the user's policy file
+//! (`~/.config/vega/permissions.json`), the project's
+//! (`.vega/permissions.json`) and the rules allowed at the confirmation prompt
+//! for the rest of the session. Every rule matching a call counts and the most
+//! restrictive decision wins, so no allow rule can override a deny. Calls no
+//! rule matches get the tool's default decision.
+//!
+//! ```json
+//! {
+//!   "rules": [
+//!     { "tool": "bash", "command": "cargo test", "decision": "allow" },
+//!     { "tool": "bash", "command": "git push", "decision": "deny" },
+//!     { "tool": "edit_file", "path": "src/**", "decision": "allow" },
+//!     { "tool": "fetch__*", "host": "docs.rs", "decision": "allow" }
+//!   ]
+//! }
+//! ```
+
+use anyhow::{Context, Result};
+use glob::{MatchOptions, Pattern};
+use reqwest::Url;
+use rig::tool::Tool;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use super::bash::simple_commands;
+use super::diff::FileChange;
+use super::{EditFileTool, ToolError};
+use crate::project::SETTINGS_DIRECTORY;
+
+/// Name of the policy files in `.vega` and the user's configuration directory
+pub const POLICY_FILE: &str = "permissions.json";
+
+/// Directories `edit_file` may not write to, whatever the policy files say
+const SENSITIVE_PATHS: [&str; 8] = [
+    "/etc",
+    "/usr/bin",
+    "/usr/sbin",
+    "/bin",
+    "/sbin",
+    "/System",
+    "/Library",
+    "/Applications",
+];
+
+/// `*` stays within a directory; `**` crosses directories
+const PATH_MATCH_OPTIONS: MatchOptions = MatchOptions {
+    case_sensitive: true,
+    require_literal_separator: true,
+    require_literal_leading_dot: false,
+};
+
+/// What happens to a tool call
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Decision {
+    /// Run without asking
+    Allow,
+    /// Ask the user first
+    Ask,
+    /// Refuse to run
+    Deny,
+}
+
+/// A rule of the policy, matching the calls of a tool that satisfy all of its conditions
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct Rule {
+    /// Tool name, or a glob such as `github__*`
+    pub tool: String,
+    /// Start of the command, in whole words; each simple command of a
+    /// command line is matched on its own
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub command: Option<String>,
+    /// Glob of the path, absolute or relative to the project root
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub path: Option<String>,
+    /// Host of the URL, matching its subdomains too
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub host: Option<String>,
+    pub decision: Decision,
+}
+
+impl Rule {
+    /// A rule matching every call of a tool
+    pub fn new(tool: impl Into<String>, decision: Decision) -> Self {
+        Self {
+            tool: tool.into(),
+            command: None,
+            path: None,
+            host: None,
+            decision,
+        }
+    }
+
+    /// Only match commands starting with `prefix`
+    pub fn with_command(mut self, prefix: impl Into<String>) -> Self {
+        self.command = Some(prefix.into());
+        self
+    }
+
+    /// Only match paths matching the glob `pattern`
+    pub fn with_path(mut self, pattern: impl Into<String>) -> Self {
+        self.path = Some(pattern.into());
+        self
+    }
+
+    /// Only match URLs on `host` or its subdomains
+    pub fn with_host(mut self, host: impl Into<String>) -> Self {
+        self.host = Some(host.into());
+        self
+    }
+
+    /// Check that the globs of the rule are valid
+    fn validate(&self) -> Result<()> {
+        Pattern::new(&self.tool).with_context(|| format!("Invalid tool pattern in {}", self))?;
+        if let Some(ref path) = self.path {
+            Pattern::new(path).with_context(|| format!("Invalid path pattern in {}", self))?;
+        }
+        Ok(())
+    }
+
+    /// Whether the rule applies to a request, resolving relative paths against `root`
+    fn matches(&self, request: &ToolRequest, root: &Path) -> bool {
+        let tool_matches = Pattern::new(&self.tool).is_ok_and(|tool| tool.matches(&request.tool));
+        let command_matches = match (&self.command, &request.command) {
+            (None, _) => true,
+            (Some(prefix), Some(command)) => starts_with_words(command, prefix),
+            (Some(_), None) => false,
+        };
+        let path_matches = match (&self.path, &request.path) {
+            (None, _) => true,
+            (Some(pattern), Some(path)) => path_matches(pattern, path, root),
+            (Some(_), None) => false,
+        };
+        let host_matches = match (&self.host, request.url.as_ref().and_then(Url::host_str)) {
+            (None, _) => true,
+            (Some(host), Some(url_host)) => {
+                url_host == host || url_host.ends_with(&format!(".{}", host))
+            }
+            (Some(_), None) => false,
+        };
+        tool_matches && command_matches && path_matches && host_matches
+    }
+}
+
+impl fmt::Display for Rule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.tool)?;
+        if let Some(ref command) = self.command {
+            write!(f, " `{}`", command)?;
+        }
+        if let Some(ref path) = self.path {
+            write!(f, " on {}", path)?;
+        }
+        if let Some(ref host) = self.host {
+            write!(f, " for {}", host)?;
+        }
+        Ok(())
+    }
+}
+
+/// Contents of a policy file
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
+pub struct PolicyFile {
+    #[serde(default)]
+    pub rules: Vec<Rule>,
+}
+
+impl PolicyFile {
+    /// Read a policy file, which may be missing
+    pub fn load(path: &Path) -> Result<Self> {
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let content = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        let file: Self = serde_json::from_str(&content)
+            .with_context(|| format!("Invalid permission policy in {}", path.display()))?;
+        for rule in &file.rules {
+            rule.validate()
+                .with_context(|| format!("Invalid permission policy in {}", path.display()))?;
+        }
+        Ok(file)
+    }
+
+    /// Write the policy file, creating its directory
+    pub fn save(&self, path: &Path) -> Result<()> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)
+                .with_context(|| format!("Failed to create {}", dir.display()))?;
+        }
+        let content = serde_json::to_string_pretty(self)?;
+        fs::write(path, content + "\n")
+            .with_context(|| format!("Failed to write {}", path.display()))
+    }
+}
+
+/// What a tool call does, as far as the policy is concerned
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ToolRequest {
+    /// Name the tool is called by
+    pub tool: String,
+    /// Command line the call runs
+    pub command: Option<String>,
+    /// Absolute path the call reads or writes
+    pub path: Option<PathBuf>,
+    /// URL the call fetches
+    pub url: Option<Url>,
+}
+
+impl ToolRequest {
+    pub fn new(tool: impl Into<String>) -> Self {
+        Self {
+            tool: tool.into(),
+            ..Default::default()
+        }
+    }
+
+    pub fn with_command(mut self, command: impl Into<String>) -> Self {
+        self.command = Some(command.into());
+        self
+    }
+
+    /// The path, relative to the current directory unless absolute
+    pub fn with_path(mut self, path: impl AsRef<Path>) -> Self {
+        let path = path.as_ref();
+        self.path = Some(std::path::absolute(path).unwrap_or_else(|_| path.to_path_buf()));
+        self
+    }
+
+    /// The URL, ignored unless it is a valid http(s) URL
+    pub fn with_url(mut self, url: &str) -> Self {
+        self.url = Url::parse(url)
+            .ok()
+            .filter(|url| matches!(url.scheme(), "http" | "https"));
+        self
+    }
+
+    /// The same request for one simple command of its command line
+    fn for_command(&self, command: &str) -> Self {
+        Self {
+            command: Some(command.to_string()),
+            ..self.clone()
+        }
+    }
+}
+
+/// A tool whose calls are checked against the permission policy
+pub trait PolicyCheck: Tool<Error = ToolError> {
+    /// Decision for the calls no rule matches
+    const DEFAULT_DECISION: Decision;
+
+    /// What a call does, to match it against the rules
+    fn request(&self, args: &Self::Args) -> ToolRequest;
+
+    /// What a call does, in words for the confirmation prompt
+    fn describe(&self, args: &Self::Args) -> String;
+
+    /// The change a call would make to a file, shown in the confirmation
+    /// prompt; an error means the call would fail, so nobody is asked
+    fn preview(&self, _args: &Self::Args) -> Result<Option<FileChange>, ToolError> {
+        Ok(None)
+    }
+
+    /// The call writing `content` to the file instead, after the user
+    /// changed the previewed content
+    fn revise(&self, args: Self::Args, _content: String) -> Self::Args {
+        args
+    }
+}
+
+/// The rules deciding which tool calls run
+#[derive(Debug)]
+pub struct PermissionPolicy {
+    /// Directory relative path patterns are resolved against
+    root: PathBuf,
+    /// Built-in rules and those of the policy files
+    rules: Vec<Rule>,
+    /// Rules allowed at the confirmation prompt
+    session: Mutex<Vec<Rule>>,
+}
+
+impl PermissionPolicy {
+    /// A policy with only the built-in rules, resolving relative paths against `root`
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        let rules = SENSITIVE_PATHS
+            .into_iter()
+            .flat_map(|dir| {
+                [dir.to_string(), format!("{}/**", dir)]
+                    .map(|path| Rule::new(EditFileTool::NAME, Decision::Deny).with_path(path))
+            })
+            .collect();
+        Self {
+            root: root.into(),
+            rules,
+            session: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// The policy of the project at `root`, with the user's rules
+    pub fn load(root: &Path) -> Result<Self> {
+        let mut policy = Self::new(root);
+        if let Some(path) = user_policy_file() {
+            policy.rules.extend(PolicyFile::load(&path)?.rules);
+        }
+        policy
+            .rules
+            .extend(PolicyFile::load(&policy.project_file())?.rules);
+        Ok(policy)
+    }
+
+    /// Add rules, e.g. ones not kept in a file
+    pub fn with_rules(mut self, rules: impl IntoIterator<Item = Rule>) -> Self {
+        self.rules.extend(rules);
+        self
+    }
+
+    /// The policy file of the project, which rules allowed for good are saved to
+    pub fn project_file(&self) -> PathBuf {
+        self.root.join(SETTINGS_DIRECTORY).join(POLICY_FILE)
+    }
+
+    /// Decide a request; `default` applies when no rule matches
+    ///
+    /// Each simple command of a command line is decided on its own and the
+    /// most restrictive decision is taken, so `cargo test && git push` asks
+    /// even when `cargo test` is allowed.
+    pub fn decide(&self, request: &ToolRequest, default: Decision) -> Decision {
+        let commands = request.command.as_deref().map(simple_commands);
+        match commands {
+            Some(commands) if !commands.is_empty() => commands
+                .into_iter()
+                .map(|command| self.decide_one(&request.for_command(command), default))
+                .max()
+                .unwrap_or(default),
+            _ => self.decide_one(request, default),
+        }
+    }
+
+    fn decide_one(&self, request: &ToolRequest, default: Decision) -> Decision {
+        let session = self.session.lock().unwrap();
+        self.rules
+            .iter()
+            .chain(session.iter())
+            .filter(|rule| rule.matches(request, &self.root))
+            .map(|rule| rule.decision)
+            .max()
+            .unwrap_or(default)
+    }
+
+    /// Rules that would let the request run without asking from now on
+    pub fn rules_allowing(&self, request: &ToolRequest, default: Decision) -> Vec<Rule> {
+        let Some(ref command) = request.command else {
+            let mut rule = Rule::new(&request.tool, Decision::Allow);
+            if let Some(ref path) = request.path {
+                let path = path.strip_prefix(&self.root).unwrap_or(path);
+                rule = rule.with_path(Pattern::escape(&path.to_string_lossy()));
+            }
+            return vec![rule];
+        };
+
+        let mut rules: Vec<Rule> = Vec::new();
+        for command in simple_commands(command) {
+            if self.decide_one(&request.for_command(command), default) == Decision::Allow {
+                continue;
+            }
+            let rule =
+                Rule::new(&request.tool, Decision::Allow).with_command(command_prefix(command));
+            if !rules.contains(&rule) {
+                rules.push(rule);
+            }
+        }
+        rules
+    }
+
+    /// Allow calls matching `rules` for the rest of the session
+    pub fn allow_for_session(&self, rules: &[Rule]) {
+        self.session.lock().unwrap().extend_from_slice(rules);
+    }
+
+    /// Allow calls matching `rules` for the session and save them to the
+    /// project's policy file
+    pub fn allow_always(&self, rules: &[Rule]) -> Result<PathBuf> {
+        self.allow_for_session(rules);
+
+        let path = self.project_file();
+        let mut file = PolicyFile::load(&path)?;
+        for rule in rules {
+            if !file.rules.contains(rule) {
+                file.rules.push(rule.clone());
+            }
+        }
+        file.save(&path)?;
+        Ok(path)
+    }
+}
+
+impl Default for PermissionPolicy {
+    /// The built-in rules, resolving relative paths against the current directory
+    fn default() -> Self {
+        Self::new(std::env::current_dir().unwrap_or_default())
+    }
+}
+
+/// The user's policy file, in `$XDG_CONFIG_HOME/vega` or `~/.config/vega`
+fn user_policy_file() -> Option<PathBuf> {
+    let config_dir = std::env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))?;
+    Some(config_dir.join("vega").join(POLICY_FILE))
+}
+
+/// Whether `command` starts with the whole words of `prefix`
+fn starts_with_words(command: &str, prefix: &str) -> bool {
+    let prefix = prefix.trim();
+    match command.trim().strip_prefix(prefix) {
+        Some(rest) => rest.is_empty() || rest.starts_with(char::is_whitespace),
+        None => false,
+    }
+}
+
+/// Whether an absolute path matches a glob, relative globs being resolved against `root`
+fn path_matches(pattern: &str, path: &Path, root: &Path) -> bool {
+    let pattern = if Path::new(pattern).is_absolute() {
+        pattern.to_string()
+    } else {
+        let root = Pattern::escape(&root.to_string_lossy());
+        format!("{}/{}", root.trim_end_matches('/'), pattern)
+    };
+    Pattern::new(&pattern).is_ok_and(|glob| glob.matches_path_with(path, PATH_MATCH_OPTIONS))
+}
+
+/// The program of a simple command and its subcommand, e.g. `cargo test`
+/// for `cargo test --lib`
+fn command_prefix(command: &str) -> String {
+    let mut words = command.split_whitespace();
+    let mut prefix = words.next().unwrap_or_default().to_string();
+    if let Some(subcommand) = words.next()
+        && subcommand.starts_with(|c: char| c.is_ascii_alphabetic())
+        && subcommand
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+    {
+        prefix.push(' ');
+        prefix.push_str(subcommand);
+    }
+    prefix
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn bash(command: &str) -> ToolRequest {
+        ToolRequest::new("bash").with_command(command)
+    }
+
+    #[test]
+    fn test_most_restrictive_rule_wins() {
+        let policy = PermissionPolicy::new("/project").with_rules([
+            Rule::new("bash", Decision::Allow).with_command("cargo"),
+            Rule::new("bash", Decision::Deny).with_command("cargo publish"),
+        ]);
+
+        assert_eq!(
+            policy.decide(&bash("cargo test --lib"), Decision::Ask),
+            Decision::Allow
+        );
+        assert_eq!(
+            policy.decide(&bash("cargo publish"), Decision::Ask),
+            Decision::Deny
+        );
+        assert_eq!(
+            policy.decide(&bash("cargoes"), Decision::Ask),
+            Decision::Ask
+        );
+        assert_eq!(
+            policy.decide(&bash("cargo build && rm -r target"), Decision::Ask),
+            Decision::Ask
+        );
+        assert_eq!(
+            policy.decide(&bash("echo 'cargo publish'"), Decision::Allow),
+            Decision::Allow
+        );
+    }
+
+    #[test]
+    fn test_path_and_host_rules() {
+        let policy = PermissionPolicy::new("/project").with_rules([
+            Rule::new("edit_file", Decision::Allow).with_path("src/**"),
+            Rule::new("fetch__*", Decision::Allow).with_host("docs.rs"),
+        ]);
+        let edit = |path: &str| ToolRequest::new("edit_file").with_path(path);
+        let fetch = |url: &str| ToolRequest::new("fetch__get").with_url(url);
+
+        assert_eq!(
+            policy.decide(&edit("/project/src/a/b.rs"), Decision::Ask),
+            Decision::Allow
+        );
+        assert_eq!(
+            policy.decide(&edit("/project/README.md"), Decision::Ask),
+            Decision::Ask
+        );
+        assert_eq!(
+            policy.decide(&edit("/etc/hosts"), Decision::Allow),
+            Decision::Deny
+        );
+        assert_eq!(
+            policy.decide(&fetch("https://api.docs.rs/x"), Decision::Ask),
+            Decision::Allow
+        );
+        assert_eq!(
+            policy.decide(&fetch("https://evildocs.rs/"), Decision::Ask),
+            Decision::Ask
+        );
+    }
+
+    #[test]
+    fn test_allowed_rules_apply_and_are_saved() {
+        let temp_dir = TempDir::new().unwrap();
+        let policy = PermissionPolicy::load(temp_dir.path()).unwrap();
+        let request = bash("cd src && cargo test --lib");
+
+        let rules = policy.rules_allowing(&request, Decision::Ask);
+        assert_eq!(
+            rules,
+            [
+                Rule::new("bash", Decision::Allow).with_command("cd src"),
+                Rule::new("bash", Decision::Allow).with_command("cargo test"),
+            ]
+        );
+
+        policy.allow_for_session(&rules[..1]);
+        assert_eq!(policy.decide(&request, Decision::Ask), Decision::Ask);
+        let path = policy.allow_always(&rules[1..]).unwrap();
+        assert_eq!(policy.decide(&request, Decision::Ask), Decision::Allow);
+
+        // Only the rule allowed for good outlives the session
+        let reloaded = PermissionPolicy::load(temp_dir.path()).unwrap();
+        assert_eq!(PolicyFile::load(&path).unwrap().rules, rules[1..]);
+        assert_eq!(
+            reloaded.decide(&bash("cargo test"), Decision::Ask),
+            Decision::Allow
+        );
+        assert_eq!(reloaded.decide(&request, Decision::Ask), Decision::Ask);
+    }
+
+    #[test]
+    fn test_invalid_policy_file_is_an_error() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join(POLICY_FILE);
+        fs::write(
+            &path,
+            r#"{"rules": [{"tool": "bash", "decision": "maybe"}]}"#,
+        )
+        .unwrap();
+        assert!(PolicyFile::load(&path).is_err());
+
+        fs::write(&path, r#"{"rules": [{"tool": "[", "decision": "deny"}]}"#).unwrap();
+        assert!(PolicyFile::load(&path).is_err());
+    }
+}
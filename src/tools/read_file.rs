@@ -7,6 +7,7 @@ use std::path::Path;
 use tokio::fs;
 
 use super::ToolError;
+use super::workspace::{Workspace, resolve_path};
 
 #[derive(Deserialize)]
 pub struct ReadFileArgs {
@@ -31,11 +32,32 @@ pub struct ReadFileOutput {
 }
 
 #[derive(Deserialize, Serialize)]
-pub struct ReadFileTool;
+pub struct ReadFileTool {
+    /// Directories the paths must stay in
+    #[serde(skip)]
+    workspace: Option<Workspace>,
+}
 
 impl ReadFileTool {
     pub fn new() -> Self {
-        Self
+        Self { workspace: None }
+    }
+
+    /// Resolve paths against a workspace and refuse those outside of it
+    pub fn with_workspace(mut self, workspace: Workspace) -> Self {
+        self.workspace = Some(workspace);
+        self
+    }
+
+    /// Workspace the paths are resolved against
+    pub(super) fn workspace(&self) -> Option<&Workspace> {
+        self.workspace.as_ref()
+    }
+
+    /// The path to use for a path given by the model
+    fn resolve(&self, path: &str) -> Result<String, ToolError> {
+        let path = resolve_path(self.workspace.as_ref(), path)?;
+        Ok(path.to_string_lossy().into_owned())
     }
 
     /// Read file with safety checks and optional line range
@@ -215,7 +237,8 @@ impl Tool for ReadFileTool {
         }
     }
 
-    async fn call(&self, args: Self::Args) -> Result<Self::Output, Self::Error> {
+    async fn call(&self, mut args: Self::Args) -> Result<Self::Output, Self::Error> {
+        args.path = self.resolve(&args.path)?;
         self.read_file_safe(&args).await
     }
 }
@@ -312,6 +335,25 @@ mod tests {
         }
     }
 
+    #[tokio::test]
+    async fn test_read_file_in_workspace() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        std::fs::write(temp_dir.path().join("notes.txt"), "inside\n").unwrap();
+        let tool = ReadFileTool::new().with_workspace(Workspace::new(temp_dir.path()).unwrap());
+        let args = |path: &str| ReadFileArgs {
+            path: path.to_string(),
+            encoding: None,
+            max_size_mb: None,
+            line_range: None,
+        };
+
+        let output = tool.call(args("notes.txt")).await.unwrap();
+        assert_eq!(output.content, "inside\n");
+
+        let outside = tool.call(args("../notes.txt")).await;
+        assert!(matches!(outside, Err(ToolError::PermissionDenied(_))));
+    }
+
     #[test]
     fn test_is_binary_content() {
         let tool = ReadFileTool::new();
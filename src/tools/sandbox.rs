@@ -0,0 +1,292 @@
+//! Sandbox for the commands run by the bash tool
+//!
+//! When enabled, every command runs under [bubblewrap](https://github.com/containers/bubblewrap):
+//! the file system is mounted read-only apart from the project root, a private
+//! `/tmp` and the configured writable paths, the command gets its own process
+//! namespace and, unless allowed, no network. Memory and CPU time are limited
+//! with resource limits applied to every process the command starts.
+//!
+//! The sandbox is opt-in and Linux-only. A sandbox that is enabled but cannot
+//! be set up refuses to run commands rather than running them unconfined.
+
+use serde::{Deserialize, Serialize};
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+use tokio::process::Command;
+
+use super::ToolError;
+use crate::project::resolve_setting_path;
+
+/// Name of the bubblewrap executable looked up on `PATH`
+const BUBBLEWRAP: &str = "bwrap";
+
+/// Sandbox settings of a project
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct SandboxConfig {
+    /// Run commands in the sandbox
+    pub enabled: bool,
+    /// Let commands reach the network
+    pub network: bool,
+    /// Paths besides the project root commands may write to, relative to the
+    /// project root or starting with `~/`
+    pub writable_paths: Vec<PathBuf>,
+    /// Address space available to each process, in megabytes
+    pub max_memory_mb: Option<u64>,
+    /// CPU time available to each process, in seconds
+    pub max_cpu_seconds: Option<u64>,
+}
+
+impl Default for SandboxConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            network: true,
+            writable_paths: Vec::new(),
+            max_memory_mb: None,
+            max_cpu_seconds: None,
+        }
+    }
+}
+
+/// Confines the commands of one project
+#[derive(Debug, Clone)]
+pub struct Sandbox {
+    config: SandboxConfig,
+    /// Directory commands may write to
+    root: PathBuf,
+    /// The bubblewrap executable, if installed
+    bubblewrap: Option<PathBuf>,
+}
+
+impl Sandbox {
+    /// A sandbox confining writes to `root`
+    pub fn new(config: SandboxConfig, root: impl Into<PathBuf>) -> Self {
+        Self {
+            config,
+            root: root.into(),
+            bubblewrap: find_program(BUBBLEWRAP),
+        }
+    }
+
+    /// Whether commands can be run in the sandbox on this system
+    pub fn is_available(&self) -> bool {
+        cfg!(target_os = "linux") && self.bubblewrap.is_some()
+    }
+
+    /// Directory commands may write to
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// A command running `program` with `args` in the sandbox, starting in
+    /// `working_directory` or the current directory
+    pub(super) fn command(
+        &self,
+        program: &str,
+        args: &[&str],
+        working_directory: Option<&str>,
+    ) -> Result<Command, ToolError> {
+        let bubblewrap = self.bubblewrap.as_ref().filter(|_| self.is_available());
+        let Some(bubblewrap) = bubblewrap else {
+            return Err(ToolError::Command(
+                "The sandbox is enabled but bubblewrap (bwrap) is not installed, so commands cannot run"
+                    .to_string(),
+            ));
+        };
+
+        let working_directory = match working_directory {
+            Some(dir) => PathBuf::from(dir),
+            None => std::env::current_dir().map_err(ToolError::Io)?,
+        };
+        let mut cmd = Command::new(bubblewrap);
+        cmd.args(self.bubblewrap_args(&working_directory))
+            .arg("--")
+            .arg(program)
+            .args(args);
+        self.limit_resources(&mut cmd);
+        Ok(cmd)
+    }
+
+    /// Arguments of bubblewrap setting up the sandbox
+    fn bubblewrap_args(&self, working_directory: &Path) -> Vec<OsString> {
+        let mut args: Vec<OsString> = [
+            "--ro-bind",
+            "/",
+            "/",
+            "--dev",
+            "/dev",
+            "--proc",
+            "/proc",
+            "--tmpfs",
+            "/tmp",
+        ]
+        .into_iter()
+        .map(OsString::from)
+        .collect();
+
+        // Bound after the private /tmp, so a project under /tmp stays visible;
+        // writable paths that do not exist are skipped
+        args.push("--bind".into());
+        args.push(self.root.clone().into());
+        args.push(self.root.clone().into());
+        for path in self.writable_paths() {
+            args.push("--bind-try".into());
+            args.push(path.clone().into());
+            args.push(path.into());
+        }
+
+        if !self.config.network {
+            args.push("--unshare-net".into());
+        }
+        // A new session keeps commands away from the terminal. Stopping the
+        // command's process group stops bubblewrap, which takes the command
+        // and its own process namespace down with it
+        for flag in ["--unshare-pid", "--new-session", "--die-with-parent"] {
+            args.push(flag.into());
+        }
+        args.push("--chdir".into());
+        args.push(working_directory.into());
+        args
+    }
+
+    /// The configured writable paths, resolved
+    fn writable_paths(&self) -> impl Iterator<Item = PathBuf> + '_ {
+        self.config
+            .writable_paths
+            .iter()
+            .map(|path| resolve_setting_path(&self.root, path))
+    }
+
+    /// Apply the memory and CPU limits to the processes the command starts
+    #[cfg(unix)]
+    fn limit_resources(&self, cmd: &mut Command) {
+        let memory = self.config.max_memory_mb.map(|mb| mb * 1024 * 1024);
+        let cpu = self.config.max_cpu_seconds;
+        if memory.is_none() && cpu.is_none() {
+            return;
+        }
+
+        // SAFETY: the closure runs between fork and exec and only calls
+        // setrlimit, which is async-signal-safe
+        unsafe {
+            cmd.pre_exec(move || {
+                if let Some(bytes) = memory {
+                    let limit = libc::rlimit {
+                        rlim_cur: bytes as libc::rlim_t,
+                        rlim_max: bytes as libc::rlim_t,
+                    };
+                    if libc::setrlimit(libc::RLIMIT_AS, &limit) != 0 {
+                        return Err(std::io::Error::last_os_error());
+                    }
+                }
+                if let Some(seconds) = cpu {
+                    let limit = libc::rlimit {
+                        rlim_cur: seconds as libc::rlim_t,
+                        rlim_max: seconds as libc::rlim_t,
+                    };
+                    if libc::setrlimit(libc::RLIMIT_CPU, &limit) != 0 {
+                        return Err(std::io::Error::last_os_error());
+                    }
+                }
+                Ok(())
+            });
+        }
+    }
+
+    #[cfg(not(unix))]
+    fn limit_resources(&self, _cmd: &mut Command) {}
+}
+
+/// Path of an executable on `PATH`
+fn find_program(name: &str) -> Option<PathBuf> {
+    let path = std::env::var_os("PATH")?;
+    std::env::split_paths(&path)
+        .map(|dir| dir.join(name))
+        .find(|candidate| candidate.is_file())
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+    use super::*;
+    use crate::tools::BashTool;
+    use crate::tools::bash::BashArgs;
+    use rig::tool::Tool;
+    use std::os::unix::fs::PermissionsExt;
+
+    /// A stand-in for bubblewrap that records its arguments and runs the command unconfined
+    fn fake_bubblewrap(dir: &Path) -> PathBuf {
+        let path = dir.join("bwrap");
+        let script = format!(
+            "#!/bin/sh\necho \"$@\" > {}\nwhile [ \"$1\" != \"--\" ]; do shift; done\nshift\nexec \"$@\"\n",
+            dir.join("args").display()
+        );
+        std::fs::write(&path, script).unwrap();
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755)).unwrap();
+        path
+    }
+
+    fn args(command: &str) -> BashArgs {
+        BashArgs {
+            command: command.to_string(),
+            timeout_seconds: 5,
+            working_directory: None,
+            reset_shell: false,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_commands_run_confined_and_limited() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = SandboxConfig {
+            enabled: true,
+            network: false,
+            writable_paths: vec![PathBuf::from("target")],
+            max_memory_mb: Some(512),
+            max_cpu_seconds: Some(7),
+        };
+        let sandbox = Sandbox {
+            config,
+            root: dir.path().to_path_buf(),
+            bubblewrap: Some(fake_bubblewrap(dir.path())),
+        };
+        let tool = BashTool::new().with_sandbox(sandbox);
+
+        let output = tool.call(args("ulimit -t; ulimit -v")).await.unwrap();
+        assert_eq!(output.stdout, format!("7\n{}\n", 512 * 1024));
+
+        let recorded = std::fs::read_to_string(dir.path().join("args")).unwrap();
+        let root = dir.path().display();
+        assert!(recorded.starts_with("--ro-bind / / "));
+        assert!(recorded.contains(&format!("--bind {root} {root} ")));
+        assert!(recorded.contains(&format!("--bind-try {root}/target {root}/target ")));
+        assert!(recorded.contains("--unshare-net"));
+        assert!(recorded.ends_with(" -- /bin/sh -c ulimit -t; ulimit -v\n"));
+    }
+
+    #[tokio::test]
+    async fn test_commands_refused_without_bubblewrap() {
+        let sandbox = Sandbox {
+            config: SandboxConfig {
+                enabled: true,
+                ..Default::default()
+            },
+            root: PathBuf::from("/"),
+            bubblewrap: None,
+        };
+        assert!(!sandbox.is_available());
+
+        let tool = BashTool::new().with_sandbox(sandbox);
+        let result = tool.call(args("echo unconfined")).await;
+        assert!(matches!(result, Err(ToolError::Command(msg)) if msg.contains("bubblewrap")));
+    }
+
+    #[test]
+    fn test_config_defaults() {
+        let config: SandboxConfig = serde_json::from_str(r#"{"enabled": true}"#).unwrap();
+        assert!(config.enabled);
+        assert!(config.network);
+        assert!(config.writable_paths.is_empty());
+        assert_eq!(config.max_memory_mb, None);
+    }
+}
@@ -0,0 +1,379 @@
+//! Persistent shells for the bash tool
+//!
+//! A [`PersistentShell`] runs every command of a session in one long-lived
+//! shell process, so `cd`, `export` and sourced scripts such as a virtualenv's
+//! `activate` carry over from one call to the next. [`ShellSessions`] hands out
+//! one shell per chat or ACP session.
+//!
+//! Each command is followed by a marker on stdout and stderr, which tells where
+//! its output ends and carries its exit status. A command that exits the shell
+//! or runs past its timeout takes the shell with it, and the next command starts
+//! in a fresh one.
+
+use std::collections::HashMap;
+use std::path::Path;
+use std::process::Stdio;
+use std::sync::{Arc, Mutex as StdMutex};
+use std::time::Duration;
+use tokio::io::AsyncWriteExt;
+use tokio::process::{Child, ChildStdin, Command};
+use tokio::sync::{Mutex, mpsc};
+use uuid::Uuid;
+
+use super::ToolError;
+use super::bash::{BashArgs, BashOutput, EXIT_DRAIN_PERIOD, ProcessGroup};
+use super::output::{CapturedOutput, OutputLine, OutputStream, read_lines};
+use super::sandbox::Sandbox;
+
+/// Shells tried in order; bash understands `source` and the other bashisms
+/// models tend to use
+const SHELL_CANDIDATES: [&str; 2] = ["/bin/bash", "/bin/sh"];
+
+/// The persistent shells of every session, started on first use
+#[derive(Debug, Default)]
+pub struct ShellSessions {
+    shells: StdMutex<HashMap<String, Arc<PersistentShell>>>,
+}
+
+impl ShellSessions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The shell of a session
+    pub fn shell(&self, session_id: &str) -> Arc<PersistentShell> {
+        self.shells
+            .lock()
+            .unwrap()
+            .entry(session_id.to_string())
+            .or_default()
+            .clone()
+    }
+
+    /// Stop the shell of a session, returning whether one was running
+    pub async fn reset(&self, session_id: &str) -> bool {
+        let shell = self.shells.lock().unwrap().get(session_id).cloned();
+        match shell {
+            Some(shell) => shell.reset().await,
+            None => false,
+        }
+    }
+}
+
+/// A shell process that runs the commands of one session in turn
+#[derive(Debug, Default)]
+pub struct PersistentShell {
+    process: Mutex<Option<ShellProcess>>,
+}
+
+impl PersistentShell {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Stop the shell and everything it started, returning whether it was running
+    ///
+    /// The next command starts in a fresh shell.
+    pub async fn reset(&self) -> bool {
+        // Dropping the process stops its process group
+        self.process.lock().await.take().is_some()
+    }
+
+    /// Run a command in the shell, starting one if none is running
+    pub(super) async fn run(
+        &self,
+        args: &BashArgs,
+        sandbox: Option<&Sandbox>,
+        mut output: CapturedOutput<'_>,
+    ) -> Result<BashOutput, ToolError> {
+        let mut slot = self.process.lock().await;
+        // A shell that ended between commands, e.g. killed from outside, is replaced
+        if let Some(process) = slot.as_mut()
+            && !process.is_running()
+        {
+            process.group.release();
+            *slot = None;
+        }
+        let mut process = match slot.take() {
+            Some(process) => process,
+            None => ShellProcess::spawn(sandbox)?,
+        };
+
+        // The process is only put back once the command has finished, so a
+        // cancelled call drops it and stops the command along with the shell
+        let marker = format!("__vega_done_{}", Uuid::new_v4().simple());
+        process
+            .stdin
+            .write_all(command_script(args, &marker).as_bytes())
+            .await
+            .map_err(|e| ToolError::Command(format!("Failed to write to the shell: {}", e)))?;
+
+        let limit = Duration::from_secs(args.timeout_seconds);
+        let finished = tokio::time::timeout(limit, process.finish(&marker, &mut output)).await;
+
+        let (exit_code, timed_out) = match finished {
+            Ok(Some(exit_code)) => {
+                *slot = Some(process);
+                (exit_code, false)
+            }
+            Ok(None) => (process.exit_code().await, false),
+            Err(_) => {
+                // Keep whatever the command prints while it shuts down
+                let ShellProcess {
+                    child,
+                    lines,
+                    group,
+                    ..
+                } = &mut process;
+                group.terminate(child, output.collect(lines)).await;
+                (process.exit_code().await, true)
+            }
+        };
+
+        Ok(output.finish(&args.command, exit_code, timed_out, slot.is_none()))
+    }
+}
+
+/// A running shell and the output it prints
+#[derive(Debug)]
+struct ShellProcess {
+    child: Child,
+    stdin: ChildStdin,
+    lines: mpsc::UnboundedReceiver<OutputLine>,
+    group: ProcessGroup,
+}
+
+impl ShellProcess {
+    /// Start a shell in its own process group, confined to the sandbox if given
+    fn spawn(sandbox: Option<&Sandbox>) -> Result<Self, ToolError> {
+        let program = SHELL_CANDIDATES
+            .into_iter()
+            .find(|shell| Path::new(shell).exists())
+            .unwrap_or("/bin/sh");
+        let mut cmd = match sandbox {
+            Some(sandbox) => sandbox.command(program, &[], None)?,
+            None => Command::new(program),
+        };
+        #[cfg(unix)]
+        cmd.process_group(0);
+
+        let mut child = cmd
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true)
+            .spawn()
+            .map_err(|e| ToolError::Command(format!("Failed to start {}: {}", program, e)))?;
+        let group = ProcessGroup::new(&child);
+
+        let stdin = child
+            .stdin
+            .take()
+            .ok_or_else(|| ToolError::Command("Shell has no stdin".to_string()))?;
+        let (lines_tx, lines) = mpsc::unbounded_channel();
+        if let Some(stdout) = child.stdout.take() {
+            tokio::spawn(read_lines(stdout, OutputStream::Stdout, lines_tx.clone()));
+        }
+        if let Some(stderr) = child.stderr.take() {
+            tokio::spawn(read_lines(stderr, OutputStream::Stderr, lines_tx));
+        }
+
+        Ok(Self {
+            child,
+            stdin,
+            lines,
+            group,
+        })
+    }
+
+    /// Whether the shell is still waiting for commands
+    fn is_running(&mut self) -> bool {
+        matches!(self.child.try_wait(), Ok(None))
+    }
+
+    /// Read the output of the running command until the markers after it
+    /// arrive on both streams, returning its exit code, or `None` if the
+    /// shell exited first
+    async fn finish(&mut self, marker: &str, output: &mut CapturedOutput<'_>) -> Option<i32> {
+        let mut exit_code = None;
+        let mut stderr_done = false;
+        while exit_code.is_none() || !stderr_done {
+            tokio::select! {
+                biased;
+                line = self.lines.recv() => {
+                    let (stream, line) = line?;
+                    let Some(at) = find(&line, marker.as_bytes()) else {
+                        output.push(stream, &line);
+                        continue;
+                    };
+                    // Output without a trailing newline shares the marker's line
+                    if at > 0 {
+                        output.push(stream, &line[..at]);
+                    }
+                    match stream {
+                        OutputStream::Stdout => {
+                            let status = String::from_utf8_lossy(&line[at + marker.len()..]);
+                            exit_code = Some(status.trim().parse().unwrap_or(-1));
+                        }
+                        OutputStream::Stderr => stderr_done = true,
+                    }
+                }
+                _ = self.child.wait() => {
+                    let _ = tokio::time::timeout(EXIT_DRAIN_PERIOD, output.collect(&mut self.lines)).await;
+                    return None;
+                }
+            }
+        }
+        exit_code
+    }
+
+    /// Wait for the shell to exit, returning its exit code
+    async fn exit_code(&mut self) -> i32 {
+        let code = match self.child.wait().await {
+            Ok(status) => status.code().unwrap_or(-1),
+            Err(_) => -1,
+        };
+        // Processes the shell left running in the background are not stopped
+        self.group.release();
+        code
+    }
+}
+
+/// The script that runs a command and prints the markers after its output
+fn command_script(args: &BashArgs, marker: &str) -> String {
+    // Evaluating the quoted command keeps a syntax error from breaking the
+    // script, and its stdin is closed so it cannot read the commands after it
+    let command = format!("eval {} < /dev/null", quote(&args.command));
+    let (command, restore) = match args.working_directory {
+        Some(ref dir) => (
+            format!("__vega_pwd=$PWD; cd -- {} && {}", quote(dir), command),
+            "cd -- \"$__vega_pwd\"\n",
+        ),
+        None => (command, ""),
+    };
+    format!(
+        "{command}\n__vega_status=$?\n{restore}printf '%s\\n' {marker} >&2\nprintf '%s %d\\n' {marker} \"$__vega_status\"\n"
+    )
+}
+
+/// Quote a string as a single shell word
+fn quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', "'\\''"))
+}
+
+/// Position of the first occurrence of `needle` in `haystack`
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack
+        .windows(needle.len())
+        .position(|window| window == needle)
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use crate::tools::BashTool;
+    use rig::tool::Tool;
+
+    fn args(command: &str) -> BashArgs {
+        BashArgs {
+            command: command.to_string(),
+            timeout_seconds: 5,
+            working_directory: None,
+            reset_shell: false,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_directory_and_environment_persist() {
+        let dir = tempfile::tempdir().unwrap();
+        let tool = BashTool::new().with_shell(Arc::new(PersistentShell::new()));
+
+        let first = tool
+            .call(args(&format!(
+                "cd {} && export GREETING=hello",
+                dir.path().display()
+            )))
+            .await
+            .unwrap();
+        assert!(first.success);
+
+        let second = tool.call(args("pwd; echo $GREETING")).await.unwrap();
+        let expected = format!("{}\nhello\n", dir.path().canonicalize().unwrap().display());
+        assert_eq!(second.stdout, expected);
+    }
+
+    #[tokio::test]
+    async fn test_output_and_exit_code_are_separated() {
+        let tool = BashTool::new().with_shell(Arc::new(PersistentShell::new()));
+
+        let output = tool
+            .call(args("echo out; echo err >&2; printf partial; (exit 3)"))
+            .await
+            .unwrap();
+        assert_eq!(output.stdout, "out\npartial");
+        assert_eq!(output.stderr, "err\n");
+        assert_eq!(output.exit_code, 3);
+        assert!(!output.success);
+        assert!(!output.shell_exited);
+
+        let next = tool.call(args("echo next")).await.unwrap();
+        assert_eq!(next.stdout, "next\n");
+        assert_eq!(next.stderr, "");
+        assert_eq!(next.exit_code, 0);
+    }
+
+    #[tokio::test]
+    async fn test_exit_and_reset_start_a_fresh_shell() {
+        let tool = BashTool::new().with_shell(Arc::new(PersistentShell::new()));
+
+        tool.call(args("export GREETING=hello")).await.unwrap();
+        let output = tool.call(args("echo bye; exit 4")).await.unwrap();
+        assert_eq!(output.stdout, "bye\n");
+        assert_eq!(output.exit_code, 4);
+        assert!(output.shell_exited);
+
+        tool.call(args("export GREETING=hello")).await.unwrap();
+        let mut reset = args("echo \"[$GREETING]\"");
+        reset.reset_shell = true;
+        assert_eq!(tool.call(reset).await.unwrap().stdout, "[]\n");
+    }
+
+    #[tokio::test]
+    async fn test_working_directory_applies_to_one_command() {
+        let dir = tempfile::tempdir().unwrap();
+        let tool = BashTool::new().with_shell(Arc::new(PersistentShell::new()));
+        let before = tool.call(args("pwd")).await.unwrap().stdout;
+
+        let mut elsewhere = args("pwd");
+        elsewhere.working_directory = Some(dir.path().display().to_string());
+        let output = tool.call(elsewhere).await.unwrap();
+        assert_eq!(
+            output.stdout.trim(),
+            dir.path().canonicalize().unwrap().display().to_string()
+        );
+
+        assert_eq!(tool.call(args("pwd")).await.unwrap().stdout, before);
+    }
+
+    #[tokio::test]
+    async fn test_timeout_stops_the_shell() {
+        let tool = BashTool::new().with_shell(Arc::new(PersistentShell::new()));
+
+        let mut slow = args("export GREETING=hello; echo partial; sleep 30");
+        slow.timeout_seconds = 1;
+        let output = tool.call(slow).await.unwrap();
+        assert!(output.timed_out);
+        assert!(output.shell_exited);
+        assert_eq!(output.stdout, "partial\n");
+
+        let next = tool.call(args("echo \"[$GREETING]\"")).await.unwrap();
+        assert_eq!(next.stdout, "[]\n");
+    }
+
+    #[test]
+    fn test_sessions_share_a_shell() {
+        let sessions = ShellSessions::new();
+        assert!(Arc::ptr_eq(&sessions.shell("a"), &sessions.shell("a")));
+        assert!(!Arc::ptr_eq(&sessions.shell("a"), &sessions.shell("b")));
+    }
+}
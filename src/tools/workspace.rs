@@ -0,0 +1,278 @@
+//! Workspace the file tools are confined to
+//!
+//! Paths given to `read_file`, `edit_file`, `list_files` and `code_search` are
+//! resolved against the workspace root and must stay inside it or one of the
+//! extra allowed roots. Paths are checked after resolving symlinks, so a link
+//! inside the workspace pointing outside of it is refused too, even when its
+//! target does not exist yet and a write would create it.
+
+use std::io;
+use std::path::{Component, Path, PathBuf};
+use tracing::warn;
+
+use super::ToolError;
+
+/// Dangling symlinks followed in a row before a path is refused
+const MAX_SYMLINKS: usize = 40;
+
+/// The directories the file tools may access
+#[derive(Debug, Clone, PartialEq)]
+pub struct Workspace {
+    /// Directory relative paths are resolved against, canonicalized
+    root: PathBuf,
+    /// Directories outside the root that may be accessed too, canonicalized
+    extra_roots: Vec<PathBuf>,
+}
+
+impl Workspace {
+    /// A workspace confined to `root`, which must exist
+    pub fn new(root: impl AsRef<Path>) -> io::Result<Self> {
+        Ok(Self {
+            root: root.as_ref().canonicalize()?,
+            extra_roots: Vec::new(),
+        })
+    }
+
+    /// Also allow access to `roots`; roots that do not exist are skipped
+    pub fn with_extra_roots(mut self, roots: impl IntoIterator<Item = PathBuf>) -> Self {
+        for root in roots {
+            match self.root.join(&root).canonicalize() {
+                Ok(root) => self.extra_roots.push(root),
+                Err(e) => warn!("Skipping allowed root {}: {}", root.display(), e),
+            }
+        }
+        self
+    }
+
+    /// The same allowed roots around another root, e.g. an ACP session's cwd
+    pub fn rooted_at(&self, root: impl AsRef<Path>) -> io::Result<Self> {
+        Ok(Self {
+            root: root.as_ref().canonicalize()?,
+            extra_roots: self.extra_roots.clone(),
+        })
+    }
+
+    /// Directory relative paths are resolved against
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// A path given to a tool, made absolute against the root without touching the file system
+    pub fn absolute(&self, path: &str) -> PathBuf {
+        normalize(&self.root.join(path))
+    }
+
+    /// Resolve a path given to a tool, refusing paths outside the workspace
+    ///
+    /// The part of the path that exists is canonicalized, resolving symlinks;
+    /// the rest, e.g. a file about to be created, is appended as is.
+    pub fn resolve(&self, path: &str) -> Result<PathBuf, ToolError> {
+        let requested = self.absolute(path);
+        let resolved = canonicalize_existing(&requested).map_err(ToolError::Io)?;
+
+        if self.contains(&resolved) {
+            return Ok(resolved);
+        }
+        if self.contains(&requested) {
+            return Err(ToolError::PermissionDenied(format!(
+                "{} leads outside the workspace through a symlink to {}",
+                path,
+                resolved.display()
+            )));
+        }
+        Err(ToolError::PermissionDenied(format!(
+            "{} is outside the workspace {}",
+            path,
+            self.root.display()
+        )))
+    }
+
+    /// Whether a path is inside the root or an extra root
+    fn contains(&self, path: &Path) -> bool {
+        std::iter::once(&self.root)
+            .chain(&self.extra_roots)
+            .any(|root| path.starts_with(root))
+    }
+}
+
+/// Resolve a path given to a tool against an optional workspace
+pub(super) fn resolve_path(
+    workspace: Option<&Workspace>,
+    path: &str,
+) -> Result<PathBuf, ToolError> {
+    match workspace {
+        Some(workspace) => workspace.resolve(path),
+        None => Ok(PathBuf::from(path)),
+    }
+}
+
+/// The path a tool call is about, for the permission policy
+pub(super) fn requested_path(workspace: Option<&Workspace>, path: &str) -> PathBuf {
+    match workspace {
+        Some(workspace) => workspace.absolute(path),
+        None => PathBuf::from(path),
+    }
+}
+
+/// Remove `.` and `..` components without resolving symlinks
+fn normalize(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                normalized.pop();
+            }
+            component => normalized.push(component),
+        }
+    }
+    normalized
+}
+
+/// Canonicalize the longest existing ancestor of an absolute, normalized path
+/// and append the rest
+///
+/// A dangling symlink is followed to its target, since writing to the path
+/// creates the target.
+fn canonicalize_existing(path: &Path) -> io::Result<PathBuf> {
+    resolve_existing(path, 0)
+}
+
+/// [`canonicalize_existing`], after following `links` dangling symlinks
+fn resolve_existing(path: &Path, links: usize) -> io::Result<PathBuf> {
+    for ancestor in path.ancestors() {
+        let resolved = match ancestor.canonicalize() {
+            Ok(resolved) => resolved,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                match std::fs::symlink_metadata(ancestor) {
+                    Ok(metadata) if metadata.is_symlink() => {
+                        resolve_dangling_link(ancestor, links)?
+                    }
+                    _ => continue,
+                }
+            }
+            Err(e) => return Err(e),
+        };
+        if ancestor == path {
+            return Ok(resolved);
+        }
+        let rest = path.strip_prefix(ancestor).unwrap_or(Path::new(""));
+        return Ok(resolved.join(rest));
+    }
+    Ok(path.to_path_buf())
+}
+
+/// Where a symlink whose target does not exist points
+fn resolve_dangling_link(link: &Path, links: usize) -> io::Result<PathBuf> {
+    if links >= MAX_SYMLINKS {
+        return Err(io::Error::other(format!(
+            "Too many levels of symbolic links at {}",
+            link.display()
+        )));
+    }
+    let target = std::fs::read_link(link)?;
+    let parent = match link.parent() {
+        Some(parent) => resolve_existing(parent, links)?,
+        None => PathBuf::from("/"),
+    };
+    resolve_existing(&normalize(&parent.join(target)), links + 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_paths_inside_the_workspace_resolve() {
+        let temp_dir = TempDir::new().unwrap();
+        std::fs::create_dir(temp_dir.path().join("src")).unwrap();
+        let workspace = Workspace::new(temp_dir.path()).unwrap();
+        let root = workspace.root().to_path_buf();
+
+        assert_eq!(
+            workspace.resolve("src/lib.rs").unwrap(),
+            root.join("src/lib.rs")
+        );
+        assert_eq!(
+            workspace.resolve("src/../Cargo.toml").unwrap(),
+            root.join("Cargo.toml")
+        );
+        assert_eq!(
+            workspace.resolve("new/dir/file.txt").unwrap(),
+            root.join("new/dir/file.txt")
+        );
+        assert_eq!(
+            workspace
+                .resolve(&root.join("src").to_string_lossy())
+                .unwrap(),
+            root.join("src")
+        );
+    }
+
+    #[test]
+    fn test_paths_outside_the_workspace_are_refused() {
+        let temp_dir = TempDir::new().unwrap();
+        let workspace = Workspace::new(temp_dir.path()).unwrap();
+
+        for path in ["../outside.txt", "/etc/passwd", "src/../../outside.txt"] {
+            let result = workspace.resolve(path);
+            assert!(
+                matches!(result, Err(ToolError::PermissionDenied(_))),
+                "{path}"
+            );
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_symlink_escape_is_refused() {
+        let temp_dir = TempDir::new().unwrap();
+        let outside = TempDir::new().unwrap();
+        std::os::unix::fs::symlink(outside.path(), temp_dir.path().join("link")).unwrap();
+        let workspace = Workspace::new(temp_dir.path()).unwrap();
+
+        match workspace.resolve("link/secret.txt") {
+            Err(ToolError::PermissionDenied(msg)) => assert!(msg.contains("symlink")),
+            other => panic!("Expected a symlink escape, got {:?}", other),
+        }
+
+        let workspace = workspace.with_extra_roots([outside.path().to_path_buf()]);
+        assert!(workspace.resolve("link/secret.txt").is_ok());
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_dangling_symlink_escape_is_refused() {
+        use crate::tools::EditFileTool;
+        use crate::tools::edit_file::EditFileArgs;
+        use rig::tool::Tool;
+
+        let temp_dir = TempDir::new().unwrap();
+        let outside = TempDir::new().unwrap();
+        let target = outside.path().join("new.txt");
+        std::os::unix::fs::symlink(&target, temp_dir.path().join("link")).unwrap();
+        let workspace = Workspace::new(temp_dir.path()).unwrap();
+        let tool = EditFileTool::new().with_workspace(workspace.clone());
+
+        let result = tool
+            .call(EditFileArgs {
+                path: "link".to_string(),
+                content: Some("pwned".to_string()),
+                ..Default::default()
+            })
+            .await;
+        match result {
+            Err(ToolError::PermissionDenied(msg)) => assert!(msg.contains("symlink")),
+            other => panic!("Expected a symlink escape, got {:?}", other.map(|o| o.path)),
+        }
+        assert!(!target.exists());
+
+        // A dangling link inside the workspace resolves to its target
+        std::os::unix::fs::symlink("real.txt", temp_dir.path().join("inner")).unwrap();
+        assert_eq!(
+            workspace.resolve("inner").unwrap(),
+            workspace.root().join("real.txt")
+        );
+    }
+}
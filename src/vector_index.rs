@@ -0,0 +1,239 @@
+//! # Approximate Nearest Neighbour Index
+//!
+//! An inverted file (IVF) index over context embeddings. Vectors are clustered
+//! with spherical k-means and each stored embedding is assigned to its nearest
+//! centroid (its "list"). A query only scores the entries in the few lists whose
+//! centroids are closest to it, instead of every stored embedding.
+//!
+//! The index itself is plain data; [`ContextStore`](crate::context::ContextStore)
+//! persists the centroids and list assignments in the SQLite database next to the
+//! embeddings, so it survives restarts and can always be rebuilt from them.
+
+use crate::embeddings::utils::{cosine_similarity, normalize_embedding};
+
+/// Minimum number of stored embeddings before an index is trained
+pub const MIN_TRAINING_VECTORS: usize = 256;
+
+/// Default number of lists probed per query
+pub const DEFAULT_NPROBE: usize = 4;
+
+/// Number of k-means refinement passes when training
+const KMEANS_ITERATIONS: usize = 10;
+
+/// Upper bound on the number of lists, regardless of collection size
+const MAX_LISTS: usize = 1024;
+
+/// Inverted file index made of normalized cluster centroids
+#[derive(Debug, Clone)]
+pub struct IvfIndex {
+    centroids: Vec<Vec<f32>>,
+}
+
+impl IvfIndex {
+    /// Create an index from previously trained centroids
+    pub fn new(centroids: Vec<Vec<f32>>) -> Self {
+        Self { centroids }
+    }
+
+    /// Choose the number of lists for a collection of `count` vectors
+    pub fn num_lists_for(count: usize) -> usize {
+        ((count as f64).sqrt() as usize).clamp(1, MAX_LISTS)
+    }
+
+    /// Train an index over `vectors` with spherical k-means.
+    ///
+    /// Returns `None` when there are no vectors to train on.
+    pub fn train(vectors: &[Vec<f32>], num_lists: usize) -> Option<Self> {
+        if vectors.is_empty() || num_lists == 0 {
+            return None;
+        }
+
+        let normalized: Vec<Vec<f32>> = vectors
+            .iter()
+            .map(|vector| {
+                let mut vector = vector.clone();
+                normalize_embedding(&mut vector);
+                vector
+            })
+            .collect();
+
+        // Deterministic farthest-point initialisation: start from the first vector and
+        // repeatedly add the vector least similar to every centroid chosen so far
+        let k = num_lists.min(normalized.len());
+        let mut centroids: Vec<Vec<f32>> = vec![normalized[0].clone()];
+        let mut best_similarity: Vec<f32> = normalized
+            .iter()
+            .map(|vector| cosine_similarity(vector, &centroids[0]))
+            .collect();
+        while centroids.len() < k {
+            let (next, _) = best_similarity
+                .iter()
+                .enumerate()
+                .min_by(|a, b| a.1.partial_cmp(b.1).unwrap_or(std::cmp::Ordering::Equal))
+                .expect("vectors is not empty");
+            let centroid = normalized[next].clone();
+            for (similarity, vector) in best_similarity.iter_mut().zip(&normalized) {
+                *similarity = similarity.max(cosine_similarity(vector, &centroid));
+            }
+            centroids.push(centroid);
+        }
+
+        let dimension = centroids[0].len();
+        for _ in 0..KMEANS_ITERATIONS {
+            let index = IvfIndex::new(centroids.clone());
+            let mut sums = vec![vec![0.0f32; dimension]; k];
+            let mut counts = vec![0usize; k];
+
+            for vector in &normalized {
+                let list = index.assign(vector);
+                counts[list] += 1;
+                for (sum, value) in sums[list].iter_mut().zip(vector) {
+                    *sum += value;
+                }
+            }
+
+            let mut changed = false;
+            for (list, sum) in sums.into_iter().enumerate() {
+                // Empty lists keep their previous centroid
+                if counts[list] == 0 {
+                    continue;
+                }
+                let mut centroid = sum;
+                normalize_embedding(&mut centroid);
+                if centroid != centroids[list] {
+                    centroids[list] = centroid;
+                    changed = true;
+                }
+            }
+
+            if !changed {
+                break;
+            }
+        }
+
+        // Drop lists that ended up empty (e.g. duplicate initial samples)
+        let index = IvfIndex::new(centroids);
+        let mut used = vec![false; k];
+        for vector in &normalized {
+            used[index.assign(vector)] = true;
+        }
+        let centroids = index
+            .centroids
+            .into_iter()
+            .zip(used)
+            .filter_map(|(centroid, used)| used.then_some(centroid))
+            .collect();
+
+        Some(IvfIndex::new(centroids))
+    }
+
+    /// The trained centroids, indexed by list id
+    pub fn centroids(&self) -> &[Vec<f32>] {
+        &self.centroids
+    }
+
+    /// Number of lists in the index
+    pub fn len(&self) -> usize {
+        self.centroids.len()
+    }
+
+    /// Whether the index has no lists
+    pub fn is_empty(&self) -> bool {
+        self.centroids.is_empty()
+    }
+
+    /// Find the list whose centroid is closest to `vector`
+    pub fn assign(&self, vector: &[f32]) -> usize {
+        self.probe(vector, 1).first().copied().unwrap_or(0)
+    }
+
+    /// Find the `nprobe` lists whose centroids are closest to `query`
+    pub fn probe(&self, query: &[f32], nprobe: usize) -> Vec<usize> {
+        let mut scored: Vec<(usize, f32)> = self
+            .centroids
+            .iter()
+            .enumerate()
+            .map(|(list, centroid)| (list, cosine_similarity(query, centroid)))
+            .collect();
+        scored.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(std::cmp::Ordering::Equal));
+        scored.truncate(nprobe);
+        scored.into_iter().map(|(list, _)| list).collect()
+    }
+}
+
+/// Encode a vector as little-endian `f32` bytes for storage
+pub fn encode_vector(vector: &[f32]) -> Vec<u8> {
+    vector
+        .iter()
+        .flat_map(|value| value.to_le_bytes())
+        .collect()
+}
+
+/// Decode a vector stored by [`encode_vector`]
+pub fn decode_vector(bytes: &[u8]) -> Vec<f32> {
+    bytes
+        .chunks_exact(4)
+        .map(|chunk| f32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn clustered_vectors() -> Vec<Vec<f32>> {
+        let mut vectors = Vec::new();
+        for i in 0..20 {
+            let jitter = i as f32 * 0.001;
+            vectors.push(vec![1.0, jitter, 0.0]);
+            vectors.push(vec![0.0, 1.0, jitter]);
+            vectors.push(vec![jitter, 0.0, 1.0]);
+        }
+        vectors
+    }
+
+    #[test]
+    fn test_train_separates_clusters() {
+        let index = IvfIndex::train(&clustered_vectors(), 3).unwrap();
+        assert_eq!(index.len(), 3);
+
+        let x = index.assign(&[1.0, 0.0, 0.0]);
+        let y = index.assign(&[0.0, 1.0, 0.0]);
+        let z = index.assign(&[0.0, 0.0, 1.0]);
+        assert_ne!(x, y);
+        assert_ne!(y, z);
+        assert_ne!(x, z);
+    }
+
+    #[test]
+    fn test_train_drops_empty_lists() {
+        let vectors = vec![vec![1.0, 0.0]; 10];
+        let index = IvfIndex::train(&vectors, 4).unwrap();
+        assert_eq!(index.len(), 1);
+    }
+
+    #[test]
+    fn test_probe_orders_by_similarity() {
+        let index = IvfIndex::new(vec![vec![1.0, 0.0], vec![0.0, 1.0], vec![0.7, 0.7]]);
+        assert_eq!(index.probe(&[0.9, 0.1], 2), vec![0, 2]);
+        assert_eq!(index.probe(&[0.0, 1.0], 10).len(), 3);
+    }
+
+    #[test]
+    fn test_train_empty() {
+        assert!(IvfIndex::train(&[], 4).is_none());
+    }
+
+    #[test]
+    fn test_num_lists_for() {
+        assert_eq!(IvfIndex::num_lists_for(0), 1);
+        assert_eq!(IvfIndex::num_lists_for(256), 16);
+        assert_eq!(IvfIndex::num_lists_for(usize::MAX / 2), 1024);
+    }
+
+    #[test]
+    fn test_vector_encoding_roundtrip() {
+        let vector = vec![0.5, -1.25, 3.0];
+        assert_eq!(decode_vector(&encode_vector(&vector)), vector);
+    }
+}
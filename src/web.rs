@@ -11,7 +11,8 @@ use std::sync::Arc;
 use tower_http::cors::CorsLayer;
 // Web server module - uses custom logger when available
 
-use crate::context::ContextStore;
+use crate::context::{ContextEntry, ContextFilter, ContextStore};
+use crate::embeddings::EmbeddingService;
 use crate::logging::Logger;
 
 /// Web server state
@@ -19,6 +20,7 @@ use crate::logging::Logger;
 pub struct WebState {
     pub context_store: Arc<ContextStore>,
     pub logger: Option<Arc<Logger>>,
+    pub embedding_service: Option<Arc<EmbeddingService>>,
 }
 
 /// Query parameters for context entries
@@ -26,6 +28,8 @@ pub struct WebState {
 pub struct ContextQuery {
     pub session_id: Option<String>,
     pub limit: Option<usize>,
+    /// Search text; when present, entries are ranked by hybrid retrieval
+    pub q: Option<String>,
 }
 
 /// Response for context entries API
@@ -33,6 +37,9 @@ pub struct ContextQuery {
 pub struct ContextResponse {
     pub entries: Vec<ContextEntryResponse>,
     pub total: usize,
+    /// Why the entries are less complete than asked for, e.g. a failed semantic search
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub warning: Option<String>,
 }
 
 /// Serializable context entry for API responses
@@ -47,6 +54,20 @@ pub struct ContextEntryResponse {
     pub metadata: HashMap<String, String>,
 }
 
+impl From<ContextEntry> for ContextEntryResponse {
+    fn from(e: ContextEntry) -> Self {
+        ContextEntryResponse {
+            id: e.id,
+            agent_name: e.agent_name,
+            session_id: e.session_id,
+            timestamp: e.timestamp.format("%Y-%m-%d %H:%M:%S UTC").to_string(),
+            content: e.content,
+            role: e.role,
+            metadata: e.metadata,
+        }
+    }
+}
+
 /// Response for sessions API
 #[derive(Serialize)]
 pub struct SessionsResponse {
@@ -90,13 +111,17 @@ pub async fn start_web_server(
     context_store: Arc<ContextStore>,
     port: u16,
 ) -> Result<(), Box<dyn std::error::Error>> {
-    start_web_server_with_logger(context_store, None, port).await
+    start_web_server_with_logger(context_store, None, None, port).await
 }
 
-/// Start the web server with optional logger
+/// Start the web server with optional logger.
+///
+/// When an embedding service is provided, `/api/context?q=` searches combine
+/// full-text and semantic ranking; otherwise they are full-text only.
 pub async fn start_web_server_with_logger(
     context_store: Arc<ContextStore>,
     logger: Option<Arc<Logger>>,
+    embedding_service: Option<Arc<EmbeddingService>>,
     port: u16,
 ) -> Result<(), Box<dyn std::error::Error>> {
     // Log using custom logger if available, otherwise use println
@@ -111,6 +136,7 @@ pub async fn start_web_server_with_logger(
     let state = WebState {
         context_store,
         logger,
+        embedding_service,
     };
 
     let app = Router::new()
@@ -183,21 +209,14 @@ async fn session_handler(
         Ok(entries) => {
             let entry_responses: Vec<ContextEntryResponse> = entries
                 .into_iter()
-                .map(|e| ContextEntryResponse {
-                    id: e.id,
-                    agent_name: e.agent_name,
-                    session_id: e.session_id,
-                    timestamp: e.timestamp.format("%Y-%m-%d %H:%M:%S UTC").to_string(),
-                    content: e.content,
-                    role: e.role,
-                    metadata: e.metadata,
-                })
+                .map(ContextEntryResponse::from)
                 .collect();
 
             let total = entry_responses.len();
             Ok(Json(ContextResponse {
                 entries: entry_responses,
                 total,
+                warning: None,
             }))
         }
         Err(e) => {
@@ -219,7 +238,32 @@ async fn context_handler(
 ) -> Result<Json<ContextResponse>, StatusCode> {
     let limit = query.limit.unwrap_or(50);
 
-    if let Some(session_id) = query.session_id {
+    if let Some(text) = query.q {
+        match search_context(&state, &text, query.session_id, limit).await {
+            Ok((entries, warning)) => {
+                let entry_responses: Vec<ContextEntryResponse> = entries
+                    .into_iter()
+                    .map(ContextEntryResponse::from)
+                    .collect();
+
+                let total = entry_responses.len();
+                Ok(Json(ContextResponse {
+                    entries: entry_responses,
+                    total,
+                    warning,
+                }))
+            }
+            Err(e) => {
+                // Log error if logger is available
+                if let Some(ref logger) = state.logger {
+                    let _ = logger
+                        .debug(format!("Error searching context entries: {}", e))
+                        .await;
+                }
+                Err(StatusCode::INTERNAL_SERVER_ERROR)
+            }
+        }
+    } else if let Some(session_id) = query.session_id {
         // Get entries for specific session
         match state
             .context_store
@@ -229,21 +273,14 @@ async fn context_handler(
             Ok(entries) => {
                 let entry_responses: Vec<ContextEntryResponse> = entries
                     .into_iter()
-                    .map(|e| ContextEntryResponse {
-                        id: e.id,
-                        agent_name: e.agent_name,
-                        session_id: e.session_id,
-                        timestamp: e.timestamp.format("%Y-%m-%d %H:%M:%S UTC").to_string(),
-                        content: e.content,
-                        role: e.role,
-                        metadata: e.metadata,
-                    })
+                    .map(ContextEntryResponse::from)
                     .collect();
 
                 let total = entry_responses.len();
                 Ok(Json(ContextResponse {
                     entries: entry_responses,
                     total,
+                    warning: None,
                 }))
             }
             Err(e) => {
@@ -262,10 +299,61 @@ async fn context_handler(
         Ok(Json(ContextResponse {
             entries: vec![],
             total: 0,
+            warning: None,
         }))
     }
 }
 
+/// Search context entries, optionally within one session
+///
+/// When the semantic search fails, e.g. because the embedding model is
+/// unavailable, the lexical matches are returned with a warning instead.
+async fn search_context(
+    state: &WebState,
+    text: &str,
+    session_id: Option<String>,
+    limit: usize,
+) -> anyhow::Result<(Vec<ContextEntry>, Option<String>)> {
+    let filter = ContextFilter {
+        session_id,
+        ..ContextFilter::default()
+    };
+
+    let mut warning = None;
+    if let Some(ref service) = state.embedding_service {
+        let results = match service.embed(text).await {
+            Ok(embedding) => {
+                state
+                    .context_store
+                    .search_hybrid(text, Some(&embedding), &filter, limit)
+                    .await
+            }
+            Err(e) => Err(e),
+        };
+        match results {
+            Ok(results) => {
+                return Ok((results.into_iter().map(|(entry, _)| entry).collect(), None));
+            }
+            Err(e) => {
+                let message = format!("Semantic search failed, showing text matches only: {}", e);
+                if let Some(ref logger) = state.logger {
+                    let _ = logger.warn(message.clone()).await;
+                }
+                warning = Some(message);
+            }
+        }
+    }
+
+    let results = state
+        .context_store
+        .search_hybrid(text, None, &filter, limit)
+        .await?;
+    Ok((
+        results.into_iter().map(|(entry, _)| entry).collect(),
+        warning,
+    ))
+}
+
 /// Get logs for a specific session
 async fn session_logs_handler(
     Path(session_id): Path<String>,
@@ -0,0 +1,132 @@
+//! Upgrade tests for context databases written by earlier schema versions.
+//!
+//! Each fixture in `tests/fixtures/context` is a SQL dump of a database as a
+//! prior version left it, with the same sample session. When adding a migration,
+//! add a dump of the schema it replaces and list it in `FIXTURES`.
+
+use rusqlite::Connection;
+use std::path::Path;
+use tempfile::tempdir;
+use vega::context::{ContextFilter, ContextStore, SCHEMA_VERSION};
+use vega::embeddings::EmbeddingModelInfo;
+
+const FIXTURES: &[(u32, &str)] = &[
+    (0, include_str!("fixtures/context/v0.sql")),
+    (1, include_str!("fixtures/context/v1.sql")),
+    (2, include_str!("fixtures/context/v2.sql")),
+    (3, include_str!("fixtures/context/v3.sql")),
+    (4, include_str!("fixtures/context/v4.sql")),
+];
+
+fn write_fixture(path: &Path, sql: &str) {
+    let conn = Connection::open(path).unwrap();
+    conn.execute_batch(sql).unwrap();
+}
+
+#[tokio::test]
+async fn test_fixtures_cover_every_prior_version() {
+    let versions: Vec<u32> = FIXTURES.iter().map(|(version, _)| *version).collect();
+    assert_eq!(versions, (0..SCHEMA_VERSION).collect::<Vec<_>>());
+}
+
+#[tokio::test]
+async fn test_open_fixture_databases_from_prior_versions() {
+    for (version, sql) in FIXTURES {
+        let temp_dir = tempdir().unwrap();
+        let db_path = temp_dir.path().join("vega_context.db");
+        write_fixture(&db_path, sql);
+
+        let store = ContextStore::new(&db_path, 4).await.unwrap();
+        assert_eq!(
+            store.schema_version().unwrap(),
+            SCHEMA_VERSION,
+            "fixture v{} was not upgraded",
+            version
+        );
+
+        // Existing data survives the upgrade
+        let history = store
+            .get_session_history("fixture-session", None)
+            .await
+            .unwrap();
+        assert_eq!(history.len(), 2, "fixture v{}", version);
+        assert_eq!(history[0].content, "How do I run cargo test for vega?");
+        assert_eq!(history[1].metadata.get("model").unwrap(), "llama3.1");
+
+        let commands = store
+            .get_command_history("fixture-session", None)
+            .await
+            .unwrap();
+        assert_eq!(commands, vec!["/help".to_string()]);
+
+        // Existing entries are searchable through indexes added by later versions
+        let matches = store
+            .search_lexical("--workspace", &ContextFilter::default(), 10)
+            .await
+            .unwrap();
+        assert_eq!(matches.len(), 1, "fixture v{}", version);
+
+        let similar = store
+            .search_similar(&[1.0, 0.0, 0.0, 0.0], &ContextFilter::default(), 1)
+            .await
+            .unwrap();
+        assert_eq!(similar[0].0.id, "entry-1");
+    }
+}
+
+#[tokio::test]
+async fn test_upgraded_embeddings_are_untagged() {
+    for (version, sql) in FIXTURES {
+        let temp_dir = tempdir().unwrap();
+        let db_path = temp_dir.path().join("vega_context.db");
+        write_fixture(&db_path, sql);
+
+        // Embeddings from before model tagging are compared by dimension only,
+        // and stay queued for re-embedding with the current model
+        let model = EmbeddingModelInfo::new("ollama", "all-minilm", 4);
+        let store = ContextStore::with_embedding_model(&db_path, model)
+            .await
+            .unwrap();
+        let similar = store
+            .search_similar(&[0.0, 1.0, 0.0, 0.0], &ContextFilter::default(), 1)
+            .await
+            .unwrap();
+        assert_eq!(similar[0].0.id, "entry-2", "fixture v{}", version);
+        assert_eq!(store.count_entries_needing_embedding().await.unwrap(), 2);
+    }
+}
+
+#[tokio::test]
+async fn test_upgraded_database_reopens_unchanged() {
+    let temp_dir = tempdir().unwrap();
+    let db_path = temp_dir.path().join("vega_context.db");
+    write_fixture(&db_path, FIXTURES[0].1);
+
+    drop(ContextStore::new(&db_path, 4).await.unwrap());
+    let store = ContextStore::new(&db_path, 4).await.unwrap();
+    assert_eq!(store.schema_version().unwrap(), SCHEMA_VERSION);
+
+    let conn = Connection::open(&db_path).unwrap();
+    let applied: u32 = conn
+        .query_row("SELECT COUNT(*) FROM schema_version", [], |row| row.get(0))
+        .unwrap();
+    assert_eq!(applied, SCHEMA_VERSION);
+}
+
+#[tokio::test]
+async fn test_refuses_newer_database() {
+    let temp_dir = tempdir().unwrap();
+    let db_path = temp_dir.path().join("vega_context.db");
+    drop(ContextStore::new(&db_path, 4).await.unwrap());
+
+    let conn = Connection::open(&db_path).unwrap();
+    conn.execute(
+        "INSERT INTO schema_version (version, description, applied_at) VALUES (?1, 'from the future', 0)",
+        [SCHEMA_VERSION + 1],
+    )
+    .unwrap();
+    drop(conn);
+
+    let error = ContextStore::new(&db_path, 4).await.err().unwrap();
+    assert!(error.to_string().contains("newer"));
+}
@@ -0,0 +1,31 @@
+BEGIN TRANSACTION;
+CREATE TABLE context_entries (
+                id TEXT PRIMARY KEY,
+                agent_name TEXT NOT NULL,
+                session_id TEXT NOT NULL,
+                timestamp INTEGER NOT NULL,
+                content TEXT NOT NULL,
+                role TEXT NOT NULL,
+                metadata TEXT NOT NULL
+            );
+CREATE TABLE embeddings (
+                entry_id TEXT PRIMARY KEY,
+                embedding BLOB NOT NULL,
+                FOREIGN KEY(entry_id) REFERENCES context_entries(id)
+            );
+CREATE TABLE command_history (
+                id TEXT PRIMARY KEY,
+                session_id TEXT NOT NULL,
+                command TEXT NOT NULL,
+                timestamp INTEGER NOT NULL
+            );
+CREATE INDEX idx_session_id ON context_entries(session_id);
+CREATE INDEX idx_timestamp ON context_entries(timestamp);
+CREATE INDEX idx_command_session_id ON command_history(session_id);
+CREATE INDEX idx_command_timestamp ON command_history(timestamp);
+INSERT INTO context_entries VALUES('entry-1','chat_agent','fixture-session',1700000000,'How do I run cargo test for vega?','user','{}');
+INSERT INTO context_entries VALUES('entry-2','chat_agent','fixture-session',1700000060,'Run cargo test --workspace from the repository root.','assistant','{"model":"llama3.1"}');
+INSERT INTO embeddings VALUES('entry-1',X'0000803F000000000000000000000000');
+INSERT INTO embeddings VALUES('entry-2',X'000000000000803F0000000000000000');
+INSERT INTO command_history VALUES('command-1','fixture-session','/help',1700000000);
+COMMIT;
@@ -0,0 +1,456 @@
+//! Serves Vega's tools over the Streamable HTTP transport and talks to them with
+//! Vega's own MCP client and with plain HTTP requests.
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use reqwest::StatusCode;
+use serde_json::{Value, json};
+use tempfile::tempdir;
+
+use vega::context::{ContextEntry, ContextStore};
+use vega::mcp::config::{RateLimit, ServerSettings, TransportConfig, TransportType};
+use vega::mcp::sse::SseParser;
+use vega::mcp::{McpClient, McpClientConfig, McpServer, McpServerConfig};
+
+async fn start_server(settings: ServerSettings) -> String {
+    let config = McpServerConfig {
+        exposed_tools: vec!["read_file".to_string(), "list_files".to_string()],
+        settings,
+        ..Default::default()
+    };
+    serve(McpServer::new(config).await.unwrap()).await
+}
+
+async fn serve(server: McpServer) -> String {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}/mcp", listener.local_addr().unwrap());
+    tokio::spawn(server.serve_http(listener));
+    url
+}
+
+fn client_config(url: &str) -> McpClientConfig {
+    McpClientConfig {
+        url: Some(url.to_string()),
+        transport: TransportConfig {
+            transport_type: TransportType::Http,
+            ..Default::default()
+        },
+        ..Default::default()
+    }
+}
+
+fn initialize() -> Value {
+    json!({
+        "jsonrpc": "2.0",
+        "id": 1,
+        "method": "initialize",
+        "params": {"protocolVersion": "2025-06-18", "capabilities": {}}
+    })
+}
+
+#[tokio::test]
+async fn test_vega_client_uses_vega_server() {
+    let url = start_server(ServerSettings::default()).await;
+    let dir = tempdir().unwrap();
+    let file = dir.path().join("notes.txt");
+    std::fs::write(&file, "shared tool server").unwrap();
+
+    // Two clients share the server, each in its own session
+    let first = McpClient::connect("vega", client_config(&url))
+        .await
+        .unwrap();
+    let second = McpClient::connect("vega", client_config(&url))
+        .await
+        .unwrap();
+    assert_eq!(first.list_tools(), vec!["list_files", "read_file"]);
+    assert_eq!(
+        first.get_server_info().unwrap()["serverInfo"]["name"],
+        "vega-mcp-server"
+    );
+
+    let response = second
+        .call_tool("read_file", Some(json!({ "path": file.to_string_lossy() })))
+        .await
+        .unwrap();
+    assert_eq!(response.is_error, Some(false));
+    assert!(response.text().contains("shared tool server"));
+
+    let missing = dir.path().join("missing.txt");
+    let response = second
+        .call_tool(
+            "read_file",
+            Some(json!({ "path": missing.to_string_lossy() })),
+        )
+        .await
+        .unwrap();
+    assert_eq!(response.is_error, Some(true));
+
+    // Ending one session leaves the other usable
+    first.disconnect().await.unwrap();
+    assert_eq!(second.refresh_tools().await.unwrap(), 2);
+}
+
+#[tokio::test]
+async fn test_sessions_are_required() {
+    let url = start_server(ServerSettings::default()).await;
+    let http = reqwest::Client::new();
+    let list = json!({"jsonrpc": "2.0", "id": 2, "method": "tools/list"});
+
+    let response = http.post(&url).json(&list).send().await.unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+    let response = http
+        .post(&url)
+        .header("mcp-session-id", "unknown")
+        .json(&list)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+    let response = http.post(&url).json(&initialize()).send().await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let session = response.headers()["mcp-session-id"].clone();
+
+    let notification = json!({"jsonrpc": "2.0", "method": "notifications/initialized"});
+    let response = http
+        .post(&url)
+        .header("mcp-session-id", session.clone())
+        .json(&notification)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::ACCEPTED);
+
+    let response = http.get(&url).send().await.unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+    let response = http
+        .delete(&url)
+        .header("mcp-session-id", session.clone())
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let response = http
+        .post(&url)
+        .header("mcp-session-id", session)
+        .json(&list)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn test_request_size_and_rate_limit() {
+    let url = start_server(ServerSettings {
+        max_request_size: 1024,
+        rate_limit: Some(RateLimit {
+            max_requests: 2,
+            window_seconds: 60,
+        }),
+        ..Default::default()
+    })
+    .await;
+    let http = reqwest::Client::new();
+
+    let mut oversized = initialize();
+    oversized["params"]["padding"] = json!("x".repeat(2048));
+    let response = http.post(&url).json(&oversized).send().await.unwrap();
+    assert_eq!(response.status(), StatusCode::PAYLOAD_TOO_LARGE);
+
+    let response = http.post(&url).json(&initialize()).send().await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let response = http.post(&url).json(&initialize()).send().await.unwrap();
+    assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+    assert!(response.headers().contains_key("retry-after"));
+}
+
+#[tokio::test]
+async fn test_cors_setting() {
+    let http = reqwest::Client::new();
+
+    // By default only pages from the local machine may call the server
+    let url = start_server(ServerSettings::default()).await;
+    let response = http
+        .post(&url)
+        .header("origin", "https://editor.example")
+        .json(&initialize())
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+    let response = http
+        .post(&url)
+        .header("origin", "http://localhost:3000")
+        .json(&initialize())
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    // With CORS, allowed origins are answered and others still refused
+    let url = start_server(ServerSettings {
+        enable_cors: true,
+        allowed_origins: vec!["https://editor.example".to_string()],
+        ..Default::default()
+    })
+    .await;
+    let response = http
+        .post(&url)
+        .header("origin", "https://editor.example")
+        .json(&initialize())
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(
+        response.headers()["access-control-allow-origin"],
+        "https://editor.example"
+    );
+
+    let response = http
+        .post(&url)
+        .header("origin", "https://attacker.example")
+        .json(&initialize())
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+    assert!(
+        !response
+            .headers()
+            .contains_key("access-control-allow-origin")
+    );
+}
+
+#[tokio::test]
+async fn test_auth_token() {
+    let url = start_server(ServerSettings {
+        auth_token: Some("secret".to_string()),
+        ..Default::default()
+    })
+    .await;
+    let http = reqwest::Client::new();
+
+    let response = http.post(&url).json(&initialize()).send().await.unwrap();
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+    let response = http
+        .post(&url)
+        .bearer_auth("wrong")
+        .json(&initialize())
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+    // Vega's client sends the token from its configured headers
+    let mut config = client_config(&url);
+    config
+        .headers
+        .insert("Authorization".to_string(), "Bearer secret".to_string());
+    let client = McpClient::connect("vega", config).await.unwrap();
+    assert_eq!(client.list_tools(), vec!["list_files", "read_file"]);
+}
+
+#[tokio::test]
+async fn test_sessions_are_limited_and_expire() {
+    let url = start_server(ServerSettings {
+        max_sessions: 2,
+        session_idle_timeout: 1,
+        ..Default::default()
+    })
+    .await;
+    let http = reqwest::Client::new();
+    let ping = json!({"jsonrpc": "2.0", "id": 2, "method": "ping"});
+    let mut sessions = Vec::new();
+    for _ in 0..3 {
+        let response = http.post(&url).json(&initialize()).send().await.unwrap();
+        sessions.push(response.headers()["mcp-session-id"].clone());
+    }
+    let status = |session| {
+        let request = http
+            .post(&url)
+            .header("mcp-session-id", session)
+            .json(&ping);
+        async move { request.send().await.unwrap().status() }
+    };
+
+    // The least recently used session made room for the third
+    assert_eq!(status(sessions[0].clone()).await, StatusCode::NOT_FOUND);
+    assert_eq!(status(sessions[1].clone()).await, StatusCode::OK);
+    assert_eq!(status(sessions[2].clone()).await, StatusCode::OK);
+
+    tokio::time::sleep(Duration::from_millis(1100)).await;
+    assert_eq!(status(sessions[2].clone()).await, StatusCode::NOT_FOUND);
+}
+
+fn chat_entry(content: &str) -> ContextEntry {
+    ContextEntry::new(
+        "chat".to_string(),
+        "s1".to_string(),
+        content.to_string(),
+        "user".to_string(),
+    )
+}
+
+#[tokio::test]
+async fn test_sessions_as_resources() {
+    let dir = tempdir().unwrap();
+    let store = Arc::new(
+        ContextStore::new(dir.path().join("context.db"), 4)
+            .await
+            .unwrap(),
+    );
+    store
+        .store_context(chat_entry("Where did we leave off?"), vec![0.0; 4])
+        .await
+        .unwrap();
+
+    let server = McpServer::new(McpServerConfig::default())
+        .await
+        .unwrap()
+        .with_context(store.clone());
+    let url = serve(server).await;
+
+    // Vega's client lists and reads the session
+    let client = McpClient::connect("vega", client_config(&url))
+        .await
+        .unwrap();
+    assert_eq!(
+        client.list_resources(),
+        vec!["vega://sessions/s1", "vega://sessions/s1/logs"]
+    );
+    let read = client.read_resource("vega://sessions/s1").await.unwrap();
+    assert!(
+        read["contents"][0]["text"]
+            .as_str()
+            .unwrap()
+            .contains("Where did we leave off?")
+    );
+    client.disconnect().await.unwrap();
+
+    // A subscriber is notified on the session's event stream
+    let http = reqwest::Client::new();
+    let response = http.post(&url).json(&initialize()).send().await.unwrap();
+    let session = response.headers()["mcp-session-id"].clone();
+
+    let mut stream = http
+        .get(&url)
+        .header("mcp-session-id", session.clone())
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(stream.status(), StatusCode::OK);
+    let response = http
+        .get(&url)
+        .header("mcp-session-id", session.clone())
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::CONFLICT);
+
+    let subscribe = json!({
+        "jsonrpc": "2.0",
+        "id": 2,
+        "method": "resources/subscribe",
+        "params": {"uri": "vega://sessions/s1"}
+    });
+    let response = http
+        .post(&url)
+        .header("mcp-session-id", session.clone())
+        .json(&subscribe)
+        .send()
+        .await
+        .unwrap();
+    let response: Value = response.json().await.unwrap();
+    assert_eq!(response["result"], json!({}));
+
+    store
+        .store_context(chat_entry("Right here"), vec![0.0; 4])
+        .await
+        .unwrap();
+
+    let mut parser = SseParser::new();
+    let notification = tokio::time::timeout(Duration::from_secs(10), async {
+        loop {
+            let chunk = stream.chunk().await.unwrap().expect("stream ended");
+            if let Some(event) = parser.feed(&chunk).into_iter().next() {
+                return serde_json::from_str::<Value>(&event.data).unwrap();
+            }
+        }
+    })
+    .await
+    .expect("no notification");
+    assert_eq!(notification["method"], "notifications/resources/updated");
+    assert_eq!(notification["params"]["uri"], "vega://sessions/s1");
+
+    // Deleting the session ends its stream
+    http.delete(&url)
+        .header("mcp-session-id", session)
+        .send()
+        .await
+        .unwrap();
+    let end = tokio::time::timeout(Duration::from_secs(5), stream.chunk()).await;
+    assert!(matches!(end, Ok(Ok(None))));
+}
+
+#[tokio::test]
+async fn test_tool_progress_and_cancellation() {
+    let config = McpServerConfig {
+        exposed_tools: vec!["bash".to_string()],
+        settings: ServerSettings {
+            allow_ask: true,
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    let url = serve(McpServer::new(config).await.unwrap()).await;
+    let client = McpClient::connect("vega", client_config(&url))
+        .await
+        .unwrap();
+
+    // Progress arrives on the event stream answering the call
+    let (progress, mut updates) = tokio::sync::mpsc::unbounded_channel();
+    let command = "for i in 1 2 3; do echo line $i; sleep 0.3; done";
+    let response = client
+        .call_tool_with_progress("bash", Some(json!({ "command": command })), Some(progress))
+        .await
+        .unwrap();
+    assert!(response.text().contains("line 3"));
+    let mut reported = Vec::new();
+    while let Some(update) = updates.recv().await {
+        reported.push(update.progress);
+    }
+    assert_eq!(reported, vec![1.0, 2.0, 3.0]);
+
+    // Abandoning a call cancels it, so the command never finishes
+    let dir = tempdir().unwrap();
+    let marker = dir.path().join("finished");
+    let command = format!("sleep 1 && touch {}", marker.display());
+    let call = tokio::spawn({
+        let client = client.clone();
+        async move {
+            client
+                .call_tool("bash", Some(json!({ "command": command })))
+                .await
+        }
+    });
+    tokio::time::sleep(Duration::from_millis(300)).await;
+    call.abort();
+
+    tokio::time::sleep(Duration::from_millis(1500)).await;
+    assert!(!marker.exists());
+    assert!(
+        client
+            .call_tool("bash", Some(json!({ "command": "true" })))
+            .await
+            .is_ok()
+    );
+}
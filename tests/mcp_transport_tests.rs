@@ -0,0 +1,258 @@
+//! Exercises the MCP HTTP transports against a local server speaking both the
+//! Streamable HTTP transport and the legacy HTTP+SSE transport.
+
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+
+use axum::body::Body;
+use axum::extract::{Query, State};
+use axum::http::{HeaderMap, StatusCode, header};
+use axum::response::{IntoResponse, Response};
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use serde_json::{Value, json};
+use tokio::sync::mpsc;
+
+use vega::mcp::McpClient;
+use vega::mcp::config::{McpClientConfig, TransportConfig, TransportType};
+
+const SESSION_ID: &str = "session-1";
+
+#[derive(Default)]
+struct TestServer {
+    /// Session of the Streamable HTTP endpoint, `None` once it expired
+    session: Mutex<Option<String>>,
+    /// Events a resumed stream replays, by the event id they follow
+    replay: Mutex<HashMap<String, String>>,
+    saw_protocol_version: AtomicBool,
+    deleted: AtomicBool,
+    /// Event streams of legacy clients, by session
+    legacy: Mutex<HashMap<String, mpsc::UnboundedSender<Value>>>,
+}
+
+fn response(id: &Value, result: Value) -> Value {
+    json!({"jsonrpc": "2.0", "id": id, "result": result})
+}
+
+fn text(text: &str) -> Value {
+    json!({"content": [{"type": "text", "text": text}]})
+}
+
+/// Result of a request both transports answer the same way
+fn handle(request: &Value) -> Value {
+    match request["method"].as_str().unwrap_or_default() {
+        "initialize" => json!({
+            "protocolVersion": "2025-06-18",
+            "capabilities": {"tools": {"listChanged": true}},
+            "serverInfo": {"name": "test-server", "version": "1.0"}
+        }),
+        "tools/list" => json!({"tools": [
+            {"name": "echo", "inputSchema": {"type": "object"}},
+            {"name": "flaky", "inputSchema": {"type": "object"}}
+        ]}),
+        "tools/call" => text(
+            request["params"]["arguments"]["text"]
+                .as_str()
+                .unwrap_or(""),
+        ),
+        _ => json!({}),
+    }
+}
+
+fn event_stream(body: String) -> Response {
+    ([(header::CONTENT_TYPE, "text/event-stream")], body).into_response()
+}
+
+async fn streamable_post(
+    State(server): State<Arc<TestServer>>,
+    headers: HeaderMap,
+    Json(message): Json<Value>,
+) -> Response {
+    if message["method"] == "initialize" {
+        *server.session.lock().unwrap() = Some(SESSION_ID.to_string());
+        let body = response(&message["id"], handle(&message));
+        return ([("mcp-session-id", SESSION_ID)], Json(body)).into_response();
+    }
+
+    let session = headers.get("mcp-session-id").and_then(|v| v.to_str().ok());
+    match (session, server.session.lock().unwrap().as_deref()) {
+        (None, _) => return StatusCode::BAD_REQUEST.into_response(),
+        (Some(sent), Some(current)) if sent == current => {}
+        _ => return StatusCode::NOT_FOUND.into_response(),
+    }
+    if headers.contains_key("mcp-protocol-version") {
+        server.saw_protocol_version.store(true, Ordering::SeqCst);
+    }
+
+    // Notifications and responses are only acknowledged
+    let Some(id) = message.get("id") else {
+        return StatusCode::ACCEPTED.into_response();
+    };
+
+    let result = response(id, handle(&message));
+    let progress = json!({
+        "jsonrpc": "2.0",
+        "method": "notifications/message",
+        "params": {"level": "info", "data": "working"}
+    });
+
+    if message["params"]["name"] == "flaky" {
+        // The stream breaks before the response, which a resumed stream replays
+        server.replay.lock().unwrap().insert(
+            "ev-1".to_string(),
+            format!("id: ev-2\ndata: {}\n\n", result),
+        );
+        return event_stream(format!("retry: 10\nid: ev-1\ndata: {}\n\n", progress));
+    }
+
+    match message["method"].as_str() {
+        Some("tools/list") => Json(result).into_response(),
+        _ => event_stream(format!(
+            "id: ev-1\ndata: {}\n\n: keep-alive\n\nid: ev-2\nevent: message\ndata: {}\n\n",
+            progress, result
+        )),
+    }
+}
+
+async fn streamable_get(State(server): State<Arc<TestServer>>, headers: HeaderMap) -> Response {
+    let last_event_id = headers.get("last-event-id").and_then(|v| v.to_str().ok());
+    match last_event_id.and_then(|id| server.replay.lock().unwrap().remove(id)) {
+        Some(events) => event_stream(events),
+        None => StatusCode::METHOD_NOT_ALLOWED.into_response(),
+    }
+}
+
+async fn streamable_delete(State(server): State<Arc<TestServer>>) -> StatusCode {
+    server.deleted.store(true, Ordering::SeqCst);
+    StatusCode::OK
+}
+
+async fn legacy_stream(State(server): State<Arc<TestServer>>) -> Response {
+    let (tx, rx) = mpsc::unbounded_channel::<Value>();
+    server.legacy.lock().unwrap().insert("abc".to_string(), tx);
+
+    let endpoint = futures::stream::once(async {
+        Ok::<_, Infallible>("event: endpoint\ndata: /messages?session_id=abc\n\n".to_string())
+    });
+    let messages = futures::stream::unfold(rx, |mut rx| async move {
+        let message = rx.recv().await?;
+        Some((Ok(format!("event: message\ndata: {}\n\n", message)), rx))
+    });
+    let body = Body::from_stream(futures::StreamExt::chain(endpoint, messages));
+    ([(header::CONTENT_TYPE, "text/event-stream")], body).into_response()
+}
+
+async fn legacy_post(
+    State(server): State<Arc<TestServer>>,
+    Query(query): Query<HashMap<String, String>>,
+    Json(message): Json<Value>,
+) -> StatusCode {
+    let legacy = server.legacy.lock().unwrap();
+    let Some(stream) = query.get("session_id").and_then(|id| legacy.get(id)) else {
+        return StatusCode::NOT_FOUND;
+    };
+    if let Some(id) = message.get("id") {
+        let _ = stream.send(response(id, handle(&message)));
+    }
+    StatusCode::ACCEPTED
+}
+
+async fn start_server() -> (String, Arc<TestServer>) {
+    let server = Arc::new(TestServer::default());
+    let app = Router::new()
+        .route(
+            "/mcp",
+            post(streamable_post)
+                .get(streamable_get)
+                .delete(streamable_delete),
+        )
+        .route("/sse", get(legacy_stream))
+        .route("/messages", post(legacy_post))
+        .with_state(server.clone());
+
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let address = listener.local_addr().unwrap();
+    tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+    (format!("http://{}", address), server)
+}
+
+fn config(url: String, transport_type: TransportType) -> McpClientConfig {
+    McpClientConfig {
+        url: Some(url),
+        transport: TransportConfig {
+            transport_type,
+            ..Default::default()
+        },
+        ..Default::default()
+    }
+}
+
+async fn call(client: &McpClient, tool: &str, input: &str) -> String {
+    client
+        .call_tool(tool, Some(json!({ "text": input })))
+        .await
+        .unwrap()
+        .text()
+}
+
+#[tokio::test]
+async fn test_streamable_http_transport() {
+    let (base, server) = start_server().await;
+    let client = McpClient::connect("web", config(format!("{}/mcp", base), TransportType::Http))
+        .await
+        .unwrap();
+
+    // tools/list is answered with plain JSON, tools/call with an event stream
+    assert_eq!(client.list_tools(), vec!["echo", "flaky"]);
+    assert_eq!(call(&client, "echo", "hello").await, "hello");
+    assert!(server.saw_protocol_version.load(Ordering::SeqCst));
+
+    // A stream that breaks before the response is resumed with Last-Event-ID
+    assert_eq!(call(&client, "flaky", "resumed").await, "resumed");
+    assert!(server.replay.lock().unwrap().is_empty());
+
+    client.disconnect().await.unwrap();
+    assert!(server.deleted.load(Ordering::SeqCst));
+    assert!(!client.is_connected());
+}
+
+#[tokio::test]
+async fn test_streamable_http_session_expiry() {
+    let (base, server) = start_server().await;
+    let client = McpClient::connect("web", config(format!("{}/mcp", base), TransportType::Http))
+        .await
+        .unwrap();
+
+    server.session.lock().unwrap().take();
+    let error = client.refresh_tools().await.unwrap_err();
+    assert!(error.to_string().contains("session expired"));
+}
+
+#[tokio::test]
+async fn test_legacy_sse_transport() {
+    let (base, _server) = start_server().await;
+    let client = McpClient::connect(
+        "legacy",
+        config(format!("{}/sse", base), TransportType::Sse),
+    )
+    .await
+    .unwrap();
+
+    assert_eq!(client.list_tools(), vec!["echo", "flaky"]);
+    assert_eq!(call(&client, "echo", "over sse").await, "over sse");
+    client.disconnect().await.unwrap();
+}
+
+#[tokio::test]
+async fn test_http_transport_unreachable_server() {
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}/mcp", listener.local_addr().unwrap());
+    drop(listener);
+
+    let error = McpClient::connect("gone", config(url, TransportType::Http))
+        .await
+        .unwrap_err();
+    assert!(error.to_string().contains("Failed to send message"));
+}